@@ -0,0 +1,193 @@
+//! `pilot bench`, runs a task again and again to see how long it takes.
+
+use std::{
+    fs::{create_dir_all, read_to_string, write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use yaml_rust::YamlLoader;
+
+use crate::{clock, shutdown, state, Error, OrError, Pilotfile, RunOptions, Runner};
+
+const USAGE: &str =
+    "Usage: pilot bench <task> [--runs <n>] [--save-baseline] [--max-regression <percent>]";
+const DEFAULT_RUNS: usize = 10;
+const BENCH_DIR: &str = "bench";
+
+fn baseline_path(pilotfile_dir: &Path, task: &str) -> PathBuf {
+    state::state_dir(pilotfile_dir)
+        .join(BENCH_DIR)
+        .join(state::file_name(task) + ".json")
+}
+
+/// The durations of the saved runs in seconds, if there are any.
+fn load_baseline(path: &Path) -> Option<Vec<f64>> {
+    let file = read_to_string(path).ok()?;
+    let doc = YamlLoader::load_from_str(&file).ok()?.into_iter().next()?;
+
+    doc["runs"]
+        .as_vec()?
+        .iter()
+        .map(|run| run.as_f64())
+        .collect::<Option<Vec<_>>>()
+        .filter(|runs| !runs.is_empty())
+}
+
+fn median(durations: &[f64]) -> f64 {
+    let mut sorted = durations.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let middle = sorted.len() / 2;
+
+    if sorted.len().is_multiple_of(2) {
+        (sorted[middle - 1] + sorted[middle]) / 2.0
+    } else {
+        sorted[middle]
+    }
+}
+
+/// The sample standard deviation, 0 for a single run.
+fn stddev(durations: &[f64]) -> f64 {
+    if durations.len() < 2 {
+        return 0.0;
+    }
+
+    let mean = durations.iter().sum::<f64>() / durations.len() as f64;
+    let variance = durations
+        .iter()
+        .map(|duration| (duration - mean).powi(2))
+        .sum::<f64>()
+        / (durations.len() - 1) as f64;
+
+    variance.sqrt()
+}
+
+/// Aligns the durations like the `--timings` of a task.
+fn print_duration(seconds: f64, label: &str) {
+    println!(
+        "{:>10} {}",
+        clock::human(Duration::from_secs_f64(seconds)),
+        label
+    );
+}
+
+/// `pilot bench <task>`, runs the task without its output and reports how long the runs took,
+/// compared to the saved baseline of the task. Gives the exit code of pilot, a failed run or a
+/// regression is an error.
+pub fn cli(args: &[String], pilotfile: Pilotfile) -> Result<i32, Error> {
+    let mut task = None;
+    let mut runs = DEFAULT_RUNS;
+    let mut save_baseline = false;
+    let mut max_regression = None;
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--runs" => {
+                runs = args
+                    .next()
+                    .and_then(|runs| runs.parse::<usize>().ok())
+                    .filter(|runs| *runs > 0)
+                    .or_error("--runs needs a number of runs")?
+            }
+            "--save-baseline" => save_baseline = true,
+            "--max-regression" => {
+                max_regression = Some(
+                    args.next()
+                        .and_then(|percent| percent.trim_end_matches('%').parse::<f64>().ok())
+                        .or_error("--max-regression needs a percentage")?,
+                )
+            }
+            _ if task.is_none() && !arg.starts_with('-') => task = Some(arg.clone()),
+            _ => return Err(Error(USAGE.to_string())),
+        }
+    }
+
+    let task = task.or_error(USAGE)?;
+
+    let path = baseline_path(pilotfile.dir(), &task);
+    let baseline_median = load_baseline(&path).as_deref().map(median);
+
+    // fail before spending the time on the runs
+    if max_regression.is_some() && baseline_median.is_none() {
+        return Err(Error(format!(
+            "Task {} has no baseline yet, --save-baseline saves one",
+            task
+        )));
+    }
+
+    let mut options = RunOptions::new(&pilotfile);
+    options.silent = true;
+    let runner = Runner::new(pilotfile, options);
+
+    shutdown::install();
+    println!("bench {}", task);
+
+    let mut durations = vec![];
+
+    for run in 1..=runs {
+        let start = clock::Stopwatch::start();
+        let result = runner.run(&task)?;
+
+        if shutdown::is_interrupted() {
+            return Ok(shutdown::INTERRUPTED_EXIT_CODE);
+        }
+
+        if !result.success() {
+            return Err(Error(format!(
+                "Task {} failed in run {}, pilot {} shows its output",
+                task, run, task
+            )));
+        }
+
+        durations.push(start.elapsed().as_secs_f64());
+        print_duration(durations[run - 1], &format!("run {}", run));
+    }
+
+    let median = median(&durations);
+
+    println!("summary of {} runs", runs);
+    print_duration(durations.iter().copied().fold(f64::MAX, f64::min), "min");
+    print_duration(median, "median");
+    print_duration(durations.iter().copied().fold(0.0, f64::max), "max");
+    print_duration(stddev(&durations), "stddev");
+
+    let mut regression = None;
+
+    if let Some(baseline_median) = baseline_median {
+        let change = (median / baseline_median - 1.0) * 100.0;
+        print_duration(
+            baseline_median,
+            &format!("baseline median, {:+.1}%", change),
+        );
+
+        regression = max_regression
+            .filter(|max_regression| change > *max_regression)
+            .map(|max_regression| (change, max_regression));
+    }
+
+    if save_baseline {
+        let runs: Vec<_> = durations
+            .iter()
+            .map(|duration| format!("{:.6}", duration))
+            .collect();
+
+        create_dir_all(path.parent().unwrap())
+            .or_error("Could not create the benchmark baselines in .pilot/bench")?;
+        write(
+            &path,
+            format!("{{\n  \"runs\": [{}]\n}}\n", runs.join(", ")),
+        )
+        .or_error("Could not save the baseline")?;
+        println!("saved the runs as the baseline of {}", task);
+    }
+
+    if let Some((change, max_regression)) = regression {
+        return Err(Error(format!(
+            "Task {} got {:.1}% slower than its baseline, only {}% are allowed",
+            task, change, max_regression
+        )));
+    }
+
+    Ok(0)
+}
@@ -0,0 +1,466 @@
+//! The `cache` of a task, which restores its outputs instead of running it again when its inputs
+//! didn't change. The entries are in `.pilot/cache`, a `shared_cache` directory or HTTP endpoint
+//! lets CI runners and teammates reuse each other's entries.
+
+use std::{
+    env,
+    fs::{self, create_dir_all, remove_file, rename},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::{self, Command, Stdio},
+};
+
+use sha2::{Digest, Sha256};
+use yaml_rust::Yaml;
+
+use crate::{config::Task, state};
+
+const CACHE_DIR: &str = "cache";
+
+/// `cache` of a task, the files it reads and the ones it makes.
+#[derive(Clone)]
+pub struct TaskCache {
+    /// the files and directories the outputs depend on, besides the steps of the task
+    pub inputs: Vec<String>,
+    /// the files and directories the task makes, they are what is restored
+    pub outputs: Vec<String>,
+}
+
+impl TaskCache {
+    /// `cache: {inputs: [src, Cargo.toml], outputs: [target/release/app]}`, the inputs are
+    /// optional.
+    pub(crate) fn from_yaml(yaml: &Yaml) -> Option<Self> {
+        let paths = |yaml: &Yaml| match yaml {
+            Yaml::BadValue => Some(vec![]),
+            Yaml::String(path) => Some(vec![path.clone()]),
+            _ => yaml
+                .as_vec()?
+                .iter()
+                .map(|path| path.as_str().map(str::to_string))
+                .collect(),
+        };
+
+        Some(TaskCache {
+            inputs: paths(&yaml["inputs"])?,
+            outputs: paths(&yaml["outputs"])?,
+        })
+        .filter(|cache| !cache.outputs.is_empty())
+    }
+}
+
+/// Where the entries are shared.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Backend {
+    /// a directory every machine can reach, like a network drive
+    Dir(PathBuf),
+    /// an HTTP endpoint taking `GET` and `PUT` of the entries, with a region the requests are
+    /// signed like the ones of S3, the credentials come from `$AWS_ACCESS_KEY_ID` and
+    /// `$AWS_SECRET_ACCESS_KEY`
+    Url {
+        url: String,
+        s3_region: Option<String>,
+    },
+}
+
+/// `shared_cache`, where the task caches are shared beyond the machine.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SharedCache {
+    pub backend: Backend,
+    /// only restore entries, for machines whose results shouldn't be shared
+    pub read_only: bool,
+}
+
+impl SharedCache {
+    /// `shared_cache: {dir: <path>}` or `{url: <url>, s3_region: <region>}`, with
+    /// `mode: read_only` or `read_write`, the default. Relative directories are in `dir`.
+    pub(crate) fn from_yaml(yaml: &Yaml, dir: &Path) -> Result<Self, String> {
+        let invalid =
+            || "shared_cache needs a dir or a url, and optionally s3_region and a mode".to_string();
+
+        let backend = match (yaml["dir"].as_str(), yaml["url"].as_str()) {
+            (Some(shared_dir), None) if yaml["s3_region"].is_badvalue() => {
+                Backend::Dir(dir.join(shared_dir))
+            }
+            (None, Some(url)) => Backend::Url {
+                url: url.trim_end_matches('/').to_string(),
+                s3_region: match &yaml["s3_region"] {
+                    Yaml::BadValue => None,
+                    region => Some(region.as_str().ok_or_else(invalid)?.to_string()),
+                },
+            },
+            _ => return Err(invalid()),
+        };
+
+        let read_only = match &yaml["mode"] {
+            Yaml::BadValue => false,
+            mode => match mode.as_str() {
+                Some("read_only") => true,
+                Some("read_write") => false,
+                _ => return Err("the mode of shared_cache needs read_only or read_write".into()),
+            },
+        };
+
+        Ok(SharedCache { backend, read_only })
+    }
+
+    /// Copies the entry to `to`, whether the shared cache has it.
+    fn fetch(&self, key: &str, to: &Path) -> io::Result<bool> {
+        let url = match &self.backend {
+            Backend::Dir(dir) => {
+                let from = dir.join(entry_file(key));
+
+                return match from.exists() {
+                    true => fs::copy(from, to).map(|_| true),
+                    false => Ok(false),
+                };
+            }
+            Backend::Url { url, .. } => format!("{}/{}", url, entry_file(key)),
+        };
+
+        // without --fail a missing entry can be told apart from an endpoint that is down
+        let status = self.curl(&[
+            "--output",
+            &to.to_string_lossy(),
+            "--write-out",
+            "%{http_code}",
+            &url,
+        ])?;
+
+        match status.trim() {
+            "200" => Ok(true),
+            "404" => {
+                let _ = remove_file(to);
+                Ok(false)
+            }
+            status => {
+                let _ = remove_file(to);
+                Err(io::Error::other(format!(
+                    "{} answered with {}",
+                    url, status
+                )))
+            }
+        }
+    }
+
+    fn store(&self, key: &str, from: &Path) -> io::Result<()> {
+        match &self.backend {
+            Backend::Dir(dir) => {
+                create_dir_all(dir)?;
+                // the other machines never see half of an entry
+                let partial = dir.join(partial_file(key));
+                fs::copy(from, &partial)?;
+                rename(partial, dir.join(entry_file(key)))
+            }
+            Backend::Url { url, .. } => self
+                .curl(&[
+                    "--fail",
+                    "--upload-file",
+                    &from.to_string_lossy(),
+                    &format!("{}/{}", url, entry_file(key)),
+                ])
+                .map(|_| ()),
+        }
+    }
+
+    /// Runs curl with `args` and gives its output. The credentials go through stdin, so other
+    /// users don't see them in the process list.
+    fn curl(&self, args: &[&str]) -> io::Result<String> {
+        let mut command = Command::new("curl");
+        command
+            .args(["--silent", "--show-error", "--max-time", "60"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+
+        let credentials = match &self.backend {
+            Backend::Url {
+                s3_region: Some(region),
+                ..
+            } => {
+                command.args(["--aws-sigv4", &format!("aws:amz:{}:s3", region)]);
+                command.args(["--config", "-"]);
+                let id = env::var("AWS_ACCESS_KEY_ID").unwrap_or_default();
+                let secret = env::var("AWS_SECRET_ACCESS_KEY").unwrap_or_default();
+                format!("user = \"{}:{}\"\n", id, secret)
+            }
+            _ => String::new(),
+        };
+
+        let mut curl = command.args(args).spawn()?;
+
+        if let Some(mut stdin) = curl.stdin.take() {
+            stdin.write_all(credentials.as_bytes())?;
+        }
+
+        let output = curl.wait_with_output()?;
+
+        match output.status.success() {
+            true => Ok(String::from_utf8_lossy(&output.stdout).into_owned()),
+            false => Err(io::Error::other(
+                String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            )),
+        }
+    }
+}
+
+fn entry_file(key: &str) -> String {
+    key.to_string() + ".tar"
+}
+
+fn partial_file(key: &str) -> String {
+    format!("{}.tar.{}", key, process::id())
+}
+
+/// Adds a file or a directory with everything in it to the key, missing files count too. A
+/// symlink counts with where it points, like tar archives it, following it could leave the
+/// directory of the task or never end.
+fn hash_path(hasher: &mut Sha256, dir: &Path, path: &str) {
+    let full = dir.join(path);
+
+    let Ok(metadata) = fs::symlink_metadata(&full) else {
+        hasher.update(format!("missing {}\n", path));
+        return;
+    };
+
+    if metadata.is_symlink() {
+        let target = fs::read_link(&full).unwrap_or_default();
+        hasher.update(format!("link {} {}\n", path, target.to_string_lossy()));
+    } else if metadata.is_dir() {
+        let mut names: Vec<_> = fs::read_dir(&full)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|entry| entry.file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+
+        hasher.update(format!("dir {}\n", path));
+
+        for name in names {
+            hash_path(
+                hasher,
+                dir,
+                &format!("{}/{}", path.trim_end_matches('/'), name),
+            );
+        }
+    } else {
+        match fs::read(&full) {
+            Ok(content) => {
+                hasher.update(format!("file {} {}\n", path, content.len()));
+                hasher.update(content);
+            }
+            Err(_) => hasher.update(format!("missing {}\n", path)),
+        }
+    }
+}
+
+/// Runs tar in `dir`, which makes the archives the same on every platform pilot runs on.
+fn tar(dir: &Path, args: &[&str]) -> io::Result<()> {
+    let output = Command::new("tar")
+        .args(args)
+        .current_dir(dir)
+        .stdin(Stdio::null())
+        .output()?;
+
+    match output.status.success() {
+        true => Ok(()),
+        false => Err(io::Error::other(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        )),
+    }
+}
+
+/// The cache entry of a task for its current inputs.
+pub(crate) struct Entry<'a> {
+    key: String,
+    task: &'a str,
+    outputs: &'a [String],
+    /// the directory of the Pilotfile, the paths are relative to it
+    dir: &'a Path,
+    shared: Option<&'a SharedCache>,
+}
+
+impl<'a> Entry<'a> {
+    /// The key depends on the version of pilot, the task as it is written and its inputs.
+    pub(crate) fn new(
+        task: &'a Task,
+        cache: &'a TaskCache,
+        shared: Option<&'a SharedCache>,
+        dir: &'a Path,
+    ) -> Self {
+        let mut hasher = Sha256::new();
+        hasher.update(format!("pilot {}\n", env!("CARGO_PKG_VERSION")));
+        hasher.update(format!("task {}\n{}\n", task.name, task.definition));
+
+        for output in &cache.outputs {
+            hasher.update(format!("output {}\n", output));
+        }
+
+        for input in &cache.inputs {
+            hash_path(&mut hasher, dir, input);
+        }
+
+        let key = hasher
+            .finalize()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+
+        Entry {
+            key,
+            task: &task.name,
+            outputs: &cache.outputs,
+            dir,
+            shared,
+        }
+    }
+
+    fn local_file(&self) -> PathBuf {
+        state::state_dir(self.dir)
+            .join(CACHE_DIR)
+            .join(entry_file(&self.key))
+    }
+
+    /// Restores the outputs from the cache of this machine or else the shared one, whether
+    /// either had them. A cache that can't be read only means the task runs.
+    pub(crate) fn restore(&self) -> bool {
+        let local = self.local_file();
+
+        if !local.exists() {
+            let Some(shared) = self.shared else {
+                return false;
+            };
+
+            let partial = local.with_file_name(partial_file(&self.key));
+            let fetched = local
+                .parent()
+                .map_or(Ok(()), create_dir_all)
+                .and_then(|_| shared.fetch(&self.key, &partial))
+                .and_then(|fetched| match fetched {
+                    true => rename(&partial, &local).map(|_| true),
+                    false => Ok(false),
+                });
+
+            match fetched {
+                Ok(true) => {}
+                Ok(false) => return false,
+                Err(err) => {
+                    let _ = remove_file(&partial);
+                    eprintln!(
+                        "Could not read the shared cache of task {}: {}",
+                        self.task, err
+                    );
+                    return false;
+                }
+            }
+        }
+
+        if let Err(err) = tar(self.dir, &["-xf", &local.to_string_lossy()]) {
+            eprintln!(
+                "Could not restore the outputs of task {} from the cache: {}",
+                self.task, err
+            );
+            return false;
+        }
+
+        true
+    }
+
+    /// Saves the outputs after the task succeeded, in the shared cache too unless it is read
+    /// only. A cache that can't be written doesn't fail the task.
+    pub(crate) fn save(&self) {
+        let local = self.local_file();
+        let partial = local.with_file_name(partial_file(&self.key));
+
+        let mut args = vec!["-cf".to_string(), partial.to_string_lossy().into_owned()];
+        args.extend(self.outputs.iter().cloned());
+        let args: Vec<_> = args.iter().map(String::as_str).collect();
+
+        let saved = local
+            .parent()
+            .map_or(Ok(()), create_dir_all)
+            .and_then(|_| tar(self.dir, &args))
+            .and_then(|_| rename(&partial, &local));
+
+        if let Err(err) = saved {
+            let _ = remove_file(&partial);
+            eprintln!(
+                "Could not save the outputs of task {} in the cache: {}",
+                self.task, err
+            );
+            return;
+        }
+
+        if let Some(shared) = self.shared.filter(|shared| !shared.read_only) {
+            if let Err(err) = shared.store(&self.key, &local) {
+                eprintln!(
+                    "Could not write the shared cache of task {}: {}",
+                    self.task, err
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shared_cache(yaml: &str) -> Result<SharedCache, String> {
+        let yaml = yaml_rust::YamlLoader::load_from_str(yaml)
+            .unwrap()
+            .remove(0);
+        SharedCache::from_yaml(&yaml, Path::new("/project"))
+    }
+
+    #[test]
+    fn shared_cache_backends() {
+        assert_eq!(
+            shared_cache("{dir: ../cache, mode: read_only}"),
+            Ok(SharedCache {
+                backend: Backend::Dir(PathBuf::from("/project/../cache")),
+                read_only: true,
+            })
+        );
+        assert_eq!(
+            shared_cache("{url: 'https://cache.example.com/pilot/', s3_region: eu-west-1}"),
+            Ok(SharedCache {
+                backend: Backend::Url {
+                    url: "https://cache.example.com/pilot".to_string(),
+                    s3_region: Some("eu-west-1".to_string()),
+                },
+                read_only: false,
+            })
+        );
+        assert!(shared_cache("{dir: cache, url: 'https://cache.example.com'}").is_err());
+        assert!(shared_cache("{dir: cache, s3_region: eu-west-1}").is_err());
+        assert!(shared_cache("{dir: cache, mode: write_only}").is_err());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn hash_symlinks_by_their_target() {
+        let dir = env::temp_dir().join(format!("pilot-cache-links-{}", process::id()));
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::write(dir.join("src/main.rs"), "fn main() {}").unwrap();
+        std::os::unix::fs::symlink("..", dir.join("src/parent")).unwrap();
+        std::os::unix::fs::symlink("/etc/hostname", dir.join("src/outside")).unwrap();
+
+        let hash = || {
+            let mut hasher = Sha256::new();
+            hash_path(&mut hasher, &dir, "src");
+            hasher.finalize()
+        };
+        let before = hash();
+
+        // neither following the link to the parent nor reading the file it points to
+        fs::write(dir.join("other.txt"), "changed").unwrap();
+        assert_eq!(hash(), before);
+
+        fs::remove_file(dir.join("src/outside")).unwrap();
+        std::os::unix::fs::symlink("/etc/hosts", dir.join("src/outside")).unwrap();
+        assert_ne!(hash(), before);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
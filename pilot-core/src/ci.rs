@@ -0,0 +1,161 @@
+//! Annotations for CI systems, so failed tasks and invalid Pilotfiles show up next to the
+//! Pilotfile in a pull request instead of only in the log of the job.
+
+use std::{
+    collections::hash_map::DefaultHasher,
+    env,
+    fs::{read_to_string, write},
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+};
+
+use crate::{edit, pilotfile, state::json_string, status};
+
+/// The report GitLab shows in merge requests, when the job lists it under
+/// `artifacts: reports: codequality`.
+const GITLAB_REPORT: &str = "gl-code-quality-report.json";
+
+enum Ci {
+    GitHub,
+    GitLab,
+}
+
+fn detect() -> Option<Ci> {
+    let is_set = |name| env::var(name).is_ok_and(|value| value == "true");
+
+    if is_set("GITHUB_ACTIONS") {
+        Some(Ci::GitHub)
+    } else if is_set("GITLAB_CI") {
+        Some(Ci::GitLab)
+    } else {
+        None
+    }
+}
+
+/// The Pilotfile relative to the checkout, which is what the annotations refer to.
+fn pilotfile_path(pilotfile_dir: &Path, root_variable: &str) -> PathBuf {
+    let path = pilotfile_dir.join(pilotfile::FILE_NAME);
+
+    env::var_os(root_variable)
+        .and_then(|root| path.strip_prefix(root).ok().map(Path::to_path_buf))
+        .unwrap_or(path)
+}
+
+/// The line of each task in the Pilotfile, none if it can't even be parsed.
+fn task_lines(pilotfile_dir: &Path) -> Vec<(String, usize)> {
+    read_to_string(pilotfile_dir.join(pilotfile::FILE_NAME))
+        .ok()
+        .and_then(|file| edit::task_lines(&file).ok())
+        .unwrap_or_default()
+}
+
+/// The line of the first task an error is about, like `step 2 of task build: ...`.
+fn mentioned_task_line(pilotfile_dir: &Path, message: &str) -> Option<usize> {
+    task_lines(pilotfile_dir)
+        .into_iter()
+        .filter_map(|(task, line)| {
+            let mention = format!("task {}", task);
+            let (index, _) = message.match_indices(&mention).find(|(index, _)| {
+                // `task build` mustn't match `task build-docs`
+                matches!(
+                    message[index + mention.len()..].chars().next(),
+                    None | Some(' ' | ':' | ',')
+                )
+            })?;
+
+            Some((index, line))
+        })
+        .min()
+        .map(|(_, line)| line)
+}
+
+/// Escapes the data of a GitHub workflow command, properties escape `:` and `,` as well.
+fn github_escape(string: &str, property: bool) -> String {
+    let escaped = string
+        .replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A");
+
+    match property {
+        true => escaped.replace(':', "%3A").replace(',', "%2C"),
+        false => escaped,
+    }
+}
+
+/// Adds an issue to the code quality report of GitLab, next to the ones pilot added before.
+fn gitlab_report(path: &Path, line: Option<usize>, message: &str) {
+    let dir = env::var_os("CI_PROJECT_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_default();
+    let report_path = dir.join(GITLAB_REPORT);
+
+    let mut hasher = DefaultHasher::new();
+    (path, line, message).hash(&mut hasher);
+
+    let issue = format!(
+        "{{\"description\": {}, \"check_name\": \"pilot\", \"fingerprint\": \"{:016x}\", \
+\"severity\": \"major\", \"location\": {{\"path\": {}, \"lines\": {{\"begin\": {}}}}}}}",
+        json_string(message),
+        hasher.finish(),
+        json_string(&path.to_string_lossy()),
+        line.unwrap_or(1)
+    );
+
+    // the report is a JSON list with an issue on each line, so appending keeps it valid
+    let report = match read_to_string(&report_path) {
+        Ok(report) if report.trim_end().ends_with(']') => {
+            let issues = report.trim_end().trim_end_matches(']').trim_end();
+            match issues == "[" {
+                true => format!("[\n{}\n]\n", issue),
+                false => format!("{},\n{}\n]\n", issues, issue),
+            }
+        }
+        _ => format!("[\n{}\n]\n", issue),
+    };
+
+    if let Err(err) = write(&report_path, report) {
+        eprintln!("Could not write {}: {}", report_path.display(), err);
+    }
+}
+
+fn annotate(pilotfile_dir: &Path, line: Option<usize>, title: &str, message: &str) {
+    match detect() {
+        Some(Ci::GitHub) => {
+            let path = pilotfile_path(pilotfile_dir, "GITHUB_WORKSPACE");
+            let line = line
+                .map(|line| format!(",line={}", line))
+                .unwrap_or_default();
+
+            status::println(&format!(
+                "::error file={}{},title={}::{}",
+                github_escape(&path.to_string_lossy(), true),
+                line,
+                github_escape(title, true),
+                github_escape(message, false)
+            ));
+        }
+        Some(Ci::GitLab) => {
+            let path = pilotfile_path(pilotfile_dir, "CI_PROJECT_DIR");
+            gitlab_report(&path, line, message);
+        }
+        None => {}
+    }
+}
+
+/// Annotates a task of the command line that failed, at its line of the Pilotfile.
+pub(crate) fn task_failed(pilotfile_dir: &Path, task: &str, code: i32) {
+    let line = task_lines(pilotfile_dir)
+        .into_iter()
+        .find(|(name, _)| name == task)
+        .map(|(_, line)| line);
+    let message = format!("Task {} failed with exit code {}", task, code);
+
+    annotate(pilotfile_dir, line, "Failed task", &message);
+}
+
+/// Annotates a Pilotfile that pilot can't load, at the task the error is about if there is one.
+pub fn invalid_pilotfile(pilotfile_dir: &Path, message: &str) {
+    let line = mentioned_task_line(pilotfile_dir, message);
+
+    annotate(pilotfile_dir, line, "Invalid Pilotfile", message);
+}
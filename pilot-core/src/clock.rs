@@ -0,0 +1,95 @@
+//! How pilot measures durations and shows them and points in time, so the timings, the status
+//! line, the reports and the history all agree.
+
+use std::time::{Duration, Instant};
+
+use chrono::Local;
+
+/// the times in the ids of runs and in the names of diagnostics files, they sort by time
+pub(crate) const ID_FORMAT: &str = "%Y%m%d-%H%M%S";
+
+/// Measures how long something takes on the monotonic clock, which unlike the time of day
+/// doesn't jump when the system clock is set.
+#[derive(Clone, Copy)]
+pub(crate) struct Stopwatch(Instant);
+
+impl Stopwatch {
+    pub(crate) fn start() -> Self {
+        Stopwatch(Instant::now())
+    }
+
+    pub(crate) fn elapsed(&self) -> Duration {
+        self.0.elapsed()
+    }
+}
+
+/// A duration for people, like `450ms`, `12.3s`, `1m 23.4s` or `2h 5m 3s`.
+pub(crate) fn human(duration: Duration) -> String {
+    if duration < Duration::from_secs(1) {
+        return format!("{}ms", duration.as_millis());
+    }
+
+    // rounded first, so 59.96 seconds become 1m 0.0s instead of 60.0s
+    let tenths = (duration.as_secs_f64() * 10.0).round() as u64;
+    let (minutes, tenths) = (tenths / 600, tenths % 600);
+
+    match minutes {
+        0 => format!("{}.{}s", tenths / 10, tenths % 10),
+        1..=59 => format!("{}m {}.{}s", minutes, tenths / 10, tenths % 10),
+        _ => format!("{}h {}m {}s", minutes / 60, minutes % 60, tenths / 10),
+    }
+}
+
+/// A duration in whole seconds for the status line, which is redrawn too often for tenths.
+pub(crate) fn whole(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+
+    match seconds / 60 {
+        0 => format!("{}s", seconds),
+        minutes @ 1..=59 => format!("{}m {}s", minutes, seconds % 60),
+        minutes => format!("{}h {}m", minutes / 60, minutes % 60),
+    }
+}
+
+/// A duration for machines, as seconds with millisecond precision, like `83.412`.
+pub(crate) fn seconds(duration: Duration) -> String {
+    format!("{:.3}", duration.as_secs_f64())
+}
+
+/// The time of day in a `strftime` format, like `%H:%M:%S`.
+pub(crate) fn now(format: &str) -> String {
+    Local::now().format(format).to_string()
+}
+
+/// The time of day for reports, in RFC 3339 like `2024-05-01T14:03:12.123+02:00`.
+pub(crate) fn now_rfc3339() -> String {
+    Local::now().to_rfc3339()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn human_durations() {
+        assert_eq!(human(Duration::from_millis(450)), "450ms");
+        assert_eq!(human(Duration::from_millis(12_340)), "12.3s");
+        assert_eq!(human(Duration::from_millis(83_400)), "1m 23.4s");
+        assert_eq!(human(Duration::from_secs(7503)), "2h 5m 3s");
+        // rounding carries over into the minutes
+        assert_eq!(human(Duration::from_millis(59_960)), "1m 0.0s");
+    }
+
+    #[test]
+    fn whole_durations() {
+        assert_eq!(whole(Duration::from_millis(9_900)), "9s");
+        assert_eq!(whole(Duration::from_secs(83)), "1m 23s");
+        assert_eq!(whole(Duration::from_secs(7503)), "2h 5m");
+    }
+
+    #[test]
+    fn seconds_for_machines() {
+        assert_eq!(seconds(Duration::from_millis(83_412)), "83.412");
+        assert_eq!(seconds(Duration::ZERO), "0.000");
+    }
+}
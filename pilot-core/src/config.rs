@@ -0,0 +1,1786 @@
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use yaml_rust::{Yaml, YamlEmitter};
+
+use crate::{
+    cache::{SharedCache, TaskCache},
+    container::Container,
+    duration, events,
+    files::FileStep,
+    kubernetes::Kubernetes,
+    notify,
+    remote::Remote,
+    shutdown,
+    sink::SinkSpec,
+    spawn,
+    wait::{Poll, WaitFor},
+};
+
+/// Entries of a task that describe or guard the task instead of being a step.
+pub(crate) const TASK_KEYS: &[&str] = &[
+    "extends",
+    "description",
+    "tags",
+    "kind",
+    "confirm",
+    "preview",
+    "args",
+    "ports",
+    "requires_env",
+    "timestamp",
+    "max_line_length",
+    "idle_timeout",
+    "stop_signal",
+    "stop_grace_period",
+    "restart",
+    "health",
+    "cache",
+    "needs",
+    "on_dependency_failure",
+    "output",
+    "tee_to",
+    "on_failure",
+    "on_success",
+    "finally",
+];
+
+/// The keys of the steps, misspelled keys are compared against them.
+const STEP_KEYS: &[&str] = &[
+    "shell",
+    "task",
+    "parallel",
+    "pipe",
+    "compose",
+    "wait_for",
+    "poll",
+    "copy",
+    "remove",
+    "mkdir",
+    "touch",
+    "raw",
+    "user",
+    "nice",
+    "umask",
+    "setsid",
+    "env",
+    "container",
+    "path_prepend",
+    "k8s",
+    "host",
+    "limits",
+    "success_codes",
+];
+
+/// Keys of the top level that configure pilot instead of defining a task.
+pub(crate) const CONFIG_KEYS: &[&str] = &[
+    "before_all",
+    "after_all",
+    "hooks",
+    "notify",
+    "path_prepend",
+    "shell",
+    "portable",
+    "timestamp",
+    "strict",
+    "shared_cache",
+    "profiles",
+];
+
+/// The keys of the steps that take a map, unknown keys in them are ignored unless strict.
+pub(crate) const STEP_MAP_KEYS: &[(&str, &[&str])] = &[
+    ("task", &["name", "env", "args"]),
+    ("compose", &["service", "file"]),
+    ("wait_for", &["tcp", "http", "file", "timeout"]),
+    ("poll", &["shell", "interval", "timeout"]),
+    ("copy", &["from", "to"]),
+    (
+        "container",
+        &["image", "volumes", "ports", "workdir", "engine"],
+    ),
+    (
+        "k8s",
+        &[
+            "context",
+            "namespace",
+            "pod_selector",
+            "container",
+            "kubectl",
+        ],
+    ),
+    ("host", &["host", "port", "dir", "ssh"]),
+    ("limits", &["memory", "cpu", "open_files"]),
+];
+
+/// Whether an entry of the top level configures pilot. Older Pilotfiles can have tasks called
+/// strict or notify, their lists of steps tell them apart from the settings.
+pub(crate) fn is_config_entry(key: &str, value: &Yaml) -> bool {
+    match key {
+        "strict" => !value.is_array(),
+        "notify" => value
+            .as_vec()
+            .is_none_or(|targets| targets.iter().all(|target| target.as_str().is_some())),
+        key => CONFIG_KEYS.contains(&key),
+    }
+}
+
+/// A Pilotfile, parsed once when it is loaded.
+pub struct Config {
+    pub tasks: Vec<Task>,
+    pub before_all: Option<Vec<Step>>,
+    pub after_all: Option<Vec<Step>>,
+    /// the commands run on the events of a run, like `task_failed`
+    pub hooks: Vec<(String, String)>,
+    /// the webhooks and files or named pipes that get the summary of a run
+    pub notify: Vec<String>,
+    pub path_prepend: Vec<PathBuf>,
+    pub shell: Option<String>,
+    /// the commands are written for POSIX shells and translated for PowerShell
+    pub portable: bool,
+    /// the default for printing timestamps, if the task and the command line don't choose
+    pub timestamp: Option<bool>,
+    /// misspelled keys are errors, see `check_strict`
+    pub strict: bool,
+    /// where the task caches are shared with other machines
+    pub shared_cache: Option<SharedCache>,
+}
+
+#[derive(Clone)]
+pub struct Task {
+    pub name: String,
+    /// the task whose steps and settings this one inherits, until they are resolved
+    pub extends: Option<String>,
+    pub description: Option<String>,
+    /// the tags shown next to the task in the list of tasks, like `ci`
+    pub tags: Vec<String>,
+    /// whether the task is a service or a job, if it says so, see [`Task::kind`]
+    pub kind: Option<Kind>,
+    /// the question asked before the task runs
+    pub confirm: Option<String>,
+    /// the command showing what the task would change, before the question or in a dry run
+    pub preview: Option<String>,
+    /// the arguments the task takes as `name=value`
+    pub args: Vec<TaskArg>,
+    /// the ports that have to be free before the task starts
+    pub ports: Vec<u16>,
+    /// the environment variables that have to be set before the task starts
+    pub requires_env: Vec<String>,
+    pub timestamp: Option<bool>,
+    /// longer lines of output are cut
+    pub max_line_length: Option<usize>,
+    /// processes that print nothing for this long are stopped
+    pub idle_timeout: Option<Duration>,
+    /// the signal the processes get when they are stopped, like `SIGINT` for a database
+    pub stop_signal: Option<&'static str>,
+    /// how long the processes get to exit after the stop signal before they are killed
+    pub stop_grace_period: Option<Duration>,
+    /// how often a service that exits by itself or fails its health check is started again
+    pub restart: Option<u32>,
+    /// the command that checks whether a service is up, while it runs
+    pub health: Option<Poll>,
+    /// restores the outputs instead of running the task when its inputs didn't change
+    pub cache: Option<TaskCache>,
+    /// the tasks that start before it, jobs have to finish and services have to be ready
+    pub needs: Vec<String>,
+    /// what happens to the task when one of the tasks it needs fails
+    pub on_dependency_failure: Option<OnDependencyFailure>,
+    /// where the output of the task goes instead of the sinks of the run
+    pub output: Option<Vec<SinkSpec>>,
+    /// the command the output of the task is piped into
+    pub tee_to: Option<TeeTo>,
+    pub on_failure: Option<Vec<Step>>,
+    pub on_success: Option<Vec<Step>>,
+    pub finally: Option<Vec<Step>>,
+    pub steps: Vec<Step>,
+    /// the `id` of each step, for `--from-step` and `--until-step`
+    pub step_ids: Vec<Option<String>>,
+    /// the task as it is written, followed by its base tasks, the cache depends on it
+    pub definition: String,
+}
+
+/// `tee_to`, a command like `grep -v healthcheck | slacktee` that gets the output of the task.
+#[derive(Clone)]
+pub struct TeeTo {
+    pub command: String,
+    /// show the output of the command instead of the one of the task
+    pub replace: bool,
+}
+
+/// `kind`, whether a task runs until it is stopped or until it is done.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    /// runs until it is stopped, like a server, being stopped on purpose is how it ends well
+    Service,
+    /// runs until it is done, like a build, its exit code is its outcome
+    Job,
+}
+
+impl Kind {
+    pub fn name(self) -> &'static str {
+        match self {
+            Kind::Service => "service",
+            Kind::Job => "job",
+        }
+    }
+}
+
+/// `on_dependency_failure`, how a running task ends when a task it `needs` fails, one that
+/// didn't start yet is skipped either way.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OnDependencyFailure {
+    /// with its stop signal and grace period, like on an interrupt
+    #[default]
+    Stop,
+    /// its processes are killed right away
+    Cancel,
+}
+
+/// An argument a task declares, the shell steps get it as an environment variable.
+#[derive(Clone)]
+pub struct TaskArg {
+    pub name: String,
+    /// the only values the argument accepts, any value if it's empty
+    pub choices: Vec<String>,
+    /// the value if the argument isn't given, without one it's required
+    pub default: Option<String>,
+}
+
+impl Task {
+    /// Whether the task runs until it is stopped, like a server, or until it is done. Without a
+    /// `kind` pilot guesses it from the settings that only make sense for services, only for
+    /// showing it though, the runner treats such a task as a job.
+    pub fn kind(&self) -> Kind {
+        let guess = self.stop_signal.is_some()
+            || self.stop_grace_period.is_some()
+            || !self.ports.is_empty();
+
+        match (self.kind, guess) {
+            (Some(kind), _) => kind,
+            (None, true) => Kind::Service,
+            (None, false) => Kind::Job,
+        }
+    }
+
+    /// Whether the task says it is a service, its processes are supposed to run until they are
+    /// stopped.
+    pub fn is_service(&self) -> bool {
+        self.kind == Some(Kind::Service)
+    }
+
+    /// Takes over what the task leaves open from `base`. The steps of `base` run first, except
+    /// that the settings the task starts with, like `env`, override the ones of `base`.
+    fn inherit(&mut self, base: &Task) -> Result<(), String> {
+        for id in self.step_ids.iter().flatten() {
+            if base.step_ids.iter().flatten().any(|base_id| base_id == id) {
+                return Err(format!(
+                    "More than one step with the id {} in task {}, one is from {}",
+                    id, self.name, base.name
+                ));
+            }
+        }
+
+        self.extends = None;
+        self.kind = self.kind.or(base.kind);
+        self.confirm = self.confirm.take().or(base.confirm.clone());
+        self.preview = self.preview.take().or(base.preview.clone());
+        self.timestamp = self.timestamp.or(base.timestamp);
+        self.max_line_length = self.max_line_length.or(base.max_line_length);
+        self.idle_timeout = self.idle_timeout.or(base.idle_timeout);
+        self.stop_signal = self.stop_signal.or(base.stop_signal);
+        self.stop_grace_period = self.stop_grace_period.or(base.stop_grace_period);
+        self.restart = self.restart.or(base.restart);
+        self.health = self.health.take().or(base.health.clone());
+        self.cache = self.cache.take().or(base.cache.clone());
+        self.on_dependency_failure = self.on_dependency_failure.or(base.on_dependency_failure);
+        self.definition = format!("{}\n{}", self.definition, base.definition);
+        self.output = self.output.take().or(base.output.clone());
+        self.tee_to = self.tee_to.take().or(base.tee_to.clone());
+        self.on_failure = self.on_failure.take().or(base.on_failure.clone());
+        self.on_success = self.on_success.take().or(base.on_success.clone());
+        self.finally = self.finally.take().or(base.finally.clone());
+
+        if self.args.is_empty() {
+            self.args = base.args.clone();
+        }
+
+        if self.ports.is_empty() {
+            self.ports = base.ports.clone();
+        }
+
+        if self.needs.is_empty() {
+            self.needs = base.needs.clone();
+        }
+
+        if self.requires_env.is_empty() {
+            self.requires_env = base.requires_env.clone();
+        }
+
+        // the settings the task starts with go between the ones of base and its other steps
+        let settings_end = |steps: &[Step]| {
+            steps
+                .iter()
+                .position(|step| !matches!(step, Step::Setting(_)))
+                .unwrap_or(steps.len())
+        };
+        let base_end = settings_end(&base.steps);
+        let own_end = settings_end(&self.steps);
+
+        let base_steps: Vec<_> = base
+            .steps
+            .iter()
+            .cloned()
+            .zip(base.step_ids.clone())
+            .collect();
+        let mut own_steps: Vec<_> = self.steps.drain(..).zip(self.step_ids.drain(..)).collect();
+        let own_rest = own_steps.split_off(own_end);
+
+        (self.steps, self.step_ids) = base_steps[..base_end]
+            .iter()
+            .cloned()
+            .chain(own_steps)
+            .chain(base_steps[base_end..].iter().cloned())
+            .chain(own_rest)
+            .unzip();
+
+        Ok(())
+    }
+
+    /// The usage of the task generated from its arguments, like
+    /// `Usage: pilot deploy env=dev|prod [tag=latest]`.
+    pub fn usage(&self) -> String {
+        let mut usage = format!("Usage: pilot {}", self.name);
+
+        for arg in &self.args {
+            let value = match (&arg.default, arg.choices.is_empty()) {
+                (_, false) => arg.choices.join("|"),
+                (Some(default), true) => default.clone(),
+                (None, true) => format!("<{}>", arg.name),
+            };
+
+            match arg.default {
+                Some(_) => usage += &format!(" [{}={}]", arg.name, value),
+                None => usage += &format!(" {}={}", arg.name, value),
+            }
+        }
+
+        usage
+    }
+
+    /// The values of the arguments of the task, from the given `name=value` pairs and the
+    /// defaults.
+    pub fn arg_values(&self, given: &[(String, String)]) -> Result<Vec<(String, String)>, String> {
+        if let Some((name, _)) = given
+            .iter()
+            .find(|(name, _)| !self.args.iter().any(|arg| arg.name == *name))
+        {
+            return Err(format!("Task {} has no argument {}", self.name, name));
+        }
+
+        self.args
+            .iter()
+            .map(|arg| {
+                let value = given
+                    .iter()
+                    .rev()
+                    .find(|(name, _)| *name == arg.name)
+                    .map(|(_, value)| value)
+                    .or(arg.default.as_ref())
+                    .ok_or_else(|| format!("Task {} needs the argument {}", self.name, arg.name))?;
+
+                if !arg.choices.is_empty() && !arg.choices.contains(value) {
+                    return Err(format!(
+                        "{} of task {} can't be {}, only {}",
+                        arg.name,
+                        self.name,
+                        value,
+                        arg.choices.join(" or ")
+                    ));
+                }
+
+                Ok((arg.name.clone(), value.clone()))
+            })
+            .collect()
+    }
+}
+
+/// Splits arguments like `env=prod tag=v1.2` into their names and values.
+pub fn named_args(args: &str) -> Option<Vec<(String, String)>> {
+    args.split_whitespace()
+        .map(|arg| {
+            let (name, value) = arg.split_once('=')?;
+            Some((name.to_string(), value.to_string()))
+        })
+        .collect()
+}
+
+#[derive(Clone)]
+pub enum Step {
+    Shell {
+        command: ShellCommand,
+        /// the variable the trimmed stdout is stored in, for the following steps
+        capture: Option<String>,
+        /// what the command reads instead of the terminal
+        stdin: Option<StepInput>,
+    },
+    Task(TaskRef),
+    Parallel(Vec<Step>),
+    /// runs the steps at the same time, with the stdout of each step as the stdin of the next
+    Pipe(Vec<Step>),
+    Compose {
+        service: String,
+        file: Option<String>,
+    },
+    WaitFor(WaitFor),
+    /// runs a command until it succeeds, like a health check of a service
+    Poll(Poll),
+    /// `copy`, `remove`, `mkdir` and `touch`, which pilot runs itself instead of a shell
+    File(FileStep),
+    /// waits before running the step, up to `jitter` longer, like the branches of a parallel
+    /// step that shouldn't all start at once
+    Delayed {
+        step: Box<Step>,
+        delay: Duration,
+        jitter: Duration,
+    },
+    /// starts the step and goes on with the next ones, the step is stopped once they are done
+    Background(Box<Step>),
+    /// a branch of a parallel step whose output shows its label, like `run/infra > server`
+    Labeled {
+        label: String,
+        step: Box<Step>,
+    },
+    /// settings change how the following steps run
+    Setting(Setting),
+}
+
+#[derive(Clone)]
+pub enum Setting {
+    Raw(bool),
+    User(String),
+    Nice(i32),
+    /// the permissions new files don't get, like `umask 022`
+    Umask(u32),
+    /// run the processes in their own session, without the terminal of pilot
+    Setsid(bool),
+    Env(Vec<(String, String)>),
+    Container(Container),
+    PathPrepend(Vec<PathBuf>),
+    Kubernetes(Kubernetes),
+    Host(Remote),
+    Limits(spawn::Limits),
+    SuccessCodes(Vec<i32>),
+}
+
+/// A `task` step, which is either the name of the task or a map with the name, the
+/// environment variables and the arguments for this call of the task.
+#[derive(Clone)]
+pub struct TaskRef {
+    pub name: String,
+    pub env: Vec<(String, String)>,
+    /// replaces `{{args}}` in the shell steps of the task
+    pub args: Option<String>,
+}
+
+/// The `stdin` of a shell step, a file or the input itself.
+#[derive(Clone)]
+pub enum StepInput {
+    File(PathBuf),
+    Text(String),
+}
+
+/// The command of a shell step, which is either a string or a map from the operating system
+/// (like `linux`, `macos` or `windows`) to the command. `unix` and `default` are the fallbacks.
+#[derive(Clone)]
+pub enum ShellCommand {
+    Command(String),
+    PerOs(Vec<(String, String)>),
+}
+
+impl ShellCommand {
+    pub fn for_os(&self, os: &str) -> Option<&str> {
+        let commands = match self {
+            ShellCommand::Command(command) => return Some(command),
+            ShellCommand::PerOs(commands) => commands,
+        };
+        let family = if os == "windows" { "windows" } else { "unix" };
+
+        [os, family, "default"].iter().find_map(|key| {
+            commands
+                .iter()
+                .find(|(os, _)| os == key)
+                .map(|(_, command)| command.as_str())
+        })
+    }
+}
+
+impl Step {
+    /// The key of the step in the Pilotfile.
+    pub fn key(&self) -> &'static str {
+        match self {
+            Step::Shell { .. } => "shell",
+            Step::Task(_) => "task",
+            Step::Parallel(_) => "parallel",
+            Step::Pipe(_) => "pipe",
+            Step::Compose { .. } => "compose",
+            Step::WaitFor(_) => "wait_for",
+            Step::Poll(_) => "poll",
+            Step::File(file) => file.key(),
+            Step::Delayed { .. } => "delay",
+            Step::Background(_) => "background",
+            Step::Labeled { .. } => "label",
+            Step::Setting(setting) => match setting {
+                Setting::Raw(_) => "raw",
+                Setting::User(_) => "user",
+                Setting::Nice(_) => "nice",
+                Setting::Umask(_) => "umask",
+                Setting::Setsid(_) => "setsid",
+                Setting::Env(_) => "env",
+                Setting::Container(_) => "container",
+                Setting::PathPrepend(_) => "path_prepend",
+                Setting::Kubernetes(_) => "k8s",
+                Setting::Host(_) => "host",
+                Setting::Limits(_) => "limits",
+                Setting::SuccessCodes(_) => "success_codes",
+            },
+        }
+    }
+}
+
+/// The error of an entry of a Pilotfile, with its key if it is about a single entry.
+pub(crate) type EntryError = (Option<String>, String);
+
+impl Config {
+    /// `dir` resolves the relative paths of the Pilotfile.
+    pub fn from_yaml(yaml: &Yaml, dir: &Path) -> Result<Self, String> {
+        Config::parse(yaml, dir, None)
+    }
+
+    /// Parses as much of a Pilotfile as it can, leaving out the entries with errors. Each error
+    /// comes with the key of its entry, if it is about a single one.
+    pub(crate) fn from_yaml_lenient(
+        yaml: &Yaml,
+        dir: &Path,
+    ) -> Result<(Self, Vec<EntryError>), String> {
+        let mut errors = vec![];
+        let config = Config::parse(yaml, dir, Some(&mut errors))?;
+
+        Ok((config, errors))
+    }
+
+    /// Fails on the first invalid entry, unless there is a list to collect the errors in.
+    fn parse(
+        yaml: &Yaml,
+        dir: &Path,
+        mut errors: Option<&mut Vec<EntryError>>,
+    ) -> Result<Self, String> {
+        let hash = yaml
+            .as_hash()
+            .ok_or("the top level has to map task names to their steps")?;
+
+        let mut config = Config {
+            tasks: vec![],
+            before_all: None,
+            after_all: None,
+            hooks: vec![],
+            notify: vec![],
+            path_prepend: vec![],
+            shell: None,
+            portable: false,
+            timestamp: None,
+            strict: false,
+            shared_cache: None,
+        };
+
+        let mut fail = |key: Option<&str>, err: String| match errors.as_mut() {
+            Some(errors) => {
+                errors.push((key.map(str::to_string), err));
+                Ok(())
+            }
+            None => Err(err),
+        };
+
+        for (name, value) in hash {
+            let Some(name) = name.as_str() else {
+                fail(None, "task names have to be strings".to_string())?;
+                continue;
+            };
+
+            if let Err(err) = config.add_entry(name, value, dir) {
+                fail(Some(name), err)?;
+            }
+        }
+
+        if let Err(err) = resolve_extends(&mut config.tasks) {
+            fail(None, err)?;
+        }
+
+        if let Err(err) = check_needs(&config.tasks) {
+            fail(None, err)?;
+        }
+
+        // only services are restarted and checked and only jobs make outputs to cache, the kind
+        // can come from the base task
+        for task in &config.tasks {
+            let err = match (task.is_service(), task.restart, &task.health, &task.cache) {
+                (false, Some(_), _, _) => "restart needs kind: service",
+                (false, _, Some(_), _) => "health needs kind: service",
+                (true, _, _, Some(_)) => "cache is for jobs, services run until they are stopped",
+                _ => continue,
+            };
+
+            fail(Some(&task.name), format!("task {}: {}", task.name, err))?;
+        }
+
+        Ok(config)
+    }
+
+    /// Adds an entry of the top level, which is a task unless it configures pilot itself.
+    fn add_entry(&mut self, name: &str, value: &Yaml, dir: &Path) -> Result<(), String> {
+        match name {
+            "before_all" => self.before_all = Some(parse_steps(value, dir, name)?),
+            "after_all" => self.after_all = Some(parse_steps(value, dir, name)?),
+            "hooks" => self.hooks = events::from_yaml(value)?,
+            "notify" if is_config_entry(name, value) => self.notify = notify::from_yaml(value)?,
+            "path_prepend" => self.path_prepend = path_prepend(value, dir)?,
+            "shell" => self.shell = Some(value.as_str().ok_or("shell needs a string")?.to_string()),
+            "portable" => self.portable = value.as_bool().ok_or("portable needs true or false")?,
+            "timestamp" => {
+                self.timestamp = Some(value.as_bool().ok_or("timestamp needs true or false")?)
+            }
+            "strict" if is_config_entry(name, value) => {
+                self.strict = value.as_bool().ok_or("strict needs true or false")?
+            }
+            "shared_cache" => self.shared_cache = Some(SharedCache::from_yaml(value, dir)?),
+            // profiles are only parsed once they are applied
+            "profiles" => {}
+            _ => self.tasks.push(parse_task(name, value, dir)?),
+        }
+
+        Ok(())
+    }
+
+    pub fn task(&self, name: &str) -> Option<&Task> {
+        self.tasks.iter().find(|task| task.name == name)
+    }
+
+    /// The names of the tasks matching a pattern like `test:*`, in the order of the Pilotfile.
+    pub fn tasks_matching(&self, pattern: &str) -> Vec<&str> {
+        self.tasks
+            .iter()
+            .map(|task| task.name.as_str())
+            .filter(|name| glob_match(pattern, name))
+            .collect()
+    }
+}
+
+/// Matches a name against a pattern where `*` stands for any text and `?` for one character.
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let mut chars = pattern.chars();
+
+    match chars.next() {
+        None => name.is_empty(),
+        Some('*') => name
+            .char_indices()
+            .map(|(i, _)| i)
+            .chain([name.len()])
+            .any(|i| glob_match(chars.as_str(), &name[i..])),
+        Some('?') => {
+            let mut name_chars = name.chars();
+            name_chars.next().is_some() && glob_match(chars.as_str(), name_chars.as_str())
+        }
+        Some(char) => name
+            .strip_prefix(char)
+            .is_some_and(|rest| glob_match(chars.as_str(), rest)),
+    }
+}
+
+/// A scalar as the string the processes see.
+fn scalar_string(yaml: &Yaml) -> Option<String> {
+    match yaml {
+        Yaml::String(value) | Yaml::Real(value) => Some(value.clone()),
+        Yaml::Integer(value) => Some(value.to_string()),
+        Yaml::Boolean(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+/// The variables of an `env` map, scalars are turned into the strings the processes see.
+pub fn env_from_yaml(yaml: &Yaml) -> Result<Vec<(String, String)>, String> {
+    let invalid = || "env needs a map of names to strings or numbers".to_string();
+
+    yaml.as_hash()
+        .ok_or_else(invalid)?
+        .iter()
+        .map(|(key, value)| {
+            let value = scalar_string(value).ok_or_else(invalid)?;
+            Ok((key.as_str().ok_or_else(invalid)?.to_string(), value))
+        })
+        .collect()
+}
+
+/// The arguments of a task, like `[{name: env, choices: [dev, prod]}, {name: tag, default: latest}]`.
+fn parse_task_args(yaml: &Yaml) -> Result<Vec<TaskArg>, String> {
+    let mut args: Vec<TaskArg> = vec![];
+
+    for arg in yaml.as_vec().ok_or("args needs a list of arguments")? {
+        let name = arg["name"]
+            .as_str()
+            .filter(|name| !name.is_empty() && !name.contains(['=', ' ']))
+            .ok_or("every argument needs a name without = and spaces")?;
+
+        if args.iter().any(|arg| arg.name == name) {
+            return Err(format!("More than one argument {}", name));
+        }
+
+        let choices = match &arg["choices"] {
+            Yaml::BadValue => vec![],
+            choices => choices
+                .as_vec()
+                .and_then(|choices| choices.iter().map(scalar_string).collect())
+                .ok_or_else(|| format!("choices of argument {} needs a list of values", name))?,
+        };
+
+        let default = match &arg["default"] {
+            Yaml::BadValue => None,
+            default => Some(
+                scalar_string(default)
+                    .ok_or_else(|| format!("default of argument {} needs a value", name))?,
+            ),
+        };
+
+        if let Some(default) = default.as_ref().filter(|_| !choices.is_empty()) {
+            if !choices.contains(default) {
+                return Err(format!(
+                    "default of argument {} has to be one of its choices, not {}",
+                    name, default
+                ));
+            }
+        }
+
+        args.push(TaskArg {
+            name: name.to_string(),
+            choices,
+            default,
+        });
+    }
+
+    Ok(args)
+}
+
+/// The directories of a `path_prepend` list, relative ones are resolved against the Pilotfile.
+fn path_prepend(paths: &Yaml, dir: &Path) -> Result<Vec<PathBuf>, String> {
+    paths
+        .as_vec()
+        .ok_or("path_prepend needs a list of directories")?
+        .iter()
+        .map(|path| {
+            path.as_str()
+                .map(|path| dir.join(path))
+                .ok_or_else(|| "path_prepend needs a list of directories".to_string())
+        })
+        .collect()
+}
+
+/// Keys next to the key of a step, that change how the step runs.
+pub(crate) const MODIFIER_KEYS: &[&str] = &[
+    "capture",
+    "stdin",
+    "delay",
+    "jitter",
+    "background",
+    "id",
+    "label",
+];
+
+/// Splits a step into its key and value, a step is a map with a single entry besides its
+/// modifiers.
+fn step_entry(step: &Yaml) -> Result<(&str, &Yaml), String> {
+    let not_a_step = || "steps have to be maps like `shell: <command>`".to_string();
+    let hash = step.as_hash().ok_or_else(not_a_step)?;
+    let mut keys = hash
+        .iter()
+        .map(|(key, value)| Ok((key.as_str().ok_or_else(not_a_step)?, value)))
+        .collect::<Result<Vec<_>, String>>()?;
+
+    let index = keys
+        .iter()
+        .position(|(key, _)| !MODIFIER_KEYS.contains(key))
+        .ok_or_else(not_a_step)?;
+    let (key, value) = keys.remove(index);
+
+    if let Some((other, _)) = keys.iter().find(|(key, _)| !MODIFIER_KEYS.contains(key)) {
+        return Err(format!("{} and {} have to be separate steps", key, other));
+    }
+
+    Ok((key, value))
+}
+
+/// Takes the `id` off a step of a task.
+fn step_id(step: &Yaml) -> Result<(Yaml, Option<String>), String> {
+    let id_key = Yaml::String("id".to_string());
+
+    match step.as_hash() {
+        Some(hash) => match hash.get(&id_key) {
+            Some(Yaml::String(id)) => {
+                let mut hash = hash.clone();
+                hash.remove(&id_key);
+                Ok((Yaml::Hash(hash), Some(id.clone())))
+            }
+            Some(_) => Err("id needs a string".to_string()),
+            None => Ok((step.clone(), None)),
+        },
+        None => Ok((step.clone(), None)),
+    }
+}
+
+/// Parses a branch of a parallel step, which can have a `label`.
+fn parse_branch(branch: &Yaml, dir: &Path) -> Result<Step, String> {
+    let label_key = Yaml::String("label".to_string());
+
+    let Some(hash) = branch
+        .as_hash()
+        .filter(|hash| hash.contains_key(&label_key))
+    else {
+        return parse_step_map(branch, dir);
+    };
+
+    let label = match &hash[&label_key] {
+        Yaml::String(label) if !label.trim().is_empty() => label.clone(),
+        _ => return Err("label needs a name".to_string()),
+    };
+
+    let mut hash = hash.clone();
+    hash.remove(&label_key);
+
+    Ok(Step::Labeled {
+        label,
+        step: Box::new(parse_step_map(&Yaml::Hash(hash), dir)?),
+    })
+}
+
+/// Parses a step with its modifiers.
+fn parse_step_map(step: &Yaml, dir: &Path) -> Result<Step, String> {
+    let (key, value) = step_entry(step)?;
+    let mut parsed = parse_step(key, value, dir)?;
+
+    // only the steps of a task itself can be where a run starts or ends
+    if !step["id"].is_badvalue() {
+        return Err("id only works on the steps of a task, not in hooks or branches".to_string());
+    }
+
+    if !step["label"].is_badvalue() {
+        return Err("label only works on the branches of parallel steps".to_string());
+    }
+
+    match (&step["capture"], &mut parsed) {
+        (Yaml::BadValue, _) => {}
+        (Yaml::String(name), Step::Shell { capture, .. }) => *capture = Some(name.clone()),
+        (_, Step::Shell { .. }) => return Err("capture needs the name of a variable".to_string()),
+        _ => return Err(format!("capture only works on shell steps, not on {}", key)),
+    }
+
+    match (&step["stdin"], &mut parsed) {
+        (Yaml::BadValue, _) => {}
+        // a block like `stdin: |` ends with a line break, a path doesn't
+        (Yaml::String(input), Step::Shell { stdin, .. }) if input.contains('\n') => {
+            *stdin = Some(StepInput::Text(input.clone()))
+        }
+        (Yaml::String(path), Step::Shell { stdin, .. }) => {
+            *stdin = Some(StepInput::File(dir.join(path)))
+        }
+        (_, Step::Shell { .. }) => {
+            return Err("stdin needs a file or the input as a block like `stdin: |`".to_string())
+        }
+        _ => return Err(format!("stdin only works on shell steps, not on {}", key)),
+    }
+
+    let duration = |key: &str| match &step[key] {
+        Yaml::BadValue => Ok(None),
+        Yaml::Integer(seconds) => u64::try_from(*seconds)
+            .map(|seconds| Some(Duration::from_secs(seconds)))
+            .map_err(|_| format!("{} needs a duration like 2s", key)),
+        Yaml::String(duration) => duration::parse(duration)
+            .map(Some)
+            .ok_or_else(|| format!("{} needs a duration like 2s", key)),
+        _ => Err(format!("{} needs a duration like 2s", key)),
+    };
+
+    match (duration("delay")?, duration("jitter")?) {
+        (None, None) => {}
+        _ if matches!(parsed, Step::Setting(_)) => {
+            return Err(format!(
+                "delay and jitter only work on steps that run something, not on {}",
+                key
+            ))
+        }
+        (delay, jitter) => {
+            parsed = Step::Delayed {
+                step: Box::new(parsed),
+                delay: delay.unwrap_or_default(),
+                jitter: jitter.unwrap_or_default(),
+            }
+        }
+    }
+
+    match &step["background"] {
+        Yaml::BadValue | Yaml::Boolean(false) => {}
+        Yaml::Boolean(true) => match parsed {
+            // the variable would only exist in the background
+            Step::Shell {
+                capture: Some(_), ..
+            } => return Err("capture doesn't work on background steps".to_string()),
+            Step::Setting(_) => {
+                return Err(format!(
+                    "background only works on steps that run something, not on {}",
+                    key
+                ))
+            }
+            step => parsed = Step::Background(Box::new(step)),
+        },
+        _ => return Err("background needs true or false".to_string()),
+    }
+
+    Ok(parsed)
+}
+
+fn parse_task(name: &str, yaml: &Yaml, dir: &Path) -> Result<Task, String> {
+    let mut task = Task {
+        name: name.to_string(),
+        extends: None,
+        description: None,
+        tags: vec![],
+        kind: None,
+        confirm: None,
+        preview: None,
+        args: vec![],
+        ports: vec![],
+        requires_env: vec![],
+        timestamp: None,
+        max_line_length: None,
+        idle_timeout: None,
+        stop_signal: None,
+        stop_grace_period: None,
+        restart: None,
+        health: None,
+        cache: None,
+        needs: vec![],
+        on_dependency_failure: None,
+        output: None,
+        tee_to: None,
+        on_failure: None,
+        on_success: None,
+        finally: None,
+        steps: vec![],
+        step_ids: vec![],
+        definition: String::new(),
+    };
+    let mut seen = vec![];
+
+    // the cache keys depend on the task as it is written, which stays the same between machines
+    let _ = YamlEmitter::new(&mut task.definition).dump(yaml);
+
+    let steps = yaml
+        .as_vec()
+        .ok_or_else(|| format!("task {} needs a list of steps", name))?;
+
+    for (index, step) in steps.iter().enumerate() {
+        let context = |err: String| format!("step {} of task {}: {}", index + 1, name, err);
+        let (key, value) = step_entry(step).map_err(context)?;
+
+        if !TASK_KEYS.contains(&key) {
+            let (step, id) = step_id(step).map_err(context)?;
+
+            if let Some(id) = id.as_ref().filter(|_| task.step_ids.contains(&id)) {
+                return Err(format!(
+                    "More than one step with the id {} in task {}",
+                    id, name
+                ));
+            }
+
+            task.steps
+                .push(parse_step_map(&step, dir).map_err(context)?);
+            task.step_ids.push(id);
+            continue;
+        }
+
+        if seen.contains(&key) {
+            return Err(format!("More than one {} for task {}", key, name));
+        }
+        seen.push(key);
+
+        let hook = |hook| parse_steps(value, dir, &format!("{} of task {}", hook, name));
+
+        match key {
+            "extends" => {
+                task.extends = Some(
+                    value
+                        .as_str()
+                        .ok_or_else(|| context("extends needs the name of a task".to_string()))?
+                        .to_string(),
+                )
+            }
+            "description" => {
+                task.description = Some(
+                    value
+                        .as_str()
+                        .ok_or_else(|| context("description needs a string".to_string()))?
+                        .to_string(),
+                )
+            }
+            "confirm" => {
+                task.confirm = Some(
+                    value
+                        .as_str()
+                        .ok_or_else(|| context("confirm needs a question".to_string()))?
+                        .to_string(),
+                )
+            }
+            "preview" => {
+                task.preview = Some(
+                    value
+                        .as_str()
+                        .ok_or_else(|| context("preview needs a command".to_string()))?
+                        .to_string(),
+                )
+            }
+            "args" => task.args = parse_task_args(value).map_err(context)?,
+            "ports" => {
+                task.ports = value
+                    .as_vec()
+                    .and_then(|ports| {
+                        ports
+                            .iter()
+                            .map(|port| port.as_i64().and_then(|port| u16::try_from(port).ok()))
+                            .collect()
+                    })
+                    .ok_or_else(|| context("ports needs a list of port numbers".to_string()))?
+            }
+            "tags" => {
+                task.tags = match value {
+                    Yaml::String(tag) => Some(vec![tag.clone()]),
+                    _ => value.as_vec().and_then(|tags| {
+                        tags.iter()
+                            .map(|tag| tag.as_str().map(str::to_string))
+                            .collect()
+                    }),
+                }
+                .ok_or_else(|| context("tags needs a tag or a list of tags".to_string()))?
+            }
+            "kind" => {
+                task.kind = Some(match value.as_str() {
+                    Some("service") => Kind::Service,
+                    Some("job") => Kind::Job,
+                    _ => return Err(context("kind needs service or job".to_string())),
+                })
+            }
+            "requires_env" => {
+                task.requires_env = value
+                    .as_vec()
+                    .and_then(|names| {
+                        names
+                            .iter()
+                            .map(|name| name.as_str().map(str::to_string))
+                            .collect()
+                    })
+                    .ok_or_else(|| {
+                        context("requires_env needs a list of variable names".to_string())
+                    })?
+            }
+            "timestamp" => {
+                task.timestamp = Some(
+                    value
+                        .as_bool()
+                        .ok_or_else(|| context("timestamp needs true or false".to_string()))?,
+                )
+            }
+            "max_line_length" => {
+                task.max_line_length = Some(
+                    value
+                        .as_i64()
+                        .and_then(|length| usize::try_from(length).ok())
+                        .ok_or_else(|| {
+                            context("max_line_length needs a number of characters".to_string())
+                        })?,
+                )
+            }
+            "idle_timeout" => {
+                let timeout = match value {
+                    Yaml::Integer(seconds) => u64::try_from(*seconds).ok().map(Duration::from_secs),
+                    Yaml::String(duration) => duration::parse(duration),
+                    _ => None,
+                };
+
+                task.idle_timeout = Some(
+                    timeout
+                        .filter(|timeout| !timeout.is_zero())
+                        .ok_or_else(|| {
+                            context("idle_timeout needs a duration like 2m".to_string())
+                        })?,
+                )
+            }
+            "stop_signal" => {
+                let signal = value.as_str().unwrap_or_default().to_ascii_uppercase();
+                let signal = signal.strip_prefix("SIG").unwrap_or(&signal);
+
+                task.stop_signal = Some(
+                    shutdown::STOP_SIGNALS
+                        .iter()
+                        .find(|known| known[3..] == *signal)
+                        .copied()
+                        .ok_or_else(|| {
+                            context(format!(
+                                "stop_signal needs one of {}",
+                                shutdown::STOP_SIGNALS.join(", ")
+                            ))
+                        })?,
+                )
+            }
+            "stop_grace_period" => {
+                let grace_period = match value {
+                    Yaml::Integer(seconds) => u64::try_from(*seconds).ok().map(Duration::from_secs),
+                    Yaml::String(duration) => duration::parse(duration),
+                    _ => None,
+                };
+
+                task.stop_grace_period = Some(grace_period.ok_or_else(|| {
+                    context("stop_grace_period needs a duration like 30s".to_string())
+                })?)
+            }
+            "restart" => {
+                task.restart = Some(
+                    value
+                        .as_i64()
+                        .and_then(|restarts| u32::try_from(restarts).ok())
+                        .ok_or_else(|| context("restart needs a number of restarts".to_string()))?,
+                )
+            }
+            "needs" => {
+                task.needs = match value {
+                    Yaml::String(need) => Some(vec![need.clone()]),
+                    _ => value.as_vec().and_then(|needs| {
+                        needs
+                            .iter()
+                            .map(|need| need.as_str().map(str::to_string))
+                            .collect()
+                    }),
+                }
+                .ok_or_else(|| context("needs needs a task or a list of tasks".to_string()))?
+            }
+            "on_dependency_failure" => {
+                task.on_dependency_failure = Some(match value.as_str() {
+                    Some("stop") => OnDependencyFailure::Stop,
+                    Some("cancel") => OnDependencyFailure::Cancel,
+                    _ => {
+                        return Err(context(
+                            "on_dependency_failure needs stop or cancel".to_string(),
+                        ))
+                    }
+                })
+            }
+            "cache" => {
+                task.cache = Some(TaskCache::from_yaml(value).ok_or_else(|| {
+                    context(
+                        "cache needs outputs and optionally inputs, each a path or a list of paths"
+                            .to_string(),
+                    )
+                })?)
+            }
+            "health" => {
+                task.health = Some(Poll::from_yaml(value).ok_or_else(|| {
+                    context(
+                        "health needs a shell command and optionally an interval and a timeout"
+                            .to_string(),
+                    )
+                })?)
+            }
+            "output" => {
+                let sinks = match value {
+                    Yaml::String(sink) => vec![sink.as_str()],
+                    _ => value
+                        .as_vec()
+                        .and_then(|sinks| sinks.iter().map(|sink| sink.as_str()).collect())
+                        .ok_or_else(|| {
+                            context("output needs a sink or a list of them".to_string())
+                        })?,
+                };
+
+                task.output = Some(
+                    sinks
+                        .into_iter()
+                        .map(|sink| SinkSpec::parse(sink, dir))
+                        .collect::<Result<_, _>>()
+                        .map_err(context)?,
+                )
+            }
+            "tee_to" => task.tee_to = Some(parse_tee_to(value).map_err(context)?),
+            "on_failure" => task.on_failure = Some(hook(key)?),
+            "on_success" => task.on_success = Some(hook(key)?),
+            _ => task.finally = Some(hook(key)?),
+        }
+    }
+
+    Ok(task)
+}
+
+/// Fails on `needs` that are no task or that need each other, which would never start.
+fn check_needs(tasks: &[Task]) -> Result<(), String> {
+    for task in tasks {
+        for need in &task.needs {
+            if !tasks.iter().any(|other| &other.name == need) {
+                return Err(format!(
+                    "task {} needs {}, which is no task",
+                    task.name, need
+                ));
+            }
+        }
+    }
+
+    // a path of needs that comes back to a task on it is a cycle
+    fn visit<'a>(tasks: &'a [Task], task: &'a Task, path: &mut Vec<&'a str>) -> Result<(), String> {
+        if let Some(start) = path.iter().position(|name| *name == task.name) {
+            return Err(match path[start..] {
+                [name] => format!("task {} needs itself", name),
+                ref names => format!("the tasks {} need each other", names.join(", ")),
+            });
+        }
+
+        path.push(&task.name);
+
+        for need in &task.needs {
+            if let Some(need) = tasks.iter().find(|other| &other.name == need) {
+                visit(tasks, need, path)?;
+            }
+        }
+
+        path.pop();
+        Ok(())
+    }
+
+    for task in tasks {
+        visit(tasks, task, &mut vec![])?;
+    }
+
+    Ok(())
+}
+
+/// Lets the tasks with `extends` inherit from their base tasks, the bases first if they extend
+/// another task themselves.
+fn resolve_extends(tasks: &mut [Task]) -> Result<(), String> {
+    for task in tasks.iter() {
+        if let Some(base) = &task.extends {
+            if !tasks.iter().any(|other| &other.name == base) {
+                return Err(format!(
+                    "task {} extends {}, which is no task",
+                    task.name, base
+                ));
+            }
+        }
+    }
+
+    while let Some(index) = tasks.iter().position(|task| task.extends.is_some()) {
+        // follow the chain of bases to one that extends nothing, a task seen twice is a cycle
+        let mut chain = vec![index];
+
+        loop {
+            let task = &tasks[*chain.last().unwrap_or(&index)];
+            let Some(base) = &task.extends else { break };
+            let base = tasks
+                .iter()
+                .position(|other| &other.name == base)
+                .unwrap_or_default();
+
+            if let Some(start) = chain.iter().position(|&index| index == base) {
+                let names: Vec<_> = chain[start..]
+                    .iter()
+                    .map(|&index| tasks[index].name.as_str())
+                    .collect();
+
+                return Err(match names[..] {
+                    [name] => format!("task {} extends itself", name),
+                    _ => format!("the tasks {} extend each other", names.join(", ")),
+                });
+            }
+
+            chain.push(base);
+        }
+
+        // the last one extends nothing, so the ones before it can inherit in turn
+        for pair in chain.windows(2).rev() {
+            let base = tasks[pair[1]].clone();
+            tasks[pair[0]].inherit(&base)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// `tee_to: <command>` or `tee_to: {command: <command>, replace: true}`.
+fn parse_tee_to(yaml: &Yaml) -> Result<TeeTo, String> {
+    let invalid = || "tee_to needs a command or a map with command and replace".to_string();
+
+    let mut tee_to = TeeTo {
+        command: String::new(),
+        replace: false,
+    };
+
+    match yaml {
+        Yaml::String(command) => tee_to.command = command.clone(),
+        Yaml::Hash(hash) => {
+            for (key, value) in hash {
+                match key.as_str() {
+                    Some("command") => {
+                        tee_to.command = value.as_str().ok_or_else(invalid)?.to_string()
+                    }
+                    Some("replace") => tee_to.replace = value.as_bool().ok_or_else(invalid)?,
+                    _ => return Err(invalid()),
+                }
+            }
+        }
+        _ => return Err(invalid()),
+    }
+
+    if tee_to.command.trim().is_empty() {
+        return Err(invalid());
+    }
+
+    Ok(tee_to)
+}
+
+/// Parses a list of steps outside of a task, like `before_all` or a hook.
+fn parse_steps(yaml: &Yaml, dir: &Path, name: &str) -> Result<Vec<Step>, String> {
+    yaml.as_vec()
+        .ok_or_else(|| format!("{} needs a list of steps", name))?
+        .iter()
+        .enumerate()
+        .map(|(index, step)| {
+            parse_step_map(step, dir)
+                .map_err(|err| format!("step {} of {}: {}", index + 1, name, err))
+        })
+        .collect()
+}
+
+fn parse_step(key: &str, value: &Yaml, dir: &Path) -> Result<Step, String> {
+    let string = |expected: &str| {
+        value
+            .as_str()
+            .map(|value| value.to_string())
+            .ok_or_else(|| format!("{} needs {}", key, expected))
+    };
+    let invalid = |expected: &str| format!("{} needs {}", key, expected);
+
+    Ok(match key {
+        "shell" => Step::Shell {
+            command: match value {
+                Yaml::String(command) => ShellCommand::Command(command.clone()),
+                Yaml::Hash(commands) => ShellCommand::PerOs(
+                    commands
+                        .iter()
+                        .map(|(os, command)| {
+                            Some((os.as_str()?.to_string(), command.as_str()?.to_string()))
+                        })
+                        .collect::<Option<_>>()
+                        .ok_or_else(|| invalid("a map of operating systems to commands"))?,
+                ),
+                _ => {
+                    return Err(invalid(
+                        "a command or a map of operating systems to commands",
+                    ))
+                }
+            },
+            capture: None,
+            stdin: None,
+        },
+        "task" => Step::Task(match value {
+            Yaml::String(name) => TaskRef {
+                name: name.clone(),
+                env: vec![],
+                args: None,
+            },
+            task => TaskRef {
+                name: task["name"]
+                    .as_str()
+                    .ok_or_else(|| invalid("the name of a task"))?
+                    .to_string(),
+                env: match &task["env"] {
+                    Yaml::BadValue => vec![],
+                    env => env_from_yaml(env)?,
+                },
+                args: match &task["args"] {
+                    Yaml::BadValue => None,
+                    Yaml::String(args) => Some(args.clone()),
+                    _ => return Err(invalid("args as a string")),
+                },
+            },
+        }),
+        "parallel" => Step::Parallel(
+            value
+                .as_vec()
+                .ok_or_else(|| invalid("a list of steps"))?
+                .iter()
+                .map(|step| parse_branch(step, dir))
+                .collect::<Result<_, _>>()?,
+        ),
+        "pipe" => Step::Pipe(
+            value
+                .as_vec()
+                .filter(|steps| steps.len() >= 2)
+                .ok_or_else(|| invalid("a list of at least two steps"))?
+                .iter()
+                .map(|step| parse_step_map(step, dir))
+                .collect::<Result<_, _>>()?,
+        ),
+        "compose" => match value {
+            // either just the service or the service and the compose file
+            Yaml::String(service) => Step::Compose {
+                service: service.clone(),
+                file: None,
+            },
+            compose => Step::Compose {
+                service: compose["service"]
+                    .as_str()
+                    .ok_or_else(|| invalid("a service"))?
+                    .to_string(),
+                file: compose["file"].as_str().map(|file| file.to_string()),
+            },
+        },
+        "wait_for" => Step::WaitFor(
+            WaitFor::from_yaml(value, dir)
+                .ok_or_else(|| invalid("a tcp address, an http url or a file"))?,
+        ),
+        "poll" => {
+            Step::Poll(Poll::from_yaml(value).ok_or_else(|| {
+                invalid("a shell command and optionally an interval and a timeout")
+            })?)
+        }
+        "copy" | "remove" | "mkdir" | "touch" => {
+            Step::File(FileStep::from_yaml(key, value).ok_or_else(|| match key {
+                "copy" => invalid("the paths from and to"),
+                _ => invalid("a path or a list of paths"),
+            })?)
+        }
+        "raw" => Step::Setting(Setting::Raw(
+            value.as_bool().ok_or_else(|| invalid("true or false"))?,
+        )),
+        "user" => Step::Setting(Setting::User(string("the name of a user")?)),
+        "nice" => {
+            // either a niceness or the name of a windows priority class
+            let nice = match value {
+                Yaml::Integer(nice) => Some(*nice as i32),
+                Yaml::String(class) => spawn::nice_from_priority_class(class),
+                _ => None,
+            };
+
+            Step::Setting(Setting::Nice(nice.ok_or_else(|| {
+                invalid("a niceness or the name of a priority class")
+            })?))
+        }
+        "umask" => {
+            // `umask: 022` is the integer 22 in YAML, its digits are still the octal ones
+            let umask = match value {
+                Yaml::Integer(umask) => Some(umask.to_string()),
+                Yaml::String(umask) => Some(umask.clone()),
+                _ => None,
+            };
+
+            Step::Setting(Setting::Umask(
+                umask
+                    .and_then(|umask| u32::from_str_radix(&umask, 8).ok())
+                    .filter(|umask| *umask <= 0o777)
+                    .ok_or_else(|| invalid("octal permissions like 022"))?,
+            ))
+        }
+        "setsid" => Step::Setting(Setting::Setsid(
+            value.as_bool().ok_or_else(|| invalid("true or false"))?,
+        )),
+        "env" => Step::Setting(Setting::Env(env_from_yaml(value)?)),
+        "container" => Step::Setting(Setting::Container(
+            Container::from_yaml(value).ok_or_else(|| invalid("an image"))?,
+        )),
+        "path_prepend" => Step::Setting(Setting::PathPrepend(path_prepend(value, dir)?)),
+        "k8s" => Step::Setting(Setting::Kubernetes(
+            Kubernetes::from_yaml(value).ok_or_else(|| invalid("a pod_selector"))?,
+        )),
+        "host" => Step::Setting(Setting::Host(
+            Remote::from_yaml(value).ok_or_else(|| invalid("a host"))?,
+        )),
+        "limits" => Step::Setting(Setting::Limits(
+            spawn::Limits::from_yaml(value)
+                .ok_or_else(|| invalid("memory, cpu or open_files limits"))?,
+        )),
+        "success_codes" => Step::Setting(Setting::SuccessCodes(
+            value
+                .as_vec()
+                .and_then(|codes| {
+                    codes
+                        .iter()
+                        .map(|code| code.as_i64().and_then(|code| i32::try_from(code).ok()))
+                        .collect()
+                })
+                .ok_or_else(|| invalid("a list of exit codes"))?,
+        )),
+        key if TASK_KEYS.contains(&key) => {
+            return Err(format!("{} is only allowed in the steps of a task", key))
+        }
+        key => {
+            let keys = STEP_KEYS.iter().chain(TASK_KEYS).chain(MODIFIER_KEYS);
+
+            return Err(match suggestion(key, keys) {
+                Some(known) => format!("unknown step {}, did you mean {}?", key, known),
+                None => format!("unknown step {}", key),
+            });
+        }
+    })
+}
+
+/// The number of characters to insert, remove or replace to turn one word into the other.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<_> = b.chars().collect();
+    let mut previous: Vec<_> = (0..=b.len()).collect();
+
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+
+        for (j, b_char) in b.iter().enumerate() {
+            let replace = previous[j] + usize::from(a_char != *b_char);
+            current.push(replace.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+/// The known key closest to a misspelled one, if it is close enough to be a typo.
+fn suggestion<'a>(key: &str, known: impl IntoIterator<Item = &'a &'a str>) -> Option<&'a str> {
+    known
+        .into_iter()
+        .map(|known| (edit_distance(key, known), *known))
+        .filter(|(distance, _)| *distance <= 2 && *distance < key.len())
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, known)| known)
+}
+
+/// Checks the keys that are ignored when parsing, like misspelled pilot settings on the top
+/// level, which would be tasks, or unknown keys in the map of a step.
+pub fn check_strict(yaml: &Yaml) -> Result<(), String> {
+    let hash = yaml
+        .as_hash()
+        .ok_or("the top level has to map task names to their steps")?;
+
+    for (name, value) in hash {
+        let name = name.as_str().ok_or("task names have to be strings")?;
+
+        match name {
+            "before_all" | "after_all" => check_steps_strict(value, name)?,
+            "profiles" => {
+                for (profile, profile_yaml) in value.as_hash().into_iter().flatten() {
+                    let profile = profile.as_str().unwrap_or_default();
+
+                    for (task, steps) in profile_yaml["tasks"].as_hash().into_iter().flatten() {
+                        let task = task.as_str().unwrap_or_default();
+                        check_steps_strict(
+                            steps,
+                            &format!("task {} of profile {}", task, profile),
+                        )?;
+                    }
+                }
+            }
+            name if is_config_entry(name, value) => {}
+            name => {
+                if let Some(known) = suggestion(name, CONFIG_KEYS).filter(|known| *known != name) {
+                    return Err(format!(
+                        "{} looks like a misspelled {}, rename the task if it is one",
+                        name, known
+                    ));
+                }
+
+                check_steps_strict(value, &format!("task {}", name))?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks the maps of a list of steps, including the nested steps.
+fn check_steps_strict(steps: &Yaml, name: &str) -> Result<(), String> {
+    for (index, step) in steps.as_vec().into_iter().flatten().enumerate() {
+        let context = |err: String| format!("step {} of {}: {}", index + 1, name, err);
+
+        for (key, value) in step.as_hash().into_iter().flatten() {
+            let key = key.as_str().unwrap_or_default();
+
+            match key {
+                "parallel" | "pipe" | "on_failure" | "on_success" | "finally" => {
+                    check_steps_strict(value, &format!("{} of {}", key, name))?
+                }
+                key => {
+                    let Some((_, known_keys)) = STEP_MAP_KEYS.iter().find(|(step, _)| *step == key)
+                    else {
+                        continue;
+                    };
+
+                    for map_key in value.as_hash().into_iter().flatten().map(|(key, _)| key) {
+                        let map_key = map_key.as_str().unwrap_or_default();
+
+                        if known_keys.contains(&map_key) {
+                            continue;
+                        }
+
+                        return Err(context(match suggestion(map_key, *known_keys) {
+                            Some(known) => {
+                                format!("{} has no {}, did you mean {}?", key, map_key, known)
+                            }
+                            None => format!("{} has no {}", key, map_key),
+                        }));
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use yaml_rust::YamlLoader;
+
+    use super::*;
+
+    fn tasks(pilotfile: &str) -> Vec<Task> {
+        let yaml = YamlLoader::load_from_str(pilotfile).unwrap().remove(0);
+
+        yaml.as_hash()
+            .unwrap()
+            .iter()
+            .map(|(name, steps)| parse_task(name.as_str().unwrap(), steps, Path::new(".")).unwrap())
+            .collect()
+    }
+
+    fn commands(task: &Task) -> Vec<&str> {
+        task.steps
+            .iter()
+            .filter_map(|step| match step {
+                Step::Shell {
+                    command: ShellCommand::Command(command),
+                    ..
+                } => Some(command.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn extends_inherit_from_base_first() {
+        // the base of a base comes after it, it still has to be resolved first
+        let mut tasks = tasks(
+            "worker: [extends: api, shell: echo worker]
+api: [extends: service, timestamp: true, shell: echo api]
+service: [idle_timeout: 5s, shell: echo service]",
+        );
+
+        resolve_extends(&mut tasks).unwrap();
+
+        let worker = &tasks[0];
+        assert_eq!(worker.extends, None);
+        assert_eq!(
+            commands(worker),
+            ["echo service", "echo api", "echo worker"]
+        );
+        assert_eq!(worker.timestamp, Some(true));
+        assert_eq!(worker.idle_timeout, Some(Duration::from_secs(5)));
+        assert_eq!(commands(&tasks[1]), ["echo service", "echo api"]);
+    }
+
+    #[test]
+    fn extends_keep_own_settings() {
+        let mut tasks = tasks(
+            "base: [timestamp: true, max_line_length: 80, shell: echo base]
+task: [extends: base, timestamp: false]",
+        );
+
+        resolve_extends(&mut tasks).unwrap();
+
+        assert_eq!(tasks[1].timestamp, Some(false));
+        assert_eq!(tasks[1].max_line_length, Some(80));
+    }
+
+    #[test]
+    fn extends_unknown_task() {
+        let mut tasks = tasks("task: [extends: nope]");
+
+        assert_eq!(
+            resolve_extends(&mut tasks),
+            Err("task task extends nope, which is no task".to_string())
+        );
+    }
+
+    #[test]
+    fn extends_cycle() {
+        let mut cycle = tasks("a: [extends: b]\nb: [extends: c]\nc: [extends: a]");
+        let mut itself = tasks("a: [extends: a]");
+
+        assert_eq!(
+            resolve_extends(&mut cycle),
+            Err("the tasks a, b, c extend each other".to_string())
+        );
+        assert_eq!(
+            resolve_extends(&mut itself),
+            Err("task a extends itself".to_string())
+        );
+    }
+
+    #[test]
+    fn restart_and_health_need_services() {
+        let error = |pilotfile: &str| {
+            let yaml = YamlLoader::load_from_str(pilotfile).unwrap().remove(0);
+            Config::from_yaml(&yaml, Path::new(".")).err()
+        };
+
+        assert_eq!(
+            error("api: [restart: 2, shell: serve]").as_deref(),
+            Some("task api: restart needs kind: service")
+        );
+        assert_eq!(
+            error("api: [health: {shell: curl localhost}, shell: serve]").as_deref(),
+            Some("task api: health needs kind: service")
+        );
+        // the kind can come from the base task
+        assert_eq!(
+            error(
+                "server: [kind: service, shell: serve]
+api: [extends: server, restart: 2, health: {shell: curl localhost}]"
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn needs_existing_tasks_without_cycles() {
+        let error = |pilotfile: &str| {
+            let yaml = YamlLoader::load_from_str(pilotfile).unwrap().remove(0);
+            Config::from_yaml(&yaml, Path::new(".")).err()
+        };
+
+        assert_eq!(
+            error("api: [needs: db, shell: serve]").as_deref(),
+            Some("task api needs db, which is no task")
+        );
+        assert_eq!(
+            error("api: [needs: api, shell: serve]").as_deref(),
+            Some("task api needs itself")
+        );
+        assert_eq!(
+            error("a: [needs: b]\nb: [needs: c]\nc: [needs: [a]]").as_deref(),
+            Some("the tasks a, b, c need each other")
+        );
+        assert_eq!(
+            error("db: [kind: service]\napi: [needs: [db], on_dependency_failure: cancel]"),
+            None
+        );
+    }
+}
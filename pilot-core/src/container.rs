@@ -0,0 +1,122 @@
+use std::{
+    path::{Component, Path},
+    process::Command,
+};
+
+use yaml_rust::Yaml;
+
+/// Runs the shell steps of a task inside a container, through docker or podman.
+#[derive(Clone)]
+pub struct Container {
+    image: String,
+    volumes: Vec<String>,
+    ports: Vec<String>,
+    workdir: Option<String>,
+    engine: Option<String>,
+}
+
+impl Container {
+    pub fn from_yaml(yaml: &Yaml) -> Option<Self> {
+        let volumes = match &yaml["volumes"] {
+            Yaml::BadValue => vec![],
+            volumes => volumes
+                .as_vec()?
+                .iter()
+                .map(|volume| volume.as_str().map(|volume| volume.to_string()))
+                .collect::<Option<_>>()?,
+        };
+
+        let ports = match &yaml["ports"] {
+            Yaml::BadValue => vec![],
+            ports => ports
+                .as_vec()?
+                .iter()
+                .map(|port| match port {
+                    Yaml::String(port) => Some(port.clone()),
+                    Yaml::Integer(port) => Some(port.to_string()),
+                    _ => None,
+                })
+                .collect::<Option<_>>()?,
+        };
+
+        Some(Container {
+            image: yaml["image"].as_str()?.to_string(),
+            volumes,
+            ports,
+            workdir: yaml["workdir"].as_str().map(|workdir| workdir.to_string()),
+            engine: yaml["engine"].as_str().map(|engine| engine.to_string()),
+        })
+    }
+
+    /// The container engine to use, docker is preferred over podman if both are installed.
+    fn engine(&self) -> String {
+        if let Some(engine) = &self.engine {
+            return engine.clone();
+        }
+
+        let installed = |engine: &str| {
+            Command::new(engine)
+                .arg("--version")
+                .output()
+                .map(|output| output.status.success())
+                .unwrap_or(false)
+        };
+
+        if !installed("docker") && installed("podman") {
+            "podman".to_string()
+        } else {
+            "docker".to_string()
+        }
+    }
+
+    /// Builds the command that runs `shell_command` in a new container.
+    ///
+    /// Relative host paths of volumes are resolved against the Pilotfile directory.
+    pub fn command(
+        &self,
+        shell_command: &str,
+        dir: &Path,
+        env: &[(String, String)],
+        tty: bool,
+    ) -> Command {
+        let mut command = Command::new(self.engine());
+        command.args(["run", "--rm", "-i"]);
+
+        if tty {
+            command.arg("-t");
+        }
+
+        for volume in &self.volumes {
+            let volume = match volume.split_once(':') {
+                Some((host, container)) if host.starts_with('.') => {
+                    let host = Path::new(host)
+                        .components()
+                        .filter(|component| *component != Component::CurDir)
+                        .fold(dir.to_path_buf(), |path, component| path.join(component));
+                    format!("{}:{}", host.display(), container)
+                }
+                _ => volume.clone(),
+            };
+
+            command.arg("-v").arg(volume);
+        }
+
+        for port in &self.ports {
+            command.arg("-p").arg(port);
+        }
+
+        if let Some(workdir) = &self.workdir {
+            command.arg("-w").arg(workdir);
+        }
+
+        for (key, value) in env {
+            command.arg("-e").arg(format!("{}={}", key, value));
+        }
+
+        command
+            .arg(&self.image)
+            .args(["sh", "-c", shell_command])
+            .current_dir(dir);
+        command
+    }
+}
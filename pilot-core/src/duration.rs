@@ -0,0 +1,21 @@
+use std::time::Duration;
+
+/// Parses durations like `30s`, `500ms`, `2m` or `1h`, plain numbers are seconds.
+pub fn parse(duration: &str) -> Option<Duration> {
+    let duration = duration.trim();
+    let split = duration
+        .find(|char: char| !char.is_ascii_digit() && char != '.')
+        .unwrap_or(duration.len());
+    let (number, unit) = duration.split_at(split);
+    let number = number.parse::<f64>().ok()?;
+
+    let seconds = match unit.trim() {
+        "ms" => number / 1000.0,
+        "" | "s" => number,
+        "m" => number * 60.0,
+        "h" => number * 60.0 * 60.0,
+        _ => return None,
+    };
+
+    Some(Duration::from_secs_f64(seconds))
+}
@@ -0,0 +1,141 @@
+//! `pilot edit`, opens the Pilotfile in the editor of the user.
+
+use std::{env, fs::read_to_string, path::Path, process::Command};
+
+use yaml_rust::{
+    parser::{Event, MarkedEventReceiver, Parser},
+    scanner::Marker,
+};
+
+use crate::{pilotfile, user_config, Error, OrError};
+
+const USAGE: &str = "Usage: pilot edit [task]";
+
+#[cfg(target_family = "windows")]
+const DEFAULT_EDITOR: &str = "notepad";
+#[cfg(not(target_family = "windows"))]
+const DEFAULT_EDITOR: &str = "vi";
+
+/// Collects the line of every task, which are the keys of the top level of the first document.
+#[derive(Default)]
+struct TaskLines {
+    lines: Vec<(String, usize)>,
+    depth: usize,
+    /// whether the next node of the top level is a key instead of a value
+    key_next: bool,
+    done: bool,
+}
+
+impl MarkedEventReceiver for TaskLines {
+    fn on_event(&mut self, event: Event, marker: Marker) {
+        if self.done {
+            return;
+        }
+
+        match event {
+            Event::MappingStart(_) | Event::SequenceStart(_) => {
+                if self.depth == 1 {
+                    self.key_next = !self.key_next;
+                }
+                self.depth += 1;
+            }
+            Event::MappingEnd | Event::SequenceEnd => self.depth -= 1,
+            Event::Scalar(value, ..) if self.depth == 1 => {
+                if self.key_next {
+                    self.lines.push((value, marker.line()));
+                }
+                self.key_next = !self.key_next;
+            }
+            Event::Alias(_) if self.depth == 1 => self.key_next = !self.key_next,
+            Event::DocumentEnd => self.done = true,
+            _ => {}
+        }
+    }
+}
+
+/// The line of each task in a Pilotfile, starting at 1.
+pub fn task_lines(file: &str) -> Result<Vec<(String, usize)>, String> {
+    let mut receiver = TaskLines {
+        key_next: true,
+        ..TaskLines::default()
+    };
+
+    Parser::new(file.chars())
+        .load(&mut receiver, false)
+        .map_err(|err| format!("That is not a valid Pilotfile: {}", err))?;
+
+    Ok(receiver.lines)
+}
+
+/// The editor of the user config, `$VISUAL` or `$EDITOR`, with its flags.
+fn editor() -> Vec<String> {
+    let editor = user_config::get()
+        .editor
+        .clone()
+        .or_else(|| env::var("VISUAL").ok())
+        .or_else(|| env::var("EDITOR").ok())
+        .filter(|editor| !editor.trim().is_empty())
+        .unwrap_or(DEFAULT_EDITOR.to_string());
+
+    editor.split_whitespace().map(str::to_string).collect()
+}
+
+/// The arguments that open the file at a line, the editors disagree on them.
+fn goto_args(editor: &str, path: &Path, line: usize) -> Vec<String> {
+    let path = path.to_string_lossy();
+    let name = Path::new(editor)
+        .file_stem()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    match name.as_str() {
+        "code" | "codium" | "code-insiders" => {
+            vec!["--goto".to_string(), format!("{}:{}", path, line)]
+        }
+        "subl" | "zed" | "hx" => vec![format!("{}:{}", path, line)],
+        "notepad" => vec![path.to_string()],
+        // vi, vim, nvim, nano, emacs, micro, kak and most others
+        _ => vec![format!("+{}", line), path.to_string()],
+    }
+}
+
+/// `pilot edit [task]`, opens the Pilotfile in the editor, at the line of the task if there is
+/// one. Gives the exit code of the editor.
+pub fn cli(args: &[String], pilotfile_dir: &Path) -> Result<i32, Error> {
+    let task = match args {
+        [] => None,
+        [task] if !task.starts_with('-') => Some(task),
+        _ => return Err(Error(USAGE.to_string())),
+    };
+
+    let path = pilotfile_dir.join(pilotfile::FILE_NAME);
+    let file = read_to_string(&path)
+        .ok()
+        .or_error("pilot edit only opens a Pilotfile.yaml")?;
+
+    let mut editor = editor();
+    let program = editor.remove(0);
+    let mut command = Command::new(&program);
+    command.args(editor);
+
+    match task {
+        Some(task) => {
+            let lines = task_lines(&file)?;
+            let (_, line) = lines
+                .iter()
+                .find(|(name, _)| name == task)
+                .or_error(&format!("Task {} not found in Pilotfile", task))?;
+
+            command.args(goto_args(&program, &path, *line));
+        }
+        None => {
+            command.arg(&path);
+        }
+    }
+
+    let status = command
+        .status()
+        .or_error(&format!("Could not start the editor {}", program))?;
+
+    Ok(status.code().unwrap_or(1))
+}
@@ -0,0 +1,130 @@
+//! The `hooks` of a Pilotfile, commands that learn about the events of a run, like a failed task,
+//! for integrations like renaming a tmux window or posting to a chat.
+
+use std::{path::Path, process::Stdio};
+
+use tokio::{io::AsyncWriteExt, process::Command};
+use yaml_rust::Yaml;
+
+use crate::{config::Config, get_shell, state::json_string};
+
+pub(crate) const EVENTS: &[&str] = &[
+    "run_started",
+    "task_failed",
+    "task_restarted",
+    "run_finished",
+];
+
+/// A field of an event, the hook gets it as `PILOT_<NAME>` and in the JSON on its stdin.
+pub(crate) enum Value<'a> {
+    Text(&'a str),
+    Number(i32),
+    Bool(bool),
+    List(&'a [String]),
+}
+
+impl Value<'_> {
+    fn json(&self) -> String {
+        match self {
+            Value::Text(text) => json_string(text),
+            Value::Number(number) => number.to_string(),
+            Value::Bool(bool) => bool.to_string(),
+            Value::List(list) => format!(
+                "[{}]",
+                list.iter()
+                    .map(|item| json_string(item))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+    }
+
+    fn env(&self) -> String {
+        match self {
+            Value::Text(text) => text.to_string(),
+            Value::List(list) => list.join(" "),
+            value => value.json(),
+        }
+    }
+}
+
+/// Parses the `hooks` map from events to commands.
+pub(crate) fn from_yaml(yaml: &Yaml) -> Result<Vec<(String, String)>, String> {
+    let invalid = || "hooks needs a map of events like run_started to commands".to_string();
+
+    yaml.as_hash()
+        .ok_or_else(invalid)?
+        .iter()
+        .map(|(event, command)| {
+            let event = event.as_str().ok_or_else(invalid)?;
+            let command = command.as_str().ok_or_else(invalid)?;
+
+            if !EVENTS.contains(&event) {
+                return Err(format!(
+                    "hooks has no event {}, the events are {}",
+                    event,
+                    EVENTS.join(", ")
+                ));
+            }
+
+            Ok((event.to_string(), command.to_string()))
+        })
+        .collect()
+}
+
+/// Runs the hook of an event in the Pilotfile directory and waits for it, so hooks see the
+/// events in order. `shell` is the one of the tasks, after `--shell` and the user config. A
+/// failing hook doesn't change the outcome of the run.
+pub(crate) async fn fire(
+    config: &Config,
+    dir: &Path,
+    shell: Option<&str>,
+    event: &str,
+    fields: &[(&str, Value<'_>)],
+) {
+    let Some((_, command)) = config.hooks.iter().find(|(name, _)| name == event) else {
+        return;
+    };
+
+    let json = format!(
+        "{{\"event\": {}{}}}\n",
+        json_string(event),
+        fields
+            .iter()
+            .map(|(name, value)| format!(", {}: {}", json_string(name), value.json()))
+            .collect::<String>()
+    );
+
+    let mut hook = Command::from(get_shell(shell));
+    hook.arg(command)
+        .current_dir(dir)
+        .env("PILOT_EVENT", event)
+        .envs(
+            fields
+                .iter()
+                .map(|(name, value)| (format!("PILOT_{}", name.to_ascii_uppercase()), value.env())),
+        )
+        .stdin(Stdio::piped());
+
+    let result = match hook.spawn() {
+        Ok(mut child) => {
+            if let Some(mut stdin) = child.stdin.take() {
+                // hooks that only look at the variables don't read their stdin
+                let _ = stdin.write_all(json.as_bytes()).await;
+            }
+
+            child.wait().await
+        }
+        Err(err) => Err(err),
+    };
+
+    match result {
+        Ok(status) if status.success() => {}
+        Ok(status) => eprintln!(
+            "The {} hook failed with exit code {}",
+            event,
+            status.code().unwrap_or(-1)
+        ),
+        Err(err) => eprintln!("Could not run the {} hook: {}", event, err),
+    }
+}
@@ -0,0 +1,188 @@
+//! `${VAR}` and `${VAR:-default}` in commands and paths, independent of the shell running them.
+//!
+//! The values are never pasted into commands, where the shell would read them a second time.
+//! The references are left for the shell to expand from the environment of the task instead, in
+//! the syntax of that shell. `$${` stands for a literal `${`, escaped for the shell so it doesn't
+//! expand it either.
+
+/// The syntax the references are written in for the shell running the command.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum Shell {
+    Posix,
+    PowerShell,
+}
+
+/// Quotes a word for PowerShell, nothing is special in single quotes except the quote itself.
+fn quote_powershell(word: &str) -> String {
+    format!("'{}'", word.replace('\'', "''"))
+}
+
+/// Goes through the references in a command, `replace` gets the name and default of each and
+/// gives what to put in its place, `None` leaves the reference as it is. An escaped `$${` becomes
+/// `escaped`.
+fn scan(
+    command: &str,
+    escaped: &str,
+    mut replace: impl FnMut(&str, Option<&str>) -> Option<String>,
+) -> String {
+    let mut expanded = String::with_capacity(command.len());
+    let mut rest = command;
+
+    while let Some(start) = rest.find("${") {
+        // $${ escapes the expansion
+        if rest[..start].ends_with('$') {
+            expanded.push_str(&rest[..start - 1]);
+            expanded.push_str(escaped);
+            rest = &rest[start + 2..];
+            continue;
+        }
+
+        expanded.push_str(&rest[..start]);
+        let reference = &rest[start..];
+
+        let end = match reference.find('}') {
+            Some(end) => end,
+            None => {
+                rest = reference;
+                break;
+            }
+        };
+
+        let inner = &reference[2..end];
+        let (name, default) = match inner.split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (inner, None),
+        };
+
+        let is_name = name.starts_with(|char: char| char.is_ascii_alphabetic() || char == '_')
+            && name
+                .chars()
+                .all(|char| char.is_ascii_alphanumeric() || char == '_');
+
+        // other shell syntax like ${#list} or ${file%.txt} is left alone
+        match is_name.then(|| replace(name, default)).flatten() {
+            Some(replacement) => expanded.push_str(&replacement),
+            None => expanded.push_str(&reference[..=end]),
+        }
+
+        rest = &reference[end + 1..];
+    }
+
+    expanded.push_str(rest);
+    expanded
+}
+
+/// The names of the variables a command references, for environments that don't inherit the
+/// one of pilot, like remote hosts.
+pub(crate) fn names(command: &str) -> Vec<String> {
+    let mut names = vec![];
+
+    scan(command, "${", |name, _| {
+        names.push(name.to_string());
+        None
+    });
+
+    names
+}
+
+/// Replaces the references in a path, which no shell reads. Variables that are neither set nor
+/// have a default are left as they are.
+pub(crate) fn substitute(path: &str, lookup: impl Fn(&str) -> Option<String>) -> String {
+    scan(path, "${", |name, default| match (lookup(name), default) {
+        (Some(value), Some(default)) if value.is_empty() => Some(default.to_string()),
+        (Some(value), _) => Some(value),
+        (None, default) => default.map(|default| default.to_string()),
+    })
+}
+
+/// Writes the references of a command in the syntax of `shell`. `is_set` tells whether a
+/// variable is in the environment of the task, the others are left to the shell as they are, the
+/// command may set them itself.
+pub(crate) fn expand(command: &str, shell: Shell, is_set: impl Fn(&str) -> bool) -> String {
+    let escaped = match shell {
+        Shell::Posix => "\\${",
+        Shell::PowerShell => "`${",
+    };
+
+    scan(command, escaped, |name, default| match (shell, default) {
+        // POSIX shells understand both forms themselves
+        (Shell::Posix, _) => None,
+        // like in POSIX shells, the default also replaces an empty value
+        (Shell::PowerShell, Some(default)) => Some(format!(
+            "$(if ($env:{0}) {{ $env:{0} }} else {{ {1} }})",
+            name,
+            quote_powershell(default)
+        )),
+        (Shell::PowerShell, None) if is_set(name) => Some(format!("${{env:{}}}", name)),
+        (Shell::PowerShell, None) => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn posix_leaves_references_to_the_shell() {
+        let command = "echo ${NAME} ${UNSET:-a default} ${#list} ${file%.txt}";
+
+        assert_eq!(expand(command, Shell::Posix, |_| true), command);
+    }
+
+    #[test]
+    fn powershell_reads_the_environment() {
+        assert_eq!(
+            expand("echo ${NAME} ${UNSET}", Shell::PowerShell, |name| name
+                == "NAME"),
+            "echo ${env:NAME} ${UNSET}"
+        );
+        assert_eq!(
+            expand("echo ${NAME:-it's}", Shell::PowerShell, |_| false),
+            "echo $(if ($env:NAME) { $env:NAME } else { 'it''s' })"
+        );
+    }
+
+    #[test]
+    fn escaped_references() {
+        assert_eq!(
+            expand("echo $${NAME}", Shell::PowerShell, |_| true),
+            "echo `${NAME}"
+        );
+        assert_eq!(
+            expand("echo $${NAME}", Shell::Posix, |_| true),
+            "echo \\${NAME}"
+        );
+        assert_eq!(substitute("$${NAME}", |_| Some("x".to_string())), "${NAME}");
+    }
+
+    #[test]
+    fn substitute_paths() {
+        let lookup = |name: &str| match name {
+            "DIR" => Some("out".to_string()),
+            "EMPTY" => Some(String::new()),
+            _ => None,
+        };
+
+        assert_eq!(substitute("${DIR}/app", lookup), "out/app");
+        assert_eq!(substitute("${EMPTY:-tmp}/app", lookup), "tmp/app");
+        assert_eq!(substitute("${UNSET:-tmp}/app", lookup), "tmp/app");
+        assert_eq!(substitute("${UNSET}/app", lookup), "${UNSET}/app");
+        // without the closing brace, there is no reference
+        assert_eq!(substitute("${DIR/app", lookup), "${DIR/app");
+        assert_eq!(substitute("${DIR}/${app", lookup), "out/${app");
+    }
+
+    #[test]
+    fn names_of_references() {
+        assert_eq!(names("echo ${A} ${B:-b} $${C} ${#D} ${E%.txt}"), ["A", "B"]);
+    }
+
+    #[test]
+    fn unclosed_reference() {
+        assert_eq!(expand("echo a ${b", Shell::Posix, |_| true), "echo a ${b");
+        assert_eq!(
+            expand("echo ${A} b ${c", Shell::PowerShell, |_| true),
+            "echo ${env:A} b ${c"
+        );
+    }
+}
@@ -0,0 +1,415 @@
+use std::{path::Path, time::Duration};
+
+use crate::{
+    config::{Config, Setting, Step, Task},
+    procfile, spawn, Error, OrError, Pilotfile,
+};
+
+const USAGE: &str = "Usage:
+    pilot export procfile
+    pilot export systemd <task>
+    pilot export launchd <task>";
+
+/// What other tools can take over from a task, without running it through pilot.
+struct Service<'a> {
+    description: Option<&'a str>,
+    commands: Vec<&'a str>,
+    env: Vec<(String, String)>,
+    nice: Option<i32>,
+    umask: Option<u32>,
+    limits: spawn::Limits,
+    stop_signal: Option<&'static str>,
+    stop_grace_period: Option<Duration>,
+    /// the steps that can't be represented outside of pilot
+    unsupported: Vec<&'a str>,
+}
+
+impl<'a> Service<'a> {
+    /// `os` picks the command of shell steps that differ between operating systems.
+    fn from_task(task: &'a Task, os: &str) -> Self {
+        let mut service = Service {
+            description: task.description.as_deref(),
+            commands: vec![],
+            env: vec![],
+            nice: None,
+            umask: None,
+            limits: spawn::Limits::default(),
+            stop_signal: task.stop_signal,
+            stop_grace_period: task.stop_grace_period,
+            unsupported: vec![],
+        };
+
+        for step in &task.steps {
+            match step {
+                // the variable only exists while pilot runs the task
+                Step::Shell {
+                    capture: Some(_), ..
+                } => service.unsupported("capture"),
+                Step::Shell { stdin: Some(_), .. } => service.unsupported("stdin"),
+                Step::Shell { command, .. } => match command.for_os(os) {
+                    Some(command) => service.commands.push(command),
+                    None if !service.unsupported.contains(&"shell") => {
+                        service.unsupported.push("shell")
+                    }
+                    None => {}
+                },
+                Step::Setting(setting) => match setting {
+                    // raw only changes how pilot shows the output
+                    Setting::Raw(_) => {}
+                    Setting::Env(env) => service.env.extend(env.iter().cloned()),
+                    Setting::Nice(nice) => service.nice = Some(*nice),
+                    Setting::Umask(umask) => service.umask = Some(*umask),
+                    // services run in their own session anyway
+                    Setting::Setsid(_) => {}
+                    Setting::Limits(limits) => service.limits = limits.clone(),
+                    _ => service.unsupported(step.key()),
+                },
+                _ => service.unsupported(step.key()),
+            }
+        }
+
+        let hooks = [
+            ("confirm", task.confirm.is_some()),
+            ("ports", !task.ports.is_empty()),
+            ("requires_env", !task.requires_env.is_empty()),
+            ("idle_timeout", task.idle_timeout.is_some()),
+            ("health", task.health.is_some()),
+            ("needs", !task.needs.is_empty()),
+            ("tee_to", task.tee_to.is_some()),
+            ("on_failure", task.on_failure.is_some()),
+            ("on_success", task.on_success.is_some()),
+            ("finally", task.finally.is_some()),
+        ];
+
+        for (hook, _) in hooks.iter().filter(|(_, used)| *used) {
+            service.unsupported(hook);
+        }
+
+        service
+    }
+
+    fn unsupported(&mut self, key: &'a str) {
+        if !self.unsupported.contains(&key) {
+            self.unsupported.push(key);
+        }
+    }
+
+    /// The shell steps as a single command, stopping at the first failing one.
+    fn command(&self) -> String {
+        self.commands
+            .iter()
+            .map(|command| command.trim())
+            .collect::<Vec<_>>()
+            .join(" && ")
+    }
+
+    /// The settings this service uses, besides its commands.
+    fn settings(&self) -> Vec<&'a str> {
+        let limits = &self.limits;
+        let has_limits =
+            limits.memory.is_some() || limits.cpu.is_some() || limits.open_files.is_some();
+
+        [
+            ("env", !self.env.is_empty()),
+            ("nice", self.nice.is_some()),
+            ("umask", self.umask.is_some()),
+            ("limits", has_limits),
+            ("stop_signal", self.stop_signal.is_some()),
+            ("stop_grace_period", self.stop_grace_period.is_some()),
+        ]
+        .iter()
+        .filter(|(_, used)| *used)
+        .map(|(setting, _)| *setting)
+        .collect()
+    }
+}
+
+/// Flattens the shell steps of every task into a Procfile entry.
+/// Tasks that can't be represented are skipped with a warning.
+fn procfile(config: &Config) -> String {
+    let mut procfile = String::new();
+
+    for task in &config.tasks {
+        let name = &task.name;
+
+        if !procfile::is_valid_name(name) {
+            eprintln!(
+                "Skipping task {}, its name is not allowed in a Procfile",
+                name
+            );
+            continue;
+        }
+
+        // the platforms running Procfiles are linux based
+        let service = Service::from_task(task, "linux");
+        let mut unsupported = service.unsupported.clone();
+        unsupported.extend(service.settings());
+
+        if !unsupported.is_empty() {
+            eprintln!(
+                "Skipping task {}, a Procfile can't represent its {} steps",
+                name,
+                unsupported.join(", ")
+            );
+            continue;
+        }
+
+        if service.commands.is_empty() {
+            eprintln!("Skipping task {}, it has no shell steps", name);
+            continue;
+        }
+
+        if service
+            .commands
+            .iter()
+            .any(|command| command.trim().contains('\n'))
+        {
+            eprintln!(
+                "Skipping task {}, a Procfile can't represent commands spanning multiple lines",
+                name
+            );
+            continue;
+        }
+
+        procfile += &format!("{}: {}\n", name, service.command());
+    }
+
+    procfile
+}
+
+/// Quotes a value for a systemd unit file, where `%` starts a specifier and `$` a variable.
+fn systemd_quote(value: &str) -> String {
+    let mut quoted = String::from('"');
+
+    for char in value.chars() {
+        match char {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '%' => quoted.push_str("%%"),
+            '$' => quoted.push_str("$$"),
+            char => quoted.push(char),
+        }
+    }
+
+    quoted.push('"');
+    quoted
+}
+
+/// Loads a task to run as a service, warning about the steps `format` can't represent.
+fn service<'a>(
+    config: &'a Config,
+    task: &str,
+    format: &str,
+    os: &str,
+) -> Result<Service<'a>, Error> {
+    let found = config
+        .task(task)
+        .or_error(&format!("Task {} not found", task))?;
+    let service = Service::from_task(found, os);
+
+    if !service.unsupported.is_empty() {
+        eprintln!(
+            "Ignoring the {} steps of task {}, {} can't represent them",
+            service.unsupported.join(", "),
+            task,
+            format
+        );
+    }
+
+    if service.commands.is_empty() {
+        return Err(Error(format!("Task {} has no shell steps", task)));
+    }
+
+    Ok(service)
+}
+
+/// Generates a systemd user unit running the task as a service.
+fn systemd(config: &Config, task: &str, dir: &Path) -> Result<String, Error> {
+    let service = service(config, task, "a systemd unit", "linux")?;
+    let command = service.command();
+
+    let mut unit = format!(
+        "[Unit]
+Description={}
+
+[Service]
+Type=simple
+WorkingDirectory={}
+",
+        service
+            .description
+            .map(|description| description.to_string())
+            .unwrap_or(format!("pilot task {}", task)),
+        // paths aren't quoted, but their specifiers are still expanded
+        dir.to_string_lossy().replace('%', "%%")
+    );
+
+    for (key, value) in &service.env {
+        unit += &format!(
+            "Environment={}\n",
+            systemd_quote(&format!("{}={}", key, value))
+        );
+    }
+
+    unit += &format!("ExecStart=/bin/sh -c {}\n", systemd_quote(&command));
+    unit += "Restart=on-failure\n";
+
+    if let Some(nice) = service.nice {
+        unit += &format!("Nice={}\n", nice);
+    }
+
+    if let Some(umask) = service.umask {
+        unit += &format!("UMask={:04o}\n", umask);
+    }
+
+    if let Some(memory) = service.limits.memory {
+        unit += &format!("MemoryMax={}\n", memory);
+    }
+
+    if let Some(cpu) = service.limits.cpu {
+        unit += &format!("CPUQuota={}%\n", (cpu * 100.0).round());
+    }
+
+    if let Some(open_files) = service.limits.open_files {
+        unit += &format!("LimitNOFILE={}\n", open_files);
+    }
+
+    if let Some(stop_signal) = service.stop_signal {
+        unit += &format!("KillSignal={}\n", stop_signal);
+    }
+
+    if let Some(grace_period) = service.stop_grace_period {
+        unit += &match grace_period.subsec_millis() {
+            0 => format!("TimeoutStopSec={}s\n", grace_period.as_secs()),
+            _ => format!("TimeoutStopSec={}ms\n", grace_period.as_millis()),
+        };
+    }
+
+    unit += "
+[Install]
+WantedBy=default.target
+";
+
+    Ok(unit)
+}
+
+fn xml_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Generates a launchd agent plist running the task as a service.
+fn launchd(config: &Config, task: &str, dir: &Path) -> Result<String, Error> {
+    let service = service(config, task, "a launchd agent", "macos")?;
+
+    if service.limits.memory.is_some() || service.limits.cpu.is_some() {
+        eprintln!(
+            "Ignoring the memory and cpu limits of task {}, launchd doesn't enforce them",
+            task
+        );
+    }
+
+    if service.stop_signal.is_some() {
+        eprintln!(
+            "Ignoring the stop_signal of task {}, launchd always stops it with SIGTERM",
+            task
+        );
+    }
+
+    let mut plist = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" \"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">
+<plist version=\"1.0\">
+<dict>
+\t<key>Label</key>
+\t<string>pilot.{}</string>
+\t<key>ProgramArguments</key>
+\t<array>
+\t\t<string>/bin/sh</string>
+\t\t<string>-c</string>
+\t\t<string>{}</string>
+\t</array>
+\t<key>WorkingDirectory</key>
+\t<string>{}</string>
+",
+        xml_escape(task),
+        xml_escape(&service.command()),
+        xml_escape(&dir.to_string_lossy())
+    );
+
+    if !service.env.is_empty() {
+        plist += "\t<key>EnvironmentVariables</key>\n\t<dict>\n";
+
+        for (key, value) in &service.env {
+            plist += &format!(
+                "\t\t<key>{}</key>\n\t\t<string>{}</string>\n",
+                xml_escape(key),
+                xml_escape(value)
+            );
+        }
+
+        plist += "\t</dict>\n";
+    }
+
+    // like Restart=on-failure, only restart the task if it failed
+    plist += "\t<key>RunAtLoad</key>
+\t<true/>
+\t<key>KeepAlive</key>
+\t<dict>
+\t\t<key>SuccessfulExit</key>
+\t\t<false/>
+\t</dict>
+";
+
+    if let Some(nice) = service.nice {
+        plist += &format!("\t<key>Nice</key>\n\t<integer>{}</integer>\n", nice);
+    }
+
+    if let Some(umask) = service.umask {
+        plist += &format!("\t<key>Umask</key>\n\t<integer>{}</integer>\n", umask);
+    }
+
+    if let Some(open_files) = service.limits.open_files {
+        for limits in ["SoftResourceLimits", "HardResourceLimits"] {
+            plist += &format!(
+                "\t<key>{}</key>
+\t<dict>
+\t\t<key>NumberOfFiles</key>
+\t\t<integer>{}</integer>
+\t</dict>
+",
+                limits, open_files
+            );
+        }
+    }
+
+    if let Some(grace_period) = service.stop_grace_period {
+        // launchd only takes whole seconds, rounding down could kill the task too early
+        plist += &format!(
+            "\t<key>ExitTimeOut</key>\n\t<integer>{}</integer>\n",
+            grace_period.as_secs_f64().ceil()
+        );
+    }
+
+    plist += "</dict>\n</plist>\n";
+    Ok(plist)
+}
+
+/// `pilot export <format>`, prints the tasks of the Pilotfile in that format to stdout.
+pub fn cli(args: &[String], pilotfile: &Pilotfile) -> Result<(), Error> {
+    let config = pilotfile.config();
+    let dir = pilotfile.dir();
+
+    let exported = match (args.first().map(|arg| arg.as_str()), args.get(1)) {
+        (Some("procfile"), _) => procfile(config),
+        (Some("systemd"), Some(task)) => systemd(config, task, dir)?,
+        (Some("launchd"), Some(task)) => launchd(config, task, dir)?,
+        _ => return Err(Error(USAGE.to_string())),
+    };
+
+    print!("{}", exported);
+    Ok(())
+}
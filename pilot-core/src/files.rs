@@ -0,0 +1,154 @@
+//! The built-in file steps, `copy`, `remove`, `mkdir` and `touch`. They don't need a shell, so
+//! they work the same on Windows and Unix.
+
+use std::{
+    fs::{self, File},
+    io,
+    path::Path,
+    time::SystemTime,
+};
+
+use yaml_rust::Yaml;
+
+#[derive(Clone)]
+pub enum FileStep {
+    /// copies a file or a directory with everything in it, into `to` if that is a directory
+    Copy { from: String, to: String },
+    /// removes files and directories, it's fine if they don't exist, like `rm -rf`
+    Remove(Vec<String>),
+    /// creates directories with their parents, like `mkdir -p`
+    Mkdir(Vec<String>),
+    /// creates empty files or sets the modification time of existing ones to now
+    Touch(Vec<String>),
+}
+
+impl FileStep {
+    /// `copy` takes `from` and `to`, the others a path or a list of paths.
+    pub(crate) fn from_yaml(key: &str, yaml: &Yaml) -> Option<Self> {
+        if key == "copy" {
+            return Some(FileStep::Copy {
+                from: yaml["from"].as_str()?.to_string(),
+                to: yaml["to"].as_str()?.to_string(),
+            });
+        }
+
+        let paths = match yaml {
+            Yaml::String(path) => vec![path.clone()],
+            Yaml::Array(paths) => paths
+                .iter()
+                .map(|path| path.as_str().map(|path| path.to_string()))
+                .collect::<Option<_>>()?,
+            _ => return None,
+        };
+
+        match key {
+            _ if paths.is_empty() => None,
+            "remove" => Some(FileStep::Remove(paths)),
+            "mkdir" => Some(FileStep::Mkdir(paths)),
+            "touch" => Some(FileStep::Touch(paths)),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn key(&self) -> &'static str {
+        match self {
+            FileStep::Copy { .. } => "copy",
+            FileStep::Remove(_) => "remove",
+            FileStep::Mkdir(_) => "mkdir",
+            FileStep::Touch(_) => "touch",
+        }
+    }
+
+    /// Runs the step with relative paths in `dir`, `expand` replaces the variables in the paths.
+    pub(crate) fn run(&self, dir: &Path, expand: impl Fn(&str) -> String) -> Result<(), String> {
+        let path = |path: &str| {
+            let expanded = expand(path);
+
+            // an unset variable must not make `remove` delete the whole directory
+            match expanded.trim().is_empty() {
+                true => Err(io::Error::other("the path is empty")),
+                false => Ok(dir.join(expanded)),
+            }
+        };
+
+        match self {
+            FileStep::Copy { from, to } => {
+                let copy = || {
+                    let (from_path, mut to_path) = (path(from)?, path(to)?);
+
+                    // like cp, a directory or a path ending in a slash is where the copy goes
+                    if to_path.is_dir() || to.ends_with(['/', '\\']) {
+                        to_path = to_path.join(from_path.file_name().unwrap_or_default());
+                    }
+
+                    copy(&from_path, &to_path)
+                };
+
+                copy().map_err(|err| format!("could not copy {} to {}: {}", from, to, err))
+            }
+            FileStep::Remove(paths) => each(paths, "remove", |file| remove(&path(file)?)),
+            FileStep::Mkdir(paths) => each(paths, "create", |dir| fs::create_dir_all(path(dir)?)),
+            FileStep::Touch(paths) => each(paths, "touch", |file| touch(&path(file)?)),
+        }
+    }
+}
+
+fn each(
+    paths: &[String],
+    action: &str,
+    run: impl Fn(&str) -> io::Result<()>,
+) -> Result<(), String> {
+    paths.iter().try_for_each(|path| {
+        run(path).map_err(|err| format!("could not {} {}: {}", action, path, err))
+    })
+}
+
+fn copy(from: &Path, to: &Path) -> io::Result<()> {
+    // a directory copied into itself would never end
+    if from.is_dir() && to.starts_with(from) {
+        return Err(io::Error::other("it would be copied into itself"));
+    }
+
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    copy_all(from, to)
+}
+
+fn copy_all(from: &Path, to: &Path) -> io::Result<()> {
+    if !from.is_dir() {
+        return fs::copy(from, to).map(|_| ());
+    }
+
+    fs::create_dir_all(to)?;
+
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        copy_all(&entry.path(), &to.join(entry.file_name()))?;
+    }
+
+    Ok(())
+}
+
+fn remove(path: &Path) -> io::Result<()> {
+    // a link to a directory is removed, not what it points to
+    let result = match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(path),
+        Ok(_) => fs::remove_file(path),
+        Err(err) => Err(err),
+    };
+
+    match result {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+fn touch(path: &Path) -> io::Result<()> {
+    File::options()
+        .create(true)
+        .append(true)
+        .open(path)?
+        .set_modified(SystemTime::now())
+}
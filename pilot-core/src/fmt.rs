@@ -0,0 +1,771 @@
+//! `pilot fmt`, rewrites the Pilotfile in a canonical form.
+
+use std::{
+    fs::{read_to_string, write},
+    path::Path,
+};
+
+use yaml_rust::{
+    parser::{Event, MarkedEventReceiver, Parser},
+    scanner::Marker,
+    yaml::Hash,
+    Yaml, YamlLoader,
+};
+
+use crate::{
+    config::{is_config_entry, CONFIG_KEYS, MODIFIER_KEYS, STEP_MAP_KEYS, TASK_KEYS},
+    pilotfile,
+    state::json_string,
+    Error, OrError,
+};
+
+const USAGE: &str = "Usage: pilot fmt [--check]";
+/// The entries of a task that come after its steps, the other task keys come before them.
+const HOOK_KEYS: &[&str] = &["on_failure", "on_success", "finally"];
+const INDENT: usize = 2;
+
+/// The key of a step, which is the first one that isn't a modifier.
+fn step_key(step: &Hash) -> Option<&str> {
+    step.keys()
+        .filter_map(|key| key.as_str())
+        .find(|key| !MODIFIER_KEYS.contains(key))
+}
+
+/// Sorts the entries of a map in the order of `keys`, unknown keys keep their order at the end.
+fn sort_keys(hash: &Hash, keys: &[&str]) -> Hash {
+    let position = |key: &Yaml| {
+        key.as_str()
+            .and_then(|key| keys.iter().position(|known| *known == key))
+            .unwrap_or(keys.len())
+    };
+    let mut entries: Vec<_> = hash.iter().collect();
+    entries.sort_by_key(|(key, _)| position(key));
+
+    entries
+        .into_iter()
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect()
+}
+
+fn format_steps(steps: &Yaml) -> Yaml {
+    match steps {
+        Yaml::Array(steps) => Yaml::Array(steps.iter().map(format_step).collect()),
+        steps => steps.clone(),
+    }
+}
+
+/// Puts the key of a step before its modifiers and uses the short form of `task` and `compose`
+/// steps if they only have a name.
+fn format_step(step: &Yaml) -> Yaml {
+    let Some(hash) = step.as_hash() else {
+        return step.clone();
+    };
+    let Some(key) = step_key(hash) else {
+        return step.clone();
+    };
+
+    let value = &hash[&Yaml::String(key.to_string())];
+    let value = match (key, value) {
+        ("parallel" | "pipe", branches) => format_steps(branches),
+        ("task" | "compose", Yaml::Hash(map)) if map.len() == 1 => {
+            let name = if key == "task" { "name" } else { "service" };
+
+            match map.get(&Yaml::String(name.to_string())) {
+                Some(name @ Yaml::String(_)) => name.clone(),
+                _ => value.clone(),
+            }
+        }
+        (_, Yaml::Hash(map)) => match STEP_MAP_KEYS.iter().find(|(step, _)| *step == key) {
+            Some((_, keys)) => Yaml::Hash(sort_keys(map, keys)),
+            None => value.clone(),
+        },
+        _ => value.clone(),
+    };
+
+    let keys = [&[key], MODIFIER_KEYS].concat();
+
+    Yaml::Hash(
+        sort_keys(hash, &keys)
+            .into_iter()
+            .map(|(entry_key, entry_value)| match entry_key.as_str() {
+                Some(entry_key) if entry_key == key => {
+                    (Yaml::String(key.to_string()), value.clone())
+                }
+                _ => (entry_key, entry_value),
+            })
+            .collect(),
+    )
+}
+
+/// Moves the task keys like `description` before the steps and the hooks after them.
+fn format_task(task: &Yaml) -> Yaml {
+    let Some(entries) = task.as_vec() else {
+        return task.clone();
+    };
+
+    let task_key = |entry: &Yaml| {
+        let key = entry.as_hash().and_then(step_key)?;
+        TASK_KEYS.iter().find(|task_key| **task_key == key).copied()
+    };
+
+    let mut info = vec![];
+    let mut steps = vec![];
+    let mut hooks = vec![];
+
+    for entry in entries {
+        match task_key(entry) {
+            Some(hook) if HOOK_KEYS.contains(&hook) => {
+                let mut hash = Hash::new();
+                hash.insert(Yaml::String(hook.to_string()), format_steps(&entry[hook]));
+                hooks.push((hook, Yaml::Hash(hash)));
+            }
+            Some(key) => info.push((key, entry.clone())),
+            None => steps.push(format_step(entry)),
+        }
+    }
+
+    let position = |key: &str| TASK_KEYS.iter().position(|task_key| *task_key == key);
+    info.sort_by_key(|(key, _)| position(key));
+    hooks.sort_by_key(|(key, _)| position(key));
+
+    Yaml::Array(
+        info.into_iter()
+            .map(|(_, entry)| entry)
+            .chain(steps)
+            .chain(hooks.into_iter().map(|(_, entry)| entry))
+            .collect(),
+    )
+}
+
+/// Puts the settings of pilot before the tasks, which keep their order. The documents after the
+/// first one start with the profile they overlay.
+fn format_document(document: &Yaml, overlay: bool) -> Yaml {
+    let Some(hash) = document.as_hash() else {
+        return document.clone();
+    };
+
+    let mut formatted = Hash::new();
+
+    if overlay {
+        if let Some(profile) = hash.get(&Yaml::String("profile".to_string())) {
+            formatted.insert(Yaml::String("profile".to_string()), profile.clone());
+        }
+    }
+
+    for key in CONFIG_KEYS {
+        let yaml_key = Yaml::String(key.to_string());
+        let Some(value) = hash
+            .get(&yaml_key)
+            .filter(|value| is_config_entry(key, value))
+        else {
+            continue;
+        };
+
+        let value = match *key {
+            "before_all" | "after_all" => format_steps(value),
+            "profiles" => format_profiles(value),
+            _ => value.clone(),
+        };
+
+        formatted.insert(yaml_key, value);
+    }
+
+    for (key, value) in hash {
+        if !formatted.contains_key(key) {
+            formatted.insert(key.clone(), format_task(value));
+        }
+    }
+
+    Yaml::Hash(formatted)
+}
+
+fn format_profiles(profiles: &Yaml) -> Yaml {
+    let Some(profiles) = profiles.as_hash() else {
+        return profiles.clone();
+    };
+
+    Yaml::Hash(
+        profiles
+            .iter()
+            .map(|(name, profile)| {
+                let profile = match profile.as_hash() {
+                    Some(profile) => {
+                        let mut profile = sort_keys(profile, &["env", "tasks"]);
+
+                        if let Some(Yaml::Hash(tasks)) =
+                            profile.get_mut(&Yaml::String("tasks".to_string()))
+                        {
+                            for (_, task) in tasks.iter_mut() {
+                                *task = format_task(task);
+                            }
+                        }
+
+                        Yaml::Hash(profile)
+                    }
+                    None => profile.clone(),
+                };
+
+                (name.clone(), profile)
+            })
+            .collect(),
+    )
+}
+
+/// A string without quotes, if it reads back as the same string. In a flow list like `[a, b]`,
+/// commas and brackets need quotes as well.
+fn is_plain(string: &str, flow: bool) -> bool {
+    let yaml = match flow {
+        true => format!("[{}]", string),
+        false => format!("- {}", string),
+    };
+    let expected = Yaml::Array(vec![Yaml::String(string.to_string())]);
+
+    !string.is_empty()
+        && string.trim() == string
+        && !string.contains(|char: char| char.is_control())
+        && YamlLoader::load_from_str(&yaml)
+            .is_ok_and(|documents| documents.first() == Some(&expected))
+}
+
+fn scalar(yaml: &Yaml, flow: bool) -> String {
+    match yaml {
+        Yaml::String(string) if is_plain(string, flow) => string.clone(),
+        // escapes are only needed for control characters, quotes are easier to read without
+        Yaml::String(string)
+            if string.contains(|char: char| char.is_control()) || !string.contains(['"', '\\']) =>
+        {
+            json_string(string)
+        }
+        Yaml::String(string) => format!("'{}'", string.replace('\'', "''")),
+        Yaml::Integer(integer) => integer.to_string(),
+        Yaml::Real(real) => real.clone(),
+        Yaml::Boolean(boolean) => boolean.to_string(),
+        _ => "~".to_string(),
+    }
+}
+
+/// Lists of single-line scalars like `ports: [8080, 8081]` fit on the line of their key.
+fn flow_list(items: &[Yaml]) -> Option<String> {
+    let single_line = |item: &Yaml| match item {
+        Yaml::String(string) => !string.contains(|char: char| char.is_control()),
+        Yaml::Integer(_) | Yaml::Real(_) | Yaml::Boolean(_) => true,
+        _ => false,
+    };
+
+    if items.is_empty() || !items.iter().all(single_line) {
+        return None;
+    }
+
+    let items: Vec<_> = items.iter().map(|item| scalar(item, true)).collect();
+    Some(format!("[{}]", items.join(", ")))
+}
+
+/// Multi-line strings like scripts are written as literal blocks, if they can be.
+fn literal_block(string: &str, indent: usize) -> Option<String> {
+    let body = string.trim_end_matches('\n');
+    let newlines = string.len() - body.len();
+    let first_line = body.lines().find(|line| !line.is_empty())?;
+
+    let fits = body.contains('\n')
+        && !first_line.starts_with([' ', '\t'])
+        && !string.contains(|char: char| char.is_control() && char != '\n' && char != '\t');
+
+    if !fits {
+        return None;
+    }
+
+    let chomping = match newlines {
+        0 => "-",
+        1 => "",
+        _ => "+",
+    };
+    let mut block = format!(" |{}\n", chomping);
+
+    for line in body.split('\n') {
+        if !line.is_empty() {
+            block += &" ".repeat(indent);
+            block += line;
+        }
+
+        block.push('\n');
+    }
+
+    block += &"\n".repeat(newlines.saturating_sub(1));
+    Some(block)
+}
+
+/// Writes what follows a key or a dash, `indent` is the indentation of nested entries.
+fn emit_value(yaml: &Yaml, indent: usize, out: &mut String) {
+    match yaml {
+        Yaml::Hash(hash) if hash.is_empty() => out.push_str(" {}\n"),
+        Yaml::Array(array) if array.is_empty() => out.push_str(" []\n"),
+        Yaml::Hash(_) | Yaml::Array(_) => match yaml.as_vec().and_then(|items| flow_list(items)) {
+            Some(list) => out.push_str(&format!(" {}\n", list)),
+            None => {
+                out.push('\n');
+                emit_entries(yaml, indent, false, out);
+            }
+        },
+        Yaml::Null => out.push('\n'),
+        Yaml::String(string) => match literal_block(string, indent) {
+            Some(block) => out.push_str(&block),
+            None => out.push_str(&format!(" {}\n", scalar(yaml, false))),
+        },
+        _ => out.push_str(&format!(" {}\n", scalar(yaml, false))),
+    }
+}
+
+/// Writes the entries of a map or a list in block style, each on lines starting at `indent`.
+/// `list_item` is set for a map that is an item of a list.
+fn emit_entries(yaml: &Yaml, indent: usize, list_item: bool, out: &mut String) {
+    let padding = " ".repeat(indent);
+
+    match yaml {
+        Yaml::Hash(hash) => {
+            for (index, (key, value)) in hash.iter().enumerate() {
+                // an empty line between the tasks
+                if indent == 0 && index > 0 {
+                    out.push('\n');
+                }
+
+                out.push_str(&format!("{}{}:", padding, scalar(key, false)));
+
+                match value {
+                    // the lists of a list item line up with its keys, like the steps of a hook
+                    Yaml::Array(items) if list_item && flow_list(items).is_none() => {
+                        out.push('\n');
+                        emit_entries(value, indent, false, out);
+                    }
+                    _ => emit_value(value, indent + INDENT, out),
+                }
+            }
+        }
+        Yaml::Array(items) => {
+            for item in items {
+                match item {
+                    // the first entry of a nested map or list goes on the line of the dash
+                    Yaml::Hash(hash) if !hash.is_empty() => {
+                        let mut nested = String::new();
+                        emit_entries(item, indent + INDENT, true, &mut nested);
+                        out.push_str(&format!("{}- {}", padding, &nested[indent + INDENT..]));
+                    }
+                    Yaml::Array(array) if flow_list(array).is_none() && !array.is_empty() => {
+                        let mut nested = String::new();
+                        emit_entries(item, indent + INDENT, false, &mut nested);
+                        out.push_str(&format!("{}- {}", padding, &nested[indent + INDENT..]));
+                    }
+                    _ => {
+                        out.push_str(&format!("{}-", padding));
+                        emit_value(item, indent + INDENT, out);
+                    }
+                }
+            }
+        }
+        _ => out.push_str(&format!("{}{}\n", padding, scalar(yaml, false))),
+    }
+}
+
+/// A step of the path to a node. The items of a list are told apart by their key, like the key
+/// of a step, and how many items with that key come before them, which unlike their index
+/// stays the same when the keys of a task are sorted.
+#[derive(Clone, PartialEq, Eq, Debug)]
+enum Segment {
+    Document(usize),
+    Key(String),
+    Item(Option<String>, usize),
+}
+
+/// Collects the line each node starts on, with its path by key and index.
+#[derive(Default)]
+struct NodeLines {
+    /// the path with the index of the list items, in the order of the file
+    nodes: Vec<(usize, Vec<Segment>)>,
+    /// the open maps and lists, with whether the next node of a map is a key
+    open: Vec<(Vec<Segment>, Option<bool>)>,
+    /// the key of the value that comes next in each open map
+    keys: Vec<Option<String>>,
+    /// the next index in each open list
+    indexes: Vec<usize>,
+    documents: usize,
+}
+
+impl MarkedEventReceiver for NodeLines {
+    fn on_event(&mut self, event: Event, marker: Marker) {
+        let path = match self.open.last_mut() {
+            _ if matches!(
+                event,
+                Event::StreamStart
+                    | Event::StreamEnd
+                    | Event::DocumentStart
+                    | Event::DocumentEnd
+                    | Event::MappingEnd
+                    | Event::SequenceEnd
+            ) =>
+            {
+                None
+            }
+            None => Some(vec![Segment::Document(self.documents)]),
+            // a key is on the line of its entry
+            Some((path, Some(true))) => {
+                let key = match &event {
+                    Event::Scalar(key, ..) => Some(key.clone()),
+                    _ => None,
+                };
+                let mut key_path = path.clone();
+                key_path.push(Segment::Key(key.clone().unwrap_or_default()));
+                self.open.last_mut().unwrap().1 = Some(false);
+                *self.keys.last_mut().unwrap() = key;
+                self.nodes.push((marker.line(), key_path));
+                None
+            }
+            Some((path, Some(false))) => {
+                let mut value_path = path.clone();
+                value_path.push(Segment::Key(
+                    self.keys.last().cloned().flatten().unwrap_or_default(),
+                ));
+                self.open.last_mut().unwrap().1 = Some(true);
+                Some(value_path)
+            }
+            Some((path, None)) => {
+                let mut item_path = path.clone();
+                let index = self.indexes.last_mut().unwrap();
+                item_path.push(Segment::Item(None, *index));
+                *index += 1;
+                Some(item_path)
+            }
+        };
+
+        if let Some(path) = &path {
+            self.nodes.push((marker.line(), path.clone()));
+        }
+
+        match event {
+            Event::DocumentStart => self.documents += 1,
+            Event::MappingStart(_) => {
+                self.open.push((path.unwrap_or_default(), Some(true)));
+                self.keys.push(None);
+            }
+            Event::SequenceStart(_) => {
+                self.open.push((path.unwrap_or_default(), None));
+                self.indexes.push(0);
+            }
+            Event::MappingEnd => {
+                self.open.pop();
+                self.keys.pop();
+            }
+            Event::SequenceEnd => {
+                self.open.pop();
+                self.indexes.pop();
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Turns the indexes of the list items in a path into their keys and counts.
+fn identify(documents: &[Yaml], path: &[Segment]) -> Vec<Segment> {
+    let item_key = |item: &Yaml| item.as_hash().and_then(step_key).map(str::to_string);
+    let mut node = &Yaml::BadValue;
+    let mut identified = vec![];
+
+    for segment in path {
+        let segment = match segment {
+            Segment::Document(index) => {
+                node = documents.get(index - 1).unwrap_or(&Yaml::BadValue);
+                segment.clone()
+            }
+            Segment::Key(key) => {
+                node = &node[key.as_str()];
+                segment.clone()
+            }
+            Segment::Item(_, index) => {
+                let items = node.as_vec().map(Vec::as_slice).unwrap_or_default();
+                node = items.get(*index).unwrap_or(&Yaml::BadValue);
+                let key = item_key(node);
+                let count = items[..(*index).min(items.len())]
+                    .iter()
+                    .filter(|item| item_key(item) == key)
+                    .count();
+                Segment::Item(key, count)
+            }
+        };
+
+        identified.push(segment);
+    }
+
+    identified
+}
+
+/// The nodes of a file by the line they start on, each path only with its first line.
+fn node_lines(file: &str, documents: &[Yaml]) -> Vec<(usize, Vec<Segment>)> {
+    let mut receiver = NodeLines::default();
+    let _ = Parser::new(file.chars()).load(&mut receiver, true);
+
+    let mut lines: Vec<(usize, Vec<Segment>)> = vec![];
+
+    for (line, path) in receiver.nodes {
+        let path = identify(documents, &path);
+
+        if !lines.iter().any(|(_, known)| *known == path) {
+            lines.push((line, path));
+        }
+    }
+
+    lines
+}
+
+/// A comment of the Pilotfile, on a line of its own or after a node.
+struct Comment {
+    line: usize,
+    text: String,
+    own_line: bool,
+}
+
+/// The comments of a file. A `#` starts a comment if removing the rest of the line leaves the
+/// documents the same, which isn't the case in strings and literal blocks.
+fn comments(file: &str, documents: &[Yaml]) -> Vec<Comment> {
+    let lines: Vec<_> = file.split('\n').collect();
+    let mut comments = vec![];
+
+    for (index, line) in lines.iter().enumerate() {
+        let starts = line.char_indices().filter(|(position, char)| {
+            *char == '#'
+                && line[..*position]
+                    .chars()
+                    .next_back()
+                    .is_none_or(char::is_whitespace)
+        });
+
+        for (position, _) in starts {
+            let mut stripped = lines.clone();
+            stripped[index] = &line[..position];
+
+            if YamlLoader::load_from_str(&stripped.join("\n"))
+                .ok()
+                .as_deref()
+                == Some(documents)
+            {
+                comments.push(Comment {
+                    line: index + 1,
+                    text: line[position..].trim_end().to_string(),
+                    own_line: line[..position].trim().is_empty(),
+                });
+                break;
+            }
+        }
+    }
+
+    comments
+}
+
+/// Puts the comments of the file back into the formatted file. A comment on a line of its own
+/// goes before the node that followed it, the others after the node they were on the line of.
+/// Without that node in the formatted file, like a key that moved into a short form, they go to
+/// the closest node containing it.
+fn keep_comments(
+    file: &str,
+    documents: &[Yaml],
+    formatted: &str,
+    formatted_documents: &[Yaml],
+) -> String {
+    let comments = comments(file, documents);
+
+    if comments.is_empty() {
+        return formatted.to_string();
+    }
+
+    let nodes = node_lines(file, documents);
+    let formatted_nodes = node_lines(formatted, formatted_documents);
+
+    let formatted_line = |path: &[Segment]| {
+        (1..=path.len()).rev().find_map(|length| {
+            formatted_nodes
+                .iter()
+                .find(|(_, formatted)| formatted[..] == path[..length])
+                .map(|(line, _)| *line)
+        })
+    };
+
+    let mut before: Vec<(usize, &str)> = vec![];
+    let mut after: Vec<(usize, &str)> = vec![];
+    let mut end = vec![];
+
+    for comment in &comments {
+        let on_line = nodes
+            .iter()
+            .find(|(line, _)| *line == comment.line && !comment.own_line);
+        let next = || nodes.iter().find(|(line, _)| *line > comment.line);
+
+        match on_line.or_else(next) {
+            Some((_, path)) => match formatted_line(path) {
+                Some(line) if on_line.is_some() => after.push((line, &comment.text)),
+                Some(line) => before.push((line, &comment.text)),
+                None => end.push(comment.text.as_str()),
+            },
+            None => end.push(comment.text.as_str()),
+        }
+    }
+
+    let mut out = String::new();
+
+    for (index, line) in formatted.lines().enumerate() {
+        let indent = &line[..line.len() - line.trim_start().len()];
+
+        for (_, text) in before.iter().filter(|(before, _)| *before == index + 1) {
+            out += &format!("{}{}\n", indent, text);
+        }
+
+        out += line;
+
+        for (_, text) in after.iter().filter(|(after, _)| *after == index + 1) {
+            out += &format!(" {}", text);
+        }
+
+        out.push('\n');
+    }
+
+    for text in end {
+        out += &format!("{}\n", text);
+    }
+
+    out
+}
+
+/// The Pilotfile in its canonical form, the documents of the profiles follow the first one.
+/// The comments are kept with the nodes they belong to.
+pub(crate) fn format(file: &str) -> Result<String, String> {
+    let original = pilotfile::parse(file)?;
+    let documents: Vec<_> = original
+        .iter()
+        .enumerate()
+        .map(|(index, document)| format_document(document, index > 0))
+        .collect();
+
+    let formatted = documents
+        .iter()
+        .map(|document| {
+            let mut out = String::new();
+            emit_entries(document, 0, false, &mut out);
+            out
+        })
+        .collect::<Vec<_>>()
+        .join("---\n");
+    let formatted = keep_comments(file, &original, &formatted, &documents);
+
+    if YamlLoader::load_from_str(&formatted).ok() != Some(documents) {
+        return Err("pilot fmt can't format the Pilotfile without changing it".to_string());
+    }
+
+    Ok(formatted)
+}
+
+/// `pilot fmt [--check]`, formats the Pilotfile or with `--check` fails if it isn't formatted.
+pub fn cli(args: &[String], pilotfile_dir: &Path) -> Result<(), Error> {
+    let check = match args {
+        [] => false,
+        [check] if check == "--check" => true,
+        _ => return Err(Error(USAGE.to_string())),
+    };
+
+    let path = pilotfile_dir.join(pilotfile::FILE_NAME);
+    let file = read_to_string(&path)
+        .ok()
+        .or_error("pilot fmt only formats a Pilotfile.yaml")?;
+
+    let formatted = format(&file)?;
+
+    if formatted == file {
+        return Ok(());
+    }
+
+    if check {
+        return Err(Error(
+            "Pilotfile.yaml is not formatted, pilot fmt formats it".to_string(),
+        ));
+    }
+
+    write(&path, formatted).or_error("Could not write Pilotfile.yaml")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_steps_and_task_keys() {
+        let file = "build:
+  - {id: compile, shell: cargo build}
+  - on_failure: [{shell: echo failed}]
+  - description: build it
+  - task: {name: lint}
+";
+
+        assert_eq!(
+            format(file).unwrap(),
+            "build:
+  - description: build it
+  - shell: cargo build
+    id: compile
+  - task: lint
+  - on_failure:
+    - shell: echo failed
+"
+        );
+    }
+
+    #[test]
+    fn format_config_before_tasks() {
+        let file = "test: [shell: cargo test]\ntimestamp: true\n";
+
+        assert_eq!(
+            format(file).unwrap(),
+            "timestamp: true\n\ntest:\n  - shell: cargo test\n"
+        );
+    }
+
+    #[test]
+    fn format_scalars() {
+        let file = "run:\n  - shell: 'echo a: b'\n  - shell: \"echo one\\necho two\\n\"\n  - ports: [8080, 8081]\n";
+
+        assert_eq!(
+            format(file).unwrap(),
+            "run:\n  - ports: [8080, 8081]\n  - shell: \"echo a: b\"\n  - shell: |\n      echo one\n      echo two\n"
+        );
+    }
+
+    #[test]
+    fn format_is_stable() {
+        let file = "build:\n  - description: build it\n  - shell: cargo build\n";
+
+        assert_eq!(format(file).unwrap(), file);
+    }
+
+    #[test]
+    fn format_keeps_comments() {
+        let file = "# the tasks
+build: # compiles everything
+  # the command
+  - {id: compile, shell: cargo build}   # the debug build
+  - description: build it
+  - shell: |
+      # not a comment
+      echo \"# neither\"
+# the end
+";
+
+        assert_eq!(
+            format(file).unwrap(),
+            "# the tasks
+build: # compiles everything
+  - description: build it
+  # the command
+  - shell: cargo build # the debug build
+    id: compile
+  - shell: |
+      # not a comment
+      echo \"# neither\"
+# the end
+"
+        );
+    }
+}
@@ -0,0 +1,895 @@
+//! The history of the runs in `.pilot/runs`, with the output of their tasks.
+
+use std::{
+    collections::BTreeMap,
+    env,
+    fs::{create_dir, create_dir_all, read_dir, read_to_string, remove_dir_all, write, File},
+    io::{BufRead, BufReader, ErrorKind, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    thread,
+    time::Duration,
+};
+
+use yaml_rust::{Yaml, YamlLoader};
+
+use crate::{
+    clock, color_default, diagnostics,
+    pilotfile::FILE_NAME,
+    procfile, runtime,
+    shutdown::{self, Stop},
+    state::{self, json_string},
+    supervise, user_config, Error, OrError, Pilotfile,
+};
+
+const RUNS_DIR: &str = "runs";
+const RUN_FILE: &str = "run.json";
+const OUTPUT_FILE: &str = "output.log";
+/// what pilot prints after its terminal hung up, with `--detach-on-hup`
+const DETACHED_FILE: &str = "detached.log";
+const TASKS_DIR: &str = "tasks";
+/// the colors of the task names as `<SGR parameters> <task>` lines, for `pilot logs -f`
+const COLORS_FILE: &str = "colors";
+/// the pid of pilot while the run is going on, for `pilot cancel`
+const PID_FILE: &str = "pilot.pid";
+/// the processes of the tasks as `<pid> <stop signal> <grace period> <task>` lines
+const PROCESSES_FILE: &str = "processes";
+/// the tasks `pilot cancel` stopped, so their run doesn't report them as failures to look into
+const CANCELLED_FILE: &str = "cancelled";
+/// the oldest runs are removed when a new one starts
+const KEPT_RUNS: usize = 20;
+/// the estimated duration of a task is the average of its last runs
+const ESTIMATED_RUNS: usize = 5;
+
+const LOGS_USAGE: &str = "Usage: pilot logs <run-id|last> [task] or pilot logs <task> -f";
+/// how often `pilot logs -f` looks for new lines
+const FOLLOW_INTERVAL: Duration = Duration::from_millis(200);
+const CANCEL_USAGE: &str = "Usage: pilot cancel <task>";
+const COMPARE_USAGE: &str = "Usage: pilot compare [<run-id|last> <run-id|last>]";
+const REPRO_USAGE: &str = "Usage: pilot repro <run-id|last>";
+/// secrets only get a fingerprint in the history, like `hidden:1c9d0e2a`
+const HIDDEN_PREFIX: &str = "hidden:";
+
+static RUN: Mutex<Option<Run>> = Mutex::new(None);
+
+/// The run that is going on, its output is added to the logs as it is printed.
+struct Run {
+    id: String,
+    pilotfile_dir: PathBuf,
+    dir: PathBuf,
+    started: String,
+    output: File,
+    task_logs: Vec<(String, File)>,
+    /// how long the tasks that succeeded took
+    durations: Vec<(String, Duration)>,
+    /// the processes of the tasks that are running, with the name of their task
+    processes: Vec<(u32, Stop, String)>,
+    command_line: Vec<String>,
+}
+
+/// `.pilot/runs`, or the directory of the project in the `log_dir` of the user.
+fn runs_dir(pilotfile_dir: &Path) -> PathBuf {
+    match &user_config::get().log_dir {
+        Some(log_dir) => log_dir.join(state::file_name(&pilotfile_dir.to_string_lossy())),
+        None => state::state_dir(pilotfile_dir).join(RUNS_DIR),
+    }
+}
+
+fn log_file_name(task: &str) -> String {
+    state::file_name(task) + ".log"
+}
+
+/// The ids of the runs in the history, the oldest first.
+fn run_ids(pilotfile_dir: &Path) -> Vec<String> {
+    let Ok(entries) = read_dir(runs_dir(pilotfile_dir)) else {
+        return vec![];
+    };
+
+    let mut ids: Vec<_> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_dir())
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .collect();
+    ids.sort();
+    ids
+}
+
+/// Starts recording a new run, making room for it by removing the oldest ones. A copy of the
+/// Pilotfile is kept with the run, so `pilot repro` runs the same commands after it changed.
+pub(crate) fn start(pilotfile: &Pilotfile, command_line: &[String]) -> Result<(), Error> {
+    let pilotfile_dir = pilotfile.dir();
+    let runs_dir = runs_dir(pilotfile_dir);
+    let error = format!("Could not create the run history in {}", runs_dir.display());
+    create_dir_all(&runs_dir).or_error(&error)?;
+
+    let old_runs = run_ids(pilotfile_dir);
+
+    for id in &old_runs[..old_runs.len().saturating_sub(KEPT_RUNS - 1)] {
+        let _ = remove_dir_all(runs_dir.join(id));
+    }
+
+    let timestamp = clock::now(clock::ID_FORMAT);
+    let mut id = timestamp.clone();
+
+    // runs started in the same second get a counter
+    for count in 2.. {
+        match create_dir(runs_dir.join(&id)) {
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => {
+                id = format!("{}-{}", timestamp, count)
+            }
+            result => {
+                result.or_error(&error)?;
+                break;
+            }
+        }
+    }
+
+    let dir = runs_dir.join(&id);
+    create_dir(dir.join(TASKS_DIR)).or_error(&error)?;
+    let output = File::create(dir.join(OUTPUT_FILE)).or_error(&error)?;
+    write(dir.join(PID_FILE), std::process::id().to_string()).or_error(&error)?;
+
+    let file_name = match pilotfile.is_procfile() {
+        true => procfile::FILE_NAME,
+        false => FILE_NAME,
+    };
+    write(dir.join(file_name), pilotfile.source()).or_error(&error)?;
+
+    *supervise::lock(&RUN) = Some(Run {
+        id,
+        pilotfile_dir: pilotfile_dir.to_path_buf(),
+        dir,
+        started: clock::now_rfc3339(),
+        output,
+        task_logs: vec![],
+        durations: vec![],
+        processes: vec![],
+        command_line: mask_command_line(command_line),
+    });
+
+    Ok(())
+}
+
+/// The id of the run that is going on, for `pilot logs <run-id>`.
+pub(crate) fn run_id() -> Option<String> {
+    supervise::lock(&RUN).as_ref().map(|run| run.id.clone())
+}
+
+/// The file of the run that is going on for the output after a hangup of the terminal.
+pub(crate) fn detached_log() -> Option<PathBuf> {
+    supervise::lock(&RUN)
+        .as_ref()
+        .map(|run| run.dir.join(DETACHED_FILE))
+}
+
+/// The log of the whole run that is going on and the logs of the tasks that printed something.
+pub(crate) fn logs() -> Option<(PathBuf, Vec<(String, PathBuf)>)> {
+    let run = supervise::lock(&RUN);
+    let run = run.as_ref()?;
+
+    let task_logs = run
+        .task_logs
+        .iter()
+        .map(|(task, _)| {
+            (
+                task.clone(),
+                run.dir.join(TASKS_DIR).join(log_file_name(task)),
+            )
+        })
+        .collect();
+
+    Some((run.dir.join(OUTPUT_FILE), task_logs))
+}
+
+/// Adds a line of output to the logs of the run, if one was started. The color of the task name
+/// is kept with the first line of a task.
+pub(crate) fn log(task: &str, color: &str, line: &str) {
+    let mut run = supervise::lock(&RUN);
+    let Some(run) = run.as_mut() else {
+        return;
+    };
+
+    // the history is best effort, a full disk shouldn't fail the tasks
+    let _ = writeln!(run.output, "{}: {}", task, line);
+
+    let index = match run.task_logs.iter().position(|(name, _)| name == task) {
+        Some(index) => index,
+        None => {
+            let path = run.dir.join(TASKS_DIR).join(log_file_name(task));
+
+            let Ok(file) = File::options().create(true).append(true).open(path) else {
+                return;
+            };
+
+            let sgr = color.trim_start_matches("\x1b[").trim_end_matches('m');
+            let _ = File::options()
+                .create(true)
+                .append(true)
+                .open(run.dir.join(COLORS_FILE))
+                .and_then(|mut colors| writeln!(colors, "{} {}", sgr, task));
+
+            run.task_logs.push((task.to_string(), file));
+            run.task_logs.len() - 1
+        }
+    };
+
+    let _ = writeln!(run.task_logs[index].1, "{}", line);
+}
+
+/// Records a process of a task, so `pilot cancel` can stop it.
+pub(crate) fn add_process(pid: u32, task: &str, stop: Stop) {
+    let mut run = supervise::lock(&RUN);
+    let Some(run) = run.as_mut() else {
+        return;
+    };
+
+    run.processes.push((pid, stop, task.to_string()));
+    write_processes(run);
+}
+
+pub(crate) fn remove_process(pid: u32) {
+    let mut run = supervise::lock(&RUN);
+    let Some(run) = run.as_mut() else {
+        return;
+    };
+
+    run.processes.retain(|(process, _, _)| *process != pid);
+    write_processes(run);
+}
+
+fn write_processes(run: &Run) {
+    let processes: String = run
+        .processes
+        .iter()
+        .map(|(pid, stop, task)| {
+            format!(
+                "{} {} {} {}\n",
+                pid,
+                stop.signal,
+                clock::seconds(stop.grace_period),
+                task
+            )
+        })
+        .collect();
+
+    // without the file the tasks can't be cancelled, but they still run
+    let _ = write(run.dir.join(PROCESSES_FILE), processes);
+}
+
+/// Whether `pilot cancel` stopped the task, it only counts once for every cancel.
+pub(crate) fn take_cancelled(task: &str) -> bool {
+    let Some(path) = supervise::lock(&RUN)
+        .as_ref()
+        .map(|run| run.dir.join(CANCELLED_FILE))
+    else {
+        return false;
+    };
+
+    let cancelled = read_to_string(&path).unwrap_or_default();
+    let mut tasks: Vec<_> = cancelled.lines().collect();
+    let Some(index) = tasks.iter().position(|name| *name == task) else {
+        return false;
+    };
+
+    tasks.remove(index);
+    let _ = write(
+        &path,
+        tasks
+            .iter()
+            .map(|task| format!("{}\n", task))
+            .collect::<String>(),
+    );
+    true
+}
+
+/// Remembers how long a task took, for the estimates of the next runs.
+pub(crate) fn record_duration(task: &str, duration: Duration) {
+    let mut run = supervise::lock(&RUN);
+    let Some(run) = run.as_mut() else {
+        return;
+    };
+
+    // a task that ran more than once counts with its last run
+    run.durations.retain(|(name, _)| name != task);
+    run.durations.push((task.to_string(), duration));
+}
+
+/// The average duration of a task in the last runs it succeeded in, with the number of runs.
+pub(crate) fn estimate(task: &str) -> Option<(Duration, usize)> {
+    let pilotfile_dir = supervise::lock(&RUN).as_ref()?.pilotfile_dir.clone();
+    let runs_dir = runs_dir(&pilotfile_dir);
+
+    let durations: Vec<_> = run_ids(&pilotfile_dir)
+        .iter()
+        .rev()
+        .filter_map(|id| read_to_string(runs_dir.join(id).join(RUN_FILE)).ok())
+        .filter_map(|file| YamlLoader::load_from_str(&file).ok()?.into_iter().next())
+        .filter_map(|doc| doc["durations"][task].as_f64())
+        .take(ESTIMATED_RUNS)
+        .collect();
+
+    if durations.is_empty() {
+        return None;
+    }
+
+    let average = durations.iter().sum::<f64>() / durations.len() as f64;
+    Some((Duration::from_secs_f64(average), durations.len()))
+}
+
+/// The environment of pilot, with a fingerprint instead of the secrets so the history shows that
+/// they changed without keeping them. The same variables and url passwords are secrets as in the
+/// diagnostics.
+fn env_json() -> String {
+    let mut vars: Vec<_> = env::vars_os()
+        .map(|(name, value)| {
+            let name = name.to_string_lossy().to_string();
+            let value = value.to_string_lossy().to_string();
+            let value = diagnostics::mask_with(&name, &value, hide);
+
+            format!("    {}: {}", json_string(&name), json_string(&value))
+        })
+        .collect();
+    vars.sort();
+
+    format!("\"env\": {{\n{}\n  }}", vars.join(",\n"))
+}
+
+/// Hides a secret behind its fingerprint, which still tells whether it changed between runs.
+fn hide(secret: &str) -> String {
+    format!("{}{:08x}", HIDDEN_PREFIX, fingerprint(secret))
+}
+
+/// The command line with the values of `-e KEY=VALUE` hidden like the environment of the run.
+fn mask_command_line(command_line: &[String]) -> Vec<String> {
+    let mut args = command_line.iter();
+    let mut masked = vec![];
+
+    while let Some(arg) = args.next() {
+        masked.push(arg.clone());
+
+        match arg.as_str() {
+            // the command of `pilot exec`
+            "--" => masked.extend(args.by_ref().cloned()),
+            "-e" | "--env" => masked.extend(args.next().map(|env| match env.split_once('=') {
+                Some((key, value)) => {
+                    format!("{}={}", key, diagnostics::mask_with(key, value, hide))
+                }
+                None => env.clone(),
+            })),
+            _ => {}
+        }
+    }
+
+    masked
+}
+
+/// The command line of run `id` to run it again. The values of `-e` the history hides are taken
+/// from the environment, a run can't be reproduced without them.
+fn unmask_command_line(id: &str, command_line: &[String]) -> Result<Vec<String>, Error> {
+    let mut args = command_line.iter();
+    let mut unmasked = vec![];
+
+    while let Some(arg) = args.next() {
+        unmasked.push(arg.clone());
+
+        match arg.as_str() {
+            "--" => unmasked.extend(args.by_ref().cloned()),
+            "-e" | "--env" => {
+                let Some(env) = args.next() else {
+                    continue;
+                };
+
+                // masking depends only on the name and the shape of the value, so a value that
+                // would be masked again was masked when it was recorded
+                let unmasked_env = match env.split_once('=') {
+                    Some((key, value)) if diagnostics::mask_with(key, value, hide) != value => {
+                        let value = env::var(key).ok().or_error(&format!(
+                            "Run {} set {} with -e, which the history hides, set {} in the \
+environment to reproduce it",
+                            id, key, key
+                        ))?;
+                        format!("{}={}", key, value)
+                    }
+                    _ => env.clone(),
+                };
+
+                unmasked.push(unmasked_env);
+            }
+            _ => {}
+        }
+    }
+
+    Ok(unmasked)
+}
+
+/// FNV-1a, which unlike the hasher of the standard library stays the same between versions.
+fn fingerprint(value: &str) -> u32 {
+    value.bytes().fold(0x811c9dc5, |hash, byte| {
+        (hash ^ byte as u32).wrapping_mul(0x01000193)
+    })
+}
+
+/// Stores the outcome of the top-level tasks with the run.
+pub(crate) fn finish(results: &[(String, bool)]) -> Result<(), Error> {
+    let Some(run) = supervise::lock(&RUN).take() else {
+        return Ok(());
+    };
+
+    // the run is over, there is nothing to cancel anymore
+    for file in [PID_FILE, PROCESSES_FILE, CANCELLED_FILE] {
+        let _ = std::fs::remove_file(run.dir.join(file));
+    }
+
+    let durations: Vec<_> = run
+        .durations
+        .iter()
+        .map(|(task, duration)| format!("    {}: {}", json_string(task), clock::seconds(*duration)))
+        .collect();
+
+    let command_line: Vec<_> = run
+        .command_line
+        .iter()
+        .map(|arg| json_string(arg))
+        .collect();
+
+    let json = format!(
+        "{{\n  \"started\": {},\n  \"command_line\": [{}],\n  {},\n  \"durations\": \
+{{\n{}\n  }},\n  {}\n}}\n",
+        json_string(&run.started),
+        command_line.join(", "),
+        state::tasks_json(results),
+        durations.join(",\n"),
+        env_json()
+    );
+    write(run.dir.join(RUN_FILE), json).or_error("Could not write the run history")
+}
+
+/// `pilot runs`, lists the runs of the history, the latest first.
+pub fn cli_runs(pilotfile_dir: &Path) {
+    let ids = run_ids(pilotfile_dir);
+
+    if ids.is_empty() {
+        println!("No runs yet");
+        return;
+    }
+
+    for id in ids.iter().rev() {
+        let doc = read_to_string(runs_dir(pilotfile_dir).join(id).join(RUN_FILE))
+            .ok()
+            .and_then(|file| YamlLoader::load_from_str(&file).ok())
+            .and_then(|docs| docs.into_iter().next());
+
+        // runs without an outcome are still going on or pilot was killed
+        let Some(tasks) = doc.as_ref().and_then(|doc| doc["tasks"].as_vec()) else {
+            println!("{}  unfinished", id);
+            continue;
+        };
+
+        let success = tasks
+            .iter()
+            .all(|task| task["status"].as_str() == Some("success"));
+        let names: Vec<_> = tasks
+            .iter()
+            .filter_map(|task| task["name"].as_str())
+            .collect();
+
+        println!(
+            "{}  {:<10} {}",
+            id,
+            if success { "finished" } else { "failed" },
+            names.join(", ")
+        );
+    }
+}
+
+/// The run with an id or the last one, an error if the history doesn't have it.
+fn find_run(pilotfile_dir: &Path, id: &str) -> Result<String, Error> {
+    let ids = run_ids(pilotfile_dir);
+    let found = if id == "last" {
+        ids.last()
+    } else {
+        ids.iter().find(|run| *run == id)
+    };
+
+    found.cloned().ok_or_else(|| {
+        Error(format!(
+            "No run {} in the history, pilot runs lists them",
+            id
+        ))
+    })
+}
+
+/// `pilot logs <run-id|last> [task]`, prints the output of a run or of one of its tasks.
+/// `pilot logs <task> -f` follows the output of a task in the last run instead.
+pub fn cli_logs(args: &[String], pilotfile_dir: &Path) -> Result<(), Error> {
+    let follow = |arg: &String| arg == "-f" || arg == "--follow";
+
+    if args.iter().any(follow) {
+        let args: Vec<_> = args.iter().filter(|arg| !follow(arg)).collect();
+        let [task] = args[..] else {
+            return Err(Error(LOGS_USAGE.to_string()));
+        };
+
+        return follow_log(pilotfile_dir, task);
+    }
+
+    let id = args.first().or_error(LOGS_USAGE)?;
+    let found = find_run(pilotfile_dir, id)?;
+    let dir = runs_dir(pilotfile_dir).join(&found);
+
+    match args.get(1) {
+        Some(task) => match read_to_string(dir.join(TASKS_DIR).join(log_file_name(task))) {
+            Ok(log) => print!("{}", log),
+            Err(_) => {
+                return Err(Error(format!(
+                    "Task {} printed nothing in run {}",
+                    task, found
+                )))
+            }
+        },
+        None => print!(
+            "{}",
+            read_to_string(dir.join(OUTPUT_FILE)).unwrap_or_default()
+        ),
+    }
+
+    Ok(())
+}
+
+/// A finished run of the history with what `pilot repro` needs to run it again.
+pub struct RecordedRun {
+    pub id: String,
+    /// the directory with the copy of the Pilotfile the run used
+    pub files: PathBuf,
+    /// the command line as the history keeps it, with its secrets hidden
+    pub command_line: Vec<String>,
+    /// the command line to run it again
+    pub args: Vec<String>,
+    /// the tasks the run started, after the default tasks or `--rerun-failed` picked them
+    pub tasks: Vec<String>,
+}
+
+/// The run of `pilot repro <run-id|last>`, an error if it can't be run again.
+pub fn recorded_run(args: &[String], pilotfile_dir: &Path) -> Result<RecordedRun, Error> {
+    let [id] = args else {
+        return Err(Error(REPRO_USAGE.to_string()));
+    };
+
+    let id = find_run(pilotfile_dir, id)?;
+    let report = load_report(pilotfile_dir, &id)?;
+    let files = runs_dir(pilotfile_dir).join(&id);
+
+    // runs of older versions of pilot didn't keep their command line and Pilotfile
+    let command_line = report["command_line"]
+        .as_vec()
+        .filter(|_| files.join(FILE_NAME).exists() || files.join(procfile::FILE_NAME).exists());
+    let command_line = command_line.or_error(&format!(
+        "Run {} has no copy of its Pilotfile to run it again",
+        id
+    ))?;
+
+    let command_line: Vec<_> = command_line
+        .iter()
+        .filter_map(|arg| Some(arg.as_str()?.to_string()))
+        .collect();
+
+    Ok(RecordedRun {
+        args: unmask_command_line(&id, &command_line)?,
+        command_line,
+        tasks: report["tasks"]
+            .as_vec()
+            .into_iter()
+            .flatten()
+            .filter_map(|task| Some(task["name"].as_str()?.to_string()))
+            .collect(),
+        id,
+        files,
+    })
+}
+
+/// Whether the pilot of the run in `dir` is still going.
+fn is_active(dir: &Path) -> bool {
+    read_to_string(dir.join(PID_FILE))
+        .ok()
+        .and_then(|pid| pid.trim().parse().ok())
+        .is_some_and(is_running)
+}
+
+/// Prints the output of a task in the last run with its colored name, like pilot printed it.
+/// While the run is going on, it waits for more lines until the run is over.
+fn follow_log(pilotfile_dir: &Path, task: &str) -> Result<(), Error> {
+    let id = find_run(pilotfile_dir, "last")?;
+    let dir = runs_dir(pilotfile_dir).join(&id);
+    let path = dir.join(TASKS_DIR).join(log_file_name(task));
+
+    // the color is written before the first line of the task, so it is known once there is one
+    let prefix = || {
+        let colors = read_to_string(dir.join(COLORS_FILE)).unwrap_or_default();
+        let sgr = colors
+            .lines()
+            .filter_map(|line| line.split_once(' '))
+            .find(|(_, name)| *name == task)
+            .map(|(sgr, _)| sgr);
+
+        match sgr {
+            Some(sgr) if color_default() => format!("\x1b[{}m{}:\x1b[0m ", sgr, task),
+            _ => format!("{}: ", task),
+        }
+    };
+
+    let mut log = None;
+    let mut line = String::new();
+    let mut task_prefix = None;
+
+    loop {
+        // checked before reading, so the lines written before the run ended are all printed
+        let active = is_active(&dir);
+
+        if log.is_none() {
+            log = File::open(&path).ok().map(BufReader::new);
+        }
+
+        if let Some(log) = &mut log {
+            // a line without its end yet is finished by a later read
+            while log.read_line(&mut line).is_ok_and(|read| read > 0) {
+                if !line.ends_with('\n') && active {
+                    break;
+                }
+
+                let task_prefix = task_prefix.get_or_insert_with(prefix);
+                println!("{}{}", task_prefix, line.trim_end_matches('\n'));
+                line.clear();
+            }
+        }
+
+        if !active {
+            break;
+        }
+
+        thread::sleep(FOLLOW_INTERVAL);
+    }
+
+    match log {
+        Some(_) => Ok(()),
+        None => Err(Error(format!(
+            "Task {} printed nothing in run {}",
+            task, id
+        ))),
+    }
+}
+
+/// The report of a finished run, an error if pilot didn't finish it.
+fn load_report(pilotfile_dir: &Path, id: &str) -> Result<Yaml, Error> {
+    read_to_string(runs_dir(pilotfile_dir).join(id).join(RUN_FILE))
+        .ok()
+        .and_then(|file| YamlLoader::load_from_str(&file).ok())
+        .and_then(|docs| docs.into_iter().next())
+        .or_error(&format!("Run {} is unfinished, it has no report", id))
+}
+
+fn string_map(yaml: &Yaml) -> BTreeMap<&str, &Yaml> {
+    yaml.as_hash()
+        .into_iter()
+        .flatten()
+        .filter_map(|(key, value)| Some((key.as_str()?, value)))
+        .collect()
+}
+
+fn failed(report: &Yaml, task: &str) -> bool {
+    report["tasks"].as_vec().into_iter().flatten().any(|entry| {
+        entry["name"].as_str() == Some(task) && entry["status"].as_str() == Some("failed")
+    })
+}
+
+/// `pilot compare [<run-id> <run-id>]`, shows what changed from one run to another, the last two
+/// if none are given: which tasks got slower or faster, which failed and the environment.
+pub fn cli_compare(args: &[String], pilotfile_dir: &Path) -> Result<(), Error> {
+    let (old_id, new_id) = match args {
+        [] => {
+            let ids = run_ids(pilotfile_dir);
+
+            if ids.len() < 2 {
+                return Err(Error(
+                    "The history needs two runs to compare them".to_string(),
+                ));
+            }
+
+            (ids[ids.len() - 2].clone(), ids[ids.len() - 1].clone())
+        }
+        [old, new] => (find_run(pilotfile_dir, old)?, find_run(pilotfile_dir, new)?),
+        _ => return Err(Error(COMPARE_USAGE.to_string())),
+    };
+
+    let (old, new) = (
+        load_report(pilotfile_dir, &old_id)?,
+        load_report(pilotfile_dir, &new_id)?,
+    );
+    println!("compare {} with {}", old_id, new_id);
+
+    let old_durations = string_map(&old["durations"]);
+    let mut durations: Vec<_> = string_map(&new["durations"])
+        .into_iter()
+        .filter_map(|(task, new)| {
+            let (old, new) = (old_durations.get(task)?.as_f64()?, new.as_f64()?);
+            (old > 0.0).then(|| (task, old, new, (new / old - 1.0) * 100.0))
+        })
+        .collect();
+    // the tasks that got slower the most first
+    durations.sort_by(|a, b| b.3.total_cmp(&a.3));
+
+    for (task, old, new, change) in durations {
+        println!(
+            "{:>9} {} took {} instead of {}",
+            format!("{:+.1}%", change),
+            task,
+            clock::human(Duration::from_secs_f64(new)),
+            clock::human(Duration::from_secs_f64(old))
+        );
+    }
+
+    let tasks = |report: &Yaml| -> Vec<String> {
+        report["tasks"]
+            .as_vec()
+            .into_iter()
+            .flatten()
+            .filter_map(|task| task["name"].as_str().map(str::to_string))
+            .collect()
+    };
+
+    let newly_failed: Vec<_> = tasks(&new)
+        .into_iter()
+        .filter(|task| failed(&new, task) && !failed(&old, task))
+        .collect();
+    let fixed: Vec<_> = tasks(&old)
+        .into_iter()
+        .filter(|task| failed(&old, task) && tasks(&new).contains(task) && !failed(&new, task))
+        .collect();
+
+    if !newly_failed.is_empty() {
+        println!("newly failed: {}", newly_failed.join(", "));
+    }
+
+    if !fixed.is_empty() {
+        println!("fixed: {}", fixed.join(", "));
+    }
+
+    let (old_env, new_env) = (string_map(&old["env"]), string_map(&new["env"]));
+    let mut names: Vec<_> = old_env.keys().chain(new_env.keys()).collect();
+    names.sort();
+    names.dedup();
+
+    let changes: Vec<_> = names
+        .into_iter()
+        .filter_map(|name| {
+            let old = old_env.get(name).and_then(|value| value.as_str());
+            let new = new_env.get(name).and_then(|value| value.as_str());
+            let hidden =
+                |value: Option<&str>| value.is_some_and(|value| value.starts_with(HIDDEN_PREFIX));
+            let shown = |value: Option<&str>| value.unwrap_or("(unset)").to_string();
+
+            match old == new {
+                true => None,
+                false if hidden(old) && hidden(new) => Some(format!("  {}: changed", name)),
+                false => Some(format!("  {}: {} -> {}", name, shown(old), shown(new))),
+            }
+        })
+        .collect();
+
+    if !changes.is_empty() {
+        println!("changed environment:\n{}", changes.join("\n"));
+    }
+
+    Ok(())
+}
+
+/// Whether the pilot of a run is still going.
+fn is_running(pid: u32) -> bool {
+    #[cfg(unix)]
+    unsafe {
+        // EPERM means the process exists but belongs to someone else
+        libc::kill(pid as libc::pid_t, 0) == 0
+            || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+    }
+
+    #[cfg(windows)]
+    unsafe {
+        use std::ffi::c_void;
+
+        // kernel32 is linked by std anyway
+        extern "system" {
+            fn OpenProcess(access: u32, inherit: i32, pid: u32) -> *mut c_void;
+            fn GetExitCodeProcess(process: *mut c_void, code: *mut u32) -> i32;
+            fn CloseHandle(handle: *mut c_void) -> i32;
+        }
+
+        const PROCESS_QUERY_LIMITED_INFORMATION: u32 = 0x1000;
+        const ERROR_ACCESS_DENIED: i32 = 5;
+        const STILL_ACTIVE: u32 = 259;
+
+        let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
+
+        // like EPERM, the process exists but belongs to someone else
+        if process.is_null() {
+            return std::io::Error::last_os_error().raw_os_error() == Some(ERROR_ACCESS_DENIED);
+        }
+
+        // a process that exited stays around while the handles to it are open
+        let mut code = 0;
+        let running = GetExitCodeProcess(process, &mut code) != 0 && code == STILL_ACTIVE;
+        CloseHandle(process);
+        running
+    }
+}
+
+/// `pilot cancel <task>`, stops the processes of a task in the runs that are going on, the other
+/// tasks of the runs keep running.
+pub fn cli_cancel(args: &[String], pilotfile_dir: &Path) -> Result<(), Error> {
+    let [task] = args else {
+        return Err(Error(CANCEL_USAGE.to_string()));
+    };
+
+    let runs_dir = runs_dir(pilotfile_dir);
+    let active: Vec<_> = run_ids(pilotfile_dir)
+        .into_iter()
+        .map(|id| runs_dir.join(id))
+        .filter(|dir| is_active(dir))
+        .collect();
+
+    if active.is_empty() {
+        return Err(Error("No run is going on".to_string()));
+    }
+
+    let mut cancelled = false;
+
+    for dir in active {
+        let processes = read_to_string(dir.join(PROCESSES_FILE)).unwrap_or_default();
+        let stops: Vec<_> = processes
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.splitn(4, ' ');
+                let (pid, signal, grace_period) = (fields.next()?, fields.next()?, fields.next()?);
+
+                let stop = Stop {
+                    signal: shutdown::STOP_SIGNALS
+                        .iter()
+                        .find(|known| **known == signal)?,
+                    grace_period: Duration::from_secs_f64(grace_period.parse().ok()?),
+                };
+
+                (fields.next()? == task).then_some((pid.parse().ok()?, stop))
+            })
+            .collect();
+
+        if stops.is_empty() {
+            continue;
+        }
+
+        // the run learns about the cancel before the processes stop
+        let marked = File::options()
+            .create(true)
+            .append(true)
+            .open(dir.join(CANCELLED_FILE))
+            .and_then(|mut file| stops.iter().try_for_each(|_| writeln!(file, "{}", task)));
+        marked.or_error(&format!("Could not cancel task {}", task))?;
+
+        // the processes get their grace period at the same time
+        let stopping: Vec<_> = stops
+            .into_iter()
+            .map(|(pid, stop)| runtime::spawn(shutdown::stop(pid, stop)))
+            .collect();
+        runtime::block_on(async {
+            for stop in stopping {
+                let _ = stop.await;
+            }
+        });
+
+        cancelled = true;
+    }
+
+    if !cancelled {
+        return Err(Error(format!("Task {} isn't running", task)));
+    }
+
+    println!("cancelled {}", task);
+    Ok(())
+}
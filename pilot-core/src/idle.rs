@@ -0,0 +1,109 @@
+//! The `idle_timeout` of a task, which stops processes that printed nothing for too long.
+
+use std::{
+    io,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use tokio::{
+    io::{AsyncRead, ReadBuf},
+    sync::Notify,
+};
+
+use crate::{clock, runtime, shutdown};
+
+/// Watches the output of a single process.
+pub(crate) struct IdleTimeout {
+    timeout: Duration,
+    /// when the process printed something last, and whether it exited
+    state: Mutex<(Instant, bool)>,
+    finished: Notify,
+}
+
+impl IdleTimeout {
+    fn new(timeout: Duration) -> Arc<Self> {
+        Arc::new(IdleTimeout {
+            timeout,
+            state: Mutex::new((Instant::now(), false)),
+            finished: Notify::new(),
+        })
+    }
+
+    fn touch(&self) {
+        self.state.lock().unwrap().0 = Instant::now();
+    }
+
+    /// Stops the process once it printed nothing for the timeout, until [`IdleTimeout::finish`].
+    pub(crate) fn watch(timeout: Duration, pid: u32, task_name: &str) -> Arc<Self> {
+        let idle = IdleTimeout::new(timeout);
+        let watch = idle.clone();
+        let task_name = task_name.to_string();
+
+        runtime::spawn(async move {
+            loop {
+                let (last_output, finished) = *watch.state.lock().unwrap();
+
+                if finished {
+                    return;
+                }
+
+                let idle_for = last_output.elapsed();
+
+                if idle_for >= watch.timeout {
+                    eprintln!(
+                        "Task {} printed nothing for {}, stopping it",
+                        task_name,
+                        clock::human(watch.timeout)
+                    );
+                    shutdown::terminate(pid);
+                    return;
+                }
+
+                // the output resets the deadline, so sleep until the latest one
+                tokio::select! {
+                    () = watch.finished.notified() => {}
+                    () = tokio::time::sleep(watch.timeout - idle_for) => {}
+                }
+            }
+        });
+
+        idle
+    }
+
+    /// Ends the watch after the process exited.
+    pub(crate) fn finish(&self) {
+        self.state.lock().unwrap().1 = true;
+        self.finished.notify_one();
+    }
+}
+
+/// Reads the output of a process, every read counts as activity even without a full line, like
+/// a progress bar.
+pub(crate) struct Watched<R> {
+    pub(crate) inner: R,
+    pub(crate) idle: Option<Arc<IdleTimeout>>,
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for Watched<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        context: &mut Context<'_>,
+        buffer: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let filled = buffer.filled().len();
+        let read = Pin::new(&mut self.inner).poll_read(context, buffer);
+
+        if let Some(idle) = self
+            .idle
+            .as_ref()
+            .filter(|_| buffer.filled().len() > filled)
+        {
+            idle.touch();
+        }
+
+        read
+    }
+}
@@ -0,0 +1,501 @@
+use std::{fs::read_to_string, path::Path};
+
+use yaml_rust::{yaml::Hash, Yaml, YamlEmitter, YamlLoader};
+
+use crate::{Error, OrError};
+
+const USAGE: &str = "Usage:
+    pilot import compose [docker-compose.yml]
+    pilot import npm [package.json]
+    pilot import make [Makefile]
+    pilot import just [justfile]";
+
+/// A task of the generated Pilotfile, with its steps.
+type Task = (String, Vec<Yaml>);
+
+fn string(value: &str) -> Yaml {
+    Yaml::String(value.to_string())
+}
+
+/// Builds a step like `- shell: echo`.
+fn step(key: &str, value: Yaml) -> Yaml {
+    let mut hash = Hash::new();
+    hash.insert(string(key), value);
+    Yaml::Hash(hash)
+}
+
+/// Renders the tasks as a Pilotfile, with an empty line between the tasks.
+fn to_pilotfile(tasks: Vec<Task>) -> Result<String, Error> {
+    Ok(tasks
+        .into_iter()
+        .map(|(name, steps)| {
+            let mut hash = Hash::new();
+            hash.insert(string(&name), Yaml::Array(steps));
+
+            let mut out = String::new();
+            YamlEmitter::new(&mut out)
+                .dump(&Yaml::Hash(hash))
+                .or_error("Could not write the Pilotfile")?;
+
+            // the emitter always starts a new document
+            Ok(out.trim_start_matches("---\n").to_string() + "\n")
+        })
+        .collect::<Result<Vec<_>, Error>>()?
+        .join("\n"))
+}
+
+fn load_yaml(path: &Path) -> Result<Yaml, Error> {
+    let file = read_to_string(path).or_error(&format!("Could not read {}", path.display()))?;
+    YamlLoader::load_from_str(&file)
+        .or_error(&format!("{} is not valid YAML", path.display()))?
+        .into_iter()
+        .next()
+        .or_error(&format!("{} is empty", path.display()))
+}
+
+/// Turns a scalar into the string a shell or environment would see.
+fn scalar_to_string(yaml: &Yaml) -> Option<String> {
+    match yaml {
+        Yaml::String(string) | Yaml::Real(string) => Some(string.clone()),
+        Yaml::Integer(integer) => Some(integer.to_string()),
+        Yaml::Boolean(boolean) => Some(boolean.to_string()),
+        _ => None,
+    }
+}
+
+/// Quotes a word for a POSIX shell, if it has to be.
+fn shell_quote(word: &str) -> String {
+    if !word.is_empty()
+        && word
+            .chars()
+            .all(|char| char.is_ascii_alphanumeric() || "-_./:=@%+,".contains(char))
+    {
+        word.to_string()
+    } else {
+        format!("'{}'", word.replace('\'', "'\\''"))
+    }
+}
+
+/// Converts the services of a docker-compose file into tasks that run the same
+/// containers, plus a `compose-up` task starting all of them.
+fn compose(path: &Path) -> Result<String, Error> {
+    let compose = load_yaml(path)?;
+    let services = compose["services"]
+        .as_hash()
+        .or_error(&format!("{} has no services", path.display()))?;
+
+    let mut tasks = vec![];
+    let mut names = vec![];
+
+    for (name, service) in services {
+        let name = name.as_str().or_error("Invalid service name")?.to_string();
+        let mut steps = vec![];
+
+        let depends_on: Vec<String> = match &service["depends_on"] {
+            Yaml::Array(services) => services.iter().filter_map(scalar_to_string).collect(),
+            // the long syntax maps the services to conditions
+            Yaml::Hash(services) => services.keys().filter_map(scalar_to_string).collect(),
+            _ => vec![],
+        };
+
+        if !depends_on.is_empty() {
+            steps.push(step(
+                "description",
+                string(&format!("depends on {}", depends_on.join(", "))),
+            ));
+        }
+
+        let env: Vec<_> = match &service["environment"] {
+            Yaml::Hash(env) => env
+                .iter()
+                .filter_map(|(key, value)| Some((scalar_to_string(key)?, scalar_to_string(value)?)))
+                .collect(),
+            Yaml::Array(env) => env
+                .iter()
+                .filter_map(|entry| entry.as_str()?.split_once('='))
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+            _ => vec![],
+        };
+
+        if !env.is_empty() {
+            let mut hash = Hash::new();
+
+            for (key, value) in env {
+                hash.insert(string(&key), string(&value));
+            }
+
+            steps.push(step("env", Yaml::Hash(hash)));
+        }
+
+        let command = match &service["command"] {
+            Yaml::String(command) => command.clone(),
+            Yaml::Array(words) => words
+                .iter()
+                .filter_map(scalar_to_string)
+                .map(|word| shell_quote(&word))
+                .collect::<Vec<_>>()
+                .join(" "),
+            _ => {
+                // without a command we can only let compose start the service
+                let mut compose = Hash::new();
+                compose.insert(string("service"), string(&name));
+                compose.insert(string("file"), string(&path.to_string_lossy()));
+                steps.push(step("compose", Yaml::Hash(compose)));
+
+                tasks.push((name.clone(), steps, depends_on));
+                names.push(name);
+                continue;
+            }
+        };
+
+        match service["image"].as_str() {
+            Some(image) => {
+                let mut container = Hash::new();
+                container.insert(string("image"), string(image));
+
+                let volumes: Vec<_> = service["volumes"]
+                    .as_vec()
+                    .map(|volumes| {
+                        volumes
+                            .iter()
+                            .filter(|v| v.as_str().is_some())
+                            .cloned()
+                            .collect()
+                    })
+                    .unwrap_or_default();
+
+                if !volumes.is_empty() {
+                    container.insert(string("volumes"), Yaml::Array(volumes));
+                }
+
+                let ports: Vec<_> = service["ports"]
+                    .as_vec()
+                    .map(|ports| {
+                        ports
+                            .iter()
+                            .filter_map(scalar_to_string)
+                            .map(|p| string(&p))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+
+                if !ports.is_empty() {
+                    container.insert(string("ports"), Yaml::Array(ports));
+                }
+
+                if let Some(working_dir) = service["working_dir"].as_str() {
+                    container.insert(string("workdir"), string(working_dir));
+                }
+
+                steps.push(step("container", Yaml::Hash(container)));
+            }
+            None => eprintln!(
+                "Service {} has no image, its command runs on the host",
+                name
+            ),
+        }
+
+        steps.push(step("shell", string(&command)));
+
+        tasks.push((name.clone(), steps, depends_on));
+        names.push(name);
+    }
+
+    // start the dependencies before the services depending on them
+    let mut ordered: Vec<String> = vec![];
+
+    while ordered.len() < names.len() {
+        let before = ordered.len();
+
+        for (name, _, depends_on) in &tasks {
+            if !ordered.contains(name)
+                && depends_on
+                    .iter()
+                    .all(|dep| ordered.contains(dep) || !names.contains(dep))
+            {
+                ordered.push(name.clone());
+            }
+        }
+
+        // circular dependencies, just keep the order of the file for the rest
+        if ordered.len() == before {
+            for name in &names {
+                if !ordered.contains(name) {
+                    ordered.push(name.clone());
+                }
+            }
+        }
+    }
+
+    let mut pilotfile: Vec<Task> = tasks
+        .into_iter()
+        .map(|(name, steps, _)| (name, steps))
+        .collect();
+
+    let parallel = ordered
+        .iter()
+        .map(|name| step("task", string(name)))
+        .collect();
+    pilotfile.push((
+        "compose-up".to_string(),
+        vec![
+            step("description", string("start all services")),
+            step("parallel", Yaml::Array(parallel)),
+        ],
+    ));
+
+    to_pilotfile(pilotfile)
+}
+
+/// The package manager of a JavaScript project, detected from its lockfile.
+fn package_manager(dir: &Path) -> &'static str {
+    const LOCKFILES: &[(&str, &str)] = &[
+        ("pnpm-lock.yaml", "pnpm"),
+        ("yarn.lock", "yarn"),
+        ("bun.lockb", "bun"),
+        ("bun.lock", "bun"),
+        ("package-lock.json", "npm"),
+    ];
+
+    LOCKFILES
+        .iter()
+        .find(|(lockfile, _)| dir.join(lockfile).exists())
+        .map(|(_, manager)| *manager)
+        .unwrap_or("npm")
+}
+
+/// Converts the scripts of a package.json into tasks running them through the package manager.
+fn npm(path: &Path) -> Result<String, Error> {
+    // JSON is valid YAML, so the YAML parser reads package.json just fine
+    let package = load_yaml(path)?;
+    let scripts = package["scripts"]
+        .as_hash()
+        .or_error(&format!("{} has no scripts", path.display()))?;
+    let manager = package_manager(path.parent().unwrap_or_else(|| Path::new(".")));
+
+    let names: Vec<_> = scripts.keys().filter_map(|name| name.as_str()).collect();
+
+    let tasks = scripts
+        .iter()
+        .filter_map(|(name, script)| Some((name.as_str()?, script.as_str()?)))
+        // the package manager already runs pre<script> and post<script> around a script
+        .filter(|(name, _)| {
+            !["pre", "post"].iter().any(|prefix| {
+                name.strip_prefix(prefix)
+                    .map(|script| names.contains(&script))
+                    .unwrap_or(false)
+            })
+        })
+        .map(|(name, script)| {
+            (
+                name.to_string(),
+                vec![
+                    step("description", string(script)),
+                    step(
+                        "shell",
+                        string(&format!("{} run {}", manager, shell_quote(name))),
+                    ),
+                ],
+            )
+        })
+        .collect();
+
+    to_pilotfile(tasks)
+}
+
+/// Converts the top-level targets of a Makefile into tasks calling make, the `##` comments
+/// before a target or after its prerequisites become the descriptions.
+fn make(path: &Path) -> Result<String, Error> {
+    let makefile = read_to_string(path).or_error(&format!("Could not read {}", path.display()))?;
+    let make = if path == Path::new("Makefile") {
+        "make".to_string()
+    } else {
+        format!("make -f {}", shell_quote(&path.to_string_lossy()))
+    };
+
+    let mut tasks: Vec<Task> = vec![];
+    let mut doc: Option<String> = None;
+
+    for line in makefile.lines() {
+        if let Some(comment) = line.strip_prefix("##") {
+            doc = Some(comment.trim().to_string());
+            continue;
+        }
+
+        let line_doc = doc.take();
+
+        // recipes are indented, so targets start at the beginning of the line
+        if line.starts_with(|char: char| char.is_whitespace() || char == '#') {
+            continue;
+        }
+
+        let (targets, rest) = match line.split_once(':') {
+            Some(split) => split,
+            None => continue,
+        };
+
+        // := and ::= are assignments, not rules
+        if rest.starts_with('=') || rest.starts_with(":=") || targets.contains('=') {
+            continue;
+        }
+
+        let doc = rest
+            .split_once("##")
+            .map(|(_, comment)| comment.trim().to_string())
+            .or(line_doc);
+
+        for target in targets.split_whitespace() {
+            // special targets, pattern rules and computed names can't be called by name
+            if target.starts_with('.')
+                || target.contains(['%', '$'])
+                || tasks.iter().any(|(name, _)| name == target)
+            {
+                continue;
+            }
+
+            let mut steps = vec![];
+
+            if let Some(doc) = &doc {
+                steps.push(step("description", string(doc)));
+            }
+
+            steps.push(step(
+                "shell",
+                string(&format!("{} {}", make, shell_quote(target))),
+            ));
+            tasks.push((target.to_string(), steps));
+        }
+    }
+
+    to_pilotfile(tasks)
+}
+
+fn is_just_identifier(name: &str) -> bool {
+    name.starts_with(|char: char| char.is_ascii_alphabetic() || char == '_')
+        && name
+            .chars()
+            .all(|char| char.is_ascii_alphanumeric() || char == '_' || char == '-')
+}
+
+/// Converts the public recipes of a justfile into tasks, the comment before a recipe becomes
+/// its description. Simple recipes are copied line by line, the others call just.
+fn just(path: &Path) -> Result<String, Error> {
+    let justfile = read_to_string(path).or_error(&format!("Could not read {}", path.display()))?;
+    let just = if path == Path::new("justfile") {
+        "just".to_string()
+    } else {
+        format!("just --justfile {}", shell_quote(&path.to_string_lossy()))
+    };
+
+    let mut tasks: Vec<Task> = vec![];
+    let mut doc: Option<String> = None;
+    let mut private = false;
+    let mut lines = justfile.lines().peekable();
+
+    while let Some(line) = lines.next() {
+        if let Some(comment) = line.strip_prefix('#') {
+            if !comment.starts_with('!') {
+                doc = Some(comment.trim().to_string());
+            }
+            continue;
+        }
+
+        // attributes like [private] or [no-cd] stand on their own line
+        if line.starts_with('[') {
+            private |= line.contains("private");
+            continue;
+        }
+
+        let recipe_doc = doc.take();
+        let recipe_private = std::mem::take(&mut private);
+
+        if line.trim().is_empty() || line.starts_with(char::is_whitespace) {
+            continue;
+        }
+
+        let (header, dependencies) = match line.split_once(':') {
+            // := assigns variables, settings and aliases
+            Some((_, rest)) if rest.starts_with('=') => continue,
+            Some(split) => split,
+            None => continue,
+        };
+
+        let mut words = header.split_whitespace();
+        let name = match words.next() {
+            Some(name) => name.trim_start_matches('@'),
+            None => continue,
+        };
+        let has_parameters = words.next().is_some();
+
+        let mut body = vec![];
+
+        while let Some(line) = lines.peek() {
+            if !line.trim().is_empty() && !line.starts_with(char::is_whitespace) {
+                break;
+            }
+
+            let line = lines.next().unwrap().trim();
+
+            if !line.is_empty() {
+                body.push(line);
+            }
+        }
+
+        if !is_just_identifier(name) || name.starts_with('_') || recipe_private {
+            continue;
+        }
+
+        let dependencies: Vec<_> = dependencies.split_whitespace().collect();
+
+        // just runs every line in its own shell, and so do separate shell steps
+        let simple = !has_parameters
+            && dependencies
+                .iter()
+                .all(|dependency| is_just_identifier(dependency))
+            && body.iter().all(|line| {
+                !line.starts_with("#!") && !line.starts_with('-') && !line.contains("{{")
+            });
+
+        let mut steps = vec![];
+
+        if let Some(doc) = recipe_doc {
+            steps.push(step("description", string(&doc)));
+        }
+
+        if simple {
+            for dependency in dependencies {
+                steps.push(step("task", string(dependency)));
+            }
+
+            for line in body {
+                steps.push(step("shell", string(line.trim_start_matches('@'))));
+            }
+        } else {
+            steps.push(step(
+                "shell",
+                string(&format!("{} {}", just, shell_quote(name))),
+            ));
+        }
+
+        tasks.push((name.to_string(), steps));
+    }
+
+    to_pilotfile(tasks)
+}
+
+/// `pilot import <format> [file]`, prints the generated tasks to stdout.
+pub fn cli(args: &[String]) -> Result<(), Error> {
+    let path = |default| Path::new(args.get(1).map(|path| path.as_str()).unwrap_or(default));
+
+    let imported = match args.first().map(|arg| arg.as_str()) {
+        Some("compose") => compose(path("docker-compose.yml"))?,
+        Some("npm") => npm(path("package.json"))?,
+        Some("make") => make(path("Makefile"))?,
+        Some("just") => just(path("justfile"))?,
+        _ => return Err(Error(USAGE.to_string())),
+    };
+
+    print!("{}", imported);
+    Ok(())
+}
@@ -0,0 +1,95 @@
+use std::{path::Path, process::Command};
+
+use yaml_rust::Yaml;
+
+/// Runs the shell steps of a task inside a pod of a Kubernetes cluster, through kubectl.
+/// kubectl brings the credentials and cluster configuration the team already uses.
+#[derive(Clone)]
+pub struct Kubernetes {
+    context: Option<String>,
+    namespace: Option<String>,
+    pod_selector: String,
+    container: Option<String>,
+    kubectl: Option<String>,
+}
+
+impl Kubernetes {
+    pub fn from_yaml(yaml: &Yaml) -> Option<Self> {
+        let string = |key: &str| yaml[key].as_str().map(|value| value.to_string());
+
+        Some(Kubernetes {
+            context: string("context"),
+            namespace: string("namespace"),
+            pod_selector: string("pod_selector")?,
+            container: string("container"),
+            kubectl: string("kubectl"),
+        })
+    }
+
+    /// kubectl with the context and namespace of the task.
+    fn kubectl(&self, dir: &Path) -> Command {
+        let mut command = Command::new(self.kubectl.as_deref().unwrap_or("kubectl"));
+        command.current_dir(dir);
+
+        if let Some(context) = &self.context {
+            command.arg("--context").arg(context);
+        }
+
+        if let Some(namespace) = &self.namespace {
+            command.arg("--namespace").arg(namespace);
+        }
+
+        command
+    }
+
+    /// The name of the first running pod matching the selector.
+    fn pod(&self, dir: &Path) -> Result<String, String> {
+        let output = self
+            .kubectl(dir)
+            .args(["get", "pods", "--selector", &self.pod_selector])
+            .args(["--field-selector", "status.phase=Running"])
+            .args(["--output", "jsonpath={.items[0].metadata.name}"])
+            .output()
+            .map_err(|err| format!("could not run kubectl: {}", err))?;
+
+        let pod = String::from_utf8_lossy(&output.stdout).trim().to_string();
+
+        if !output.status.success() || pod.is_empty() {
+            return Err(format!("no running pod matches {}", self.pod_selector));
+        }
+
+        Ok(pod)
+    }
+
+    /// Builds the command that runs `shell_command` in the pod.
+    pub fn command(
+        &self,
+        shell_command: &str,
+        dir: &Path,
+        env: &[(String, String)],
+        tty: bool,
+    ) -> Result<Command, String> {
+        let pod = self.pod(dir)?;
+
+        let mut command = self.kubectl(dir);
+        command.args(["exec", "--stdin"]);
+
+        if tty {
+            command.arg("--tty");
+        }
+
+        command.arg(pod);
+
+        if let Some(container) = &self.container {
+            command.arg("--container").arg(container);
+        }
+
+        // kubectl exec doesn't pass any environment on its own
+        command
+            .args(["--", "env"])
+            .args(env.iter().map(|(key, value)| format!("{}={}", key, value)))
+            .args(["sh", "-c", shell_command]);
+
+        Ok(command)
+    }
+}
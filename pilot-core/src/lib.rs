@@ -0,0 +1,3285 @@
+//! The task model, Pilotfile loading and the runner of pilot.
+//!
+//! ```no_run
+//! use pilot_core::{Pilotfile, RunOptions, Runner};
+//!
+//! let pilotfile = Pilotfile::load(&Pilotfile::find(".").unwrap()).unwrap();
+//! let options = RunOptions::new(&pilotfile);
+//! let result = Runner::new(pilotfile, options).run("build").unwrap();
+//! assert!(result.success());
+//! ```
+
+use std::{
+    borrow::Cow,
+    collections::VecDeque,
+    env,
+    fs::File,
+    future::Future,
+    io::{self, pipe, stdin, stdout, ErrorKind, PipeReader, PipeWriter, Read, Write},
+    path::{Path, PathBuf},
+    pin::Pin,
+    process::{Command, Stdio},
+    sync::{
+        atomic::{AtomicU32, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use chrono::Local;
+use config::{named_args, Config, Setting, Step, StepInput, Task, TaskRef};
+use sink::{OutputSink, SinkSpec};
+use tokio::{
+    io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    process::Child,
+    task::{block_in_place, JoinHandle},
+};
+use wait::Poll;
+
+pub mod bench;
+mod cache;
+pub mod ci;
+mod clock;
+mod config;
+mod container;
+mod diagnostics;
+mod duration;
+pub mod edit;
+mod events;
+mod expand;
+pub mod export;
+mod files;
+pub mod fmt;
+pub mod history;
+mod idle;
+pub mod import;
+mod kubernetes;
+pub mod list;
+mod needs;
+mod notify;
+mod pilotfile;
+mod portable;
+mod ports;
+mod procfile;
+#[cfg(unix)]
+mod pty;
+mod reload;
+mod remote;
+mod runtime;
+mod service;
+pub mod shuffle;
+pub mod shutdown;
+pub mod sink;
+mod spawn;
+pub mod state;
+mod status;
+mod supervise;
+mod trace;
+pub mod upgrade;
+pub mod user_config;
+mod wait;
+
+pub use pilotfile::Pilotfile;
+
+const NOT_VALID: &str = "This is not a valid Pilotfile";
+
+/// A mistake the user has to fix, like an unknown task or arguments a task doesn't take. The
+/// library returns it instead of exiting, how the tasks that ran went is in their results.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Error(pub String);
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<String> for Error {
+    fn from(message: String) -> Self {
+        Error(message)
+    }
+}
+
+/// Turns a failure into an [`Error`] with the message, followed by the cause of a failed result.
+pub(crate) trait OrError<T> {
+    fn or_error(self, msg: &str) -> Result<T, Error>;
+}
+
+impl<T, E: std::error::Error> OrError<T> for Result<T, E> {
+    fn or_error(self, msg: &str) -> Result<T, Error> {
+        self.map_err(|err| Error(format!("{}: {}", msg, err)))
+    }
+}
+
+impl<T> OrError<T> for Option<T> {
+    fn or_error(self, msg: &str) -> Result<T, Error> {
+        self.ok_or_else(|| Error(msg.to_string()))
+    }
+}
+
+static INDEX: AtomicU32 = AtomicU32::new(1);
+static PADDING: AtomicUsize = AtomicUsize::new(0);
+/// ports are never handed out twice, unlike the colors
+static PORT_OFFSET: AtomicU32 = AtomicU32::new(0);
+
+/// The future of a step or task that runs other steps and tasks, boxed to break the recursion.
+type Running<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Hands the terminal to a raw process, also in the middle of a run that isn't raw: it reads the
+/// keys and gets Ctrl-C, and the output of the other tasks waits until it's done. The modes of
+/// the terminal are restored afterwards, in case the process left them changed.
+async fn hand_over_terminal(run: impl Future<Output = i32>) -> i32 {
+    let modes = spawn::terminal_modes();
+    let code = status::handed_over(shutdown::foreground(run)).await;
+    spawn::restore_terminal_modes(&modes);
+    code
+}
+
+/// Removes the escape sequences of a line, except for colors. We boldly assume that any other
+/// sequence wanted to delete the line, so the text before it is dropped as well.
+fn sanitize_string(line: String) -> String {
+    if !line.contains('\u{1b}') {
+        return line;
+    }
+
+    let mut sanitized = String::with_capacity(line.len());
+    let mut rest = line.as_str();
+
+    while let Some(start) = rest.find('\u{1b}') {
+        sanitized.push_str(&rest[..start]);
+        let sequence = &rest[start + 1..];
+
+        match sequence
+            .char_indices()
+            .find(|(_, char)| *char != '[' && *char != ';' && !char.is_ascii_digit())
+        {
+            // it is a coloring sequence, keep it
+            Some((end, 'm')) => {
+                sanitized.push_str(&rest[start..start + end + 2]);
+                rest = &sequence[end + 1..];
+            }
+            Some((end, char)) => {
+                sanitized.clear();
+                rest = &sequence[end + char.len_utf8()..];
+            }
+            // an unfinished sequence takes the rest of the line with it
+            None => rest = "",
+        }
+    }
+
+    sanitized.push_str(rest);
+    sanitized
+}
+
+/// Lines are cut after this many bytes while they are read, so a process printing megabytes
+/// without a newline doesn't make pilot hold all of them.
+const MAX_LINE_BYTES: usize = 64 * 1024;
+
+/// Reads the next line of output without its line ending, `None` at the end of the output.
+async fn read_line(output: &mut (impl AsyncBufRead + Unpin)) -> Option<String> {
+    let mut line = vec![];
+    let mut cut_bytes = 0;
+    let mut read = false;
+
+    loop {
+        let buffer = match output.fill_buf().await {
+            Ok(buffer) => buffer,
+            Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+            // output that can't be read anymore ended as well
+            Err(_) => break,
+        };
+
+        if buffer.is_empty() {
+            break;
+        }
+
+        read = true;
+        let newline = buffer.iter().position(|byte| *byte == b'\n');
+        let mut chunk = &buffer[..newline.unwrap_or(buffer.len())];
+
+        // ptys end their lines with \r\n
+        if newline.is_some() {
+            chunk = chunk.strip_suffix(b"\r").unwrap_or(chunk);
+        }
+
+        let kept = chunk.len().min(MAX_LINE_BYTES - line.len());
+        line.extend_from_slice(&chunk[..kept]);
+        cut_bytes += chunk.len() - kept;
+
+        match newline {
+            Some(newline) => {
+                output.consume(newline + 1);
+                break;
+            }
+            None => {
+                let length = buffer.len();
+                output.consume(length);
+            }
+        }
+    }
+
+    if !read {
+        return None;
+    }
+
+    let line = String::from_utf8_lossy(&line).into_owned();
+
+    if cut_bytes > 0 {
+        Some(line + &format!("… ({} more bytes)", cut_bytes))
+    } else {
+        Some(line)
+    }
+}
+
+/// The shell configured with `--shell` or in the Pilotfile, `posix` always means `sh -c`.
+fn get_shell(shell: Option<&str>) -> Command {
+    match shell {
+        Some("posix") => {
+            let mut command = Command::new("sh");
+            command.arg("-c");
+            command
+        }
+        Some(shell) => {
+            // the flag that makes the shell run a command differs, sh, bash, zsh and fish use -c
+            let mut words = shell.split_whitespace();
+            let mut command = Command::new(words.next().unwrap_or("sh"));
+            let flags: Vec<_> = words.collect();
+
+            if flags.is_empty() {
+                command.arg("-c");
+            } else {
+                command.args(flags);
+            }
+
+            command
+        }
+        None => default_shell(),
+    }
+}
+
+#[cfg(target_family = "windows")]
+fn default_shell() -> Command {
+    let mut command = Command::new(r"C:\Windows\System32\powershell.exe");
+    command.arg("-c");
+    command
+}
+
+#[cfg(not(target_family = "windows"))]
+fn default_shell() -> Command {
+    let shell = env::var("SHELL").unwrap_or("sh".to_string());
+    let mut command = Command::new(shell);
+    command.arg("-c");
+    command
+}
+
+/// The value a process of the task would see for an environment variable.
+fn env_value(name: &str, options: &RunOptions) -> Option<String> {
+    options
+        .env_overrides
+        .iter()
+        .rev()
+        .chain(options.task_env.iter().rev())
+        .chain(options.env.iter().rev())
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.clone())
+        .or_else(|| env::var(name).ok())
+}
+
+/// How the lines of output show the task they come from.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum PrefixStyle {
+    /// `server: line`, with colored and aligned task names if colors are on
+    Color,
+    /// `server: line` without any escape sequences
+    Plain,
+    /// `[server] line` without any escape sequences
+    Brackets,
+}
+
+impl PrefixStyle {
+    pub fn parse(style: &str) -> Option<Self> {
+        match style {
+            "color" => Some(PrefixStyle::Color),
+            "plain" => Some(PrefixStyle::Plain),
+            "brackets" => Some(PrefixStyle::Brackets),
+            _ => None,
+        }
+    }
+}
+
+/// Settings that apply to every task of a single pilot invocation.
+#[derive(Clone)]
+pub struct RunOptions {
+    /// tasks whose output is hidden
+    pub quiet_tasks: Vec<String>,
+    /// if not empty, the only tasks whose output is shown
+    pub only_output: Vec<String>,
+    /// run the processes without a pty and without prefixing their output
+    pub raw: bool,
+    /// print a timestamp at the beginning of each line, `None` leaves it to the Pilotfile
+    pub timestamp: Option<bool>,
+    /// the timestamp setting of the running task or else of the Pilotfile
+    task_timestamp: bool,
+    /// cut lines of output after this many characters, `None` leaves it to the task
+    pub max_line_length: Option<usize>,
+    /// the `max_line_length` of the running task
+    task_max_line_length: Option<usize>,
+    /// the `idle_timeout` of the running task
+    idle_timeout: Option<Duration>,
+    /// how the processes of the running task are stopped
+    stop: shutdown::Stop,
+    /// set if the running task is a service, its health check stops its processes
+    service: Option<Arc<service::Service>>,
+    /// the `tee_to` of the running task
+    tee_to: Option<config::TeeTo>,
+    /// the task with the parallel step and the label of the branch that is running
+    branch_label: Option<(String, String)>,
+    /// the format of the timestamps, like `%H:%M:%S`
+    pub timestamp_format: String,
+    /// color and align the task names, plain prefixes keep logs readable when piped
+    pub color: bool,
+    /// how the lines show their task, the styles besides `Color` print no escape sequences
+    pub prefix_style: PrefixStyle,
+    /// the SGR parameters of the colors the task names cycle through, like `0;31`
+    pub colors: Vec<String>,
+    dir: PathBuf,
+    /// cleanup steps keep running after pilot was interrupted
+    cleanup: bool,
+    /// extra environment variables for the spawned processes
+    env: Vec<(String, String)>,
+    /// the running task with the tasks that started it, like `run > build`
+    task_path: String,
+    /// environment variables of the `task` steps that led to the running task, they win over
+    /// the ones of the task itself
+    task_env: Vec<(String, String)>,
+    /// the `args` of the `task` step that started the running task, for `{{args}}`
+    args: String,
+    /// the `name=value` arguments of the tasks on the command line
+    pub task_args: Vec<(String, Vec<(String, String)>)>,
+    /// the `name=value` arguments from the command line for the task that is starting
+    named_args: Option<Vec<(String, String)>>,
+    /// environment variables from the command line, they win over the Pilotfile
+    pub env_overrides: Vec<(String, String)>,
+    /// run `pre<task>` and `post<task>` around a task, like npm scripts
+    pub pre_post: bool,
+    /// answer every confirmation prompt with yes
+    pub yes: bool,
+    /// keep running when the terminal hangs up, instead of stopping the tasks
+    pub detach_on_hangup: bool,
+    /// show the previews of the tasks instead of running them
+    pub dry_run: bool,
+    /// start the task at the step with this `id`, skipping the ones before it
+    pub from_step: Option<String>,
+    /// stop the task after the step with this `id`
+    pub until_step: Option<String>,
+    /// ask whether to retry, skip or abort when a step fails, instead of failing the task
+    pub prompt_on_failure: bool,
+    /// print how long each step of a task took
+    pub timings: bool,
+    /// run the tasks of the command line and the ones matching a pattern at the same time,
+    /// instead of one after another
+    pub parallel: bool,
+    /// run the tasks at the same time and start, restart and stop them when the Pilotfile
+    /// changes, see [`reload`]
+    pub reload: bool,
+    /// run the tasks of the command line, the ones matching a pattern and parallel branches in
+    /// a random order, the same seed gives the same order
+    pub shuffle: Option<u64>,
+    /// print the output of each task as one block once it finished, instead of line by line
+    pub group_output: bool,
+    /// the output of the running task held back by `group_output`
+    output_group: Option<Arc<Mutex<Vec<String>>>>,
+    /// save the diagnostics of a failing process, the attempts of a `poll` step and previews
+    /// may fail
+    diagnostics: bool,
+    /// the last lines of output of the running process, for the diagnostics of a failure
+    recent_output: Option<Arc<Mutex<VecDeque<String>>>>,
+    /// write a timeline of the run in the Chrome trace event format to this file
+    pub profile_output: Option<PathBuf>,
+    /// the user the processes run as, pilot's own user if not set
+    user: Option<String>,
+    /// niceness of the processes
+    nice: Option<i32>,
+    umask: Option<u32>,
+    /// run the processes in their own session
+    setsid: bool,
+    limits: spawn::Limits,
+    /// exit codes of the processes that don't fail the task, instead of just 0
+    success_codes: Option<Vec<i32>>,
+    /// run the shell steps in a container instead of on the host
+    container: Option<container::Container>,
+    /// run the shell steps on another machine instead of locally
+    remote: Option<remote::Remote>,
+    /// run the shell steps in a pod of a Kubernetes cluster
+    kubernetes: Option<kubernetes::Kubernetes>,
+    /// the shell running the commands, like `bash` or `pwsh -Command`
+    pub shell: Option<String>,
+    /// translate the POSIX commands for PowerShell, and Windows paths for POSIX shells
+    portable: bool,
+    /// directories searched for commands before the ones in `$PATH`
+    path_prepend: Vec<PathBuf>,
+    /// `$PORT` of the first task, every further task or parallel branch gets the next port
+    pub port: Option<u16>,
+    /// color index and port offset reserved for the first shell of a parallel branch
+    reserved: Arc<Mutex<(Option<u32>, Option<u32>)>>,
+    /// port offset of the task or branch, handed out with its first process and kept for the
+    /// ones after it
+    port_offset: Arc<Mutex<Option<u32>>>,
+    /// the process is an attempt of a `poll` step, which gets no port of its own
+    probe: bool,
+    /// the end of a `pipe` the processes read their stdin from
+    pipe_stdin: Option<Arc<PipeReader>>,
+    /// the end of a `pipe` the processes write their stdout to
+    pipe_stdout: Option<Arc<PipeWriter>>,
+    /// where the output of the processes goes, the console unless the command line or the task
+    /// choose other sinks
+    pub sinks: Vec<Arc<dyn OutputSink>>,
+    /// the processes of the `background` step that is running
+    background: Option<Arc<Mutex<Background>>>,
+    /// the service a task that `needs` it waits for, until it is ready
+    needed_service: Option<Arc<service::Service>>,
+    /// hide all output except for errors, for `pilot bench`
+    silent: bool,
+    /// the options and tasks of the command line, the history keeps them for `pilot repro`
+    pub command_line: Vec<String>,
+}
+
+impl RunOptions {
+    /// The defaults for running the tasks of the Pilotfile, with its shell and `path_prepend`.
+    /// The preferences of the user fill in what the Pilotfile leaves open.
+    pub fn new(pilotfile: &Pilotfile) -> Self {
+        let config = pilotfile.config();
+        let user_config = user_config::get();
+
+        RunOptions {
+            quiet_tasks: vec![],
+            only_output: vec![],
+            raw: false,
+            timestamp: None,
+            task_timestamp: config.timestamp.unwrap_or(false),
+            max_line_length: None,
+            task_max_line_length: None,
+            idle_timeout: None,
+            stop: shutdown::Stop::default(),
+            service: None,
+            tee_to: None,
+            branch_label: None,
+            timestamp_format: user_config
+                .timestamp_format
+                .clone()
+                .unwrap_or(user_config::DEFAULT_TIMESTAMP_FORMAT.to_string()),
+            // screen readers and dumb terminals get no escape sequences at all
+            color: user_config.prefix_style.unwrap_or(PrefixStyle::Color) == PrefixStyle::Color
+                && user_config.color.unwrap_or_else(color_default),
+            prefix_style: user_config.prefix_style.unwrap_or(PrefixStyle::Color),
+            colors: if user_config.colors.is_empty() {
+                (31..=37).map(|color| format!("0;{}", color)).collect()
+            } else {
+                user_config.colors.clone()
+            },
+            dir: pilotfile.dir().to_path_buf(),
+            cleanup: false,
+            env: vec![],
+            task_path: String::new(),
+            task_env: vec![],
+            args: String::new(),
+            task_args: vec![],
+            named_args: None,
+            env_overrides: vec![],
+            pre_post: true,
+            yes: false,
+            detach_on_hangup: false,
+            dry_run: false,
+            from_step: None,
+            until_step: None,
+            prompt_on_failure: false,
+            timings: false,
+            parallel: false,
+            reload: false,
+            shuffle: None,
+            group_output: false,
+            output_group: None,
+            diagnostics: true,
+            recent_output: None,
+            profile_output: None,
+            user: None,
+            nice: None,
+            umask: None,
+            setsid: false,
+            limits: spawn::Limits::default(),
+            success_codes: None,
+            container: None,
+            remote: None,
+            kubernetes: None,
+            shell: config.shell.clone().or(user_config.shell.clone()),
+            portable: config.portable,
+            path_prepend: config.path_prepend.clone(),
+            port: None,
+            reserved: Arc::new(Mutex::new((None, None))),
+            port_offset: Arc::new(Mutex::new(None)),
+            probe: false,
+            pipe_stdin: None,
+            pipe_stdout: None,
+            sinks: vec![Arc::new(sink::Console)],
+            background: None,
+            needed_service: None,
+            silent: false,
+            command_line: vec![],
+        }
+    }
+
+    /// Whether lines start with a timestamp, the command line wins over the Pilotfile.
+    fn timestamp(&self) -> bool {
+        self.timestamp.unwrap_or(self.task_timestamp)
+    }
+
+    fn max_line_length(&self) -> Option<usize> {
+        self.max_line_length.or(self.task_max_line_length)
+    }
+}
+
+/// The processes of a `background` step, they are stopped once the following steps are done. The
+/// tasks of `--reload` and the tasks with `needs` run like one, so they can be stopped when they
+/// change or what they need fails.
+#[derive(Default)]
+struct Background {
+    pids: Vec<u32>,
+    stopped: bool,
+    /// the background it runs in, which also stops its processes
+    outer: Option<Arc<Mutex<Background>>>,
+}
+
+impl Background {
+    fn within(outer: Option<Arc<Mutex<Background>>>) -> Arc<Mutex<Background>> {
+        Arc::new(Mutex::new(Background {
+            outer,
+            ..Default::default()
+        }))
+    }
+}
+
+/// The background of the options and the ones around it, the innermost first.
+fn backgrounds(options: &RunOptions) -> Vec<Arc<Mutex<Background>>> {
+    let mut backgrounds = vec![];
+    let mut next = options.background.clone();
+
+    while let Some(background) = next {
+        next = background.lock().unwrap().outer.clone();
+        backgrounds.push(background);
+    }
+
+    backgrounds
+}
+
+/// Whether the background of the options or one around it was stopped, cleanup steps run anyway.
+fn background_stopped(options: &RunOptions) -> bool {
+    !options.cleanup
+        && backgrounds(options)
+            .iter()
+            .any(|background| background.lock().unwrap().stopped)
+}
+
+/// Remembers a spawned process, so it can be stopped on an interrupt or with its background step.
+fn register_child(pid: u32, task_name: &str, options: &RunOptions) {
+    shutdown::register_child(pid, options.stop);
+    history::add_process(pid, task_name, options.stop);
+
+    if let Some(service) = &options.service {
+        service.add_process(pid);
+    }
+
+    // cleanup steps run after their background was stopped
+    let backgrounds = match options.cleanup {
+        true => vec![],
+        false => backgrounds(options),
+    };
+    let mut backgrounds: Vec<_> = backgrounds
+        .iter()
+        .map(|background| background.lock().unwrap())
+        .collect();
+
+    // the steps were done before the process even started
+    if backgrounds.iter().any(|background| background.stopped) {
+        shutdown::terminate(pid);
+    } else {
+        for background in &mut backgrounds {
+            background.pids.push(pid);
+        }
+    }
+}
+
+fn unregister_child(pid: u32, options: &RunOptions) {
+    shutdown::unregister_child(pid);
+    history::remove_process(pid);
+
+    if let Some(service) = &options.service {
+        service.remove_process(pid);
+    }
+
+    for background in backgrounds(options) {
+        background
+            .lock()
+            .unwrap()
+            .pids
+            .retain(|child| *child != pid);
+    }
+}
+
+/// Stops the process if it prints nothing for the `idle_timeout` of its task.
+fn watch_idle(pid: u32, task_name: &str, options: &RunOptions) -> Option<Arc<idle::IdleTimeout>> {
+    options
+        .idle_timeout
+        .map(|timeout| idle::IdleTimeout::watch(timeout, pid, task_name))
+}
+
+/// Exit code reported for failures that aren't the exit of a process.
+const FAILURE_CODE: i32 = 1;
+
+#[cfg(unix)]
+fn exit_code(status: std::process::ExitStatus) -> i32 {
+    use std::os::unix::process::ExitStatusExt;
+
+    // mimic the shell convention for processes killed by a signal
+    status
+        .code()
+        .or_else(|| status.signal().map(|signal| 128 + signal))
+        .unwrap_or(FAILURE_CODE)
+}
+
+#[cfg(not(unix))]
+fn exit_code(status: std::process::ExitStatus) -> i32 {
+    status.code().unwrap_or(FAILURE_CODE)
+}
+
+/// Reports why a process of a task couldn't run, which fails the task but not the run.
+fn failed(err: Error) -> i32 {
+    eprintln!("{}", err);
+    FAILURE_CODE
+}
+
+/// Runs a shell command of a task, `capture` collects its stdout instead of printing it.
+async fn run_shell(
+    command: String,
+    task_name: String,
+    options: &RunOptions,
+    capture: Option<&mut String>,
+) -> i32 {
+    // cycle through shell colors
+    // credit: https://github.com/chrismytton/shoreman/
+    let (current_index, port_offset) = {
+        let mut reserved = options.reserved.lock().unwrap();
+        let current_index = reserved
+            .0
+            .take()
+            .unwrap_or_else(|| INDEX.fetch_add(1, Ordering::SeqCst));
+
+        // polls, previews and captures only see the port of the task, if it already has one
+        let port_offset = match capture.is_none() && !options.probe {
+            true => Some(*options.port_offset.lock().unwrap().get_or_insert_with(|| {
+                reserved
+                    .1
+                    .take()
+                    .unwrap_or_else(|| PORT_OFFSET.fetch_add(1, Ordering::SeqCst))
+            })),
+            false => *options.port_offset.lock().unwrap(),
+        };
+
+        (current_index, port_offset)
+    };
+    let sgr = options.colors[current_index as usize % options.colors.len()].clone();
+    let color = "\x1b[".to_string() + &sgr + "m";
+
+    // lets scripts match their own logging to pilot's output, only the local process gets them
+    let mut metadata = vec![
+        ("PILOT_TASK_NAME".to_string(), task_name.clone()),
+        ("PILOT_TASK_PATH".to_string(), options.task_path.clone()),
+        (
+            "PILOT_COLOR".to_string(),
+            if options.color { sgr } else { String::new() },
+        ),
+    ];
+
+    if let Some(run_id) = history::run_id() {
+        metadata.push(("PILOT_RUN_ID".to_string(), run_id));
+    }
+
+    let mut env = options.env.clone();
+
+    if let (Some(port), Some(port_offset)) = (options.port, port_offset) {
+        env.push(("PORT".to_string(), (port as u32 + port_offset).to_string()));
+    }
+
+    env.extend(options.task_env.iter().cloned());
+    env.extend(options.env_overrides.iter().cloned());
+
+    let fail = |err: String| {
+        eprintln!("Failed to run task {}: {}", task_name, err);
+        INDEX.fetch_sub(1, Ordering::SeqCst);
+        FAILURE_CODE
+    };
+
+    let local =
+        options.remote.is_none() && options.kubernetes.is_none() && options.container.is_none();
+
+    // the directories only exist locally, not on remote hosts or in containers
+    if local && !options.path_prepend.is_empty() {
+        let path = env
+            .iter()
+            .rev()
+            .find(|(key, _)| key == "PATH")
+            .map(|(_, path)| path.into())
+            .or_else(|| env::var_os("PATH"))
+            .unwrap_or_default();
+        let paths = options
+            .path_prepend
+            .iter()
+            .cloned()
+            .chain(env::split_paths(&path));
+
+        match env::join_paths(paths).map(|path| path.into_string()) {
+            Ok(Ok(path)) => env.push(("PATH".to_string(), path)),
+            _ => return fail("path_prepend makes an invalid PATH".to_string()),
+        }
+    }
+
+    // without arguments `{{args}}` is just left out, like an empty "$@"
+    let command = command.replace("{{args}}", &options.args);
+
+    // remote hosts, pods and containers run the commands in sh
+    let shell = match local && portable::is_powershell(options.shell.as_deref()) {
+        true => expand::Shell::PowerShell,
+        false => expand::Shell::Posix,
+    };
+
+    let command = match options.portable {
+        true if shell == expand::Shell::PowerShell => portable::to_powershell(&command),
+        true => portable::to_posix(&command),
+        false => command,
+    };
+
+    // only local processes inherit the environment of pilot
+    if !local {
+        for name in expand::names(&command) {
+            if !env.iter().any(|(key, _)| *key == name) {
+                if let Ok(value) = env::var(&name) {
+                    env.push((name, value));
+                }
+            }
+        }
+    }
+
+    let command = expand::expand(&command, shell, |name| {
+        env.iter().any(|(key, _)| key == name) || env::var_os(name).is_some()
+    });
+
+    let piped = capture.is_some() || options.pipe_stdin.is_some() || options.pipe_stdout.is_some();
+
+    // a raw run only gets a tty if pilot has one itself, piped output never does
+    let tty = !piped && (!options.raw || stdin_is_tty());
+
+    // a remote host takes precedence, the pod or container would have to run there as well
+    let std_command = if let Some(remote) = &options.remote {
+        Ok(remote.command(&command, &env, tty))
+    } else if let Some(kubernetes) = &options.kubernetes {
+        // kubectl looks up the pod and the engine of a container is looked up the same way
+        block_in_place(|| kubernetes.command(&command, &options.dir, &env, tty))
+    } else if let Some(container) = &options.container {
+        block_in_place(|| Ok(container.command(&command, &options.dir, &env, tty)))
+    } else {
+        let mut std_command = get_shell(options.shell.as_deref());
+        std_command.arg(&command);
+        Ok(std_command)
+    };
+
+    let std_command = std_command.and_then(|mut std_command| {
+        std_command
+            .current_dir(&options.dir)
+            .envs(metadata.iter().cloned())
+            .envs(env.iter().map(|(key, value)| (key, value)));
+
+        if let Some(user) = &options.user {
+            spawn::set_user(&mut std_command, user)?;
+        }
+
+        Ok(std_command)
+    });
+
+    let mut std_command = match std_command {
+        Ok(std_command) => std_command,
+        Err(err) => return fail(err),
+    };
+
+    if let Some(nice) = options.nice {
+        spawn::set_nice(&mut std_command, nice);
+    }
+
+    if let Some(umask) = options.umask {
+        spawn::set_umask(&mut std_command, umask);
+    }
+
+    if options.setsid {
+        spawn::set_setsid(&mut std_command);
+    }
+
+    // keep the cgroup around until the process exited
+    let cgroup = spawn::join_cgroup(&mut std_command, &options.limits).unwrap_or_else(|err| {
+        // the memory is still bounded by the rlimit, only the cpu is unlimited now
+        if options.limits.cpu.is_some() {
+            eprintln!("Could not limit the cpu of task {}: {}", task_name, err);
+        }
+
+        None
+    });
+
+    // the cgroup limits the memory exactly, the rlimit is the fallback without one
+    let rlimits = spawn::Limits {
+        memory: options.limits.memory.filter(|_| cgroup.is_none()),
+        ..options.limits.clone()
+    };
+    spawn::set_rlimits(&mut std_command, &rlimits);
+
+    let quiet = options.silent
+        || options.quiet_tasks.contains(&task_name)
+        || !(options.only_output.is_empty() || options.only_output.contains(&task_name));
+
+    if let Some(stdin) = &options.pipe_stdin {
+        match stdin.try_clone() {
+            Ok(stdin) => std_command.stdin(stdin),
+            Err(err) => return fail(err.to_string()),
+        };
+    }
+
+    let stdout = match (capture, &options.pipe_stdout) {
+        (Some(captured), _) => Some(Output::Capture(captured)),
+        (None, Some(stdout)) => match stdout.try_clone() {
+            Ok(stdout) => Some(Output::Pipe(stdout)),
+            Err(err) => return fail(err.to_string()),
+        },
+        // a pty would replace the stdin from the pipe
+        (None, None) if options.pipe_stdin.is_some() => Some(Output::Print),
+        (None, None) => None,
+    };
+
+    let recent_output = Arc::new(Mutex::new(VecDeque::new()));
+    let options = &RunOptions {
+        recent_output: Some(recent_output.clone()),
+        ..options.clone()
+    };
+    let start = clock::Stopwatch::start();
+
+    let code = if let Some(stdout) = stdout {
+        run_piped(std_command, &task_name, &color, quiet, options, stdout)
+            .await
+            .unwrap_or_else(failed)
+    } else if options.raw {
+        if quiet {
+            std_command.stdout(Stdio::null());
+            std_command.stderr(Stdio::null());
+        }
+
+        let run = async {
+            let (mut child, pid) = spawn_child(std_command, &task_name)?;
+
+            register_child(pid, &task_name, options);
+            let status = child.wait().await;
+            unregister_child(pid, options);
+
+            Ok(exit_code(
+                status.or_error(&format!("Task {} failed", task_name))?,
+            ))
+        };
+
+        match stdin_is_tty() {
+            true => hand_over_terminal(async { run.await.unwrap_or_else(failed) }).await,
+            false => status::paused(async { run.await.unwrap_or_else(failed) }).await,
+        }
+    } else {
+        run_multiplexed(std_command, &task_name, &color, quiet, options)
+            .await
+            .unwrap_or_else(failed)
+    };
+
+    // subtract one from the index
+    INDEX.fetch_sub(1, Ordering::SeqCst);
+
+    // a background step that was stopped once its task was done didn't fail
+    if background_stopped(options) {
+        return 0;
+    }
+
+    // pilot cancel stopped it on purpose, that is no failure to look into
+    if code != 0 && history::take_cancelled(&task_name) {
+        status::println(&format!("{} was cancelled", task_name));
+
+        if let Some(service) = &options.service {
+            service.cancel();
+        }
+
+        return code;
+    }
+
+    let code = match &options.success_codes {
+        Some(success_codes) if success_codes.contains(&code) => 0,
+        // only the success codes count as success, even 0
+        Some(_) if code == 0 => FAILURE_CODE,
+        _ => code,
+    };
+
+    // stopping the tasks makes them fail, that is no failure to look into
+    if code != 0 && options.diagnostics && !shutdown::is_interrupted() {
+        metadata.extend(env);
+        let recent_output = supervise::lock(&recent_output);
+
+        let failure = diagnostics::Failure {
+            task_path: &options.task_path,
+            command: &command,
+            code,
+            duration: start.elapsed(),
+            env: &metadata,
+            output: &recent_output,
+        };
+
+        match diagnostics::save(&options.dir, &failure) {
+            Ok(path) => eprintln!(
+                "Task {} failed, its diagnostics are in {}",
+                task_name,
+                path.strip_prefix(&options.dir).unwrap_or(&path).display()
+            ),
+            Err(err) => eprintln!(
+                "Could not save the diagnostics of task {}: {}",
+                task_name, err
+            ),
+        }
+
+        if quiet && !options.silent {
+            print_quiet_output(&task_name, &color, &recent_output, options);
+        }
+    }
+
+    code
+}
+
+/// Prints the last lines of a quiet task that failed, which would be lost otherwise.
+fn print_quiet_output(
+    task_name: &str,
+    color: &str,
+    output: &VecDeque<String>,
+    options: &RunOptions,
+) {
+    if output.is_empty() {
+        return;
+    }
+
+    print_output_line(
+        format!(
+            "{} is quiet, these are its last {} lines of output:",
+            task_name,
+            output.len()
+        ),
+        options,
+    );
+
+    // the other sinks got the lines already
+    for text in output {
+        sink::Console.write(&sink::Line {
+            task: &output_name(task_name, options),
+            task_path: &options.task_path,
+            text,
+            time: Local::now(),
+            color,
+            quiet: false,
+            options,
+        });
+    }
+}
+
+/// Removes the escape sequences of a line, for output that isn't shown in a terminal.
+fn strip_escapes(line: &str) -> String {
+    let mut stripped = String::with_capacity(line.len());
+    let mut chars = line.chars().peekable();
+
+    while let Some(char) = chars.next() {
+        if char != '\u{1b}' {
+            stripped.push(char);
+            continue;
+        }
+
+        // a control sequence ends with a byte in the range @ to ~
+        if chars.next_if_eq(&'[').is_some() {
+            for char in chars.by_ref() {
+                if ('@'..='~').contains(&char) {
+                    break;
+                }
+            }
+        }
+    }
+
+    stripped
+}
+
+/// Cuts a line after `max_length` characters, telling how many bytes were left out.
+fn truncate_line(mut line: String, max_length: Option<usize>) -> String {
+    let Some((cut, _)) = max_length.and_then(|max_length| line.char_indices().nth(max_length))
+    else {
+        return line;
+    };
+
+    let cut_bytes = line.len() - cut;
+    line.truncate(cut);
+    line + &format!("… ({} more bytes)", cut_bytes)
+}
+
+/// Prints a line of output, or holds it back until its task finished with `group_output`.
+fn print_output_line(line: String, options: &RunOptions) {
+    match &options.output_group {
+        Some(output_group) => supervise::lock(output_group).push(line),
+        None => status::println(&line),
+    }
+}
+
+/// Prints the output a task held back as one block.
+fn flush_output_group(options: &RunOptions) {
+    if let Some(output_group) = &options.output_group {
+        let lines = std::mem::take(&mut *supervise::lock(output_group));
+        status::println_all(&lines);
+    }
+}
+
+/// The name the output of a task shows, with the label of the parallel branch it runs in, like
+/// `run/infra` for the branch itself and `run/infra > server` for a task it started.
+fn output_name<'a>(task_name: &'a str, options: &RunOptions) -> Cow<'a, str> {
+    match &options.branch_label {
+        Some((task, label)) if task == task_name => Cow::Owned(format!("{}/{}", task, label)),
+        Some((task, label)) => Cow::Owned(format!("{}/{} > {}", task, label, task_name)),
+        None => Cow::Borrowed(task_name),
+    }
+}
+
+/// Passes a line of output to the sinks of the run.
+fn write_line(text: &str, task_name: &str, color: &str, quiet: bool, options: &RunOptions) {
+    let line = sink::Line {
+        task: &output_name(task_name, options),
+        task_path: &options.task_path,
+        text,
+        time: Local::now(),
+        color,
+        quiet,
+        options,
+    };
+
+    for sink in &options.sinks {
+        sink.write(&line);
+    }
+}
+
+/// Starts a process on the runtime, with its pid for stopping it.
+fn spawn_child(command: Command, task_name: &str) -> Result<(Child, u32), Error> {
+    // the command is dropped right away, so the process has the only copies of its pipes
+    let child = tokio::process::Command::from(command)
+        .spawn()
+        .or_error(&format!("Failed to run task {}", task_name))?;
+    // only a process that was waited for has no pid
+    let pid = child
+        .id()
+        .or_error("Could not get the pid of the process")?;
+    Ok((child, pid))
+}
+
+/// Starts the `tee_to` command of the running task, its output is only read if it replaces the
+/// one of the task.
+fn spawn_tee(task_name: &str, options: &RunOptions) -> Option<Child> {
+    let tee_to = options.tee_to.as_ref()?;
+    let mut command = get_shell(options.shell.as_deref());
+    command
+        .arg(&tee_to.command)
+        .current_dir(&options.dir)
+        .envs(options.env.iter().map(|(key, value)| (key, value)))
+        .stdin(Stdio::piped())
+        .stdout(if tee_to.replace {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        });
+
+    tokio::process::Command::from(command)
+        .spawn()
+        .map_err(|err| {
+            eprintln!(
+                "Could not start the tee_to command of task {}: {}",
+                task_name, err
+            )
+        })
+        .ok()
+}
+
+/// Passes the output of a process line by line to the sinks of the run, the console prefixes
+/// them with the colored task name. The output of quiet tasks isn't printed, it still ends up in
+/// the history of the run and the other sinks. With `tee_to` the lines go through a command as
+/// well.
+async fn print_lines(
+    mut output: impl AsyncBufRead + Unpin,
+    task_name: &str,
+    color: &str,
+    quiet: bool,
+    options: &RunOptions,
+) {
+    // the task names of the console line up once a task with a longer name started
+    if options.color && !quiet {
+        PADDING.fetch_max(output_name(task_name, options).len() + 1, Ordering::SeqCst);
+    }
+
+    let mut tee = spawn_tee(task_name, options);
+    let mut tee_stdin = tee.as_mut().and_then(|tee| tee.stdin.take());
+    let tee_stdout = tee.as_mut().and_then(|tee| tee.stdout.take());
+    let replaced = tee_stdout.is_some();
+
+    // the sinks get the output of the command instead
+    let print_tee = async {
+        if let Some(tee_stdout) = tee_stdout {
+            let mut tee_stdout = BufReader::new(tee_stdout);
+            let mut printer = supervise::Printer::new(task_name);
+
+            while let Some(text) = read_line(&mut tee_stdout).await {
+                let text = sanitize_string(text);
+                printer.print(|| write_line(&text, task_name, color, quiet, options));
+            }
+        }
+    };
+
+    let print_output = async {
+        let mut printer = supervise::Printer::new(task_name);
+
+        while let Some(text) = read_line(&mut output).await {
+            let text = sanitize_string(text);
+            let plain = strip_escapes(&text);
+
+            // a command that stopped reading, like `head`, gets no more lines
+            if let Some(stdin) = &mut tee_stdin {
+                if stdin
+                    .write_all(format!("{}\n", plain).as_bytes())
+                    .await
+                    .is_err()
+                {
+                    tee_stdin = None;
+                }
+            }
+
+            printer.print(|| {
+                history::log(task_name, color, &plain);
+
+                if let Some(recent_output) = &options.recent_output {
+                    diagnostics::push_line(&mut supervise::lock(recent_output), plain);
+                }
+
+                if !replaced {
+                    write_line(&text, task_name, color, quiet, options);
+                }
+            });
+        }
+
+        // the command ends once its input does
+        drop(tee_stdin);
+    };
+
+    tokio::join!(print_tee, print_output);
+
+    if let Some(mut tee) = tee {
+        let _ = tee.wait().await;
+    }
+}
+
+/// Runs the command in a pty, so programs keep their colors and flush their output line by line.
+#[cfg(unix)]
+async fn run_multiplexed(
+    std_command: Command,
+    task_name: &str,
+    color: &str,
+    quiet: bool,
+    options: &RunOptions,
+) -> Result<i32, Error> {
+    let (mut child, output) =
+        pty::spawn(std_command).or_error(&format!("Failed to run task {}", task_name))?;
+    let pid = child
+        .id()
+        .or_error("Could not get the pid of the process")?;
+
+    register_child(pid, task_name, options);
+    let idle = watch_idle(pid, task_name, options);
+
+    // quiet output is still read, a full pty would block the process
+    let output = idle::Watched {
+        inner: output,
+        idle: idle.clone(),
+    };
+    print_lines(BufReader::new(output), task_name, color, quiet, options).await;
+
+    let status = child.wait().await;
+    unregister_child(pid, options);
+
+    if let Some(idle) = idle {
+        idle.finish();
+    }
+
+    Ok(exit_code(
+        status.or_error(&format!("Task {} failed", task_name))?,
+    ))
+}
+
+/// Where the stdout of a process without a pty goes, its stderr is always printed.
+enum Output<'a> {
+    Print,
+    Capture(&'a mut String),
+    Pipe(PipeWriter),
+}
+
+/// Runs the command with pipes instead of a pty, printing the output as it comes.
+async fn run_piped(
+    mut std_command: Command,
+    task_name: &str,
+    color: &str,
+    quiet: bool,
+    options: &RunOptions,
+    stdout: Output<'_>,
+) -> Result<i32, Error> {
+    // the output going into the next process of a pipe can't be watched
+    let watched = !matches!(stdout, Output::Pipe(_));
+    let (stdout, mut captured) = match stdout {
+        Output::Print => (Stdio::piped(), None),
+        Output::Capture(captured) => (Stdio::piped(), Some(captured)),
+        Output::Pipe(pipe) => (pipe.into(), None),
+    };
+
+    std_command.stdout(stdout).stderr(Stdio::piped());
+    let (mut child, pid) = spawn_child(std_command, task_name)?;
+
+    register_child(pid, task_name, options);
+    let idle = watched
+        .then(|| watch_idle(pid, task_name, options))
+        .flatten();
+
+    let (stdout, stderr) = (child.stdout.take(), child.stderr.take());
+
+    let print_stderr = async {
+        if let Some(stderr) = stderr {
+            let stderr = idle::Watched {
+                inner: stderr,
+                idle: idle.clone(),
+            };
+            print_lines(BufReader::new(stderr), task_name, color, quiet, options).await;
+        }
+    };
+
+    let read_stdout = async {
+        let Some(stdout) = stdout else {
+            return Ok(0);
+        };
+
+        let mut stdout = idle::Watched {
+            inner: stdout,
+            idle: idle.clone(),
+        };
+
+        match &mut captured {
+            Some(captured) => stdout.read_to_string(captured).await,
+            None => {
+                print_lines(BufReader::new(stdout), task_name, color, quiet, options).await;
+                Ok(0)
+            }
+        }
+    };
+
+    let ((), captured_result) = tokio::join!(print_stderr, read_stdout);
+
+    // the process is waited for either way, so it doesn't outlive the task
+    let status = child.wait().await;
+    unregister_child(pid, options);
+
+    if let Some(idle) = idle {
+        idle.finish();
+    }
+
+    captured_result.or_error(&format!(
+        "Could not capture the output of task {}",
+        task_name
+    ))?;
+    Ok(exit_code(
+        status.or_error(&format!("Task {} failed", task_name))?,
+    ))
+}
+
+/// Without a pty the output is read through pipes, stdout and stderr are printed as they come.
+#[cfg(not(unix))]
+async fn run_multiplexed(
+    mut std_command: Command,
+    task_name: &str,
+    color: &str,
+    quiet: bool,
+    options: &RunOptions,
+) -> Result<i32, Error> {
+    std_command.stdin(Stdio::null());
+    run_piped(std_command, task_name, color, quiet, options, Output::Print).await
+}
+
+/// Runs a branch of a `parallel` or `pipe` step in a task of its own.
+fn spawn_branch(
+    branch: &Step,
+    config: &Arc<Config>,
+    task_prefix: &str,
+    task_name: &str,
+    mut options: RunOptions,
+) -> JoinHandle<i32> {
+    let branch = branch.clone();
+    let config = config.clone();
+    let task_prefix = task_prefix.to_string();
+    let task_name = task_name.to_string();
+
+    let reserved = reserve_color();
+    options.reserved = reserved.clone();
+    options.port_offset = Arc::new(Mutex::new(None));
+
+    supervise::spawn(task_prefix.clone(), async move {
+        let code = run_step(&branch, &config, &task_prefix, &task_name, &mut options).await;
+        release_color(&reserved);
+        code
+    })
+}
+
+/// Hands out the colors and ports in the order of the branches, not the order their tasks
+/// start in.
+fn reserve_color() -> Arc<Mutex<(Option<u32>, Option<u32>)>> {
+    Arc::new(Mutex::new((
+        Some(INDEX.fetch_add(1, Ordering::SeqCst)),
+        Some(PORT_OFFSET.fetch_add(1, Ordering::SeqCst)),
+    )))
+}
+
+/// Gives the color of a branch back if it didn't run a shell.
+fn release_color(reserved: &Mutex<(Option<u32>, Option<u32>)>) {
+    if reserved.lock().unwrap().0.take().is_some() {
+        INDEX.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// A pipe that the `stdin` of a step is written into, by a blocking thread of the runtime so the
+/// input can be larger than the buffer of the pipe.
+fn step_input(input: &StepInput) -> Result<PipeReader, String> {
+    let mut source: Box<dyn Read + Send> = match input {
+        StepInput::File(path) => Box::new(
+            File::open(path)
+                .map_err(|err| format!("could not read its stdin {}: {}", path.display(), err))?,
+        ),
+        StepInput::Text(text) => Box::new(io::Cursor::new(text.clone().into_bytes())),
+    };
+    let (reader, mut writer) = pipe().map_err(|err| err.to_string())?;
+
+    // the command doesn't have to read all of it, so a closed pipe isn't an error
+    runtime::get().spawn_blocking(move || {
+        let _ = io::copy(&mut source, &mut writer);
+    });
+
+    Ok(reader)
+}
+
+/// Waits for every branch before reporting, so no branch is left running.
+async fn join_branches(branches: Vec<JoinHandle<i32>>) -> i32 {
+    let mut results = vec![];
+
+    for branch in branches {
+        // the branches catch their own panics
+        results.push(branch.await.unwrap_or(FAILURE_CODE));
+    }
+
+    results.into_iter().find(|code| *code != 0).unwrap_or(0)
+}
+
+fn run_step<'a>(
+    step: &'a Step,
+    config: &'a Arc<Config>,
+    task_prefix: &'a str,
+    task_name: &'a str,
+    options: &'a mut RunOptions,
+) -> Running<'a, i32> {
+    Box::pin(async move {
+        match step {
+            Step::Shell {
+                command,
+                capture,
+                stdin,
+            } => match command.for_os(env::consts::OS) {
+                Some(command) => {
+                    let input_options;
+                    let shell_options = match stdin {
+                        Some(input) => match step_input(input) {
+                            Ok(reader) => {
+                                input_options = RunOptions {
+                                    pipe_stdin: Some(Arc::new(reader)),
+                                    ..options.clone()
+                                };
+                                &input_options
+                            }
+                            Err(err) => {
+                                eprintln!("Failed to run task {}: {}", task_name, err);
+                                return FAILURE_CODE;
+                            }
+                        },
+                        None => &*options,
+                    };
+
+                    let Some(name) = capture else {
+                        return run_shell(
+                            command.to_string(),
+                            task_name.to_string(),
+                            shell_options,
+                            None,
+                        )
+                        .await;
+                    };
+
+                    let mut output = String::new();
+                    let code = run_shell(
+                        command.to_string(),
+                        task_name.to_string(),
+                        shell_options,
+                        Some(&mut output),
+                    )
+                    .await;
+
+                    // like $(...) in a shell, without the trailing newline
+                    if code == 0 {
+                        options.env.push((name.clone(), output.trim().to_string()));
+                    }
+
+                    code
+                }
+                None => {
+                    eprintln!(
+                        "Task {} has no shell command for {}",
+                        task_name,
+                        env::consts::OS
+                    );
+                    FAILURE_CODE
+                }
+            },
+            Step::Task(sub_task) => {
+                let mut options = options.clone();
+                options.task_env.extend(sub_task.env.iter().cloned());
+                // the arguments are only for the referenced task, not for the tasks it runs
+                options.args = sub_task.args.clone().unwrap_or_default();
+
+                cli_run_task(
+                    config.clone(),
+                    sub_task.name.clone(),
+                    task_prefix.to_string() + " > " + &sub_task.name,
+                    options,
+                )
+                .await
+                .unwrap_or_else(failed)
+            }
+            Step::Parallel(branches) => {
+                let mut branches: Vec<_> = branches.iter().collect();
+
+                if let Some(seed) = options.shuffle {
+                    shuffle::shuffle(&mut branches, seed, task_prefix);
+                }
+
+                join_branches(
+                    branches
+                        .into_iter()
+                        .map(|branch| {
+                            spawn_branch(branch, config, task_prefix, task_name, options.clone())
+                        })
+                        .collect(),
+                )
+                .await
+            }
+            Step::Pipe(branches) => {
+                let mut branches_running = vec![];
+                let mut stdin = options.pipe_stdin.clone();
+
+                for (index, branch) in branches.iter().enumerate() {
+                    let mut branch_options = options.clone();
+                    branch_options.pipe_stdin = stdin.take();
+
+                    // the last branch writes wherever the pipe step itself writes
+                    if index + 1 < branches.len() {
+                        let (reader, writer) = match pipe() {
+                            Ok(pipe) => pipe,
+                            Err(err) => {
+                                eprintln!("Failed to run task {}: {}", task_name, err);
+                                // close the pipe, so the started branches don't wait for a reader
+                                drop(stdin.take());
+                                join_branches(branches_running).await;
+                                return FAILURE_CODE;
+                            }
+                        };
+
+                        branch_options.pipe_stdout = Some(Arc::new(writer));
+                        stdin = Some(Arc::new(reader));
+                    }
+
+                    branches_running.push(spawn_branch(
+                        branch,
+                        config,
+                        task_prefix,
+                        task_name,
+                        branch_options,
+                    ));
+                }
+
+                join_branches(branches_running).await
+            }
+            Step::Compose { service, file } => {
+                let mut command = "docker compose".to_string();
+
+                if let Some(file) = file {
+                    command += &format!(" -f '{}'", file.replace('\'', "'\\''"));
+                }
+
+                command += &format!(" up --no-log-prefix '{}'", service.replace('\'', "'\\''"));
+
+                // compose runs the containers itself
+                let host_options = RunOptions {
+                    container: None,
+                    ..options.clone()
+                };
+                run_shell(command, task_name.to_string(), &host_options, None).await
+            }
+            Step::Poll(poll) => run_poll(poll, task_prefix, task_name, options).await,
+            Step::Delayed {
+                step,
+                delay,
+                jitter,
+            } => {
+                if !shutdown::sleep(*delay + shuffle::random_duration(*jitter)).await {
+                    return shutdown::INTERRUPTED_EXIT_CODE;
+                }
+
+                run_step(step, config, task_prefix, task_name, options).await
+            }
+            // the branches of parallel and pipe steps run at the same time anyway
+            Step::Background(step) => run_step(step, config, task_prefix, task_name, options).await,
+            Step::Labeled { label, step } => {
+                options.branch_label = Some((task_name.to_string(), label.clone()));
+                let task_prefix = format!("{}/{}", task_prefix, label);
+                run_step(step, config, &task_prefix, task_name, options).await
+            }
+            Step::File(file) => {
+                // pilot runs the step itself, it can't reach into a container or onto a host
+                if options.remote.is_some()
+                    || options.kubernetes.is_some()
+                    || options.container.is_some()
+                {
+                    eprintln!(
+                    "Task {} failed: {} only works locally, use a shell step in containers, pods and on hosts",
+                    task_name,
+                    file.key()
+                );
+                    return FAILURE_CODE;
+                }
+
+                let env: Vec<_> = options
+                    .env
+                    .iter()
+                    .chain(&options.task_env)
+                    .chain(&options.env_overrides)
+                    .collect();
+                let expand = |path: &str| {
+                    expand::substitute(path, |name| {
+                        env.iter()
+                            .rev()
+                            .find(|(key, _)| key == name)
+                            .map(|(_, value)| value.clone())
+                            .or_else(|| env::var(name).ok())
+                    })
+                };
+
+                // copying a directory can take a while
+                match block_in_place(|| file.run(&options.dir, expand)) {
+                    Ok(()) => 0,
+                    Err(err) => {
+                        eprintln!("Task {} failed: {}", task_name, err);
+                        FAILURE_CODE
+                    }
+                }
+            }
+            Step::WaitFor(wait_for) => match wait_for.wait().await {
+                Ok(()) => 0,
+                Err(err) => {
+                    eprintln!("Task {} failed: {}", task_name, err);
+                    FAILURE_CODE
+                }
+            },
+            Step::Setting(setting) => {
+                match setting {
+                    Setting::Raw(raw) => options.raw = *raw,
+                    Setting::User(user) => options.user = Some(user.clone()),
+                    Setting::Nice(nice) => options.nice = Some(*nice),
+                    Setting::Umask(umask) => options.umask = Some(*umask),
+                    Setting::Setsid(setsid) => options.setsid = *setsid,
+                    Setting::Env(env) => options.env.extend(env.iter().cloned()),
+                    Setting::Container(container) => options.container = Some(container.clone()),
+                    Setting::PathPrepend(paths) => {
+                        options.path_prepend = paths
+                            .iter()
+                            .cloned()
+                            .chain(options.path_prepend.drain(..))
+                            .collect()
+                    }
+                    Setting::Kubernetes(kubernetes) => {
+                        options.kubernetes = Some(kubernetes.clone())
+                    }
+                    Setting::Host(remote) => options.remote = Some(remote.clone()),
+                    Setting::Limits(limits) => options.limits = limits.clone(),
+                    Setting::SuccessCodes(codes) => options.success_codes = Some(codes.clone()),
+                }
+
+                0
+            }
+        }
+    })
+}
+
+/// The options for the attempts of a check, their output only ends up in the history of the run.
+fn check_options(task_name: &str, options: &RunOptions) -> RunOptions {
+    let mut quiet_options = options.clone();
+    quiet_options.quiet_tasks.push(task_name.to_string());
+    quiet_options.diagnostics = false;
+    quiet_options.probe = true;
+    // the check is no process of the service
+    quiet_options.service = None;
+    quiet_options
+}
+
+/// Runs the command of a `poll` step until it succeeds, the output of the attempts only ends
+/// up in the history of the run.
+async fn run_poll(poll: &Poll, task_prefix: &str, task_name: &str, options: &RunOptions) -> i32 {
+    let start = Instant::now();
+    let status_prefix = task_prefix.to_string() + " > poll";
+    let quiet_options = check_options(task_name, options);
+
+    status::start(&status_prefix, None, false);
+    let mut attempts = 1;
+
+    let code = loop {
+        if run_shell(
+            poll.command.clone(),
+            task_name.to_string(),
+            &quiet_options,
+            None,
+        )
+        .await
+            == 0
+        {
+            if !options.silent {
+                let plural = if attempts == 1 { "" } else { "s" };
+                status::println(&format!(
+                    "poll {}: ready after {} attempt{}",
+                    task_prefix, attempts, plural
+                ));
+            }
+
+            break 0;
+        }
+
+        if shutdown::is_interrupted() {
+            break shutdown::INTERRUPTED_EXIT_CODE;
+        }
+
+        if start.elapsed() + poll.interval >= poll.timeout {
+            let hint = match history::run_id() {
+                Some(_) => format!(", pilot logs last {} shows their output", task_name),
+                None => String::new(),
+            };
+            eprintln!(
+                "Task {} failed: `{}` still failed after {} attempts in {}{}",
+                task_name,
+                poll.command,
+                attempts,
+                clock::human(poll.timeout),
+                hint
+            );
+            break FAILURE_CODE;
+        }
+
+        if !shutdown::sleep(poll.interval).await {
+            break shutdown::INTERRUPTED_EXIT_CODE;
+        }
+
+        attempts += 1;
+    };
+
+    status::finish(&status_prefix);
+    code
+}
+
+/// Runs a list of steps in order, stopping at the first one that fails.
+async fn run_steps(
+    steps: &[Step],
+    config: &Arc<Config>,
+    task: &str,
+    task_prefix: &str,
+    mut options: RunOptions,
+    mut timings: Option<&mut Vec<Duration>>,
+) -> i32 {
+    let mut background = vec![];
+
+    let code = 'steps: {
+        for step in steps {
+            // an interrupted run doesn't start anything new, except for cleanup
+            if shutdown::is_interrupted() && !options.cleanup {
+                break 'steps shutdown::INTERRUPTED_EXIT_CODE;
+            }
+
+            // the steps of a stopped background step don't go on either
+            if background_stopped(&options) {
+                break 'steps 0;
+            }
+
+            // retries count towards the step
+            let start = clock::Stopwatch::start();
+            let _span = trace::span("step", || step_label(step));
+            let mut record = || {
+                if let Some(timings) = timings.as_mut() {
+                    timings.push(start.elapsed());
+                }
+            };
+
+            if let Step::Background(step) = step {
+                let processes = Background::within(options.background.clone());
+                let background_options = RunOptions {
+                    background: Some(processes.clone()),
+                    ..options.clone()
+                };
+
+                background.push((
+                    processes,
+                    spawn_branch(step, config, task_prefix, task, background_options),
+                ));
+                record();
+                continue;
+            }
+
+            loop {
+                let code = run_step(step, config, task_prefix, task, &mut options).await;
+
+                if code == 0 {
+                    break;
+                }
+
+                // a failed step fails the whole task, the remaining steps are skipped
+                match ask_on_failure(task, step, code, &options).await {
+                    FailureAction::Retry => {
+                        events::fire(
+                            config,
+                            &options.dir,
+                            options.shell.as_deref(),
+                            "task_restarted",
+                            &[
+                                ("task", events::Value::Text(task)),
+                                ("step", events::Value::Text(step.key())),
+                                ("exit_code", events::Value::Number(code)),
+                            ],
+                        )
+                        .await
+                    }
+                    FailureAction::Skip => break,
+                    FailureAction::Abort => {
+                        record();
+                        break 'steps code;
+                    }
+                }
+            }
+
+            record();
+        }
+
+        0
+    };
+
+    let background_code = stop_background(background).await;
+
+    if code == 0 {
+        background_code
+    } else {
+        code
+    }
+}
+
+/// Stops the processes of a background step, the steps after them don't run either.
+fn stop_processes(processes: &Mutex<Background>) {
+    let mut processes = processes.lock().unwrap();
+    processes.stopped = true;
+
+    for pid in &processes.pids {
+        shutdown::terminate(*pid);
+    }
+}
+
+/// Stops the processes of background steps once the steps after them are done. A background
+/// step that failed by itself before that fails the steps as well.
+async fn stop_background(background: Vec<(Arc<Mutex<Background>>, JoinHandle<i32>)>) -> i32 {
+    let mut code = 0;
+
+    for (processes, run) in background {
+        let finished = run.is_finished();
+        stop_processes(&processes);
+
+        let background_code = run.await.unwrap_or(FAILURE_CODE);
+
+        if finished && code == 0 {
+            code = background_code;
+        }
+    }
+
+    code
+}
+
+/// Describes a step for the timings, shell steps by the first line of their command.
+fn step_label(step: &Step) -> String {
+    const MAX_LENGTH: usize = 40;
+
+    match step {
+        Step::Shell { command, .. } => {
+            let command = command
+                .for_os(env::consts::OS)
+                .and_then(|command| command.trim().lines().next())
+                .unwrap_or("");
+
+            match command.char_indices().nth(MAX_LENGTH) {
+                Some((index, _)) => format!("shell: {}...", &command[..index]),
+                None => format!("shell: {}", command),
+            }
+        }
+        Step::Task(task) => format!("task: {}", task.name),
+        Step::Delayed { step, .. } => format!("delayed {}", step_label(step)),
+        Step::Labeled { label, step } => format!("{}: {}", label, step_label(step)),
+        Step::Background(step) => format!("background {}", step_label(step)),
+        step => step.key().to_string(),
+    }
+}
+
+/// Prints how long each step of a task took, settings are left out as they take no time.
+fn print_timings(task_prefix: &str, steps: &[Step], timings: &[Duration]) {
+    let timed: Vec<_> = steps
+        .iter()
+        .zip(timings)
+        .filter(|(step, _)| !matches!(step, Step::Setting(_)))
+        .collect();
+
+    if timed.len() < 2 {
+        return;
+    }
+
+    status::println(&format!("timings {}", task_prefix));
+
+    for (step, duration) in timed {
+        status::println(&format!(
+            "{:>10} {}",
+            clock::human(*duration),
+            step_label(step)
+        ));
+    }
+}
+
+fn stdin_is_tty() -> bool {
+    #[cfg(unix)]
+    unsafe {
+        libc::isatty(libc::STDIN_FILENO) == 1
+    }
+
+    #[cfg(not(unix))]
+    false
+}
+
+fn stdout_is_tty() -> bool {
+    #[cfg(unix)]
+    unsafe {
+        libc::isatty(libc::STDOUT_FILENO) == 1
+    }
+
+    #[cfg(not(unix))]
+    false
+}
+
+/// Colors are on for terminals, `NO_COLOR` and `CLICOLOR_FORCE` override that.
+pub fn color_default() -> bool {
+    let set = |name| env::var_os(name).is_some_and(|value| !value.is_empty() && value != "0");
+
+    !set("NO_COLOR") && (set("CLICOLOR_FORCE") || stdout_is_tty())
+}
+
+/// Parallel tasks must not ask at the same time.
+static PROMPT: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+/// Prints the question and reads the answer of the user, trimmed and lowercase. An answer that
+/// can't be read is empty, which never runs anything.
+async fn ask(question: &str) -> String {
+    status::paused(async {
+        block_in_place(|| {
+            print!("{} ", question);
+            let _ = stdout().flush();
+
+            let mut answer = String::new();
+
+            if let Err(err) = stdin().read_line(&mut answer) {
+                eprintln!("Could not read the answer: {}", err);
+                answer.clear();
+            }
+
+            answer.trim().to_lowercase()
+        })
+    })
+    .await
+}
+
+/// Asks the user whether a dangerous task should really run, after showing its preview.
+async fn confirm(task: &str, question: &str, preview: Option<&str>, options: &RunOptions) -> bool {
+    let _prompt = PROMPT.lock().await;
+
+    if !stdin_is_tty() {
+        eprintln!(
+            "Task {} needs confirmation, but there is no terminal to ask. Use --yes to run it anyway",
+            task
+        );
+        return false;
+    }
+
+    if let Some(preview) = preview {
+        if run_preview(preview, task, options).await != 0 {
+            return false;
+        }
+    }
+
+    matches!(
+        ask(&format!("{} [y/N]", question)).await.as_str(),
+        "y" | "yes"
+    )
+}
+
+/// Runs the `preview` command of a task and prints its output as a section, with the added and
+/// removed lines of a diff in green and red. GitHub Actions folds the section in its logs.
+async fn run_preview(command: &str, task: &str, options: &RunOptions) -> i32 {
+    let mut output = String::new();
+    let options = RunOptions {
+        diagnostics: false,
+        ..options.clone()
+    };
+    let code = run_shell(
+        command.to_string(),
+        task.to_string(),
+        &options,
+        Some(&mut output),
+    )
+    .await;
+
+    if code != 0 {
+        eprintln!(
+            "Task {} failed: its preview `{}` failed with exit code {}",
+            task, command, code
+        );
+        return code;
+    }
+
+    let github_actions = env::var_os("GITHUB_ACTIONS").is_some();
+
+    if github_actions {
+        status::println(&format!("::group::preview {}", task));
+    } else {
+        status::println(&format!("preview {}:", task));
+    }
+
+    for line in output.lines() {
+        let line = sanitize_string(line.to_string());
+
+        // lines the command colored itself keep their colors
+        let line = match line.chars().next() {
+            _ if !options.color => strip_escapes(&line),
+            _ if line.contains('\u{1b}') => line,
+            Some('+') => format!("\x1b[0;32m{}\x1b[0m", line),
+            Some('-') => format!("\x1b[0;31m{}\x1b[0m", line),
+            _ => line,
+        };
+
+        status::println(&format!("  {}", line));
+    }
+
+    if github_actions {
+        status::println("::endgroup::");
+    }
+
+    0
+}
+
+/// The tasks the steps start, including the ones in parallel, pipe and background steps.
+fn task_refs(steps: &[Step]) -> Vec<&TaskRef> {
+    steps
+        .iter()
+        .flat_map(|step| match step {
+            Step::Task(task_ref) => vec![task_ref],
+            Step::Parallel(branches) | Step::Pipe(branches) => task_refs(branches),
+            Step::Delayed { step, .. } | Step::Background(step) | Step::Labeled { step, .. } => {
+                task_refs(std::slice::from_ref(step))
+            }
+            _ => vec![],
+        })
+        .collect()
+}
+
+/// Shows the previews of a task and the tasks it would start, without running any of them.
+async fn dry_run_task(
+    config: &Arc<Config>,
+    task: &Task,
+    task_prefix: &str,
+    options: &RunOptions,
+) -> i32 {
+    if let Some(preview) = &task.preview {
+        let code = run_preview(preview, &task.name, options).await;
+
+        if code != 0 {
+            return code;
+        }
+    }
+
+    let runs = |task: &TaskRef| options.pre_post && config.task(&task.name).is_some();
+    let pre_post = |name: String| {
+        Some(TaskRef {
+            name,
+            env: vec![],
+            args: None,
+        })
+        .filter(runs)
+    };
+    let pre_task = pre_post("pre".to_string() + &task.name);
+    let post_task = pre_post("post".to_string() + &task.name);
+    let sub_tasks = task_refs(&task.steps).into_iter().cloned();
+    // the tasks it needs start first
+    let needs = task.needs.iter().map(|name| TaskRef {
+        name: name.clone(),
+        env: vec![],
+        args: None,
+    });
+
+    for sub_task in needs.chain(pre_task).chain(sub_tasks).chain(post_task) {
+        let mut options = options.clone();
+        options.task_env.extend(sub_task.env.iter().cloned());
+        options.args = sub_task.args.clone().unwrap_or_default();
+
+        let code = cli_run_task(
+            config.clone(),
+            sub_task.name.clone(),
+            task_prefix.to_string() + " > " + &sub_task.name,
+            options,
+        )
+        .await
+        .unwrap_or_else(failed);
+
+        if code != 0 {
+            return code;
+        }
+    }
+
+    0
+}
+
+enum FailureAction {
+    Retry,
+    Skip,
+    Abort,
+}
+
+/// Asks the user what to do about a failed step, if there is a terminal to ask.
+async fn ask_on_failure(task: &str, step: &Step, code: i32, options: &RunOptions) -> FailureAction {
+    let step = match step {
+        Step::Delayed { step, .. } | Step::Labeled { step, .. } => step,
+        step => step,
+    };
+
+    // steps running other steps let those ask, cleanup must not wait for the user
+    let asks = options.prompt_on_failure
+        && !options.cleanup
+        && !shutdown::is_interrupted()
+        && !matches!(step, Step::Task(_) | Step::Parallel(_))
+        && stdin_is_tty();
+
+    if !asks {
+        return FailureAction::Abort;
+    }
+
+    let _prompt = PROMPT.lock().await;
+    let question = format!(
+        "The {} step of task {} failed with exit code {}. [r]etry, [s]kip or [a]bort?",
+        step.key(),
+        task,
+        code
+    );
+
+    loop {
+        match ask(&question).await.as_str() {
+            "r" | "retry" => return FailureAction::Retry,
+            "s" | "skip" => return FailureAction::Skip,
+            "a" | "abort" | "" => return FailureAction::Abort,
+            _ => {}
+        }
+    }
+}
+
+fn print_task_start(task_prefix: &str, options: &RunOptions) {
+    print_task_start_described(task_prefix, None, options);
+}
+
+/// Prints the start of a task with its description below it, dimmed in color so it stands out
+/// from the output. Other tasks starting at the same time can't come between the two lines.
+fn print_task_start_described(task_prefix: &str, description: Option<&str>, options: &RunOptions) {
+    if options.silent {
+        return;
+    }
+
+    let mut lines = vec![];
+
+    if options.timestamp() {
+        lines.push(format!(
+            "{} > {}",
+            clock::now(&options.timestamp_format),
+            task_prefix
+        ));
+    } else {
+        lines.push(format!("> {}", task_prefix));
+    }
+
+    match description {
+        Some(description) if options.color => {
+            lines.push(format!("\x1b[2m  {}\x1b[0m", description))
+        }
+        Some(description) => lines.push(format!("  {}", description)),
+        None => {}
+    }
+
+    status::println_all(&lines);
+}
+
+fn print_task_end(task_prefix: &str, code: i32, options: &RunOptions) {
+    let outcome = if code == 0 { "finished" } else { "failed" };
+    print_task_outcome(task_prefix, outcome, options);
+}
+
+/// Prints how a task ended, like `finished build`.
+fn print_task_outcome(task_prefix: &str, outcome: &str, options: &RunOptions) {
+    if options.silent {
+        return;
+    }
+
+    // the process exited
+    if options.timestamp() {
+        status::println(&format!(
+            "{} {} {}",
+            clock::now(&options.timestamp_format),
+            outcome,
+            task_prefix
+        ));
+    } else {
+        status::println(&format!("{} {}", outcome, task_prefix));
+    }
+}
+
+/// The steps of a task from the step with the id `from` to the one with the id `until`. The
+/// settings before `from` still apply to the steps after them.
+fn step_range<'a>(
+    task: &'a Task,
+    from: Option<&str>,
+    until: Option<&str>,
+) -> Result<Cow<'a, [Step]>, String> {
+    if from.is_none() && until.is_none() {
+        return Ok(Cow::Borrowed(&task.steps));
+    }
+
+    let position = |id: &str| {
+        task.step_ids
+            .iter()
+            .position(|step_id| step_id.as_deref() == Some(id))
+            .ok_or_else(|| format!("Task {} has no step with the id {}", task.name, id))
+    };
+
+    let start = from.map(position).transpose()?.unwrap_or(0);
+    let end = until
+        .map(position)
+        .transpose()?
+        .unwrap_or(task.steps.len() - 1);
+
+    if end < start {
+        return Err(format!(
+            "The step {} of task {} comes before the step {}",
+            until.unwrap_or_default(),
+            task.name,
+            from.unwrap_or_default()
+        ));
+    }
+
+    let settings = task.steps[..start]
+        .iter()
+        .filter(|step| matches!(step, Step::Setting(_)));
+
+    Ok(Cow::Owned(
+        settings.chain(&task.steps[start..=end]).cloned().collect(),
+    ))
+}
+
+/// Runs a task with its pre and post tasks and hooks. Mistakes in how it is run, like an unknown
+/// task or arguments it doesn't take, are errors before it starts.
+fn cli_run_task(
+    config: Arc<Config>,
+    task: String,
+    task_prefix: String,
+    mut options: RunOptions,
+) -> Running<'static, Result<i32, Error>> {
+    Box::pin(async move {
+        let started = clock::Stopwatch::start();
+        options.task_path = task_prefix.clone();
+        options.port_offset = Arc::new(Mutex::new(None));
+
+        // only the task of the command line starts or stops at a step, not the tasks it runs
+        let from_step = options.from_step.take();
+        let until_step = options.until_step.take();
+
+        // subtasks hold back their own output, so it isn't printed with the one of this task
+        if options.group_output {
+            options.output_group = Some(Arc::new(Mutex::new(vec![])));
+        }
+
+        let Some(found_task) = config.task(&task) else {
+            return Err(Error(format!("Task {} not found in Pilotfile", task)));
+        };
+
+        if let Some(timestamp) = found_task.timestamp {
+            options.task_timestamp = timestamp;
+        }
+
+        if let Some(max_line_length) = found_task.max_line_length {
+            options.task_max_line_length = Some(max_line_length);
+        }
+
+        if let Some(idle_timeout) = found_task.idle_timeout {
+            options.idle_timeout = Some(idle_timeout);
+        }
+
+        if let Some(stop_signal) = found_task.stop_signal {
+            options.stop.signal = stop_signal;
+        }
+
+        if let Some(stop_grace_period) = found_task.stop_grace_period {
+            options.stop.grace_period = stop_grace_period;
+        }
+
+        if let Some(tee_to) = &found_task.tee_to {
+            options.tee_to = Some(tee_to.clone());
+        }
+
+        if let Some(output) = &found_task.output {
+            options.sinks = output
+                .iter()
+                .map(SinkSpec::open)
+                .collect::<Result<_, _>>()?;
+        }
+
+        // a task step passes the arguments in its `args`, like `env=prod tag=v1.2`
+        let given_args = match options.named_args.take() {
+            Some(given_args) => Some(given_args),
+            None if found_task.args.is_empty() => None,
+            None => Some(named_args(&options.args).ok_or_else(|| {
+                Error(format!(
+                    "Task {} takes its arguments as name=value, not {}",
+                    task, options.args
+                ))
+            })?),
+        };
+
+        if let Some(given_args) = given_args {
+            let values = found_task
+                .arg_values(&given_args)
+                .map_err(|err| Error(format!("{}\n{}", err, found_task.usage())))?;
+            options.task_env.extend(values);
+        }
+
+        let steps = step_range(found_task, from_step.as_deref(), until_step.as_deref())?;
+
+        // subtasks are services or jobs on their own
+        // a task that needs the service waits until it is ready
+        let needed_service = options.needed_service.take();
+        let service = found_task.is_service();
+        options.service = service.then(|| needed_service.unwrap_or_default());
+
+        // the estimates would make every run print something else, so only terminals show them,
+        // services have none since they run until they are stopped
+        let estimate = (stdout_is_tty() && !service)
+            .then(|| history::estimate(&task))
+            .flatten();
+
+        let start = match estimate {
+            Some((duration, 1)) => {
+                format!(
+                    "{} (~{} based on the last run)",
+                    task_prefix,
+                    clock::whole(duration)
+                )
+            }
+            Some((duration, runs)) => format!(
+                "{} (~{} based on the last {} runs)",
+                task_prefix,
+                clock::whole(duration),
+                runs
+            ),
+            None => task_prefix.clone(),
+        };
+        print_task_start_described(&start, found_task.description.as_deref(), &options);
+
+        status::start(
+            &task_prefix,
+            estimate.map(|(duration, _)| duration),
+            service,
+        );
+
+        let _span = trace::span("task", || task_prefix.clone());
+
+        // fail before anything started, instead of with the error of some tool minutes later
+        let missing_env: Vec<_> = found_task
+            .requires_env
+            .iter()
+            .filter(|name| env_value(name, &options).is_none_or(|value| value.is_empty()))
+            .map(String::as_str)
+            .collect();
+
+        if !missing_env.is_empty() {
+            eprintln!(
+                "Task {} needs environment variables that are not set: {}",
+                task,
+                missing_env.join(", ")
+            );
+            status::finish(&task_prefix);
+            print_task_end(&task_prefix, FAILURE_CODE, &options);
+            return Ok(FAILURE_CODE);
+        }
+
+        if options.dry_run {
+            let code = dry_run_task(&config, found_task, &task_prefix, &options).await;
+            status::finish(&task_prefix);
+
+            match code {
+                0 if !options.silent => status::println(&format!("skipped {}", task_prefix)),
+                0 => {}
+                code => print_task_end(&task_prefix, code, &options),
+            }
+
+            return Ok(code);
+        }
+
+        if let Some(question) = &found_task.confirm {
+            let preview = found_task.preview.as_deref();
+
+            if !options.yes && !confirm(&task, question, preview, &options).await {
+                status::finish(&task_prefix);
+                print_task_end(&task_prefix, FAILURE_CODE, &options);
+                return Ok(FAILURE_CODE);
+            }
+        }
+
+        // fail before anything started, instead of with the bind error of some service
+        let mut ports_free = true;
+
+        for port in &found_task.ports {
+            if let Some(listener) = block_in_place(|| ports::listener(*port)) {
+                eprintln!(
+                    "Port {} needed by task {} is already in use by {}",
+                    port, task, listener
+                );
+                ports_free = false;
+            }
+        }
+
+        if !ports_free {
+            status::finish(&task_prefix);
+            print_task_end(&task_prefix, FAILURE_CODE, &options);
+            return Ok(FAILURE_CODE);
+        }
+
+        // only a whole run of the task makes its outputs
+        let dir = options.dir.clone();
+        let cache_entry = found_task
+            .cache
+            .as_ref()
+            .filter(|_| from_step.is_none() && until_step.is_none())
+            .map(|cache| cache::Entry::new(found_task, cache, config.shared_cache.as_ref(), &dir));
+        // a restored task is done, with its pre and post tasks
+        let restored = cache_entry
+            .as_ref()
+            .is_some_and(|entry| block_in_place(|| entry.restore()));
+
+        // the tasks it needs run first, a restored task doesn't need them
+        let outer_background = options.background.clone();
+        let mut dependencies = (!restored && !found_task.needs.is_empty())
+            .then(|| needs::Dependencies::start(&config, found_task, &task_prefix, &mut options));
+
+        let failed_dependency = match dependencies.as_mut() {
+            Some(dependencies) => dependencies.wait_ready().await,
+            None => None,
+        };
+
+        if let Some(failed) = failed_dependency {
+            if let Some(dependencies) = dependencies {
+                dependencies.finish().await;
+            }
+
+            status::finish(&task_prefix);
+            print_task_outcome(
+                &format!("{}: dependency {} failed", task_prefix, failed),
+                "skipped",
+                &options,
+            );
+            return Ok(FAILURE_CODE);
+        }
+
+        // finally steps must also run if pilot exits while the task is running
+        let cleanup_options = RunOptions {
+            cleanup: true,
+            ..options.clone()
+        };
+        let finally_hook = found_task.finally.clone().map(|finally_steps| {
+            let config = config.clone();
+            let task = task.clone();
+            let task_prefix = task_prefix.clone();
+            let cleanup_options = cleanup_options.clone();
+
+            shutdown::on_exit(move || {
+                runtime::block_on(run_steps(
+                    &finally_steps,
+                    &config,
+                    &task,
+                    &task_prefix,
+                    cleanup_options,
+                    None,
+                ));
+            })
+        });
+
+        let pre_task = "pre".to_string() + &task;
+        let post_task = "post".to_string() + &task;
+
+        let mut code = 0;
+        let mut timings = vec![];
+
+        // the steps before the first one already ran, like the pre task
+        if !restored && options.pre_post && from_step.is_none() && config.task(&pre_task).is_some()
+        {
+            code = cli_run_task(
+                config.clone(),
+                pre_task.clone(),
+                task_prefix.clone() + " > " + &pre_task,
+                options.clone(),
+            )
+            .await
+            .unwrap_or_else(failed);
+        }
+
+        if code == 0 && !restored {
+            let mut restarts = 0;
+
+            code = loop {
+                // the timings are the ones of the last start
+                timings.clear();
+
+                let watch =
+                    match (&options.service, &found_task.health) {
+                        (Some(service), Some(health)) => {
+                            let check_options = check_options(&task, &options);
+                            let command = health.command.clone();
+                            let task_name = task.clone();
+                            let announce = (!options.silent).then(|| task_prefix.clone());
+
+                            Some(service.watch(health, &task, announce, move || {
+                                let command = command.clone();
+                                let task_name = task_name.clone();
+                                let check_options = check_options.clone();
+
+                                async move {
+                                    run_shell(command, task_name, &check_options, None).await == 0
+                                }
+                            }))
+                        }
+                        _ => None,
+                    };
+
+                // without a health check a service is ready once it started
+                if let (Some(service), None) = (&options.service, &found_task.health) {
+                    service.mark_ready();
+                }
+
+                let code = run_steps(
+                    &steps,
+                    &config,
+                    &task,
+                    &task_prefix,
+                    options.clone(),
+                    Some(&mut timings),
+                )
+                .await;
+
+                let Some(service) = &options.service else {
+                    break code;
+                };
+
+                if let Some(watch) = watch {
+                    service.finish(watch).await;
+                }
+
+                // a service that exited by itself or failed its health check is started again
+                match found_task.restart {
+                    Some(max)
+                        if restarts < max
+                            && !service.is_stopped()
+                            && !background_stopped(&options)
+                            && until_step.is_none() =>
+                    {
+                        restarts += 1;
+                        eprintln!(
+                            "Starting service {} again, restart {} of {}",
+                            task, restarts, max
+                        );
+                        events::fire(
+                            &config,
+                            &options.dir,
+                            options.shell.as_deref(),
+                            "task_restarted",
+                            &[
+                                ("task", events::Value::Text(&task)),
+                                ("restart", events::Value::Number(restarts as i32)),
+                                ("exit_code", events::Value::Number(code)),
+                            ],
+                        )
+                        .await;
+                    }
+                    _ => break code,
+                }
+            };
+        }
+
+        if code == 0
+            && !restored
+            && options.pre_post
+            && until_step.is_none()
+            && config.task(&post_task).is_some()
+        {
+            code = cli_run_task(
+                config.clone(),
+                post_task.clone(),
+                task_prefix.clone() + " > " + &post_task,
+                options.clone(),
+            )
+            .await
+            .unwrap_or_else(failed);
+        }
+
+        // the services it needs only run as long as it does
+        let dependency_failed = match dependencies {
+            Some(dependencies) => dependencies.finish().await,
+            None => None,
+        };
+        options.background = outer_background;
+
+        if dependency_failed.is_some() {
+            code = FAILURE_CODE;
+        }
+
+        // --reload stops the tasks that changed or were removed and the services that are no longer
+        // needed are stopped, that is no failure either
+        let stopped_with_background = background_stopped(&options);
+        let reloaded = options.reload && stopped_with_background;
+
+        // a service runs until it is stopped, being stopped on purpose is how it ends well, while
+        // exiting by itself is a failure even with exit code 0
+        let stopped = match &options.service {
+            _ if reloaded => {
+                code = 0;
+                true
+            }
+            Some(service) if service.is_stopped() || stopped_with_background => {
+                code = 0;
+                true
+            }
+            // the health check already said why it stopped it
+            Some(service) if service.is_unhealthy() => {
+                if code == 0 {
+                    code = FAILURE_CODE;
+                }
+                false
+            }
+            Some(_) if code == 0 && until_step.is_none() => {
+                eprintln!(
+                    "Service {} exited by itself, services run until they are stopped",
+                    task
+                );
+                code = FAILURE_CODE;
+                false
+            }
+            _ => false,
+        };
+
+        let hook = match code {
+            // a stopped service neither succeeded nor failed
+            _ if stopped => &None,
+            0 => &found_task.on_success,
+            _ => &found_task.on_failure,
+        };
+
+        if let Some(hook_steps) = hook {
+            let mut hook_options = options.clone();
+            hook_options
+                .env
+                .push(("PILOT_EXIT_CODE".to_string(), code.to_string()));
+
+            let hook_code =
+                run_steps(hook_steps, &config, &task, &task_prefix, hook_options, None).await;
+
+            // a failing on_success hook still fails the task
+            if code == 0 {
+                code = hook_code;
+            }
+        }
+
+        if let (Some(finally_steps), Some(finally_hook)) = (&found_task.finally, finally_hook) {
+            // if the hook is gone, pilot is already exiting and runs it
+            if shutdown::take_exit_hook(finally_hook).is_some() {
+                let finally_code = run_steps(
+                    finally_steps,
+                    &config,
+                    &task,
+                    &task_prefix,
+                    cleanup_options,
+                    None,
+                )
+                .await;
+
+                if code == 0 {
+                    code = finally_code;
+                }
+            }
+        }
+
+        if let Some(cache_entry) = cache_entry.filter(|_| code == 0 && !restored && !stopped) {
+            block_in_place(|| cache_entry.save());
+        }
+
+        flush_output_group(&options);
+        status::finish(&task_prefix);
+
+        match (stopped, restored, &dependency_failed) {
+            (_, _, Some(failed)) => print_task_outcome(
+                &format!("{}: dependency {} failed", task_prefix, failed),
+                "stopped",
+                &options,
+            ),
+            (true, _, _) => print_task_outcome(&task_prefix, "stopped", &options),
+            // the outputs came from the cache
+            (_, true, _) if code == 0 => print_task_outcome(&task_prefix, "restored", &options),
+            _ => print_task_end(&task_prefix, code, &options),
+        }
+
+        // how long a service was up or restoring a task took says nothing about the next time
+        if code == 0 && !service && !restored && !stopped {
+            history::record_duration(&task, started.elapsed());
+        }
+
+        if options.timings {
+            print_timings(&task_prefix, &steps, &timings);
+        }
+
+        Ok(code)
+    })
+}
+
+/// Runs one of the top-level hook step lists like `before_all`, if the Pilotfile has it.
+async fn run_hook(config: &Arc<Config>, hook: &str, mut options: RunOptions) -> i32 {
+    let steps = match hook {
+        "before_all" => &config.before_all,
+        _ => &config.after_all,
+    };
+
+    let Some(steps) = steps.as_ref().filter(|_| !options.dry_run) else {
+        return 0;
+    };
+
+    options.task_path = hook.to_string();
+    options.port_offset = Arc::new(Mutex::new(None));
+
+    print_task_start(hook, &options);
+    let code = run_steps(steps, config, hook, hook, options.clone(), None).await;
+    print_task_end(hook, code, &options);
+    code
+}
+
+/// The outcome of a task.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TaskResult {
+    pub task: String,
+    /// the exit code of the failing step, 0 if every step succeeded
+    pub code: i32,
+    /// how long the task ran, zero if it didn't
+    pub duration: Duration,
+}
+
+impl TaskResult {
+    pub fn success(&self) -> bool {
+        self.code == 0
+    }
+}
+
+/// The outcome of a whole run, including the `before_all` and `after_all` hooks.
+#[derive(Clone, Debug)]
+pub struct RunSummary {
+    pub results: Vec<TaskResult>,
+    pub hooks_succeeded: bool,
+    /// pilot received SIGINT or SIGTERM during the run
+    pub interrupted: bool,
+}
+
+impl RunSummary {
+    /// Whether the jobs passed and the services were stopped on purpose. An interrupted run only
+    /// succeeds if it only stopped services, the tasks it didn't get to count as failed.
+    pub fn success(&self) -> bool {
+        self.hooks_succeeded && self.results.iter().all(TaskResult::success)
+    }
+}
+
+/// A task of the command line with its named arguments, like `deploy env=prod`.
+type CliTask = (String, Option<Vec<(String, String)>>);
+
+/// The tasks matching a pattern like `test:*`, without the `pre<task>` and `post<task>` tasks
+/// that already run around their task.
+fn matching_tasks<'a>(config: &'a Config, pattern: &str, options: &RunOptions) -> Vec<&'a str> {
+    let matching = config.tasks_matching(pattern);
+
+    matching
+        .iter()
+        .filter(|task| {
+            !options.pre_post
+                || !["pre", "post"].iter().any(|prefix| {
+                    task.strip_prefix(prefix)
+                        .is_some_and(|main_task| matching.contains(&main_task))
+                })
+        })
+        .copied()
+        .collect()
+}
+
+/// Runs the tasks of a Pilotfile. Mistakes in what to run, like unknown tasks, are an [`Error`]
+/// instead of a failed task.
+pub struct Runner {
+    pilotfile: Pilotfile,
+    options: RunOptions,
+}
+
+impl Runner {
+    pub fn new(pilotfile: Pilotfile, options: RunOptions) -> Self {
+        Runner { pilotfile, options }
+    }
+
+    /// Runs a single task with its pre and post tasks and its hooks. `lint,test` or `lint+test`
+    /// runs a chain of tasks and `test:*` every matching task, unless a task has that name.
+    pub fn run(&self, task: &str) -> Result<TaskResult, Error> {
+        let named_args = self
+            .options
+            .task_args
+            .iter()
+            .find(|(name, _)| name == task)
+            .map(|(_, args)| args.clone());
+
+        runtime::block_on(self.run_with_args(task, named_args))
+    }
+
+    async fn run_with_args(
+        &self,
+        task: &str,
+        named_args: Option<Vec<(String, String)>>,
+    ) -> Result<TaskResult, Error> {
+        let started = clock::Stopwatch::start();
+        let result = self.run_task_with_args(task, named_args).await?;
+
+        Ok(TaskResult {
+            duration: started.elapsed(),
+            ..result
+        })
+    }
+
+    async fn run_task_with_args(
+        &self,
+        task: &str,
+        named_args: Option<Vec<(String, String)>>,
+    ) -> Result<TaskResult, Error> {
+        let config = self.pilotfile.config();
+
+        if named_args.is_some() && config.task(task).is_none() {
+            return Err(Error(format!(
+                "Only single tasks take arguments, not {}",
+                task
+            )));
+        }
+
+        if config.task(task).is_none() && task.contains(['*', '?']) {
+            return self.run_matching(task).await;
+        }
+
+        if config.task(task).is_none() && task.contains([',', '+']) {
+            return self.run_chain(task).await;
+        }
+
+        let options = RunOptions {
+            named_args,
+            ..self.options.clone()
+        };
+
+        let code = cli_run_task(
+            self.pilotfile.config().clone(),
+            task.to_string(),
+            task.to_string(),
+            options,
+        )
+        .await?;
+
+        Ok(TaskResult {
+            task: task.to_string(),
+            code,
+            duration: Duration::ZERO,
+        })
+    }
+
+    /// Runs the tasks of a chain one after another, stopping at the first one that fails.
+    async fn run_chain(&self, chain: &str) -> Result<TaskResult, Error> {
+        let config = self.pilotfile.config();
+        let tasks: Vec<_> = chain
+            .split([',', '+'])
+            .filter(|task| !task.is_empty())
+            .collect();
+
+        // don't start a chain that can't finish
+        if let Some(task) = tasks.iter().find(|task| config.task(task).is_none()) {
+            return Err(Error(format!("Task {} not found in Pilotfile", task)));
+        }
+
+        print_task_start(chain, &self.options);
+
+        let mut code = 0;
+        let mut outcomes = vec![];
+
+        for task in tasks {
+            if code != 0 || shutdown::is_interrupted() {
+                outcomes.push(("skipped", task));
+                continue;
+            }
+
+            code = cli_run_task(
+                config.clone(),
+                task.to_string(),
+                format!("{} > {}", chain, task),
+                self.options.clone(),
+            )
+            .await
+            .unwrap_or_else(failed);
+            outcomes.push((if code == 0 { "finished" } else { "failed" }, task));
+        }
+
+        print_task_end(chain, code, &self.options);
+
+        if !self.options.silent {
+            status::println(&format!("summary {}", chain));
+
+            for (outcome, task) in outcomes {
+                match config.task(task).and_then(|task| task.description.as_ref()) {
+                    Some(description) => {
+                        status::println(&format!("  {} {} - {}", outcome, task, description))
+                    }
+                    None => status::println(&format!("  {} {}", outcome, task)),
+                }
+            }
+        }
+
+        Ok(TaskResult {
+            task: chain.to_string(),
+            code,
+            duration: Duration::ZERO,
+        })
+    }
+
+    /// Runs every task matching a pattern, like the tasks were listed one by one. With
+    /// `parallel`, they all run at the same time.
+    async fn run_matching(&self, pattern: &str) -> Result<TaskResult, Error> {
+        let config = self.pilotfile.config();
+        let mut tasks = matching_tasks(config, pattern, &self.options);
+
+        if tasks.is_empty() {
+            return Err(Error(format!(
+                "No task in the Pilotfile matches {}",
+                pattern
+            )));
+        }
+
+        if let Some(seed) = self.options.shuffle {
+            shuffle::shuffle(&mut tasks, seed, pattern);
+        }
+
+        print_task_start(pattern, &self.options);
+
+        let code = if self.options.parallel {
+            join_branches(
+                tasks
+                    .iter()
+                    .map(|task| {
+                        let step = Step::Task(TaskRef {
+                            name: task.to_string(),
+                            env: vec![],
+                            args: None,
+                        });
+                        spawn_branch(&step, config, pattern, pattern, self.options.clone())
+                    })
+                    .collect(),
+            )
+            .await
+        } else {
+            let mut code = 0;
+
+            for task in tasks {
+                if shutdown::is_interrupted() {
+                    break;
+                }
+
+                let task_code = cli_run_task(
+                    config.clone(),
+                    task.to_string(),
+                    format!("{} > {}", pattern, task),
+                    self.options.clone(),
+                )
+                .await
+                .unwrap_or_else(failed);
+
+                if code == 0 {
+                    code = task_code;
+                }
+            }
+
+            code
+        };
+
+        print_task_end(pattern, code, &self.options);
+
+        Ok(TaskResult {
+            task: pattern.to_string(),
+            code,
+            duration: Duration::ZERO,
+        })
+    }
+
+    /// Runs the tasks of the command line at the same time, like the branches of a parallel step.
+    async fn run_parallel(&self, tasks: Vec<CliTask>) -> Vec<Result<TaskResult, Error>> {
+        let running: Vec<_> = tasks
+            .into_iter()
+            .map(|(task, named_args)| {
+                let reserved = reserve_color();
+                let runner = Runner::new(
+                    self.pilotfile.clone(),
+                    RunOptions {
+                        reserved: reserved.clone(),
+                        ..self.options.clone()
+                    },
+                );
+
+                let crashed = TaskResult {
+                    task: task.clone(),
+                    code: FAILURE_CODE,
+                    duration: Duration::ZERO,
+                };
+
+                let run = supervise::spawn_or(
+                    task.clone(),
+                    async move {
+                        let result = runner.run_with_args(&task, named_args).await;
+                        release_color(&reserved);
+                        result
+                    },
+                    Ok(crashed.clone()),
+                );
+
+                (run, crashed)
+            })
+            .collect();
+
+        let mut results = vec![];
+
+        for (run, crashed) in running {
+            // the tasks catch their own panics
+            results.push(run.await.unwrap_or(Ok(crashed)));
+        }
+
+        results
+    }
+
+    /// Runs a command that isn't a task of the Pilotfile, its program names the output.
+    pub fn exec(&self, command: &str) -> TaskResult {
+        let name = command
+            .split_whitespace()
+            .next()
+            .and_then(|program| Path::new(program.trim_matches('\'')).file_name())
+            .map(|program| program.to_string_lossy().to_string())
+            .unwrap_or_else(|| "exec".to_string());
+
+        let options = RunOptions {
+            task_path: name.clone(),
+            ..self.options.clone()
+        };
+
+        shutdown::install();
+
+        let started = clock::Stopwatch::start();
+        print_task_start(&name, &options);
+        let code = runtime::block_on(run_shell(command.to_string(), name.clone(), &options, None));
+        print_task_end(&name, code, &options);
+
+        TaskResult {
+            task: name,
+            code,
+            duration: started.elapsed(),
+        }
+    }
+
+    /// Runs the tasks one after another between `before_all` and `after_all`, stopping children
+    /// and running the cleanup steps if pilot is interrupted. The outcome is saved for
+    /// `--rerun-failed` and the run with its output is added to the history. After an error, like
+    /// an unknown task, the tasks after it don't run and the run ends with the error.
+    pub fn run_all(&self, tasks: Vec<String>) -> Result<RunSummary, Error> {
+        runtime::block_on(self.run_tasks(tasks))
+    }
+
+    /// A dry run only shows what would run, integrations shouldn't hear about it.
+    async fn fire(&self, event: &str, fields: &[(&str, events::Value<'_>)]) {
+        if !self.options.dry_run {
+            let config = self.pilotfile.config();
+            let shell = self.options.shell.as_deref();
+            events::fire(config, self.pilotfile.dir(), shell, event, fields).await;
+        }
+    }
+
+    async fn task_failed(&self, result: &TaskResult) {
+        if !result.success() && !shutdown::is_interrupted() {
+            self.fire(
+                "task_failed",
+                &[
+                    ("task", events::Value::Text(&result.task)),
+                    ("exit_code", events::Value::Number(result.code)),
+                ],
+            )
+            .await;
+        }
+    }
+
+    async fn run_tasks(&self, tasks: Vec<String>) -> Result<RunSummary, Error> {
+        let config = self.pilotfile.config();
+        let cleanup_options = RunOptions {
+            cleanup: true,
+            ..self.options.clone()
+        };
+
+        shutdown::install();
+        history::start(&self.pilotfile, &self.options.command_line)?;
+
+        if self.options.detach_on_hangup {
+            if let Some(log) = history::detached_log() {
+                shutdown::detach_on_hangup(&log);
+            }
+        }
+
+        // raw processes write to the terminal themselves, they would mix with the status line
+        // the spinner redraws the last line, which screen readers would read again and again
+        let status_hook = (stdout_is_tty()
+            && !self.options.raw
+            && self.options.prefix_style == PrefixStyle::Color)
+            .then(|| {
+                status::enable();
+                shutdown::on_exit(status::disable)
+            });
+
+        if self.options.profile_output.is_some() {
+            trace::enable();
+        }
+
+        // after_all also has to run when pilot exits early
+        let after_all_config = config.clone();
+        let after_all_options = cleanup_options.clone();
+        let after_all_hook = shutdown::on_exit(move || {
+            runtime::block_on(run_hook(&after_all_config, "after_all", after_all_options));
+        });
+
+        // a task can be given more than once, with other arguments
+        let mut task_args = self.options.task_args.clone();
+        let mut tasks: Vec<_> = tasks
+            .into_iter()
+            .map(|task| {
+                let named_args = task_args
+                    .iter()
+                    .position(|(name, _)| *name == task)
+                    .map(|index| task_args.remove(index).1);
+                (task, named_args)
+            })
+            .collect();
+
+        if let Some(seed) = self.options.shuffle {
+            if !self.options.silent {
+                status::println(&format!(
+                    "shuffled with seed {}, --shuffle {} runs the tasks in this order again",
+                    seed, seed
+                ));
+            }
+
+            shuffle::shuffle(&mut tasks, seed, "");
+        }
+
+        let names: Vec<_> = tasks.iter().map(|(task, _)| task.clone()).collect();
+        self.fire("run_started", &[("tasks", events::Value::List(&names))])
+            .await;
+
+        let before_all_success = run_hook(config, "before_all", self.options.clone()).await == 0;
+
+        let mut results = vec![];
+        let mut error = None;
+
+        // tasks that could not run count as failed, so --rerun-failed picks them up
+        let not_run = |task: String| TaskResult {
+            task,
+            code: FAILURE_CODE,
+            duration: Duration::ZERO,
+        };
+
+        if self.options.reload && !self.options.dry_run && before_all_success {
+            match reload::supervise(self, &std::mem::take(&mut tasks)).await {
+                Ok(supervised) => {
+                    for result in supervised {
+                        self.task_failed(&result).await;
+                        results.push(result);
+                    }
+                }
+                Err(err) => {
+                    results.extend(names.iter().cloned().map(not_run));
+                    error = Some(err);
+                }
+            }
+        }
+
+        if self.options.parallel && tasks.len() > 1 && before_all_success {
+            let names: Vec<_> = tasks.iter().map(|(task, _)| task.clone()).collect();
+
+            for (task, result) in names
+                .into_iter()
+                .zip(self.run_parallel(std::mem::take(&mut tasks)).await)
+            {
+                match result {
+                    Ok(result) => {
+                        self.task_failed(&result).await;
+                        results.push(result);
+                    }
+                    Err(err) => {
+                        error.get_or_insert(err);
+                        results.push(not_run(task));
+                    }
+                }
+            }
+        }
+
+        for (task, named_args) in tasks {
+            if !before_all_success || shutdown::is_interrupted() || error.is_some() {
+                results.push(not_run(task));
+                continue;
+            }
+
+            match self.run_with_args(&task, named_args).await {
+                Ok(result) => {
+                    self.task_failed(&result).await;
+                    results.push(result);
+                }
+                Err(err) => {
+                    error = Some(err);
+                    results.push(not_run(task));
+                }
+            }
+        }
+
+        // on a regular exit we run after_all ourselves to get its outcome
+        let after_all_success = shutdown::take_exit_hook(after_all_hook).is_none()
+            || run_hook(config, "after_all", cleanup_options).await == 0;
+
+        if let Some(status_hook) = status_hook {
+            shutdown::take_exit_hook(status_hook);
+            status::disable();
+        }
+
+        let failed: Vec<_> = results
+            .iter()
+            .filter(|result| !result.success())
+            .map(|result| result.task.clone())
+            .collect();
+        self.fire(
+            "run_finished",
+            &[
+                (
+                    "success",
+                    events::Value::Bool(
+                        failed.is_empty() && before_all_success && after_all_success,
+                    ),
+                ),
+                ("failed", events::Value::List(&failed)),
+                (
+                    "interrupted",
+                    events::Value::Bool(shutdown::is_interrupted()),
+                ),
+            ],
+        )
+        .await;
+
+        // stopped tasks didn't fail on their own
+        if !shutdown::is_interrupted() && !self.options.dry_run {
+            for result in results.iter().filter(|result| !result.success()) {
+                ci::task_failed(self.pilotfile.dir(), &result.task, result.code);
+            }
+        }
+
+        let outcomes: Vec<_> = results
+            .iter()
+            .map(|result| (result.task.clone(), result.success()))
+            .collect();
+        // a dry run doesn't change which tasks failed
+        let saved = match self.options.dry_run {
+            true => Ok(()),
+            false => state::save(self.pilotfile.dir(), &outcomes),
+        };
+
+        let summary = RunSummary {
+            results,
+            hooks_succeeded: before_all_success && after_all_success,
+            interrupted: shutdown::is_interrupted(),
+        };
+
+        // the summary points to the logs, so it is sent while the run is still in the history
+        if !self.options.dry_run {
+            block_in_place(|| notify::send(config, self.pilotfile.dir(), &summary));
+        }
+
+        let finished = history::finish(&outcomes);
+
+        if let Some(path) = &self.options.profile_output {
+            if let Err(err) = trace::save(path) {
+                eprintln!("Could not write the profile to {}: {}", path.display(), err);
+            }
+        }
+
+        // the first error wins, the others are likely caused by it
+        match error {
+            Some(err) => Err(err),
+            None => saved.and(finished).map(|_| summary),
+        }
+    }
+}
@@ -0,0 +1,123 @@
+//! The list of tasks pilot prints without any task to run, as a table that fits the terminal.
+
+use crate::{
+    color_default,
+    config::{Kind, Task},
+    status, Pilotfile,
+};
+
+/// descriptions are only wrapped if there is at least this much room for them
+const MIN_DESCRIPTION_WIDTH: usize = 20;
+
+/// The processes of a Procfile run until they are stopped, like with foreman.
+fn kind(task: &Task, procfile: bool) -> &'static str {
+    match procfile {
+        true => Kind::Service.name(),
+        false => task.kind().name(),
+    }
+}
+
+/// Splits the text into lines of at most `width` characters, between words.
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = vec![];
+    let mut line = String::new();
+
+    for word in text.split_whitespace() {
+        if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > width {
+            lines.push(std::mem::take(&mut line));
+        }
+
+        if !line.is_empty() {
+            line.push(' ');
+        }
+
+        line.push_str(word);
+    }
+
+    lines.push(line);
+    lines
+}
+
+/// Lists the tasks with their tags, whether they are services or jobs and their descriptions.
+/// `plain` prints them as tab-separated columns for scripts instead.
+pub fn cli(pilotfile: &Pilotfile, plain: bool) {
+    let tasks = &pilotfile.config().tasks;
+    let procfile = pilotfile.is_procfile();
+
+    if plain {
+        for task in tasks {
+            println!(
+                "{}\t{}\t{}\t{}",
+                task.name,
+                task.tags.join(","),
+                kind(task, procfile),
+                task.description.as_deref().unwrap_or_default()
+            );
+        }
+
+        return;
+    }
+
+    let color = color_default();
+    let paint = |text: &str, code: &str| match color {
+        true => format!("\x1b[{}m{}\x1b[0m", code, text),
+        false => text.to_string(),
+    };
+
+    let width = |text: &str| text.chars().count();
+    let name_width = tasks.iter().map(|task| width(&task.name)).max();
+    let tags: Vec<_> = tasks.iter().map(|task| task.tags.join(", ")).collect();
+    let tags_width = tags.iter().map(|tags| width(tags)).max().unwrap_or(0);
+    let kind_width = tasks
+        .iter()
+        .map(|task| width(kind(task, procfile)))
+        .max()
+        .unwrap_or(0);
+
+    // the columns before the description, each with two spaces after it
+    let indent = 2
+        + name_width.unwrap_or(0)
+        + 2
+        + if tags_width > 0 { tags_width + 2 } else { 0 }
+        + kind_width
+        + 2;
+    let description_width = status::terminal_width().saturating_sub(indent);
+
+    println!("Available tasks:");
+
+    for (task, tags) in tasks.iter().zip(&tags) {
+        let mut line = format!(
+            "  {}{}  ",
+            paint(&task.name, "0;32"),
+            " ".repeat(name_width.unwrap_or(0) - width(&task.name))
+        );
+
+        if tags_width > 0 {
+            line += &format!(
+                "{}{}  ",
+                paint(tags, "2"),
+                " ".repeat(tags_width - width(tags))
+            );
+        }
+
+        let kind = kind(task, procfile);
+        line += &format!(
+            "{}{}  ",
+            paint(kind, "2"),
+            " ".repeat(kind_width - width(kind))
+        );
+
+        let description = task.description.as_deref().unwrap_or_default();
+        let lines = match description_width >= MIN_DESCRIPTION_WIDTH {
+            true => wrap(description, description_width),
+            false => vec![description.to_string()],
+        };
+
+        // tasks without a description don't end in spaces
+        println!("{}", (line + &lines[0]).trim_end());
+
+        for line in &lines[1..] {
+            println!("{}{}", " ".repeat(indent), line);
+        }
+    }
+}
@@ -0,0 +1,242 @@
+//! `needs`, the tasks a task needs: they start before it, jobs have to finish and services have
+//! to be ready before it starts. When one of them fails, the task is skipped, or stopped if it
+//! already runs, and the services it needs stop once it is done.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use tokio::task::JoinHandle;
+
+use crate::{
+    cli_run_task,
+    config::{Config, OnDependencyFailure, Task},
+    failed, release_color, reserve_color, runtime,
+    service::Service,
+    shutdown, stop_processes, supervise, Background, RunOptions, FAILURE_CODE,
+};
+
+/// how often the tasks it needs are checked
+const INTERVAL: Duration = Duration::from_millis(50);
+
+/// A task that is needed, while it runs and once it ended.
+struct Need {
+    name: String,
+    processes: Arc<Mutex<Background>>,
+    /// whether a service is ready, `None` for a job
+    service: Option<Arc<Service>>,
+    run: Option<JoinHandle<i32>>,
+    code: Option<i32>,
+}
+
+impl Need {
+    /// The exit code, once it ended.
+    async fn ended(&mut self) -> Option<i32> {
+        if let Some(run) = self.run.take_if(|run| run.is_finished()) {
+            self.code = Some(run.await.unwrap_or(FAILURE_CODE));
+        }
+
+        self.code
+    }
+
+    async fn is_ready(&mut self) -> bool {
+        match &self.service {
+            Some(service) => service.is_ready() && self.ended().await.is_none(),
+            None => self.ended().await == Some(0),
+        }
+    }
+
+    /// A job fails with its exit code, a service by ending while it is needed.
+    async fn has_failed(&mut self) -> bool {
+        match self.service {
+            Some(_) => self.ended().await.is_some(),
+            None => self.ended().await.is_some_and(|code| code != 0),
+        }
+    }
+
+    async fn stop(mut self) {
+        stop_processes(&self.processes);
+
+        if let Some(run) = self.run.take() {
+            let _ = run.await;
+        }
+    }
+}
+
+/// The first of the tasks that failed.
+async fn first_failed(needs: &mut [Need]) -> Option<String> {
+    for need in needs {
+        if need.has_failed().await {
+            return Some(need.name.clone());
+        }
+    }
+
+    None
+}
+
+async fn all_ready(needs: &mut [Need]) -> bool {
+    for need in needs {
+        if !need.is_ready().await {
+            return false;
+        }
+    }
+
+    true
+}
+
+/// The tasks a task needs, started for a run of it.
+pub(crate) struct Dependencies {
+    needs: Arc<tokio::sync::Mutex<Vec<Need>>>,
+    /// the processes of the task itself, they are stopped when a dependency fails
+    processes: Arc<Mutex<Background>>,
+    on_failure: OnDependencyFailure,
+    /// the dependency that failed while the task ran
+    failed: Arc<Mutex<Option<String>>>,
+    done: Arc<AtomicBool>,
+    watch: Option<JoinHandle<()>>,
+}
+
+impl Dependencies {
+    /// Starts the tasks `task` needs at the same time, their lines show them below the task.
+    /// The processes of the task run in a background of their own from then on, so they can be
+    /// stopped without the ones it needs.
+    pub(crate) fn start(
+        config: &Arc<Config>,
+        task: &Task,
+        task_prefix: &str,
+        options: &mut RunOptions,
+    ) -> Self {
+        let needs = task
+            .needs
+            .iter()
+            .map(|name| {
+                let service = config
+                    .task(name)
+                    .is_some_and(Task::is_service)
+                    .then(|| Arc::new(Service::default()));
+                let processes = Background::within(options.background.clone());
+                let reserved = reserve_color();
+                let need_options = RunOptions {
+                    // the arguments are only for the task that needs it
+                    args: String::new(),
+                    background: Some(processes.clone()),
+                    needed_service: service.clone(),
+                    reserved: reserved.clone(),
+                    port_offset: Arc::new(Mutex::new(None)),
+                    ..options.clone()
+                };
+
+                let config = config.clone();
+                let need = name.clone();
+                let prefix = format!("{} > {}", task_prefix, name);
+                let run = supervise::spawn(prefix.clone(), async move {
+                    let code = cli_run_task(config, need, prefix, need_options)
+                        .await
+                        .unwrap_or_else(failed);
+                    release_color(&reserved);
+                    code
+                });
+
+                Need {
+                    name: name.clone(),
+                    processes,
+                    service,
+                    run: Some(run),
+                    code: None,
+                }
+            })
+            .collect();
+
+        let processes = Background::within(options.background.clone());
+        options.background = Some(processes.clone());
+
+        Dependencies {
+            needs: Arc::new(tokio::sync::Mutex::new(needs)),
+            processes,
+            on_failure: task.on_dependency_failure.unwrap_or_default(),
+            failed: Arc::new(Mutex::new(None)),
+            done: Arc::new(AtomicBool::new(false)),
+            watch: None,
+        }
+    }
+
+    /// Waits until the jobs finished and the services are ready, and watches them while the
+    /// task runs. Returns the first one that failed before, an interrupt ends the wait without
+    /// one.
+    pub(crate) async fn wait_ready(&mut self) -> Option<String> {
+        loop {
+            let mut needs = self.needs.lock().await;
+
+            if shutdown::is_interrupted() {
+                return None;
+            }
+
+            let failed = first_failed(&mut needs).await;
+
+            if failed.is_some() {
+                return failed;
+            }
+
+            if all_ready(&mut needs).await {
+                break;
+            }
+
+            drop(needs);
+            shutdown::sleep(INTERVAL).await;
+        }
+
+        let needs = self.needs.clone();
+        let processes = self.processes.clone();
+        let on_failure = self.on_failure;
+        let failed = self.failed.clone();
+        let done = self.done.clone();
+
+        self.watch = Some(runtime::spawn(async move {
+            while !done.load(Ordering::SeqCst) && shutdown::sleep(INTERVAL).await {
+                let Some(need) = first_failed(&mut needs.lock().await).await else {
+                    continue;
+                };
+
+                *failed.lock().unwrap() = Some(need);
+
+                match on_failure {
+                    OnDependencyFailure::Stop => stop_processes(&processes),
+                    OnDependencyFailure::Cancel => {
+                        let mut processes = processes.lock().unwrap();
+                        processes.stopped = true;
+
+                        for pid in &processes.pids {
+                            shutdown::kill(*pid);
+                        }
+                    }
+                }
+
+                return;
+            }
+        }));
+
+        None
+    }
+
+    /// Stops the services once the task is done. Returns the dependency that failed while the
+    /// task ran.
+    pub(crate) async fn finish(self) -> Option<String> {
+        self.done.store(true, Ordering::SeqCst);
+
+        if let Some(watch) = self.watch {
+            let _ = watch.await;
+        }
+
+        let needs = std::mem::take(&mut *self.needs.lock().await);
+
+        for need in needs {
+            need.stop().await;
+        }
+
+        self.failed.lock().unwrap().take()
+    }
+}
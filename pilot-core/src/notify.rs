@@ -0,0 +1,147 @@
+//! The `notify` targets of a Pilotfile, they get a JSON summary of every run, so a long
+//! supervised session can send an alert to a chat when the dev stack crashed.
+
+use std::{
+    fs::File,
+    io::{self, Write},
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use yaml_rust::Yaml;
+
+use crate::{clock, config::Config, history, state::json_string, RunSummary};
+
+/// Parses `notify`, a webhook URL or a file or named pipe, or a list of them.
+pub(crate) fn from_yaml(yaml: &Yaml) -> Result<Vec<String>, String> {
+    let invalid = || "notify needs a URL or a path, or a list of them".to_string();
+
+    match yaml {
+        Yaml::String(target) => Ok(vec![target.clone()]),
+        Yaml::Array(targets) => targets
+            .iter()
+            .map(|target| target.as_str().map(str::to_string).ok_or_else(invalid))
+            .collect(),
+        _ => Err(invalid()),
+    }
+}
+
+fn is_url(target: &str) -> bool {
+    target.starts_with("http://") || target.starts_with("https://")
+}
+
+/// The summary of a run on one line, so readers of a pipe can split the runs by lines.
+fn summary_json(config: &Config, dir: &Path, summary: &RunSummary) -> String {
+    let logs = history::logs();
+    let task_log = |task: &str| {
+        logs.as_ref()
+            .and_then(|(_, task_logs)| task_logs.iter().find(|(name, _)| name == task))
+            .map(|(_, path)| json_string(&path.to_string_lossy()))
+            .unwrap_or_else(|| "null".to_string())
+    };
+
+    let tasks: Vec<_> = summary
+        .results
+        .iter()
+        .map(|result| {
+            // a service only succeeds by being stopped
+            let service = config
+                .task(&result.task)
+                .is_some_and(|task| task.is_service());
+            let status = match result.success() {
+                true if service => "stopped",
+                true => "success",
+                false => "failed",
+            };
+
+            format!(
+                "{{\"name\": {}, \"kind\": \"{}\", \"status\": \"{}\", \"exit_code\": {}, \
+\"duration\": {}, \"log\": {}}}",
+                json_string(&result.task),
+                if service { "service" } else { "job" },
+                status,
+                result.code,
+                clock::seconds(result.duration),
+                task_log(&result.task)
+            )
+        })
+        .collect();
+
+    format!(
+        "{{\"pilotfile_dir\": {}, \"run_id\": {}, \"success\": {}, \"interrupted\": {}, \
+\"log\": {}, \"tasks\": [{}]}}\n",
+        json_string(&dir.to_string_lossy()),
+        history::run_id()
+            .map(|id| json_string(&id))
+            .unwrap_or_else(|| "null".to_string()),
+        summary.success(),
+        summary.interrupted,
+        logs.as_ref()
+            .map(|(log, _)| json_string(&log.to_string_lossy()))
+            .unwrap_or_else(|| "null".to_string()),
+        tasks.join(", ")
+    )
+}
+
+fn post(url: &str, json: &str) -> io::Result<()> {
+    let mut curl = Command::new("curl")
+        .args(["--silent", "--show-error", "--fail", "--max-time", "10"])
+        .args(["--header", "Content-Type: application/json"])
+        .args(["--data-binary", "@-", url])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()?;
+
+    if let Some(mut stdin) = curl.stdin.take() {
+        stdin.write_all(json.as_bytes())?;
+    }
+
+    match curl.wait()? {
+        status if status.success() => Ok(()),
+        status => Err(io::Error::other(format!(
+            "curl failed with exit code {}",
+            status.code().unwrap_or(-1)
+        ))),
+    }
+}
+
+/// Appends the summary to a file or writes it to a named pipe, without waiting for a reader.
+fn write(path: &Path, json: &str) -> io::Result<()> {
+    let mut options = File::options();
+    options.create(true).append(true);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.custom_flags(libc::O_NONBLOCK);
+    }
+
+    let mut file = options.open(path).map_err(|err| match err.raw_os_error() {
+        #[cfg(unix)]
+        Some(libc::ENXIO) => io::Error::other("nothing reads the pipe"),
+        _ => err,
+    })?;
+
+    file.write_all(json.as_bytes())
+}
+
+/// Sends the summary of a run to the `notify` targets of the Pilotfile. Relative paths are in
+/// the Pilotfile directory, a target that can't be reached doesn't change the outcome.
+pub(crate) fn send(config: &Config, dir: &Path, summary: &RunSummary) {
+    if config.notify.is_empty() {
+        return;
+    }
+
+    let json = summary_json(config, dir, summary);
+
+    for target in &config.notify {
+        let result = match is_url(target) {
+            true => post(target, &json),
+            false => write(&dir.join(target), &json),
+        };
+
+        if let Err(err) = result {
+            eprintln!("Could not notify {}: {}", target, err);
+        }
+    }
+}
@@ -0,0 +1,307 @@
+use std::{
+    fs::read_to_string,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use yaml_rust::{Yaml, YamlLoader};
+
+use crate::{
+    config::{self, env_from_yaml, Config},
+    edit, procfile, NOT_VALID,
+};
+
+pub const FILE_NAME: &str = "Pilotfile.yaml";
+/// what the errors about the top level show a Pilotfile should look like
+const EXAMPLE: &str = "    build:
+      - shell: cargo build
+    test:
+      - task: build
+      - shell: cargo test";
+
+/// Parses the documents of a Pilotfile, the first one has to map task names to their steps.
+pub(crate) fn parse(file: &str) -> Result<Vec<Yaml>, String> {
+    let documents =
+        YamlLoader::load_from_str(file).map_err(|err| format!("{}: {}", NOT_VALID, err))?;
+
+    let found = match documents.first() {
+        Some(Yaml::Hash(_)) => return Ok(documents),
+        // only comments or a lone `---`
+        None | Some(Yaml::Null) => {
+            return Err(format!(
+                "{} is empty, add some tasks like\n\n{}",
+                FILE_NAME, EXAMPLE
+            ))
+        }
+        Some(Yaml::Array(_)) => "a list".to_string(),
+        Some(Yaml::String(string)) => format!("the text {:?}", string),
+        Some(Yaml::Integer(_) | Yaml::Real(_)) => "a number".to_string(),
+        Some(Yaml::Boolean(_)) => "a boolean".to_string(),
+        Some(_) => "no map".to_string(),
+    };
+
+    Err(format!(
+        "{}: the top level is {}, but it has to map task names to their steps like\n\n{}",
+        NOT_VALID, found, EXAMPLE
+    ))
+}
+
+/// A loaded Pilotfile, or a Procfile if there is no Pilotfile in the directory.
+#[derive(Clone)]
+pub struct Pilotfile {
+    dir: PathBuf,
+    /// the text of the file, the history keeps a copy of it for `pilot repro`
+    source: String,
+    yaml: Yaml,
+    /// the documents after the first one, each overlays it for a profile
+    overlays: Vec<Yaml>,
+    config: Arc<Config>,
+    procfile: bool,
+    /// the profile that was applied, a reload applies it again
+    profile: Option<String>,
+}
+
+impl Pilotfile {
+    /// Finds the directory of the Pilotfile by walking up from `start`.
+    pub fn find(start: impl AsRef<Path>) -> Result<PathBuf, String> {
+        let mut path = start.as_ref().to_path_buf();
+
+        // a Procfile is only used if there is no Pilotfile in the same directory
+        while !path.join(FILE_NAME).exists() && !path.join(procfile::FILE_NAME).exists() {
+            if !path.pop() {
+                return Err("Pilotfile.yaml not found".to_string());
+            }
+        }
+
+        Ok(path)
+    }
+
+    pub fn load(dir: impl AsRef<Path>) -> Result<Self, String> {
+        Pilotfile::load_from(&dir, &dir)
+    }
+
+    /// Loads the Pilotfile or Procfile in `files` like it was in `dir`, for the copies of the
+    /// Pilotfile the history keeps with each run.
+    pub fn load_from(dir: impl AsRef<Path>, files: impl AsRef<Path>) -> Result<Self, String> {
+        let dir = dir.as_ref().to_path_buf();
+        let files = files.as_ref();
+
+        if !files.join(FILE_NAME).exists() {
+            let file = read_to_string(files.join(procfile::FILE_NAME))
+                .map_err(|_| "Procfile not found".to_string())?;
+            let yaml = procfile::parse(&file)
+                .map_err(|err| format!("That is not a valid Procfile: {}", err))?;
+
+            return Pilotfile::new(dir, file, yaml, vec![], true);
+        }
+
+        let file = read_to_string(files.join(FILE_NAME))
+            .map_err(|_| "Pilotfile.yaml not found".to_string())?;
+        let mut documents = parse(&file)?;
+        let yaml = documents.remove(0);
+
+        for (index, overlay) in documents.iter().enumerate() {
+            if overlay["profile"].as_str().is_none() {
+                return Err(format!(
+                    "{}: document {} needs the profile it overlays, like `profile: ci`",
+                    NOT_VALID,
+                    index + 2
+                ));
+            }
+        }
+
+        Pilotfile::new(dir, file, yaml, documents, false)
+    }
+
+    /// Loads the valid entries of a Pilotfile that can't be loaded as a whole, for listing its
+    /// tasks while it is being edited. Each error starts with its line if it has one, like
+    /// `Pilotfile.yaml:4: step 2 of task build: ...`.
+    pub fn load_lenient(dir: impl AsRef<Path>) -> Result<(Self, Vec<String>), String> {
+        let dir = dir.as_ref().to_path_buf();
+        let file = read_to_string(dir.join(FILE_NAME))
+            .map_err(|_| "Pilotfile.yaml not found".to_string())?;
+        let mut documents = parse(&file)?;
+        let yaml = documents.remove(0);
+
+        let (config, errors) = Config::from_yaml_lenient(&yaml, &dir)
+            .map_err(|err| format!("{}: {}", NOT_VALID, err))?;
+        let lines = edit::task_lines(&file).unwrap_or_default();
+
+        let errors = errors
+            .into_iter()
+            .map(|(key, err)| {
+                let line = lines
+                    .iter()
+                    .find(|(name, _)| Some(name) == key.as_ref())
+                    .map(|(_, line)| format!(":{}", line))
+                    .unwrap_or_default();
+
+                format!("{}{}: {}", FILE_NAME, line, err)
+            })
+            .collect();
+
+        let pilotfile = Pilotfile {
+            dir,
+            source: file,
+            yaml,
+            overlays: documents,
+            config: Arc::new(config),
+            procfile: false,
+            profile: None,
+        };
+
+        Ok((pilotfile, errors))
+    }
+
+    /// A Pilotfile without any tasks, for running commands in a directory without one.
+    pub fn empty(dir: impl AsRef<Path>) -> Self {
+        let yaml = Yaml::Hash(Default::default());
+
+        Pilotfile::new(
+            dir.as_ref().to_path_buf(),
+            String::new(),
+            yaml,
+            vec![],
+            false,
+        )
+        .expect("an empty Pilotfile is valid")
+    }
+
+    fn new(
+        dir: PathBuf,
+        source: String,
+        yaml: Yaml,
+        overlays: Vec<Yaml>,
+        procfile: bool,
+    ) -> Result<Self, String> {
+        let config =
+            Config::from_yaml(&yaml, &dir).map_err(|err| format!("{}: {}", NOT_VALID, err))?;
+        let pilotfile = Pilotfile {
+            dir,
+            source,
+            yaml,
+            overlays,
+            config: Arc::new(config),
+            procfile,
+            profile: None,
+        };
+
+        if pilotfile.config.strict {
+            pilotfile.check_strict()?;
+        }
+
+        Ok(pilotfile)
+    }
+
+    /// Fails on keys that are ignored otherwise, like a misspelled `before_all` becoming a task,
+    /// for `--strict` or `strict: true`.
+    pub fn check_strict(&self) -> Result<(), String> {
+        config::check_strict(&self.yaml).map_err(|err| format!("{}: {}", NOT_VALID, err))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub(crate) fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn yaml(&self) -> &Yaml {
+        &self.yaml
+    }
+
+    pub(crate) fn config(&self) -> &Arc<Config> {
+        &self.config
+    }
+
+    /// The Pilotfile, or the Procfile if the tasks come from one.
+    pub(crate) fn path(&self) -> PathBuf {
+        match self.procfile {
+            true => self.dir.join(procfile::FILE_NAME),
+            false => self.dir.join(FILE_NAME),
+        }
+    }
+
+    /// Loads the file again, with the profile that was applied. The environment variables of
+    /// the profile stay the ones it had when pilot started.
+    pub(crate) fn reload(&self) -> Result<Self, String> {
+        let mut pilotfile = Pilotfile::load(&self.dir)?;
+
+        if let Some(profile) = &self.profile {
+            pilotfile.apply_profile(profile)?;
+        }
+
+        Ok(pilotfile)
+    }
+
+    /// Whether the tasks come from a Procfile.
+    pub fn is_procfile(&self) -> bool {
+        self.procfile
+    }
+
+    /// The tasks run without any task on the command line, like foreman a Procfile starts all
+    /// of its processes.
+    pub fn default_tasks(&self) -> Vec<String> {
+        if self.procfile {
+            vec![procfile::ALL_TASK.to_string()]
+        } else {
+            vec![]
+        }
+    }
+
+    /// The tasks with their descriptions, in the order of the Pilotfile.
+    pub fn tasks(&self) -> Vec<(String, Option<String>)> {
+        self.config
+            .tasks
+            .iter()
+            .map(|task| (task.name.clone(), task.description.clone()))
+            .collect()
+    }
+
+    /// Applies a profile of the Pilotfile: its tasks replace the ones of the same name,
+    /// its environment variables are returned to override the ones of the tasks. A later
+    /// document with `profile: <profile>` replaces the entries of the first one.
+    pub fn apply_profile(&mut self, profile: &str) -> Result<Vec<(String, String)>, String> {
+        let profile_yaml = self.yaml["profiles"][profile].clone();
+        let overlay = self
+            .overlays
+            .iter()
+            .find(|overlay| overlay["profile"].as_str() == Some(profile))
+            .cloned();
+
+        if profile_yaml.is_badvalue() && overlay.is_none() {
+            return Err(format!("Profile {} not found in Pilotfile", profile));
+        }
+
+        let tasks = match &mut self.yaml {
+            Yaml::Hash(tasks) => tasks,
+            _ => return Err(NOT_VALID.to_string()),
+        };
+
+        if let Some(profile_tasks) = profile_yaml["tasks"].as_hash() {
+            for (task, steps) in profile_tasks {
+                tasks.insert(task.clone(), steps.clone());
+            }
+        }
+
+        if let Some(Yaml::Hash(overlay)) = overlay {
+            for (key, value) in overlay {
+                if key.as_str() != Some("profile") {
+                    tasks.insert(key, value);
+                }
+            }
+        }
+
+        let config = Config::from_yaml(&self.yaml, &self.dir)
+            .map_err(|err| format!("{}: profile {}: {}", NOT_VALID, profile, err))?;
+        self.config = Arc::new(config);
+        self.profile = Some(profile.to_string());
+
+        match &profile_yaml["env"] {
+            Yaml::BadValue => Ok(vec![]),
+            env => env_from_yaml(env)
+                .map_err(|err| format!("{}: profile {}: {}", NOT_VALID, profile, err)),
+        }
+    }
+}
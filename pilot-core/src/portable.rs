@@ -0,0 +1,212 @@
+//! `portable: true`, runs the POSIX commands of a Pilotfile in PowerShell as well, for teams on
+//! Windows and Unix sharing one Pilotfile.
+
+use std::path::Path;
+
+/// The variable of the translated `&&` and `||` chains that holds whether the last command
+/// succeeded.
+const SUCCEEDED: &str = "$pilot_succeeded";
+
+/// Whether the shell running the commands is PowerShell, which is the default on Windows.
+pub(crate) fn is_powershell(shell: Option<&str>) -> bool {
+    let Some(shell) = shell else {
+        return cfg!(target_family = "windows");
+    };
+
+    let program = shell.split_whitespace().next().unwrap_or_default();
+    let name = Path::new(program)
+        .file_stem()
+        .map(|name| name.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default();
+
+    matches!(name.as_str(), "powershell" | "pwsh")
+}
+
+/// A piece of a command between the operators of a list.
+enum Part {
+    Command(String),
+    And,
+    Or,
+    Separator,
+}
+
+/// Splits a command at `&&`, `||` and `;` outside of quotes, translating the quoting of each
+/// command on the way.
+fn parse(command: &str, translate: impl Fn(&str) -> String) -> Vec<Part> {
+    let mut parts = vec![];
+    let mut current = String::new();
+    let mut chars = command.chars().peekable();
+    let mut quote = None;
+
+    while let Some(char) = chars.next() {
+        match (quote, char) {
+            (None, '\'' | '"') => {
+                quote = Some(char);
+                current.push(char);
+            }
+            (Some(open), _) if char == open => {
+                quote = None;
+                current.push(char);
+            }
+            // an escaped quote doesn't end the string
+            (Some('"'), '\\') => {
+                current.push(char);
+                current.extend(chars.next());
+            }
+            (None, '&') if chars.next_if_eq(&'&').is_some() => {
+                parts.push(Part::Command(translate(current.trim())));
+                parts.push(Part::And);
+                current.clear();
+            }
+            (None, '|') if chars.next_if_eq(&'|').is_some() => {
+                parts.push(Part::Command(translate(current.trim())));
+                parts.push(Part::Or);
+                current.clear();
+            }
+            // empty commands, like after a trailing `;`, are left out
+            (None, ';' | '\n') => {
+                if !current.trim().is_empty() {
+                    parts.push(Part::Command(translate(current.trim())));
+                    parts.push(Part::Separator);
+                }
+                current.clear();
+            }
+            _ => current.push(char),
+        }
+    }
+
+    if !current.trim().is_empty() {
+        parts.push(Part::Command(translate(current.trim())));
+    }
+
+    parts
+}
+
+/// Translates the variables and escapes of a single command, `$NAME` becomes `$env:NAME` and
+/// `\"` in double quotes becomes `` `" ``.
+fn translate_quoting(command: &str) -> String {
+    let mut translated = String::with_capacity(command.len());
+    let mut chars = command.chars().peekable();
+    let mut quote = None;
+
+    while let Some(char) = chars.next() {
+        match (quote, char) {
+            // nothing is special in single quotes, in both shells
+            (Some('\''), '\'') | (Some('"'), '"') => {
+                quote = None;
+                translated.push(char);
+            }
+            (Some('\''), _) => translated.push(char),
+            (None, '\'' | '"') => {
+                quote = Some(char);
+                translated.push(char);
+            }
+            (Some('"'), '\\') => match chars.next_if(|next| matches!(next, '"' | '$' | '\\')) {
+                Some('\\') => translated.push('\\'),
+                Some(escaped) => {
+                    translated.push('`');
+                    translated.push(escaped);
+                }
+                None => translated.push('\\'),
+            },
+            // the escape character of PowerShell is a plain character in POSIX strings
+            (Some('"'), '`') => translated.push_str("``"),
+            (_, '$') => {
+                let braced = chars.next_if_eq(&'{').is_some();
+                let mut name = String::new();
+
+                while let Some(char) =
+                    chars.next_if(|char| char.is_ascii_alphanumeric() || *char == '_')
+                {
+                    name.push(char);
+                }
+
+                let is_name = name.starts_with(|char: char| !char.is_ascii_digit());
+
+                match (braced, is_name) {
+                    (true, true) if chars.next_if_eq(&'}').is_some() => {
+                        translated.push_str(&format!("${{env:{}}}", name))
+                    }
+                    (false, true) => translated.push_str(&format!("$env:{}", name)),
+                    // `$?`, `$(...)` and the like mean the same in PowerShell
+                    _ => {
+                        translated.push('$');
+                        if braced {
+                            translated.push('{');
+                        }
+                        translated.push_str(&name);
+                    }
+                }
+            }
+            _ => translated.push(char),
+        }
+    }
+
+    translated
+}
+
+/// Translates a POSIX command for PowerShell. Windows PowerShell has no `&&` and `||`, so
+/// their chains remember in a variable whether the last command succeeded.
+pub(crate) fn to_powershell(command: &str) -> String {
+    let parts = parse(command, translate_quoting);
+    let mut statements = vec![];
+    let mut condition = None;
+    // whether the last command is part of a chain
+    let mut chained = false;
+
+    for (i, part) in parts.iter().enumerate() {
+        match part {
+            Part::Command(command) => {
+                let chain_follows = matches!(parts.get(i + 1), Some(Part::And | Part::Or));
+                chained = chain_follows || condition.is_some();
+
+                statements.push(match condition.take() {
+                    Some(condition) => {
+                        format!("if ({}) {{ {}; {} = $? }}", condition, command, SUCCEEDED)
+                    }
+                    None if chain_follows => format!("{}; {} = $?", command, SUCCEEDED),
+                    None => command.clone(),
+                });
+            }
+            Part::And => condition = Some(SUCCEEDED.to_string()),
+            Part::Or => condition = Some(format!("-not {}", SUCCEEDED)),
+            Part::Separator => {}
+        }
+    }
+
+    // like in a POSIX shell, a list fails if the last command of its last chain did
+    if chained {
+        statements.push(format!("if (-not {}) {{ exit 1 }}", SUCCEEDED));
+    }
+
+    statements.join("; ")
+}
+
+/// Turns the backslashes of relative paths like `.\scripts\build.sh` into slashes, for POSIX
+/// shells running commands written on Windows.
+pub(crate) fn to_posix(command: &str) -> String {
+    let mut translated = String::with_capacity(command.len());
+    let mut quote = None;
+    let mut in_path = false;
+    let mut word_start = true;
+
+    for (i, char) in command.char_indices() {
+        match (quote, char) {
+            (None, '\'' | '"') => quote = Some(char),
+            (Some(open), _) if char == open => quote = None,
+            _ => {}
+        }
+
+        if word_start {
+            let rest = &command[i..];
+            in_path = rest.starts_with(".\\") || rest.starts_with("..\\");
+        } else if char.is_whitespace() || quote.is_some() {
+            in_path = false;
+        }
+
+        translated.push(if in_path && char == '\\' { '/' } else { char });
+        word_start = quote.is_none() && (char.is_whitespace() || char == '=');
+    }
+
+    translated
+}
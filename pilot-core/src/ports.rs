@@ -0,0 +1,91 @@
+use std::net::TcpListener;
+
+#[cfg(target_os = "linux")]
+use std::fs::{read_dir, read_link, read_to_string};
+
+/// The socket inodes listening on the port, from the tables in /proc/net.
+#[cfg(target_os = "linux")]
+fn listening_inodes(port: u16) -> Vec<String> {
+    const LISTEN: &str = "0A";
+
+    ["/proc/net/tcp", "/proc/net/tcp6"]
+        .iter()
+        .filter_map(|table| read_to_string(table).ok())
+        .flat_map(|table| {
+            table
+                .lines()
+                .skip(1)
+                .filter_map(|line| {
+                    let fields: Vec<_> = line.split_whitespace().collect();
+                    let local_port = fields.get(1)?.rsplit(':').next()?;
+
+                    if u16::from_str_radix(local_port, 16).ok()? == port
+                        && *fields.get(3)? == LISTEN
+                    {
+                        Some(fields.get(9)?.to_string())
+                    } else {
+                        None
+                    }
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// The pid and name of the process owning one of the sockets, if we may look at its files.
+#[cfg(target_os = "linux")]
+fn socket_owner(inodes: &[String]) -> Option<(u32, String)> {
+    let sockets: Vec<_> = inodes
+        .iter()
+        .map(|inode| format!("socket:[{}]", inode))
+        .collect();
+
+    read_dir("/proc").ok()?.flatten().find_map(|process| {
+        let pid = process.file_name().to_str()?.parse::<u32>().ok()?;
+
+        let owns_socket = read_dir(process.path().join("fd"))
+            .ok()?
+            .flatten()
+            .filter_map(|fd| read_link(fd.path()).ok())
+            .any(|target| {
+                sockets
+                    .iter()
+                    .any(|socket| target.as_os_str() == socket.as_str())
+            });
+
+        if !owns_socket {
+            return None;
+        }
+
+        let name = read_to_string(process.path().join("comm")).unwrap_or_default();
+        Some((pid, name.trim().to_string()))
+    })
+}
+
+/// Describes who already listens on the port, or returns None if the port is free.
+#[cfg(target_os = "linux")]
+pub fn listener(port: u16) -> Option<String> {
+    let inodes = listening_inodes(port);
+
+    if inodes.is_empty() {
+        // /proc/net only shows the sockets of our network namespace, binding is the last word
+        return bind_check(port);
+    }
+
+    Some(match socket_owner(&inodes) {
+        Some((pid, name)) => format!("{} (pid {})", name, pid),
+        None => "another process".to_string(),
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn listener(port: u16) -> Option<String> {
+    bind_check(port)
+}
+
+fn bind_check(port: u16) -> Option<String> {
+    match TcpListener::bind(("0.0.0.0", port)) {
+        Ok(_) => None,
+        Err(_) => Some("another process".to_string()),
+    }
+}
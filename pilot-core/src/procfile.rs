@@ -0,0 +1,61 @@
+use yaml_rust::{yaml::Hash, Yaml};
+
+pub const FILE_NAME: &str = "Procfile";
+
+/// The task that starts every process of the Procfile, run by `pilot` without any tasks.
+pub const ALL_TASK: &str = "Procfile";
+
+fn step(key: &str, value: Yaml) -> Yaml {
+    let mut hash = Hash::new();
+    hash.insert(Yaml::String(key.to_string()), value);
+    Yaml::Hash(hash)
+}
+
+/// Procfile entries may only use letters, digits, `_` and `-` in their names.
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|char| char.is_ascii_alphanumeric() || char == '_' || char == '-')
+}
+
+/// Parses the `name: command` lines of a Procfile into the tasks of a Pilotfile.
+pub fn parse(content: &str) -> Result<Yaml, String> {
+    let mut tasks = Hash::new();
+    let mut all = vec![];
+
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (name, command) = line
+            .split_once(':')
+            .filter(|(name, _)| is_valid_name(name))
+            .ok_or(format!("Invalid Procfile entry in line {}", number + 1))?;
+
+        tasks.insert(
+            Yaml::String(name.to_string()),
+            Yaml::Array(vec![step(
+                "shell",
+                Yaml::String(command.trim().to_string()),
+            )]),
+        );
+        all.push(step("task", Yaml::String(name.to_string())));
+    }
+
+    tasks.insert(
+        Yaml::String(ALL_TASK.to_string()),
+        Yaml::Array(vec![
+            step(
+                "description",
+                Yaml::String("start all processes".to_string()),
+            ),
+            step("parallel", Yaml::Array(all)),
+        ]),
+    );
+
+    Ok(Yaml::Hash(tasks))
+}
@@ -0,0 +1,201 @@
+//! The ptys the processes of the tasks run in, so programs keep their colors and flush their
+//! output line by line. The output is read on the runtime, without a thread per process.
+
+use std::{
+    ffi::{CStr, CString},
+    io,
+    os::{
+        fd::{AsRawFd, FromRawFd, OwnedFd},
+        unix::process::CommandExt,
+    },
+    pin::Pin,
+    process::Command,
+    task::{ready, Context, Poll},
+};
+
+use tokio::{
+    io::{unix::AsyncFd, AsyncRead, ReadBuf},
+    process::Child,
+};
+
+/// the size programs see, like a terminal that was never resized
+const ROWS: u16 = 24;
+const COLUMNS: u16 = 80;
+
+/// The end of a pty that pilot reads the output of the process from.
+pub(crate) struct Pty {
+    master: AsyncFd<OwnedFd>,
+}
+
+/// Fails with the error of the last call, if it returned a negative number.
+fn check(result: libc::c_int) -> io::Result<libc::c_int> {
+    match result {
+        result if result < 0 => Err(io::Error::last_os_error()),
+        result => Ok(result),
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn slave_name(master: &OwnedFd) -> io::Result<CString> {
+    let mut name = [0; 128];
+    let result = unsafe { libc::ptsname_r(master.as_raw_fd(), name.as_mut_ptr(), name.len()) };
+
+    match result {
+        0 => Ok(unsafe { CStr::from_ptr(name.as_ptr()) }.to_owned()),
+        err => Err(io::Error::from_raw_os_error(err)),
+    }
+}
+
+/// `ptsname` returns a static buffer, the ptys of parallel tasks take turns.
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn slave_name(master: &OwnedFd) -> io::Result<CString> {
+    static PTSNAME: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+    let _ptsname = crate::supervise::lock(&PTSNAME);
+    let name = unsafe { libc::ptsname(master.as_raw_fd()) };
+
+    match name.is_null() {
+        true => Err(io::Error::last_os_error()),
+        false => Ok(unsafe { CStr::from_ptr(name) }.to_owned()),
+    }
+}
+
+/// Opens a pty, the master end doesn't block and neither end is inherited by other processes.
+fn open() -> io::Result<(OwnedFd, OwnedFd)> {
+    let master = check(unsafe { libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY) })?;
+    let master = unsafe { OwnedFd::from_raw_fd(master) };
+    let fd = master.as_raw_fd();
+
+    unsafe {
+        check(libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC))?;
+        let flags = check(libc::fcntl(fd, libc::F_GETFL))?;
+        check(libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK))?;
+        check(libc::grantpt(fd))?;
+        check(libc::unlockpt(fd))?;
+    }
+
+    let name = slave_name(&master)?;
+    let slave = check(unsafe {
+        libc::open(
+            name.as_ptr(),
+            libc::O_RDWR | libc::O_NOCTTY | libc::O_CLOEXEC,
+        )
+    })?;
+    let slave = unsafe { OwnedFd::from_raw_fd(slave) };
+
+    let size = libc::winsize {
+        ws_row: ROWS,
+        ws_col: COLUMNS,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    let mut modes: libc::termios = unsafe { std::mem::zeroed() };
+
+    unsafe {
+        check(libc::ioctl(slave.as_raw_fd(), libc::TIOCSWINSZ, &size))?;
+        check(libc::tcgetattr(slave.as_raw_fd(), &mut modes))?;
+        // nothing is typed into it, the output is all there is
+        modes.c_lflag &= !libc::ECHO;
+        check(libc::tcsetattr(slave.as_raw_fd(), libc::TCSANOW, &modes))?;
+    }
+
+    Ok((master, slave))
+}
+
+/// Starts the command with a pty as its terminal and its stdin, stdout and stderr.
+pub(crate) fn spawn(mut command: Command) -> io::Result<(Child, Pty)> {
+    let (master, slave) = open()?;
+
+    command
+        .stdin(slave.try_clone()?)
+        .stdout(slave.try_clone()?)
+        .stderr(slave);
+
+    unsafe {
+        command.pre_exec(|| {
+            // a process started with setsid already leads its session
+            if libc::setsid() < 0 && libc::getsid(0) != libc::getpid() {
+                return Err(io::Error::last_os_error());
+            }
+
+            // the stdin is the pty by now, it becomes the terminal of the session
+            check(libc::ioctl(libc::STDIN_FILENO, libc::TIOCSCTTY as _, 0))?;
+            Ok(())
+        });
+    }
+
+    // the command is dropped right away, the process has the only copies of the slave end, so
+    // the output ends when it and its children exit
+    let child = tokio::process::Command::from(command).spawn()?;
+
+    Ok((
+        child,
+        Pty {
+            master: AsyncFd::new(master)?,
+        },
+    ))
+}
+
+impl AsyncRead for Pty {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        context: &mut Context<'_>,
+        buffer: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        loop {
+            let mut ready = ready!(self.master.poll_read_ready(context))?;
+
+            let read = ready.try_io(|master| {
+                let unfilled = buffer.initialize_unfilled();
+                let read = unsafe {
+                    libc::read(
+                        master.as_raw_fd(),
+                        unfilled.as_mut_ptr().cast(),
+                        unfilled.len(),
+                    )
+                };
+
+                match read {
+                    read if read < 0 => Err(io::Error::last_os_error()),
+                    read => Ok(read as usize),
+                }
+            });
+
+            match read {
+                Ok(Ok(read)) => {
+                    buffer.advance(read);
+                    return Poll::Ready(Ok(()));
+                }
+                // a pty reports an error instead of the end once the processes closed it
+                Ok(Err(err)) if err.raw_os_error() == Some(libc::EIO) => {
+                    return Poll::Ready(Ok(()))
+                }
+                Ok(Err(err)) => return Poll::Ready(Err(err)),
+                // it wasn't ready after all, wait for the next time
+                Err(_) => continue,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::AsyncReadExt;
+
+    use super::*;
+
+    #[test]
+    fn read_output_until_exit() {
+        crate::runtime::block_on(async {
+            let mut command = Command::new("sh");
+            command.args(["-c", "echo hi; test -t 0 && stty size"]);
+
+            let (mut child, mut pty) = spawn(command).unwrap();
+            let mut output = String::new();
+            pty.read_to_string(&mut output).await.unwrap();
+
+            assert_eq!(output, "hi\r\n24 80\r\n");
+            assert!(child.wait().await.unwrap().success());
+        });
+    }
+}
@@ -0,0 +1,226 @@
+//! `--reload`, which supervises the tasks of the command line and keeps them in line with the
+//! Pilotfile: when it is saved, the tasks that were added start, the ones whose entry changed
+//! restart and the ones that were removed stop.
+
+use std::{
+    fs,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use tokio::task::JoinHandle;
+
+use crate::{
+    cli_run_task, clock, config::Config, events, failed, matching_tasks, procfile, release_color,
+    reserve_color, shutdown, status, stop_processes, supervise, Background, CliTask, Error,
+    Pilotfile, Runner, TaskResult,
+};
+
+/// how often the Pilotfile is checked for changes
+const INTERVAL: Duration = Duration::from_millis(250);
+
+/// A task that is running, or that finished by itself.
+struct Supervised {
+    name: String,
+    /// the entry of the task when it started, a change restarts it
+    definition: String,
+    processes: Arc<Mutex<Background>>,
+    started: clock::Stopwatch,
+    run: JoinHandle<i32>,
+}
+
+impl Supervised {
+    async fn stop(self) -> TaskResult {
+        stop_processes(&self.processes);
+        self.join().await
+    }
+
+    async fn join(self) -> TaskResult {
+        TaskResult {
+            task: self.name,
+            code: self.run.await.unwrap_or(crate::FAILURE_CODE),
+            duration: self.started.elapsed(),
+        }
+    }
+}
+
+/// The tasks a task or a pattern of the command line stands for, a Procfile for all of its
+/// processes. A task that is gone stands for none.
+fn expand(pilotfile: &Pilotfile, runner: &Runner, task: &str) -> Vec<String> {
+    let config = pilotfile.config();
+
+    match task {
+        procfile::ALL_TASK if pilotfile.is_procfile() => config
+            .tasks
+            .iter()
+            .map(|task| task.name.clone())
+            .filter(|name| name != procfile::ALL_TASK)
+            .collect(),
+        _ if config.task(task).is_some() => vec![task.to_string()],
+        _ if task.contains(['*', '?']) => matching_tasks(config, task, &runner.options)
+            .into_iter()
+            .map(str::to_string)
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// The tasks of the command line, each once.
+fn wanted(pilotfile: &Pilotfile, runner: &Runner, tasks: &[CliTask]) -> Vec<String> {
+    let mut wanted: Vec<String> = vec![];
+
+    for (task, _) in tasks {
+        for name in expand(pilotfile, runner, task) {
+            if !wanted.contains(&name) {
+                wanted.push(name);
+            }
+        }
+    }
+
+    wanted
+}
+
+fn definition(config: &Config, task: &str) -> String {
+    config
+        .task(task)
+        .map(|task| task.definition.clone())
+        .unwrap_or_default()
+}
+
+fn start(runner: &Runner, config: &Arc<Config>, tasks: &[CliTask], name: &str) -> Supervised {
+    let processes = Arc::new(Mutex::new(Background::default()));
+    let reserved = reserve_color();
+    let options = crate::RunOptions {
+        named_args: tasks
+            .iter()
+            .find(|(task, _)| task == name)
+            .and_then(|(_, named_args)| named_args.clone()),
+        background: Some(processes.clone()),
+        reserved: reserved.clone(),
+        ..runner.options.clone()
+    };
+
+    let task = name.to_string();
+    let task_config = config.clone();
+    let run = supervise::spawn(task.clone(), async move {
+        let code = cli_run_task(task_config, task.clone(), task, options)
+            .await
+            .unwrap_or_else(failed);
+        release_color(&reserved);
+        code
+    });
+
+    Supervised {
+        name: name.to_string(),
+        definition: definition(config, name),
+        processes,
+        started: clock::Stopwatch::start(),
+        run,
+    }
+}
+
+/// Runs the tasks at the same time until they finished or pilot is stopped. When the Pilotfile
+/// changes, the tasks are started, restarted and stopped like it says, a Pilotfile that can't be
+/// loaded leaves them running. The results are the ones of the last run of each task.
+pub(crate) async fn supervise(
+    runner: &Runner,
+    tasks: &[CliTask],
+) -> Result<Vec<TaskResult>, Error> {
+    let mut pilotfile = runner.pilotfile.clone();
+
+    for (task, _) in tasks {
+        if task.contains(',') {
+            return Err(Error(format!(
+                "--reload runs tasks and patterns, not chains like {}",
+                task
+            )));
+        }
+
+        if expand(&pilotfile, runner, task).is_empty() {
+            return Err(Error(match task.contains(['*', '?']) {
+                true => format!("No task in the Pilotfile matches {}", task),
+                false => format!("Task {} not found in Pilotfile", task),
+            }));
+        }
+    }
+
+    let path = pilotfile.path();
+    let modified = || -> Option<SystemTime> { fs::metadata(&path).ok()?.modified().ok() };
+    let mut last_modified = modified();
+
+    let mut running: Vec<_> = wanted(&pilotfile, runner, tasks)
+        .iter()
+        .map(|name| start(runner, pilotfile.config(), tasks, name))
+        .collect();
+    let mut results = vec![];
+
+    while running.iter().any(|task| !task.run.is_finished()) {
+        if !shutdown::sleep(INTERVAL).await || modified() == last_modified {
+            continue;
+        }
+
+        last_modified = modified();
+
+        let reloaded = match pilotfile.reload() {
+            Ok(reloaded) => reloaded,
+            Err(err) => {
+                eprintln!(
+                    "Could not reload the Pilotfile, the tasks keep running: {}",
+                    err
+                );
+                continue;
+            }
+        };
+
+        let wanted = wanted(&reloaded, runner, tasks);
+        let mut changes = vec![];
+
+        for task in std::mem::take(&mut running) {
+            let changed = task.definition != definition(reloaded.config(), &task.name);
+
+            match (wanted.contains(&task.name), changed) {
+                (true, false) => running.push(task),
+                (true, true) => {
+                    changes.push(format!("restarted {}", task.name));
+                    let name = task.name.clone();
+                    task.stop().await;
+                    events::fire(
+                        reloaded.config(),
+                        reloaded.dir(),
+                        runner.options.shell.as_deref(),
+                        "task_restarted",
+                        &[("task", events::Value::Text(&name))],
+                    )
+                    .await;
+                    running.push(start(runner, reloaded.config(), tasks, &name));
+                }
+                (false, _) => {
+                    changes.push(format!("stopped {}", task.name));
+                    results.push(task.stop().await);
+                }
+            }
+        }
+
+        for name in wanted {
+            if !running.iter().any(|task| task.name == name) {
+                changes.push(format!("started {}", name));
+                running.push(start(runner, reloaded.config(), tasks, &name));
+            }
+        }
+
+        if !runner.options.silent {
+            status::println(&match changes.is_empty() {
+                true => "reloaded the Pilotfile, no task changed".to_string(),
+                false => format!("reloaded the Pilotfile: {}", changes.join(", ")),
+            });
+        }
+
+        pilotfile = reloaded;
+    }
+
+    for task in running {
+        results.push(task.join().await);
+    }
+
+    Ok(results)
+}
@@ -0,0 +1,79 @@
+use std::process::Command;
+
+use yaml_rust::Yaml;
+
+/// Runs the shell steps of a task on another machine, through ssh.
+#[derive(Clone)]
+pub struct Remote {
+    host: String,
+    port: Option<u16>,
+    dir: Option<String>,
+    ssh: Option<String>,
+}
+
+/// Quotes a word for the remote shell.
+fn quote(word: &str) -> String {
+    format!("'{}'", word.replace('\'', "'\\''"))
+}
+
+impl Remote {
+    /// Either just the host, like `user@build-box`, or a map with the host and its settings.
+    pub fn from_yaml(yaml: &Yaml) -> Option<Self> {
+        if let Some(host) = yaml.as_str() {
+            return Some(Remote {
+                host: host.to_string(),
+                port: None,
+                dir: None,
+                ssh: None,
+            });
+        }
+
+        let port = match &yaml["port"] {
+            Yaml::BadValue => None,
+            port => Some(u16::try_from(port.as_i64()?).ok()?),
+        };
+
+        Some(Remote {
+            host: yaml["host"].as_str()?.to_string(),
+            port,
+            dir: yaml["dir"].as_str().map(|dir| dir.to_string()),
+            ssh: yaml["ssh"].as_str().map(|ssh| ssh.to_string()),
+        })
+    }
+
+    /// Builds the command that runs `shell_command` on the host, with the environment of the task.
+    pub fn command(&self, shell_command: &str, env: &[(String, String)], tty: bool) -> Command {
+        let mut command = Command::new(self.ssh.as_deref().unwrap_or("ssh"));
+
+        // a remote tty makes the programs flush their output line by line, like the local pty
+        if tty {
+            command.arg("-t");
+        }
+
+        if let Some(port) = self.port {
+            command.arg("-p").arg(port.to_string());
+        }
+
+        // ssh joins its arguments into a single command line for the remote shell
+        let mut remote_command = String::new();
+
+        if let Some(dir) = &self.dir {
+            remote_command += &format!("cd {} && ", quote(dir));
+        }
+
+        if !env.is_empty() {
+            remote_command += "env";
+
+            for (key, value) in env {
+                remote_command += &format!(" {}", quote(&format!("{}={}", key, value)));
+            }
+
+            remote_command += " ";
+        }
+
+        remote_command += &format!("sh -c {}", quote(shell_command));
+
+        command.arg(&self.host).arg("--").arg(remote_command);
+        command
+    }
+}
@@ -0,0 +1,40 @@
+//! The tokio runtime the runs are executed on. The tasks and branches are tasks of the runtime,
+//! which waits for the output and exit of their processes, the timers of idle timeouts, health
+//! checks, polls and grace periods, and the signals of pilot, all in a few threads. The library
+//! stays synchronous, [`crate::Runner`] blocks on the runtime until its tasks are done.
+
+use std::{future::Future, sync::OnceLock};
+
+use tokio::{
+    runtime::{Builder, Runtime},
+    task::JoinHandle,
+};
+
+static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+
+/// The runtime, started the first time it is needed. It lives until pilot exits, so the exit
+/// hooks can still run steps on it.
+pub(crate) fn get() -> &'static Runtime {
+    RUNTIME.get_or_init(|| {
+        Builder::new_multi_thread()
+            .thread_name("pilot")
+            .enable_all()
+            .build()
+            // like a thread that can't be started, there is nothing to fall back to
+            .expect("Could not start the runtime")
+    })
+}
+
+/// Runs a future to the end on the runtime, from outside of it.
+pub(crate) fn block_on<F: Future>(future: F) -> F::Output {
+    get().block_on(future)
+}
+
+/// Starts a future on the runtime, also from threads outside of it.
+pub(crate) fn spawn<F>(future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    get().spawn(future)
+}
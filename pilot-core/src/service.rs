@@ -0,0 +1,161 @@
+//! The tasks of `kind: service`, which run until they are stopped, and their `health` check,
+//! which stops a service that keeps failing it so it can be restarted.
+
+use std::{
+    future::Future,
+    pin::pin,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use tokio::{sync::Notify, task::JoinHandle};
+
+use crate::{clock, runtime, shutdown, status, wait::Poll};
+
+/// A running service.
+#[derive(Default)]
+pub(crate) struct Service {
+    state: Mutex<State>,
+    done: Notify,
+}
+
+#[derive(Default)]
+struct State {
+    /// the processes of its shell steps
+    pids: Vec<u32>,
+    /// `pilot cancel` stopped it on purpose
+    cancelled: bool,
+    /// the health check stopped it
+    unhealthy: bool,
+    /// its health check succeeded, or it started if it has none
+    ready: bool,
+    /// its steps are done, so the health check ends
+    done: bool,
+}
+
+impl Service {
+    pub(crate) fn add_process(&self, pid: u32) {
+        self.state.lock().unwrap().pids.push(pid);
+    }
+
+    pub(crate) fn remove_process(&self, pid: u32) {
+        self.state
+            .lock()
+            .unwrap()
+            .pids
+            .retain(|child| *child != pid);
+    }
+
+    pub(crate) fn cancel(&self) {
+        self.state.lock().unwrap().cancelled = true;
+    }
+
+    /// Whether it was stopped on purpose, by `pilot cancel` or an interrupt of pilot.
+    pub(crate) fn is_stopped(&self) -> bool {
+        self.state.lock().unwrap().cancelled || shutdown::is_interrupted()
+    }
+
+    pub(crate) fn is_unhealthy(&self) -> bool {
+        self.state.lock().unwrap().unhealthy
+    }
+
+    /// Lets the tasks that need it go on.
+    pub(crate) fn mark_ready(&self) {
+        self.state.lock().unwrap().ready = true;
+    }
+
+    pub(crate) fn is_ready(&self) -> bool {
+        self.state.lock().unwrap().ready
+    }
+
+    /// Checks the service with `check` while its steps run. It is ready once a check succeeds,
+    /// if the checks keep failing for the timeout of `health` its processes are stopped.
+    /// `task_prefix` is announced once it is ready, unless it is `None`.
+    pub(crate) fn watch<F>(
+        self: &Arc<Self>,
+        health: &Poll,
+        task_name: &str,
+        task_prefix: Option<String>,
+        mut check: impl FnMut() -> F + Send + 'static,
+    ) -> JoinHandle<()>
+    where
+        F: Future<Output = bool> + Send + 'static,
+    {
+        {
+            let mut state = self.state.lock().unwrap();
+            state.unhealthy = false;
+            state.done = false;
+        }
+
+        let service = self.clone();
+        let health = health.clone();
+        let task_name = task_name.to_string();
+
+        runtime::spawn(async move {
+            let mut ready = false;
+            let mut failing_since = Instant::now();
+            let mut attempts = 0;
+
+            loop {
+                attempts += 1;
+
+                if check().await {
+                    if let (false, Some(task_prefix)) = (ready, &task_prefix) {
+                        let plural = if attempts == 1 { "" } else { "s" };
+                        status::println(&format!(
+                            "health {}: ready after {} attempt{}",
+                            task_prefix, attempts, plural
+                        ));
+                    }
+
+                    ready = true;
+                    service.mark_ready();
+                    failing_since = Instant::now();
+                    attempts = 0;
+                }
+
+                // the end of the steps can't slip in between the check and the wait for it
+                let mut done = pin!(service.done.notified());
+                done.as_mut().enable();
+                {
+                    let mut state = service.state.lock().unwrap();
+
+                    if state.done || shutdown::is_interrupted() {
+                        return;
+                    }
+
+                    // a failing check counts from the last one that succeeded, or the start
+                    if attempts > 0 && failing_since.elapsed() + health.interval >= health.timeout {
+                        eprintln!(
+                            "Service {} failed its health check: `{}` still failed after {} attempts \
+in {}, stopping it",
+                            task_name,
+                            health.command,
+                            attempts,
+                            clock::human(health.timeout)
+                        );
+                        state.unhealthy = true;
+
+                        for pid in &state.pids {
+                            shutdown::terminate(*pid);
+                        }
+
+                        return;
+                    }
+                }
+
+                tokio::select! {
+                    () = done => return,
+                    () = tokio::time::sleep(health.interval) => {}
+                }
+            }
+        })
+    }
+
+    /// Ends the health check after the steps are done.
+    pub(crate) async fn finish(&self, watch: JoinHandle<()>) {
+        self.state.lock().unwrap().done = true;
+        self.done.notify_waiters();
+        let _ = watch.await;
+    }
+}
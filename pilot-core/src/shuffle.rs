@@ -0,0 +1,57 @@
+//! `--shuffle`, runs the tasks in a random order that the seed of the run reproduces. The
+//! `jitter` of steps is random as well.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// A seed for a run that didn't get one.
+pub fn new_seed() -> u64 {
+    // short enough to type it again
+    nanos() % 100_000
+}
+
+fn nanos() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_nanos() as u64)
+        .unwrap_or_default()
+}
+
+/// SplitMix64, good enough to order a few tasks and the same on every platform.
+struct Random(u64);
+
+impl Random {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+/// Shuffles the items, `context` like the task running them makes each list of a run get its
+/// own order. Parallel tasks shuffle in any order, but every list still comes out the same.
+pub(crate) fn shuffle<T>(items: &mut [T], seed: u64, context: &str) {
+    // FNV-1a, unlike the hasher of std it is guaranteed to stay the same
+    let hash = context
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325, |hash: u64, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100_0000_01b3)
+        });
+    let mut random = Random(seed ^ hash);
+
+    // Fisher-Yates
+    for i in (1..items.len()).rev() {
+        let j = (random.next() % (i as u64 + 1)) as usize;
+        items.swap(i, j);
+    }
+}
+
+/// A random duration up to `max`, unlike the order of a run it's different every time.
+pub(crate) fn random_duration(max: Duration) -> Duration {
+    match u64::try_from(max.as_nanos()) {
+        Ok(0) => Duration::ZERO,
+        Ok(max) => Duration::from_nanos(Random(nanos()).next() % max),
+        Err(_) => max,
+    }
+}
@@ -0,0 +1,324 @@
+#[cfg(unix)]
+use std::time::Instant;
+use std::{
+    ffi::CString,
+    future::Future,
+    path::Path,
+    pin::pin,
+    process,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Mutex, OnceLock,
+    },
+    time::Duration,
+};
+
+use tokio::sync::Notify;
+
+use crate::runtime;
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+/// wakes the sleeps of the tasks when pilot is interrupted
+static INTERRUPT: Notify = Notify::const_new();
+static HUNG_UP: AtomicBool = AtomicBool::new(false);
+/// the file the output goes to after a hangup, if pilot keeps running then
+static DETACHED_LOG: OnceLock<CString> = OnceLock::new();
+static CHILDREN: Mutex<Vec<(u32, Stop)>> = Mutex::new(vec![]);
+/// the raw processes that have the terminal, Ctrl-C is theirs while they run
+static FOREGROUND: AtomicUsize = AtomicUsize::new(0);
+
+type ExitHook = Box<dyn FnOnce() + Send>;
+static EXIT_HOOKS: Mutex<Vec<(u64, ExitHook)>> = Mutex::new(vec![]);
+static NEXT_HOOK_ID: AtomicU64 = AtomicU64::new(0);
+
+/// The signals a task can be stopped with, `stop_signal` takes them with or without `SIG`.
+pub(crate) const STOP_SIGNALS: &[&str] = &[
+    "SIGTERM", "SIGINT", "SIGHUP", "SIGQUIT", "SIGKILL", "SIGUSR1", "SIGUSR2",
+];
+/// how long a process gets to exit after its stop signal before it is killed
+const DEFAULT_GRACE_PERIOD: Duration = Duration::from_secs(10);
+
+/// How the processes of a task are stopped, the `stop_signal` and `stop_grace_period` of the task.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Stop {
+    pub(crate) signal: &'static str,
+    pub(crate) grace_period: Duration,
+}
+
+impl Default for Stop {
+    fn default() -> Self {
+        Stop {
+            signal: "SIGTERM",
+            grace_period: DEFAULT_GRACE_PERIOD,
+        }
+    }
+}
+
+/// The exit code used when the run was interrupted by a signal.
+pub const INTERRUPTED_EXIT_CODE: i32 = 130;
+/// how often a stopped process is checked for whether it exited in its grace period
+#[cfg(unix)]
+const SLEEP_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Stops the children and wakes the tasks waiting for something, the first time pilot is
+/// interrupted.
+fn interrupt() {
+    if !INTERRUPTED.swap(true, Ordering::SeqCst) {
+        INTERRUPT.notify_waiters();
+        terminate_children();
+    }
+}
+
+#[cfg(unix)]
+fn on_hangup() {
+    HUNG_UP.store(true, Ordering::SeqCst);
+
+    // the terminal is gone, printing to it would fail, so the output goes to the detached
+    // log or nowhere
+    let detached_log = DETACHED_LOG.get();
+    let output = detached_log
+        .map(|log| log.as_c_str())
+        .unwrap_or(c"/dev/null");
+
+    unsafe {
+        let stdin = libc::open(c"/dev/null".as_ptr(), libc::O_RDONLY);
+        let stdout = libc::open(
+            output.as_ptr(),
+            libc::O_WRONLY | libc::O_CREAT | libc::O_APPEND,
+            0o644,
+        );
+
+        for (fd, targets) in [(stdin, &[0][..]), (stdout, &[1, 2][..])] {
+            if fd >= 0 {
+                for target in targets {
+                    libc::dup2(fd, *target);
+                }
+
+                libc::close(fd);
+            }
+        }
+    }
+
+    if detached_log.is_none() {
+        interrupt();
+    }
+}
+
+/// Catches SIGINT, SIGTERM and SIGHUP, so pilot can stop its children and run cleanup steps
+/// instead of dying on the spot. The signals are handled by a task of the runtime.
+#[cfg(unix)]
+pub fn install() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let runtime = runtime::get();
+    let _entered = runtime.enter();
+    let listen = |kind| signal(kind).expect("Could not catch the signals of pilot");
+    let (mut int, mut term, mut hangup) = (
+        listen(SignalKind::interrupt()),
+        listen(SignalKind::terminate()),
+        listen(SignalKind::hangup()),
+    );
+
+    runtime.spawn(async move {
+        loop {
+            tokio::select! {
+                Some(()) = int.recv() => {
+                    // the process in the foreground got the Ctrl-C from the terminal as well,
+                    // it's for them
+                    if FOREGROUND.load(Ordering::SeqCst) == 0 {
+                        interrupt();
+                    }
+                }
+                Some(()) = term.recv() => interrupt(),
+                Some(()) = hangup.recv() => on_hangup(),
+            }
+        }
+    });
+}
+
+#[cfg(not(unix))]
+pub fn install() {}
+
+/// Waits until pilot is interrupted.
+pub(crate) async fn interrupted() {
+    let mut notified = pin!(INTERRUPT.notified());
+    // an interrupt between the check and the wait would be missed otherwise
+    notified.as_mut().enable();
+
+    if !is_interrupted() {
+        notified.await;
+    }
+}
+
+/// Sleeps for the duration, unless pilot is interrupted before, then it returns false.
+pub(crate) async fn sleep(duration: Duration) -> bool {
+    tokio::select! {
+        () = interrupted() => false,
+        () = tokio::time::sleep(duration) => true,
+    }
+}
+
+/// Runs a process that has the terminal, a Ctrl-C goes to it instead of pilot, like in a shell.
+/// It only stops the run if it stopped the process, SIGTERM always does.
+pub(crate) async fn foreground(run: impl Future<Output = i32>) -> i32 {
+    FOREGROUND.fetch_add(1, Ordering::SeqCst);
+    let code = run.await;
+    FOREGROUND.fetch_sub(1, Ordering::SeqCst);
+
+    if code == INTERRUPTED_EXIT_CODE {
+        interrupt();
+    }
+
+    code
+}
+
+pub fn is_interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// Whether the terminal of pilot hung up, its output doesn't go to the terminal anymore.
+pub fn is_hung_up() -> bool {
+    HUNG_UP.load(Ordering::SeqCst)
+}
+
+/// Keeps running when the terminal hangs up, like after a dropped SSH session, with the output
+/// going to a log file instead.
+pub fn detach_on_hangup(log: &Path) {
+    if let Ok(log) = CString::new(log.to_string_lossy().as_bytes()) {
+        let _ = DETACHED_LOG.set(log);
+    }
+}
+
+/// Remembers a spawned process, so it can be stopped when pilot is interrupted.
+pub(crate) fn register_child(pid: u32, stop: Stop) {
+    CHILDREN.lock().unwrap().push((pid, stop));
+}
+
+pub(crate) fn unregister_child(pid: u32) {
+    CHILDREN.lock().unwrap().retain(|(child, _)| *child != pid);
+}
+
+#[cfg(unix)]
+fn terminate_children() {
+    let children = CHILDREN.lock().unwrap().clone();
+
+    for (pid, stop) in children {
+        if send_stop_signal(pid, stop) {
+            runtime::spawn(kill_after_grace_period(pid, stop.grace_period));
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn terminate_children() {}
+
+/// Asks a spawned process to stop with the signal of its task, with everything it started, and
+/// kills it if it doesn't exit in the grace period. It returns right away.
+pub(crate) fn terminate(pid: u32) {
+    let stop = CHILDREN
+        .lock()
+        .unwrap()
+        .iter()
+        .find(|(child, _)| *child == pid)
+        .map(|(_, stop)| *stop)
+        .unwrap_or_default();
+
+    if send_stop_signal(pid, stop) {
+        runtime::spawn(kill_after_grace_period(pid, stop.grace_period));
+    }
+}
+
+/// Kills a spawned process with everything it started, without waiting for it to stop.
+pub(crate) fn kill(pid: u32) {
+    let stop = Stop {
+        signal: "SIGKILL",
+        grace_period: Duration::ZERO,
+    };
+
+    send_stop_signal(pid, stop);
+}
+
+/// Like [`terminate`] for a process of another pilot, it waits until the process is gone.
+pub(crate) async fn stop(pid: u32, stop: Stop) {
+    if send_stop_signal(pid, stop) {
+        kill_after_grace_period(pid, stop.grace_period).await;
+    }
+}
+
+/// Sends a signal to the process group of a process, or the process if it has none. Pty
+/// processes lead their own process group, so this takes down everything they started.
+#[cfg(unix)]
+fn signal(pid: u32, signal: libc::c_int) -> bool {
+    let pid = pid as libc::pid_t;
+    unsafe { libc::kill(-pid, signal) == 0 || libc::kill(pid, signal) == 0 }
+}
+
+/// Whether the process was still there to get the signal.
+#[cfg(unix)]
+fn send_stop_signal(pid: u32, stop: Stop) -> bool {
+    let number = match stop.signal {
+        "SIGINT" => libc::SIGINT,
+        "SIGHUP" => libc::SIGHUP,
+        "SIGQUIT" => libc::SIGQUIT,
+        "SIGKILL" => libc::SIGKILL,
+        "SIGUSR1" => libc::SIGUSR1,
+        "SIGUSR2" => libc::SIGUSR2,
+        _ => libc::SIGTERM,
+    };
+
+    signal(pid, number)
+}
+
+#[cfg(unix)]
+async fn kill_after_grace_period(pid: u32, grace_period: Duration) {
+    let start = Instant::now();
+
+    while signal(pid, 0) {
+        if start.elapsed() >= grace_period {
+            signal(pid, libc::SIGKILL);
+            return;
+        }
+
+        tokio::time::sleep(SLEEP_INTERVAL).await;
+    }
+}
+
+/// Windows has no signals, the process is stopped right away.
+#[cfg(not(unix))]
+fn send_stop_signal(pid: u32, _stop: Stop) -> bool {
+    let _ = process::Command::new("taskkill")
+        .args(["/pid", &pid.to_string(), "/t", "/f"])
+        .output();
+    false
+}
+
+#[cfg(not(unix))]
+async fn kill_after_grace_period(_pid: u32, _grace_period: Duration) {}
+
+/// Registers a hook that runs when pilot exits early through [`exit`].
+///
+/// The returned id can be used to take the hook back once it isn't needed anymore.
+pub fn on_exit(hook: impl FnOnce() + Send + 'static) -> u64 {
+    let id = NEXT_HOOK_ID.fetch_add(1, Ordering::SeqCst);
+    EXIT_HOOKS.lock().unwrap().push((id, Box::new(hook)));
+    id
+}
+
+/// Takes back a hook registered with [`on_exit`], e.g. to run it on a regular exit.
+///
+/// Returns `None` if pilot is already exiting and runs the hook itself.
+pub fn take_exit_hook(id: u64) -> Option<ExitHook> {
+    let mut hooks = EXIT_HOOKS.lock().unwrap();
+    let index = hooks.iter().position(|(hook_id, _)| *hook_id == id)?;
+    Some(hooks.remove(index).1)
+}
+
+/// Runs the registered exit hooks, latest first, and exits the process.
+pub fn exit(code: i32) -> ! {
+    // release the lock before running a hook, hooks may register new ones
+    while let Some((_, hook)) = EXIT_HOOKS.lock().unwrap().pop() {
+        hook();
+    }
+
+    process::exit(code)
+}
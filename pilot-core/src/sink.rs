@@ -0,0 +1,173 @@
+//! Where the output of the processes goes, like the console or a log file. A run or a task can
+//! have several sinks at once.
+
+use std::{
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{atomic::Ordering, Arc, Mutex},
+};
+
+use chrono::{DateTime, Local};
+
+use crate::{
+    print_output_line, state::json_string, strip_escapes, supervise, truncate_line, PrefixStyle,
+    RunOptions, PADDING,
+};
+
+/// A line of output of a process.
+pub struct Line<'a> {
+    /// the task running the process
+    pub task: &'a str,
+    /// the running task with the tasks that started it, like `run > build`
+    pub task_path: &'a str,
+    /// the line with the color sequences of the process, [`Line::plain`] is without them
+    pub text: &'a str,
+    pub time: DateTime<Local>,
+    /// the color of the task name
+    pub(crate) color: &'a str,
+    /// the output of quiet tasks isn't printed
+    pub(crate) quiet: bool,
+    pub(crate) options: &'a RunOptions,
+}
+
+impl Line<'_> {
+    pub fn plain(&self) -> String {
+        strip_escapes(self.text)
+    }
+}
+
+pub trait OutputSink: Send + Sync {
+    fn write(&self, line: &Line);
+}
+
+/// Prints the lines prefixed with the colored task name, the way pilot always did.
+pub struct Console;
+
+impl OutputSink for Console {
+    fn write(&self, line: &Line) {
+        if line.quiet {
+            return;
+        }
+
+        let options = line.options;
+        let mut time_prefix = "".to_string();
+
+        if options.timestamp() {
+            time_prefix = line.time.format(&options.timestamp_format).to_string() + " ";
+        }
+
+        if !options.color {
+            // the padding grows as tasks start, which misaligns logs more than it helps
+            let text = truncate_line(line.plain(), options.max_line_length());
+            let line = match options.prefix_style {
+                PrefixStyle::Brackets => format!("{}[{}] {}", time_prefix, line.task, text),
+                _ => format!("{}{}: {}", time_prefix, line.task, text),
+            };
+            print_output_line(line, options);
+            return;
+        }
+
+        let text = truncate_line(line.text.to_string(), options.max_line_length());
+        let padding = PADDING.load(Ordering::SeqCst);
+        let padding_prefix = " ".repeat(padding.saturating_sub(line.task.len() + 1));
+
+        print_output_line(
+            format!(
+                "{}{}{}:\x1b[0m{} {}",
+                time_prefix, line.color, line.task, padding_prefix, text
+            ),
+            options,
+        );
+    }
+}
+
+/// Drops the lines, for runs that only need the outcome.
+pub struct Null;
+
+impl OutputSink for Null {
+    fn write(&self, _: &Line) {}
+}
+
+/// Appends the lines without colors to a file, prefixed with the task name.
+pub struct FileSink {
+    file: Mutex<File>,
+}
+
+impl OutputSink for FileSink {
+    fn write(&self, line: &Line) {
+        // like the history, a full disk shouldn't fail the tasks
+        let _ = writeln!(
+            supervise::lock(&self.file),
+            "{}: {}",
+            line.task,
+            line.plain()
+        );
+    }
+}
+
+/// Appends the lines as JSON objects to a file, one per line.
+pub struct JsonSink {
+    file: Mutex<File>,
+}
+
+impl OutputSink for JsonSink {
+    fn write(&self, line: &Line) {
+        let _ = writeln!(
+            supervise::lock(&self.file),
+            "{{\"time\": {}, \"task\": {}, \"path\": {}, \"line\": {}}}",
+            json_string(&line.time.to_rfc3339()),
+            json_string(line.task),
+            json_string(line.task_path),
+            json_string(&line.plain())
+        );
+    }
+}
+
+/// A sink of the command line or the Pilotfile, like `console`, `null`, `file:<path>` or
+/// `json:<path>`.
+#[derive(Clone)]
+pub enum SinkSpec {
+    Console,
+    Null,
+    File(PathBuf),
+    Json(PathBuf),
+}
+
+impl SinkSpec {
+    /// Parses a sink, relative paths are resolved against `dir`.
+    pub fn parse(spec: &str, dir: &Path) -> Result<Self, String> {
+        match spec.split_once(':') {
+            _ if spec == "console" => Ok(SinkSpec::Console),
+            _ if spec == "null" => Ok(SinkSpec::Null),
+            Some(("file", path)) if !path.is_empty() => Ok(SinkSpec::File(dir.join(path))),
+            Some(("json", path)) if !path.is_empty() => Ok(SinkSpec::Json(dir.join(path))),
+            _ => Err(format!(
+                "{} is no output, use console, null, file:<path> or json:<path>",
+                spec
+            )),
+        }
+    }
+
+    pub fn open(&self) -> Result<Arc<dyn OutputSink>, String> {
+        let append = |path: &Path| {
+            File::options()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map(Mutex::new)
+                .map_err(|err| format!("Could not open {}: {}", path.display(), err))
+        };
+
+        Ok(match self {
+            SinkSpec::Console => Arc::new(Console),
+            SinkSpec::Null => Arc::new(Null),
+            SinkSpec::File(path) => Arc::new(FileSink {
+                file: append(path)?,
+            }),
+            SinkSpec::Json(path) => Arc::new(JsonSink {
+                file: append(path)?,
+            }),
+        })
+    }
+}
@@ -0,0 +1,577 @@
+use std::{
+    fs::{create_dir, read_to_string, remove_dir, write},
+    path::{Path, PathBuf},
+    process::Command,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Mutex, PoisonError,
+    },
+};
+
+use yaml_rust::Yaml;
+
+/// Makes the command run as the given user, dropping pilot's own privileges.
+#[cfg(unix)]
+pub fn set_user(command: &mut Command, user: &str) -> Result<(), String> {
+    use std::{ffi::CString, os::unix::process::CommandExt};
+
+    let name = CString::new(user).map_err(|_| format!("Invalid user name {}", user))?;
+
+    // getpwnam returns a pointer into static memory, so copy the ids right away
+    let (uid, gid, home) = unsafe {
+        let passwd = libc::getpwnam(name.as_ptr());
+
+        if passwd.is_null() {
+            return Err(format!("Unknown user {}", user));
+        }
+
+        let home = std::ffi::CStr::from_ptr((*passwd).pw_dir)
+            .to_string_lossy()
+            .to_string();
+
+        ((*passwd).pw_uid, (*passwd).pw_gid, home)
+    };
+
+    let current_uid = unsafe { libc::geteuid() };
+
+    if current_uid != 0 && current_uid != uid {
+        return Err(format!(
+            "pilot has to run as root to run tasks as user {}",
+            user
+        ));
+    }
+
+    command
+        .uid(uid)
+        .gid(gid)
+        .env("USER", user)
+        .env("LOGNAME", user)
+        .env("HOME", home);
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn set_user(_command: &mut Command, user: &str) -> Result<(), String> {
+    Err(format!(
+        "Running tasks as user {} is only supported on Unix",
+        user
+    ))
+}
+
+/// Translates the name of a Windows priority class into a niceness.
+pub fn nice_from_priority_class(name: &str) -> Option<i32> {
+    match name {
+        "idle" => Some(19),
+        "below_normal" => Some(10),
+        "normal" => Some(0),
+        "above_normal" => Some(-5),
+        "high" => Some(-10),
+        _ => None,
+    }
+}
+
+/// Sets the niceness of the command, raising the priority needs root.
+#[cfg(unix)]
+pub fn set_nice(command: &mut Command, nice: i32) {
+    use std::{io, os::unix::process::CommandExt};
+
+    unsafe {
+        command.pre_exec(move || {
+            if libc::setpriority(libc::PRIO_PROCESS, 0, nice) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            Ok(())
+        });
+    }
+}
+
+#[cfg(windows)]
+pub fn set_nice(command: &mut Command, nice: i32) {
+    use std::os::windows::process::CommandExt;
+
+    const IDLE_PRIORITY_CLASS: u32 = 0x40;
+    const BELOW_NORMAL_PRIORITY_CLASS: u32 = 0x4000;
+    const NORMAL_PRIORITY_CLASS: u32 = 0x20;
+    const ABOVE_NORMAL_PRIORITY_CLASS: u32 = 0x8000;
+    const HIGH_PRIORITY_CLASS: u32 = 0x80;
+
+    let class = match nice {
+        15.. => IDLE_PRIORITY_CLASS,
+        5..=14 => BELOW_NORMAL_PRIORITY_CLASS,
+        -4..=4 => NORMAL_PRIORITY_CLASS,
+        -14..=-5 => ABOVE_NORMAL_PRIORITY_CLASS,
+        _ => HIGH_PRIORITY_CLASS,
+    };
+
+    command.creation_flags(class);
+}
+
+/// Sets the permissions new files of the command don't get.
+#[cfg(unix)]
+pub fn set_umask(command: &mut Command, umask: u32) {
+    use std::os::unix::process::CommandExt;
+
+    unsafe {
+        command.pre_exec(move || {
+            libc::umask(umask as libc::mode_t);
+            Ok(())
+        });
+    }
+}
+
+/// Windows has no umask, new files get the permissions of their directory.
+#[cfg(not(unix))]
+pub fn set_umask(_command: &mut Command, _umask: u32) {}
+
+/// Starts the command in a new session, so it neither has the terminal of pilot nor gets its
+/// signals.
+#[cfg(unix)]
+pub fn set_setsid(command: &mut Command) {
+    use std::{io, os::unix::process::CommandExt};
+
+    unsafe {
+        command.pre_exec(|| {
+            // processes with a pty already lead their own session
+            if libc::setsid() < 0 && libc::getsid(0) != libc::getpid() {
+                return Err(io::Error::last_os_error());
+            }
+
+            Ok(())
+        });
+    }
+}
+
+#[cfg(not(unix))]
+pub fn set_setsid(_command: &mut Command) {}
+
+/// The modes of the terminal pilot reads from, like whether it echoes the keys, none without
+/// a terminal.
+#[cfg(unix)]
+pub struct TerminalModes(Option<libc::termios>);
+
+#[cfg(not(unix))]
+pub struct TerminalModes;
+
+#[cfg(unix)]
+pub fn terminal_modes() -> TerminalModes {
+    let mut modes: libc::termios = unsafe { std::mem::zeroed() };
+
+    match unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut modes) } {
+        0 => TerminalModes(Some(modes)),
+        _ => TerminalModes(None),
+    }
+}
+
+#[cfg(not(unix))]
+pub fn terminal_modes() -> TerminalModes {
+    TerminalModes
+}
+
+/// Sets the modes back, after a process that changed them exited without doing it, like an
+/// editor that crashed in raw mode.
+#[cfg(unix)]
+pub fn restore_terminal_modes(modes: &TerminalModes) {
+    if let TerminalModes(Some(modes)) = modes {
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSADRAIN, modes) };
+    }
+}
+
+#[cfg(not(unix))]
+pub fn restore_terminal_modes(_modes: &TerminalModes) {}
+
+/// Resource limits for the processes of a task.
+#[derive(Clone, Default)]
+pub struct Limits {
+    /// bytes of memory
+    pub memory: Option<u64>,
+    /// number of cpu cores
+    pub cpu: Option<f64>,
+    pub open_files: Option<u64>,
+}
+
+impl Limits {
+    pub fn from_yaml(yaml: &Yaml) -> Option<Self> {
+        let mut limits = Limits::default();
+
+        for (key, value) in yaml.as_hash()? {
+            match key.as_str()? {
+                "memory" => {
+                    limits.memory = Some(match value {
+                        Yaml::Integer(bytes) => u64::try_from(*bytes).ok()?,
+                        Yaml::String(size) => parse_size(size)?,
+                        _ => return None,
+                    })
+                }
+                "cpu" => {
+                    limits.cpu = Some(match value {
+                        Yaml::Integer(cores) => *cores as f64,
+                        Yaml::Real(cores) => cores.parse().ok()?,
+                        _ => return None,
+                    })
+                }
+                "open_files" => limits.open_files = Some(u64::try_from(value.as_i64()?).ok()?),
+                _ => return None,
+            }
+        }
+
+        Some(limits)
+    }
+}
+
+/// Parses sizes like `512MB` or `2GiB`, the units are powers of 1024 either way.
+pub fn parse_size(size: &str) -> Option<u64> {
+    let size = size.trim();
+    let split = size
+        .find(|char: char| !char.is_ascii_digit() && char != '.')
+        .unwrap_or(size.len());
+    let (number, unit) = size.split_at(split);
+
+    let factor: u64 = match unit.trim().to_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" | "kib" => 1 << 10,
+        "m" | "mb" | "mib" => 1 << 20,
+        "g" | "gb" | "gib" => 1 << 30,
+        "t" | "tb" | "tib" => 1 << 40,
+        _ => return None,
+    };
+
+    Some((number.parse::<f64>().ok()? * factor as f64) as u64)
+}
+
+/// A cgroup v2 created for a single process, removed again when dropped.
+///
+/// The cgroups are created below the one pilot runs in, which needs the cpu and memory
+/// controllers delegated to it, like `systemd-run --user --scope -p Delegate=yes pilot`.
+/// Since a cgroup with processes can't hand its controllers down, pilot moves itself into a
+/// leaf cgroup next to the ones of the tasks first. That only works while pilot is the only
+/// process in its cgroup.
+pub struct Cgroup {
+    path: PathBuf,
+}
+
+// only joined on unix, the type stays so join_cgroup has the same signature everywhere
+#[cfg_attr(not(unix), allow(dead_code))]
+impl Cgroup {
+    const ROOT: &'static str = "/sys/fs/cgroup";
+    const DELEGATE_HINT: &'static str =
+        "start pilot in a cgroup of its own like `systemd-run --user --scope -p Delegate=yes pilot`";
+
+    fn create(limits: &Limits) -> Result<Self, String> {
+        if !Path::new(Self::ROOT).join("cgroup.controllers").exists() {
+            return Err("cgroups v2 are not available".to_string());
+        }
+
+        // create the new group below the one pilot runs in, that's the part we may delegate
+        let own_cgroup = read_to_string("/proc/self/cgroup")
+            .ok()
+            .and_then(|cgroups| {
+                cgroups
+                    .lines()
+                    .find_map(|line| line.strip_prefix("0::").map(|path| path.to_string()))
+            })
+            .ok_or("could not find the cgroup of pilot")?;
+
+        Self::create_in(
+            &Path::new(Self::ROOT).join(own_cgroup.trim_start_matches('/')),
+            limits,
+        )
+    }
+
+    /// Creates the cgroup in `parent`, the cgroup pilot runs in.
+    fn create_in(parent: &Path, limits: &Limits) -> Result<Self, String> {
+        let controllers: Vec<_> = [
+            ("cpu", limits.cpu.is_some()),
+            ("memory", limits.memory.is_some()),
+        ]
+        .into_iter()
+        .filter(|(_, limited)| *limited)
+        .map(|(controller, _)| controller)
+        .collect();
+
+        {
+            // parallel tasks would move pilot and enable the controllers at the same time
+            static SETUP: Mutex<()> = Mutex::new(());
+            let _setup = SETUP.lock().unwrap_or_else(PoisonError::into_inner);
+            Self::delegate(parent, &controllers)?;
+        }
+
+        static COUNTER: AtomicU32 = AtomicU32::new(0);
+        let path = parent.join(format!(
+            "pilot-{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::SeqCst)
+        ));
+
+        create_dir(&path).map_err(|err| format!("could not create a cgroup: {}", err))?;
+        let cgroup = Cgroup { path };
+
+        if let Some(cpu) = limits.cpu {
+            let period = 100_000;
+            let quota = (cpu * period as f64) as u64;
+            cgroup.write("cpu.max", &format!("{} {}", quota, period))?;
+        }
+
+        if let Some(memory) = limits.memory {
+            cgroup.write("memory.max", &memory.to_string())?;
+        }
+
+        Ok(cgroup)
+    }
+
+    /// Enables the controllers for the cgroups below `parent`, moving pilot into a leaf cgroup
+    /// of its own first.
+    fn delegate(parent: &Path, controllers: &[&str]) -> Result<(), String> {
+        let read = |file: &str| read_to_string(parent.join(file)).unwrap_or_default();
+        let enabled = read("cgroup.subtree_control");
+
+        let missing: Vec<_> = controllers
+            .iter()
+            .filter(|controller| {
+                !enabled
+                    .split_whitespace()
+                    .any(|enabled| enabled == **controller)
+            })
+            .collect();
+
+        if missing.is_empty() {
+            return Ok(());
+        }
+
+        let available = read("cgroup.controllers");
+
+        if let Some(controller) = missing.iter().find(|controller| {
+            !available
+                .split_whitespace()
+                .any(|available| available == ***controller)
+        }) {
+            return Err(format!(
+                "the {} controller is not delegated to the cgroup of pilot, {}",
+                controller,
+                Self::DELEGATE_HINT
+            ));
+        }
+
+        let pid = std::process::id().to_string();
+
+        if read("cgroup.procs")
+            .lines()
+            .any(|other| other.trim() != pid)
+        {
+            return Err(format!(
+                "the cgroup of pilot has other processes, {}",
+                Self::DELEGATE_HINT
+            ));
+        }
+
+        let leaf = parent.join(format!("pilot-{}", pid));
+
+        if !leaf.exists() {
+            create_dir(&leaf).map_err(|err| format!("could not create a cgroup: {}", err))?;
+        }
+
+        write(leaf.join("cgroup.procs"), &pid)
+            .map_err(|err| format!("could not move pilot into its own cgroup: {}", err))?;
+
+        let enable: Vec<_> = missing
+            .iter()
+            .map(|controller| format!("+{}", controller))
+            .collect();
+        write(parent.join("cgroup.subtree_control"), enable.join(" ")).map_err(|err| {
+            format!(
+                "could not enable the {} controllers: {}",
+                missing
+                    .iter()
+                    .map(|controller| **controller)
+                    .collect::<Vec<_>>()
+                    .join(" and "),
+                err
+            )
+        })
+    }
+
+    fn write(&self, file: &str, value: &str) -> Result<(), String> {
+        write(self.path.join(file), value)
+            .map_err(|err| format!("could not configure the cgroup: {}", err))
+    }
+}
+
+impl Drop for Cgroup {
+    fn drop(&mut self) {
+        // fails if a process escaped into the background, nothing we can do about it then
+        let _ = remove_dir(&self.path);
+    }
+}
+
+/// Applies the file and memory limits to the command through rlimits. The memory limit bounds
+/// the data segment and the private mappings, which is less exact than a cgroup but unlike the
+/// address space doesn't count the memory a runtime only reserves.
+#[cfg(unix)]
+pub fn set_rlimits(command: &mut Command, limits: &Limits) {
+    use std::{io, os::unix::process::CommandExt};
+
+    let mut set_rlimit = |resource, limit: u64| unsafe {
+        command.pre_exec(move || {
+            let rlimit = libc::rlimit {
+                rlim_cur: limit as libc::rlim_t,
+                rlim_max: limit as libc::rlim_t,
+            };
+
+            if libc::setrlimit(resource, &rlimit) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            Ok(())
+        });
+    };
+
+    if let Some(open_files) = limits.open_files {
+        set_rlimit(libc::RLIMIT_NOFILE, open_files);
+    }
+
+    if let Some(memory) = limits.memory {
+        set_rlimit(libc::RLIMIT_DATA, memory);
+    }
+}
+
+#[cfg(not(unix))]
+pub fn set_rlimits(_command: &mut Command, _limits: &Limits) {}
+
+/// Moves the command into a new cgroup with the cpu and memory limits, if there are any.
+/// The cgroup has to be kept until the process exited.
+#[cfg(unix)]
+pub fn join_cgroup(command: &mut Command, limits: &Limits) -> Result<Option<Cgroup>, String> {
+    use std::{ffi::CString, io, os::unix::process::CommandExt};
+
+    if limits.cpu.is_none() && limits.memory.is_none() {
+        return Ok(None);
+    }
+
+    let cgroup = Cgroup::create(limits)?;
+    let procs = CString::new(
+        cgroup
+            .path
+            .join("cgroup.procs")
+            .to_string_lossy()
+            .as_bytes(),
+    )
+    .map_err(|_| "invalid cgroup path".to_string())?;
+
+    unsafe {
+        command.pre_exec(move || {
+            // only async-signal-safe calls after the fork, writing 0 moves the calling process
+            let fd = libc::open(procs.as_ptr(), libc::O_WRONLY);
+
+            if fd < 0 || libc::write(fd, b"0".as_ptr() as *const libc::c_void, 1) != 1 {
+                return Err(io::Error::last_os_error());
+            }
+
+            libc::close(fd);
+            Ok(())
+        });
+    }
+
+    Ok(Some(cgroup))
+}
+
+#[cfg(not(unix))]
+pub fn join_cgroup(_command: &mut Command, limits: &Limits) -> Result<Option<Cgroup>, String> {
+    if limits.cpu.is_none() && limits.memory.is_none() && limits.open_files.is_none() {
+        return Ok(None);
+    }
+
+    Err("resource limits are only supported on Unix".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{create_dir_all, remove_dir_all};
+
+    use super::*;
+
+    /// A directory standing in for the cgroup of pilot, with the files of the cgroup filesystem.
+    fn own_cgroup(name: &str, controllers: &str, procs: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("pilot-cgroup-{}-{}", name, std::process::id()));
+        let _ = remove_dir_all(&dir);
+        create_dir_all(&dir).unwrap();
+        write(dir.join("cgroup.controllers"), controllers).unwrap();
+        write(dir.join("cgroup.subtree_control"), "").unwrap();
+        write(dir.join("cgroup.procs"), procs).unwrap();
+        dir
+    }
+
+    #[test]
+    fn cgroup_enables_controllers_below_pilot() {
+        let pid = std::process::id().to_string();
+        let dir = own_cgroup("delegated", "cpu memory pids", &format!("{}\n", pid));
+        let limits = Limits {
+            memory: Some(1 << 20),
+            cpu: Some(0.5),
+            open_files: None,
+        };
+
+        let cgroup = Cgroup::create_in(&dir, &limits).unwrap();
+
+        // pilot left the cgroup, so it can hand the controllers down
+        let leaf = dir.join(format!("pilot-{}", pid));
+        assert_eq!(read_to_string(leaf.join("cgroup.procs")).unwrap(), pid);
+        assert_eq!(
+            read_to_string(dir.join("cgroup.subtree_control")).unwrap(),
+            "+cpu +memory"
+        );
+        assert_eq!(
+            read_to_string(cgroup.path.join("cpu.max")).unwrap(),
+            "50000 100000"
+        );
+        assert_eq!(
+            read_to_string(cgroup.path.join("memory.max")).unwrap(),
+            "1048576"
+        );
+
+        remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn cgroup_needs_delegated_controllers() {
+        let pid = std::process::id().to_string();
+        let dir = own_cgroup("undelegated", "memory pids", &pid);
+        let limits = Limits {
+            cpu: Some(1.0),
+            ..Limits::default()
+        };
+
+        assert_eq!(
+            Cgroup::create_in(&dir, &limits).err(),
+            Some(format!(
+                "the cpu controller is not delegated to the cgroup of pilot, {}",
+                Cgroup::DELEGATE_HINT
+            ))
+        );
+        remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn cgroup_needs_pilot_alone() {
+        let dir = own_cgroup("shared", "cpu memory", "1\n2\n");
+        let limits = Limits {
+            memory: Some(1 << 20),
+            ..Limits::default()
+        };
+
+        assert_eq!(
+            Cgroup::create_in(&dir, &limits).err(),
+            Some(format!(
+                "the cgroup of pilot has other processes, {}",
+                Cgroup::DELEGATE_HINT
+            ))
+        );
+        // nothing was moved or enabled
+        assert_eq!(
+            read_to_string(dir.join("cgroup.subtree_control")).unwrap(),
+            ""
+        );
+        remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,101 @@
+use std::{
+    fs::{create_dir_all, read_to_string, rename, write},
+    path::{Path, PathBuf},
+};
+
+use yaml_rust::YamlLoader;
+
+use crate::{Error, OrError};
+
+const STATE_DIR: &str = ".pilot";
+const STATE_FILE: &str = "state.json";
+
+pub(crate) fn state_dir(pilotfile_dir: &Path) -> PathBuf {
+    pilotfile_dir.join(STATE_DIR)
+}
+
+/// Task names can contain characters that aren't allowed in file names, names like `a:b` and
+/// `a_b` share a file.
+pub(crate) fn file_name(task: &str) -> String {
+    task.chars()
+        .map(|char| {
+            if char.is_ascii_alphanumeric() || char == '-' || char == '_' {
+                char
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Quotes and escapes a string so it can be embedded in a JSON document.
+pub(crate) fn json_string(string: &str) -> String {
+    let mut escaped = String::with_capacity(string.len() + 2);
+    escaped.push('"');
+
+    for char in string.chars() {
+        match char {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            char if (char as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", char as u32)),
+            char => escaped.push(char),
+        }
+    }
+
+    escaped.push('"');
+    escaped
+}
+
+/// The outcome of the top-level tasks as the `tasks` list of a JSON document.
+pub(crate) fn tasks_json(results: &[(String, bool)]) -> String {
+    let tasks: Vec<_> = results
+        .iter()
+        .map(|(task, success)| {
+            format!(
+                "    {{\"name\": {}, \"status\": \"{}\"}}",
+                json_string(task),
+                if *success { "success" } else { "failed" }
+            )
+        })
+        .collect();
+
+    format!("\"tasks\": [\n{}\n  ]", tasks.join(",\n"))
+}
+
+/// Persists the outcome of the top-level tasks of this run to `.pilot/state.json`.
+pub fn save(pilotfile_dir: &Path, results: &[(String, bool)]) -> Result<(), Error> {
+    let dir = state_dir(pilotfile_dir);
+    create_dir_all(&dir).or_error("Could not create the .pilot directory")?;
+
+    let json = format!("{{\n  {}\n}}\n", tasks_json(results));
+
+    // write to a temporary file first, so a concurrent reader never sees half a state file
+    let tmp_path = dir.join(format!("{}.{}.tmp", STATE_FILE, std::process::id()));
+    write(&tmp_path, json).or_error("Could not write the state file")?;
+    rename(&tmp_path, dir.join(STATE_FILE)).or_error("Could not write the state file")
+}
+
+/// Returns the top-level tasks that failed in the last run.
+pub fn failed_tasks(pilotfile_dir: &Path) -> Result<Vec<String>, Error> {
+    let path = state_dir(pilotfile_dir).join(STATE_FILE);
+
+    let file = match read_to_string(path) {
+        Ok(file) => file,
+        // no state means nothing failed yet
+        Err(_) => return Ok(vec![]),
+    };
+
+    let docs = YamlLoader::load_from_str(&file).or_error("The state file is corrupted")?;
+
+    Ok(docs
+        .first()
+        .and_then(|doc| doc["tasks"].as_vec())
+        .or_error("The state file is corrupted")?
+        .iter()
+        .filter(|task| task["status"].as_str() == Some("failed"))
+        .filter_map(|task| task["name"].as_str().map(|name| name.to_string()))
+        .collect())
+}
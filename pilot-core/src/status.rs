@@ -0,0 +1,269 @@
+//! The status line at the bottom of a terminal, showing the running tasks while their output
+//! scrolls above it.
+
+use std::{
+    future::Future,
+    io::{stdout, Write},
+    sync::{Mutex, MutexGuard},
+    time::Duration,
+};
+
+use crate::{
+    clock::{self, Stopwatch},
+    runtime, shutdown, supervise,
+};
+
+const FRAMES: &[char] = &['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+const INTERVAL: Duration = Duration::from_millis(100);
+/// moves to the start of the line and clears it
+const CLEAR_LINE: &str = "\r\x1b[2K";
+
+static STATUS: Mutex<Status> = Mutex::new(Status {
+    enabled: false,
+    paused: 0,
+    handed_over: 0,
+    held: vec![],
+    frame: 0,
+    running: vec![],
+});
+
+struct Status {
+    enabled: bool,
+    /// prompts waiting for an answer and raw processes, which use the last line themselves
+    paused: usize,
+    /// raw processes that have the whole terminal, the output of the others waits in `held`
+    handed_over: usize,
+    held: Vec<String>,
+    frame: usize,
+    /// the task prefixes like `run > build`, with the time they started, how long they took in
+    /// the last runs and whether they are services
+    running: Vec<(String, Stopwatch, Option<Duration>, bool)>,
+}
+
+/// The status, also after a panic while printing, like to a closed stdout, which supervised
+/// tasks recover from.
+fn lock() -> MutexGuard<'static, Status> {
+    supervise::lock(&STATUS)
+}
+
+impl Status {
+    fn visible(&self) -> bool {
+        // after a hangup, the output goes to a log file instead of the terminal
+        self.enabled && self.paused == 0 && !self.running.is_empty() && !shutdown::is_hung_up()
+    }
+
+    /// The line with a spinner for each running task, without the tasks that only wait for
+    /// their subtasks.
+    fn line(&self) -> String {
+        let spinner = FRAMES[self.frame % FRAMES.len()];
+        let tasks: Vec<_> = self
+            .running
+            .iter()
+            .filter(|(prefix, ..)| {
+                let parent = prefix.to_string() + " > ";
+                !self
+                    .running
+                    .iter()
+                    .any(|(other, ..)| other.starts_with(&parent))
+            })
+            .map(|(prefix, started, estimate, service)| {
+                let task = prefix.rsplit(" > ").next().unwrap_or(prefix);
+                let elapsed = clock::whole(started.elapsed());
+
+                // services run until they are stopped, they show how long they are up
+                match estimate {
+                    _ if *service => format!("{} {} up {}", spinner, task, elapsed),
+                    Some(estimate) => format!(
+                        "{} {} {}/~{}",
+                        spinner,
+                        task,
+                        elapsed,
+                        clock::whole(*estimate)
+                    ),
+                    None => format!("{} {} {}", spinner, task, elapsed),
+                }
+            })
+            .collect();
+
+        // a wrapped line couldn't be cleared anymore
+        tasks
+            .join("  ")
+            .chars()
+            .take(terminal_width().saturating_sub(1))
+            .collect()
+    }
+}
+
+/// The columns of the terminal, 80 if pilot doesn't print to one.
+#[cfg(unix)]
+pub(crate) fn terminal_width() -> usize {
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+
+    match unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } {
+        0 if size.ws_col > 0 => size.ws_col as usize,
+        _ => 80,
+    }
+}
+
+#[cfg(not(unix))]
+pub(crate) fn terminal_width() -> usize {
+    80
+}
+
+/// Shows the status line from now on, pilot has to print to a terminal.
+pub fn enable() {
+    let mut status = lock();
+
+    if status.enabled {
+        return;
+    }
+
+    status.enabled = true;
+
+    runtime::spawn(async {
+        loop {
+            tokio::time::sleep(INTERVAL).await;
+
+            let mut status = lock();
+
+            if !status.enabled {
+                break;
+            }
+
+            status.frame += 1;
+
+            if status.visible() {
+                print!("{}{}", CLEAR_LINE, status.line());
+                let _ = stdout().flush();
+            }
+        }
+    });
+}
+
+/// Removes the status line, the output continues below the last line.
+pub fn disable() {
+    let mut status = lock();
+
+    if status.visible() {
+        print!("{}", CLEAR_LINE);
+        let _ = stdout().flush();
+    }
+
+    status.enabled = false;
+}
+
+pub fn start(task_prefix: &str, estimate: Option<Duration>, service: bool) {
+    let mut status = lock();
+    status.running.push((
+        task_prefix.to_string(),
+        Stopwatch::start(),
+        estimate,
+        service,
+    ));
+}
+
+pub fn finish(task_prefix: &str) {
+    let mut status = lock();
+    let visible = status.visible();
+
+    if let Some(index) = status
+        .running
+        .iter()
+        .position(|(prefix, ..)| prefix == task_prefix)
+    {
+        status.running.remove(index);
+    }
+
+    // the following output is printed without a status line below it
+    if visible && !status.visible() {
+        print!("{}", CLEAR_LINE);
+        let _ = stdout().flush();
+    }
+}
+
+/// Prints a line of output above the status line.
+pub fn println(line: &str) {
+    let mut status = lock();
+
+    if status.handed_over > 0 {
+        status.held.push(line.to_string());
+        return;
+    }
+
+    if !status.visible() {
+        println!("{}", line);
+        return;
+    }
+
+    let mut stdout = stdout().lock();
+    let _ = write!(stdout, "{}{}\n{}", CLEAR_LINE, line, status.line());
+    let _ = stdout.flush();
+}
+
+/// Prints lines of output above the status line, without the lines of other tasks between them.
+pub fn println_all(lines: &[String]) {
+    let mut status = lock();
+
+    if status.handed_over > 0 {
+        status.held.extend(lines.iter().cloned());
+        return;
+    }
+    let mut stdout = stdout().lock();
+
+    if status.visible() {
+        let _ = write!(stdout, "{}", CLEAR_LINE);
+    }
+
+    for line in lines {
+        let _ = writeln!(stdout, "{}", line);
+    }
+
+    if status.visible() {
+        let _ = write!(stdout, "{}", status.line());
+    }
+
+    let _ = stdout.flush();
+}
+
+/// Hides the status line while something else uses the terminal, like a prompt.
+pub async fn paused<T>(use_terminal: impl Future<Output = T>) -> T {
+    {
+        let mut status = lock();
+
+        if status.visible() {
+            print!("{}", CLEAR_LINE);
+            let _ = stdout().flush();
+        }
+
+        status.paused += 1;
+    }
+
+    let result = use_terminal.await;
+    lock().paused -= 1;
+    result
+}
+
+/// Hands the terminal to a process, like a raw one reading the keys. The output of the other
+/// tasks is held back until it's done, so it doesn't end up in the middle of the process.
+pub async fn handed_over<T>(use_terminal: impl Future<Output = T>) -> T {
+    paused(async {
+        lock().handed_over += 1;
+        let result = use_terminal.await;
+
+        let mut status = lock();
+        status.handed_over -= 1;
+
+        if status.handed_over == 0 {
+            let mut stdout = stdout().lock();
+
+            for line in status.held.drain(..) {
+                let _ = writeln!(stdout, "{}", line);
+            }
+
+            let _ = stdout.flush();
+        }
+
+        result
+    })
+    .await
+}
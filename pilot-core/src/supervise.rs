@@ -0,0 +1,119 @@
+//! Keeps a bug in handling one task from taking the whole run down: the tasks are supervised on
+//! the [`runtime`](crate::runtime), a panic is reported with the task and only fails that task.
+
+use std::{
+    any::Any,
+    future::{poll_fn, Future},
+    io::{self, Write},
+    panic::{catch_unwind, AssertUnwindSafe},
+    pin::pin,
+    sync::{Mutex, MutexGuard, PoisonError},
+    task::Poll,
+};
+
+use tokio::task::JoinHandle;
+
+use crate::{runtime, FAILURE_CODE};
+
+/// how often printing the output of a process is started again after a panic, before the rest
+/// of it is only read
+const MAX_OUTPUT_RESTARTS: usize = 3;
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown error")
+}
+
+/// Runs `run` to the end, a panic is reported as a crash of the task and gives `None`.
+pub(crate) async fn catch<T>(task_prefix: &str, run: impl Future<Output = T>) -> Option<T> {
+    let mut run = pin!(run);
+
+    poll_fn(
+        |context| match catch_unwind(AssertUnwindSafe(|| run.as_mut().poll(context))) {
+            Ok(Poll::Pending) => Poll::Pending,
+            Ok(Poll::Ready(result)) => Poll::Ready(Some(result)),
+            Err(payload) => {
+                eprintln!(
+                    "Task {} crashed: {}",
+                    task_prefix,
+                    panic_message(payload.as_ref())
+                );
+                Poll::Ready(None)
+            }
+        },
+    )
+    .await
+}
+
+/// Runs the branch of a task on the runtime, a panic fails the branch.
+pub(crate) fn spawn(
+    task_prefix: String,
+    run: impl Future<Output = i32> + Send + 'static,
+) -> JoinHandle<i32> {
+    spawn_or(task_prefix, run, FAILURE_CODE)
+}
+
+/// Like [`spawn`], a panic gives the outcome `crashed`.
+pub(crate) fn spawn_or<T: Send + 'static>(
+    task_prefix: String,
+    run: impl Future<Output = T> + Send + 'static,
+    crashed: T,
+) -> JoinHandle<T> {
+    runtime::spawn(async move { catch(&task_prefix, run).await.unwrap_or(crashed) })
+}
+
+/// Locks a mutex that the output of the tasks goes through, also after a panic while holding it,
+/// which only failed printing that line.
+pub(crate) fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Prints the output of a process line by line. If printing a line panics, the error is reported
+/// with the task and printing goes on with the next line. After too many panics the rest of the
+/// output is only read, so the process doesn't block on a full pipe and its exit is still waited
+/// for. The errors are written without `eprintln!`, stderr may be what failed.
+pub(crate) struct Printer<'a> {
+    task_name: &'a str,
+    restarts: usize,
+}
+
+impl<'a> Printer<'a> {
+    pub(crate) fn new(task_name: &'a str) -> Self {
+        Printer {
+            task_name,
+            restarts: 0,
+        }
+    }
+
+    pub(crate) fn print(&mut self, print: impl FnOnce()) {
+        if self.restarts > MAX_OUTPUT_RESTARTS {
+            return;
+        }
+
+        let Err(payload) = catch_unwind(AssertUnwindSafe(print)) else {
+            return;
+        };
+
+        let message = panic_message(payload.as_ref());
+
+        let _ = match self.restarts == MAX_OUTPUT_RESTARTS {
+            true => writeln!(
+                io::stderr(),
+                "Could not print the output of task {}: {}, it keeps running without it",
+                self.task_name,
+                message
+            ),
+            false => writeln!(
+                io::stderr(),
+                "Could not print the output of task {}: {}, printing it again from the next line",
+                self.task_name,
+                message
+            ),
+        };
+
+        self.restarts += 1;
+    }
+}
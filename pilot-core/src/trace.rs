@@ -0,0 +1,102 @@
+use std::{
+    fs::write,
+    io,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, OnceLock,
+    },
+    time::Instant,
+};
+
+use tokio::task;
+
+use crate::state::json_string;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static START: OnceLock<Instant> = OnceLock::new();
+static EVENTS: Mutex<Vec<Event>> = Mutex::new(vec![]);
+/// parallel steps run in tasks of their own, numbered in the order of their first span, which
+/// shows their overlap in the timeline
+static TASKS: Mutex<Vec<Option<task::Id>>> = Mutex::new(vec![]);
+
+/// The thread of the timeline for the task of the runtime the span is in, the run itself is
+/// outside of one.
+fn thread_id() -> u64 {
+    let mut tasks = TASKS.lock().unwrap();
+    let task = task::try_id();
+
+    let index = tasks.iter().position(|id| *id == task).unwrap_or_else(|| {
+        tasks.push(task);
+        tasks.len() - 1
+    });
+
+    index as u64 + 1
+}
+
+struct Event {
+    name: String,
+    category: &'static str,
+    /// microseconds since the start of the run
+    start: u128,
+    duration: u128,
+    thread: u64,
+}
+
+/// A task or step in the timeline, it ends when dropped.
+pub struct Span {
+    name: String,
+    category: &'static str,
+    start: Instant,
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        let start = START.get_or_init(Instant::now);
+        let event = Event {
+            name: std::mem::take(&mut self.name),
+            category: self.category,
+            start: self.start.saturating_duration_since(*start).as_micros(),
+            duration: self.start.elapsed().as_micros(),
+            thread: thread_id(),
+        };
+
+        EVENTS.lock().unwrap().push(event);
+    }
+}
+
+/// Starts recording the timeline of the run.
+pub fn enable() {
+    START.get_or_init(Instant::now);
+    ENABLED.store(true, Ordering::SeqCst);
+}
+
+/// Starts a span, if the timeline is recorded.
+pub fn span(category: &'static str, name: impl FnOnce() -> String) -> Option<Span> {
+    ENABLED.load(Ordering::SeqCst).then(|| Span {
+        name: name(),
+        category,
+        start: Instant::now(),
+    })
+}
+
+/// Writes the timeline in the Chrome trace event format, for chrome://tracing or Perfetto.
+pub fn save(path: &Path) -> io::Result<()> {
+    let events = EVENTS.lock().unwrap();
+
+    let events: Vec<_> = events
+        .iter()
+        .map(|event| {
+            format!(
+                "{{\"name\":{},\"cat\":\"{}\",\"ph\":\"X\",\"ts\":{},\"dur\":{},\"pid\":1,\"tid\":{}}}",
+                json_string(&event.name),
+                event.category,
+                event.start,
+                event.duration,
+                event.thread
+            )
+        })
+        .collect();
+
+    write(path, format!("[\n{}\n]\n", events.join(",\n")))
+}
@@ -0,0 +1,260 @@
+//! `pilot upgrade`, replaces the running pilot with the binary of the latest GitHub release for
+//! this platform, after checking it against the checksum published with it.
+
+use std::{
+    env,
+    fs::{self, remove_file, rename},
+    io::Write,
+    path::Path,
+    process::{self, Command, Stdio},
+};
+
+use sha2::{Digest, Sha256};
+use yaml_rust::YamlLoader;
+
+use crate::{Error, OrError};
+
+const USAGE: &str = "Usage: pilot upgrade [--check]";
+/// the latest release as the GitHub API describes it, `$PILOT_RELEASES_URL` replaces it for
+/// mirrors
+const RELEASES_URL: &str = "https://api.github.com/repos/physics3D/pilot/releases/latest";
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// A release with the download URLs of its files.
+struct Release {
+    tag: String,
+    assets: Vec<(String, String)>,
+}
+
+impl Release {
+    fn asset(&self, name: &str) -> Option<&str> {
+        self.assets
+            .iter()
+            .find(|(asset, _)| asset == name)
+            .map(|(_, url)| url.as_str())
+    }
+}
+
+/// Runs curl with `args` and gives its output. A `$GITHUB_TOKEN` goes through stdin, so other
+/// users don't see it in the process list.
+fn curl(args: &[&str]) -> Result<String, Error> {
+    let mut curl = Command::new("curl")
+        .args(["--silent", "--show-error", "--fail", "--location"])
+        .args(["--max-time", "300", "--config", "-"])
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .or_error("Could not run curl to download the release")?;
+
+    let config = match env::var("GITHUB_TOKEN") {
+        Ok(token) if !token.is_empty() => format!("header = \"Authorization: Bearer {}\"\n", token),
+        _ => String::new(),
+    };
+
+    if let Some(mut stdin) = curl.stdin.take() {
+        stdin
+            .write_all(config.as_bytes())
+            .or_error("Could not run curl to download the release")?;
+    }
+
+    let output = curl
+        .wait_with_output()
+        .or_error("Could not run curl to download the release")?;
+
+    match output.status.success() {
+        true => Ok(String::from_utf8_lossy(&output.stdout).into_owned()),
+        false => Err(Error(format!(
+            "Could not download {}: {}",
+            args.last().unwrap_or(&""),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))),
+    }
+}
+
+fn latest_release() -> Result<Release, Error> {
+    let url = env::var("PILOT_RELEASES_URL").unwrap_or_else(|_| RELEASES_URL.to_string());
+    let json = curl(&[&url])?;
+
+    // JSON is YAML as well
+    let docs = YamlLoader::load_from_str(&json).ok();
+    let release = docs
+        .as_ref()
+        .and_then(|docs| docs.first())
+        .or_error("The latest release is not valid JSON")?;
+
+    Ok(Release {
+        tag: release["tag_name"]
+            .as_str()
+            .or_error("The latest release has no tag_name")?
+            .to_string(),
+        assets: release["assets"]
+            .as_vec()
+            .into_iter()
+            .flatten()
+            .filter_map(|asset| {
+                Some((
+                    asset["name"].as_str()?.to_string(),
+                    asset["browser_download_url"].as_str()?.to_string(),
+                ))
+            })
+            .collect(),
+    })
+}
+
+/// The numbers of a version like `v1.2.3`, without a suffix like `-rc1`.
+fn version_numbers(version: &str) -> Vec<u64> {
+    version
+        .trim_start_matches('v')
+        .split(['-', '+'])
+        .next()
+        .unwrap_or_default()
+        .split('.')
+        .map(|number| number.parse().unwrap_or_default())
+        .collect()
+}
+
+fn is_newer(tag: &str, current: &str) -> bool {
+    version_numbers(tag) > version_numbers(current)
+}
+
+/// The name of the binary of a release for this platform, like `pilot-x86_64-linux`.
+fn asset_name() -> String {
+    format!(
+        "pilot-{}-{}{}",
+        env::consts::ARCH,
+        env::consts::OS,
+        env::consts::EXE_SUFFIX
+    )
+}
+
+fn sha256(content: &[u8]) -> String {
+    Sha256::digest(content)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Moves the new binary to where pilot is, Windows can only rename a running binary out of the
+/// way instead of replacing it.
+fn replace(new: &Path, exe: &Path) -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(new, fs::Permissions::from_mode(0o755))?;
+    }
+
+    if cfg!(windows) {
+        let old = exe.with_extension("old.exe");
+        let _ = remove_file(&old);
+        rename(exe, &old)?;
+    }
+
+    rename(new, exe)
+}
+
+/// `pilot upgrade [--check]`, installs the latest release if it is newer, `--check` only tells
+/// whether there is one.
+pub fn cli(args: &[String]) -> Result<(), Error> {
+    let check = match args {
+        [] => false,
+        [check] if check == "--check" => true,
+        _ => return Err(Error(USAGE.to_string())),
+    };
+
+    let release = latest_release()?;
+
+    if !is_newer(&release.tag, VERSION) {
+        println!("pilot {} is the latest version", VERSION);
+        return Ok(());
+    }
+
+    let version = release.tag.trim_start_matches('v');
+    let asset = asset_name();
+    let binary_url = release.asset(&asset).or_error(&format!(
+        "Release {} has no binary for this platform, {}",
+        release.tag, asset
+    ))?;
+    let checksum_url = release
+        .asset(&format!("{}.sha256", asset))
+        .or_error(&format!(
+            "Release {} has no checksum for {}, pilot doesn't install a binary it can't verify",
+            release.tag, asset
+        ))?;
+
+    if check {
+        println!(
+            "pilot {} is available, this is {}, pilot upgrade installs {}",
+            version, VERSION, asset
+        );
+        return Ok(());
+    }
+
+    let exe = env::current_exe()
+        .and_then(fs::canonicalize)
+        .or_error("Could not find the pilot binary to replace")?;
+    let dir = exe
+        .parent()
+        .or_error("Could not find the pilot binary to replace")?;
+
+    // next to the binary, so it can be renamed over it
+    let download = dir.join(format!(".{}.{}", asset, process::id()));
+    let downloaded = curl(&["--output", &download.to_string_lossy(), binary_url])
+        .and_then(|_| fs::read(&download).or_error("Could not read the downloaded binary"));
+    let content = match downloaded {
+        Ok(content) => content,
+        Err(err) => {
+            let _ = remove_file(&download);
+            return Err(err);
+        }
+    };
+
+    // `sha256sum` prints the name of the file after the checksum
+    let checksum = curl(&[checksum_url]);
+    let expected = checksum
+        .as_deref()
+        .unwrap_or_default()
+        .split_whitespace()
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    let actual = sha256(&content);
+
+    if expected != actual {
+        let _ = remove_file(&download);
+        checksum?;
+
+        return Err(Error(format!(
+            "The checksum of {} is {}, but the release says {}, pilot was not upgraded",
+            asset, actual, expected
+        )));
+    }
+
+    if let Err(err) = replace(&download, &exe) {
+        let _ = remove_file(&download);
+        return Err(Error(format!(
+            "Could not replace {}: {}",
+            exe.display(),
+            err
+        )));
+    }
+
+    println!("Upgraded pilot from {} to {}", VERSION, version);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compare_versions() {
+        assert!(is_newer("v0.2.0", "0.1.0"));
+        assert!(is_newer("1.0.0", "0.10.3"));
+        assert!(is_newer("v0.10.0", "0.9.9"));
+        assert!(!is_newer("v0.1.0", "0.1.0"));
+        assert!(!is_newer("v0.1.0-rc1", "0.1.0"));
+        assert!(!is_newer("v0.0.9", "0.1.0"));
+    }
+}
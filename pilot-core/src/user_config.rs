@@ -0,0 +1,218 @@
+//! The preferences of the user in `~/.config/pilot/config.yaml`, the Pilotfile and the command
+//! line win over them.
+
+use std::{
+    env,
+    fs::read_to_string,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use chrono::format::{Item, StrftimeItems};
+use yaml_rust::{Yaml, YamlLoader};
+
+use crate::{Error, PrefixStyle};
+
+const KEYS: &[&str] = &[
+    "color",
+    "colors",
+    "prefix_style",
+    "timestamp_format",
+    "shell",
+    "log_dir",
+    "editor",
+];
+
+/// The colors of the task names, the SGR parameters like `0;31` are what `$PILOT_COLOR` gets.
+const COLORS: &[(&str, &str)] = &[
+    ("red", "0;31"),
+    ("green", "0;32"),
+    ("yellow", "0;33"),
+    ("blue", "0;34"),
+    ("magenta", "0;35"),
+    ("cyan", "0;36"),
+    ("white", "0;37"),
+    ("bright_red", "0;91"),
+    ("bright_green", "0;92"),
+    ("bright_yellow", "0;93"),
+    ("bright_blue", "0;94"),
+    ("bright_magenta", "0;95"),
+    ("bright_cyan", "0;96"),
+    ("bright_white", "0;97"),
+];
+
+pub const DEFAULT_TIMESTAMP_FORMAT: &str = "%H:%M:%S";
+
+static USER_CONFIG: OnceLock<UserConfig> = OnceLock::new();
+
+#[derive(Default)]
+pub struct UserConfig {
+    /// `None` colors the output when printing to a terminal
+    pub color: Option<bool>,
+    /// the SGR parameters the task names cycle through
+    pub colors: Vec<String>,
+    pub prefix_style: Option<PrefixStyle>,
+    pub timestamp_format: Option<String>,
+    pub shell: Option<String>,
+    /// where the run history goes instead of `.pilot/runs`, with a directory for each project
+    pub log_dir: Option<PathBuf>,
+    /// the editor of `pilot edit`, instead of `$VISUAL` and `$EDITOR`
+    pub editor: Option<String>,
+}
+
+/// `$XDG_CONFIG_HOME/pilot/config.yaml`, `~/.config/pilot/config.yaml` by default.
+pub fn path() -> Option<PathBuf> {
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from);
+
+    #[cfg(target_family = "windows")]
+    let config_home = config_home.or_else(|| env::var_os("APPDATA").map(PathBuf::from));
+
+    #[cfg(not(target_family = "windows"))]
+    let config_home =
+        config_home.or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")));
+
+    Some(config_home?.join("pilot").join("config.yaml"))
+}
+
+fn load_file() -> Result<UserConfig, Error> {
+    let Some(path) = path() else {
+        return Ok(UserConfig::default());
+    };
+
+    UserConfig::load(&path).map_err(|err| Error(format!("{}: {}", path.display(), err)))
+}
+
+/// Loads the preferences of the user, before anything uses them. A broken config file is an
+/// error, without preferences pilot goes on after it.
+pub fn init() -> Result<(), Error> {
+    let mut error = None;
+
+    USER_CONFIG.get_or_init(|| {
+        load_file().unwrap_or_else(|err| {
+            error = Some(err);
+            UserConfig::default()
+        })
+    });
+
+    error.map_or(Ok(()), Err)
+}
+
+/// The preferences of the user, loaded once. Without [`init`], a broken config file has none.
+pub fn get() -> &'static UserConfig {
+    USER_CONFIG.get_or_init(|| load_file().unwrap_or_default())
+}
+
+impl UserConfig {
+    /// Loads a config file, a missing one has no preferences.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let Ok(file) = read_to_string(path) else {
+            return Ok(UserConfig::default());
+        };
+
+        let yaml = YamlLoader::load_from_str(&file)
+            .map_err(|err| err.to_string())?
+            .into_iter()
+            .next();
+
+        match yaml {
+            Some(yaml) => UserConfig::from_yaml(&yaml, path.parent().unwrap_or(Path::new("."))),
+            None => Ok(UserConfig::default()),
+        }
+    }
+
+    /// `dir` resolves a relative `log_dir`.
+    fn from_yaml(yaml: &Yaml, dir: &Path) -> Result<Self, String> {
+        let hash = yaml
+            .as_hash()
+            .ok_or("the config has to be a map of settings")?;
+        let mut config = UserConfig::default();
+
+        for (key, value) in hash {
+            let string = |message: &str| {
+                value
+                    .as_str()
+                    .map(str::to_string)
+                    .ok_or_else(|| message.to_string())
+            };
+
+            match key.as_str().unwrap_or_default() {
+                "color" => {
+                    config.color = match value.as_str() {
+                        Some("auto") => None,
+                        Some("always") => Some(true),
+                        Some("never") => Some(false),
+                        _ => return Err("color needs auto, always or never".to_string()),
+                    }
+                }
+                "colors" => config.colors = parse_colors(value)?,
+                "prefix_style" => {
+                    config.prefix_style = Some(
+                        value
+                            .as_str()
+                            .and_then(PrefixStyle::parse)
+                            .ok_or("prefix_style needs color, plain or brackets")?,
+                    )
+                }
+                "timestamp_format" => {
+                    let format = string("timestamp_format needs a format like %H:%M:%S")?;
+
+                    if StrftimeItems::new(&format).any(|item| item == Item::Error) {
+                        return Err(format!("{} is no valid timestamp_format", format));
+                    }
+
+                    config.timestamp_format = Some(format);
+                }
+                "shell" => config.shell = Some(string("shell needs a string")?),
+                "log_dir" => {
+                    config.log_dir = Some(expand_home(&string("log_dir needs a path")?, dir))
+                }
+                "editor" => config.editor = Some(string("editor needs a command")?),
+                key => {
+                    return Err(format!(
+                        "{:?} is no setting, use one of {}",
+                        key,
+                        KEYS.join(", ")
+                    ))
+                }
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+fn parse_colors(yaml: &Yaml) -> Result<Vec<String>, String> {
+    let names: Vec<_> = COLORS.iter().map(|(name, _)| *name).collect();
+    let invalid = || format!("colors needs a list of colors out of {}", names.join(", "));
+
+    let colors = yaml
+        .as_vec()
+        .ok_or_else(invalid)?
+        .iter()
+        .map(|color| {
+            let name = color.as_str().ok_or_else(invalid)?;
+
+            COLORS
+                .iter()
+                .find(|(color, _)| *color == name)
+                .map(|(_, sgr)| sgr.to_string())
+                .ok_or_else(invalid)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if colors.is_empty() {
+        return Err(invalid());
+    }
+
+    Ok(colors)
+}
+
+/// Resolves `~/` against the home directory and relative paths against `dir`.
+fn expand_home(path: &str, dir: &Path) -> PathBuf {
+    match path.strip_prefix("~/").zip(env::var_os("HOME")) {
+        Some((path, home)) => PathBuf::from(home).join(path),
+        None => dir.join(path),
+    }
+}
@@ -0,0 +1,167 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{lookup_host, TcpStream},
+    time::timeout,
+};
+use yaml_rust::Yaml;
+
+use crate::{clock, duration, shutdown};
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+const INTERVAL: Duration = Duration::from_millis(250);
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A duration like `30s` or a number of seconds, `default` if it isn't set.
+fn duration_from_yaml(yaml: &Yaml, default: Duration) -> Option<Duration> {
+    match yaml {
+        Yaml::BadValue => Some(default),
+        Yaml::Integer(seconds) => Some(Duration::from_secs(u64::try_from(*seconds).ok()?)),
+        duration => duration::parse(duration.as_str()?),
+    }
+}
+
+#[derive(Clone)]
+enum Condition {
+    /// something accepts connections on `host:port`
+    Tcp(String),
+    /// a GET request gets a 2xx response
+    Http {
+        address: String,
+        path: String,
+    },
+    File(PathBuf),
+}
+
+/// A `wait_for` step, blocks the task until a service is ready.
+#[derive(Clone)]
+pub struct WaitFor {
+    condition: Condition,
+    description: String,
+    timeout: Duration,
+}
+
+impl WaitFor {
+    /// Relative file paths are resolved against the Pilotfile directory.
+    pub fn from_yaml(yaml: &Yaml, dir: &Path) -> Option<Self> {
+        let (condition, description) = if let Some(address) = yaml["tcp"].as_str() {
+            (
+                Condition::Tcp(address.to_string()),
+                format!("tcp {}", address),
+            )
+        } else if let Some(url) = yaml["http"].as_str() {
+            // no tls, this is for services running locally
+            let rest = url.strip_prefix("http://")?;
+            let (host, path) = match rest.find('/') {
+                Some(index) => rest.split_at(index),
+                None => (rest, "/"),
+            };
+            let address = if host.contains(':') {
+                host.to_string()
+            } else {
+                format!("{}:80", host)
+            };
+
+            (
+                Condition::Http {
+                    address,
+                    path: path.to_string(),
+                },
+                format!("http {}", url),
+            )
+        } else if let Some(file) = yaml["file"].as_str() {
+            (Condition::File(dir.join(file)), format!("file {}", file))
+        } else {
+            return None;
+        };
+
+        Some(WaitFor {
+            condition,
+            description,
+            timeout: duration_from_yaml(&yaml["timeout"], DEFAULT_TIMEOUT)?,
+        })
+    }
+
+    async fn is_ready(&self) -> bool {
+        async fn connect(address: &str) -> Option<TcpStream> {
+            for address in lookup_host(address).await.ok()? {
+                if let Ok(Ok(stream)) = timeout(INTERVAL, TcpStream::connect(address)).await {
+                    return Some(stream);
+                }
+            }
+
+            None
+        }
+
+        match &self.condition {
+            Condition::Tcp(address) => connect(address).await.is_some(),
+            Condition::Http { address, path } => {
+                let request = async {
+                    let mut stream = connect(address).await?;
+                    let request = format!(
+                        "GET {} HTTP/1.0\r\nHost: {}\r\nConnection: close\r\n\r\n",
+                        path, address
+                    );
+                    stream.write_all(request.as_bytes()).await.ok()?;
+
+                    // the status line is all we need, like `HTTP/1.1 200 OK`
+                    let mut response = [0; 12];
+                    timeout(INTERVAL, stream.read_exact(&mut response))
+                        .await
+                        .ok()?
+                        .ok()?;
+                    Some(response[9] == b'2')
+                };
+
+                request.await.unwrap_or(false)
+            }
+            Condition::File(path) => path.exists(),
+        }
+    }
+
+    /// Polls the condition until it holds, the timeout passes or pilot is interrupted.
+    pub async fn wait(&self) -> Result<(), String> {
+        let start = Instant::now();
+
+        while !self.is_ready().await {
+            if shutdown::is_interrupted() {
+                return Err(format!("interrupted waiting for {}", self.description));
+            }
+
+            if start.elapsed() >= self.timeout {
+                return Err(format!(
+                    "timed out after {} waiting for {}",
+                    clock::human(self.timeout),
+                    self.description
+                ));
+            }
+
+            shutdown::sleep(INTERVAL).await;
+        }
+
+        Ok(())
+    }
+}
+
+/// A `poll` step, runs a command until it succeeds.
+#[derive(Clone)]
+pub struct Poll {
+    pub command: String,
+    /// the time between the end of an attempt and the start of the next
+    pub interval: Duration,
+    pub timeout: Duration,
+}
+
+impl Poll {
+    pub fn from_yaml(yaml: &Yaml) -> Option<Self> {
+        Some(Poll {
+            command: yaml["shell"].as_str()?.to_string(),
+            interval: duration_from_yaml(&yaml["interval"], POLL_INTERVAL)?,
+            timeout: duration_from_yaml(&yaml["timeout"], DEFAULT_TIMEOUT)?,
+        })
+    }
+}
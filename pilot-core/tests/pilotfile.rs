@@ -0,0 +1,71 @@
+use std::path::{Path, PathBuf};
+
+use pilot_core::{Error, Pilotfile, RunOptions, Runner};
+
+fn test_data(dir: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../test_data")
+        .join(dir)
+}
+
+#[test]
+fn find_pilotfile_in_parent() {
+    let dir = Pilotfile::find(test_data("path/bin")).unwrap();
+
+    assert_eq!(dir, test_data("path"));
+}
+
+#[test]
+fn list_tasks() {
+    let pilotfile = Pilotfile::load(test_data("")).unwrap();
+    let tasks = pilotfile.tasks();
+
+    assert!(!pilotfile.is_procfile());
+    assert_eq!(
+        tasks[0],
+        ("build".to_string(), Some("build stuff".to_string()))
+    );
+    assert_eq!(tasks[3], ("straw-task".to_string(), None));
+}
+
+#[test]
+fn load_procfile() {
+    let pilotfile = Pilotfile::load(test_data("procfile")).unwrap();
+
+    assert!(pilotfile.is_procfile());
+    assert_eq!(pilotfile.default_tasks(), vec!["Procfile".to_string()]);
+}
+
+#[test]
+fn unknown_profile() {
+    let mut pilotfile = Pilotfile::load(test_data("profiles")).unwrap();
+
+    assert_eq!(
+        pilotfile.apply_profile("nope"),
+        Err("Profile nope not found in Pilotfile".to_string())
+    );
+}
+
+#[test]
+fn run_task() {
+    let pilotfile = Pilotfile::load(test_data("")).unwrap();
+    let options = RunOptions::new(&pilotfile);
+    let runner = Runner::new(pilotfile, options);
+
+    let result = runner.run("client").unwrap();
+
+    assert_eq!(result.task, "client");
+    assert!(result.success());
+}
+
+#[test]
+fn run_unknown_task() {
+    let pilotfile = Pilotfile::load(test_data("")).unwrap();
+    let options = RunOptions::new(&pilotfile);
+    let runner = Runner::new(pilotfile, options);
+
+    assert_eq!(
+        runner.run("nope"),
+        Err(Error("Task nope not found in Pilotfile".to_string()))
+    );
+}
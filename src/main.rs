@@ -1,37 +1,107 @@
 use std::{
-    env::{self, args},
+    env,
     error::Error,
-    fs::read_to_string,
-    io::{BufRead, BufReader},
-    path::PathBuf,
-    process::{exit, Command, Stdio},
-    sync::atomic::{AtomicU32, AtomicUsize, Ordering},
-    thread,
+    path::{Path, PathBuf},
 };
 
-use chrono::Local;
-use ptyprocess::PtyProcess;
-use yaml_rust::{Yaml, YamlLoader};
+use pilot_core::{
+    bench, ci, color_default, edit, export, fmt, history, import, list, shuffle, shutdown,
+    sink::SinkSpec, state, upgrade, user_config, Pilotfile, PrefixStyle, RunOptions, Runner,
+};
 
-const NOT_VALID: &str = "This is not a valid Pilotfile";
 const HELP_TEXT: &str = "pilot - a simple task runner / process manager
 
 USAGE:
     pilot [OPTIONS] [tasks]
+    pilot exec [OPTIONS] -- <command>
 
 FLAGS:
     -h, --help                  print this help text
+        --plain                 list the tasks as tab-separated names, tags, kinds and descriptions, for scripts
 
 OPTIONS:
+        --color <when>          color and align the task names: auto (when printing to a terminal), always or never
+        --detach-on-hup         keep running when the terminal hangs up, with the output going to the run history
+        --dry-run               show the previews of the tasks and the tasks they start instead of running them
+    -e, --env <KEY=VALUE>       set an environment variable for every task, overriding the Pilotfile (repeatable)
+        --max-line-length <n>   cut lines of output after n characters, like minified code or huge JSON
+        --from-step <id>        start the task at the step with this id, like after it failed there
+        --until-step <id>       stop the task after the step with this id
+        --match <pattern>       run every task matching a pattern like `test:*`, `*` is any text and `?` one character
+        --parallel              run the tasks of the command line and the ones matching a pattern at the same time
+        --reload                run the tasks at the same time and, when the Pilotfile is saved, start the added tasks,
+                                restart the changed ones and stop the removed ones
+        --group-output          print the output of each task as one block once it finished, instead of interleaved lines
     -q, --quiet <quiet-tasks>   run the following tasks without output (to run them, you still have to add them explicitly)
+                                if one fails, its last lines of output are printed after all
+        --output <sink>         where the output goes: console (the default), null, file:<path> or json:<path>
+                                for one JSON object per line, the files are appended to (repeatable)
+        --only-output <task>    show only the output of this task, the others run silently (repeatable)
+        --shuffle [seed]        run the tasks, the ones matching a pattern and parallel steps in a random order,
+                                the same seed repeats an order to find tasks that depend on running in order
+        --prefix-style <style>  how the lines show their task: color (the default), plain for `task: line` or brackets
+                                for `[task] line`, the last two without any escape sequences or status line
+        --prompt-on-failure     ask whether to retry, skip or abort when a step fails (needs a terminal)
+        --profile-output <file> write a timeline of the tasks and steps for chrome://tracing or Perfetto
+        --profile <profile>     use the tasks and environment variables of a profile of the Pilotfile, or of
+                                the later document with `profile: <profile>`, defaults to $PILOT_PROFILE
+    -p, --port <port>           set $PORT for every task, starting at <port> and counting up
     -r, --raw                   just run the tasks, without any additional output processing (useful for interactive applications)
+        --no-pre-post           don't run the pre<task> and post<task> tasks around a task
+        --rerun-failed          run only the tasks that failed in the last run
+        --strict                fail on misspelled keys of the Pilotfile, like `strict: true` in the Pilotfile
+        --shell <shell>         run the commands with this shell instead of $SHELL, `posix` means `sh -c`
+        --timings               print how long each step of a task took
     -t, --timestamp             print a timestamp at the beginning of each line
+        --no-timestamp          don't print timestamps, even for tasks with `timestamp: true`
+    -y, --yes                   run tasks that ask for confirmation without asking
 
 ARGS:
-    [tasks]                     the tasks you want to run
-
-    Without any arguments pilot will print a list of all available tasks";
-
+    [tasks]                     the tasks you want to run, `lint,test` runs a chain that stops at the first failing task
+                                and a pattern like `test:*` runs every matching task
+    [name=value]                the arguments of the task before them, like `pilot deploy env=prod`
+
+SUBCOMMANDS:
+    import compose [file]       print the services of a docker-compose file as Pilotfile tasks
+    import npm [file]           print the scripts of a package.json as Pilotfile tasks
+    import make [file]          print the targets of a Makefile as Pilotfile tasks
+    import just [file]          print the recipes of a justfile as Pilotfile tasks
+    export procfile             print the tasks as a Procfile, skipping the ones it can't represent
+    export systemd <task>       print a systemd user unit running the task as a service
+    export launchd <task>       print a launchd agent running the task as a service
+    exec -- <command>           run a command like a task, with the OPTIONS and the shell of the Pilotfile
+    runs                        list the last runs with the outcome of their tasks, the latest first
+    logs <run-id|last> [task]   print the output of a run, or only of one of its tasks
+    logs <task> -f              print the output of a task in the last run with its colored name, and while the run
+                                is going on the lines it prints next
+    cancel <task>               stop a task of the runs that are going on, from another terminal, the other tasks keep running
+    compare [<run-id> <run-id>] show which tasks got slower, faster or newly failed from one run to another and
+                                how the environment changed, the last two runs if none are given
+    repro <run-id|last>         run the tasks of a run again with its options and the Pilotfile as it was back then
+    edit [task]                 open the Pilotfile in the editor, at the line of the task
+    fmt [--check]               rewrite the Pilotfile in its canonical form, --check fails if it isn't formatted
+    bench <task> [--runs <n>]   run a task repeatedly without its output and print how long the runs took,
+          [--save-baseline]     keep the runs as the baseline the next benchmarks of the task compare against
+          [--max-regression <percent>]
+                                fail if the median is this many percent slower than the baseline
+    upgrade [--check]           replace pilot with the binary of the latest release after verifying its checksum,
+                                --check only tells whether there is a newer one
+
+CONFIG:
+    ~/.config/pilot/config.yaml (or $XDG_CONFIG_HOME/pilot/config.yaml) holds the preferences of the user,
+    the Pilotfile and the OPTIONS override them:
+        color: auto|always|never
+        colors: [cyan, magenta, ...]    the colors of the task names, bright_<color> for the bright ones
+        prefix_style: color|plain|brackets
+        timestamp_format: %H:%M:%S      the format of the timestamps, like strftime
+        shell: <shell>                  like --shell
+        log_dir: <dir>                  keep the run history there instead of in .pilot/runs
+        editor: <command>               the editor of pilot edit, instead of $VISUAL or $EDITOR
+
+    Without any arguments pilot will print a list of all available tasks.
+    Without a Pilotfile.yaml, the entries of a Procfile are the tasks and pilot starts all of them by default";
+
+/// Unwraps a value or exits pilot with the message, for errors the user has to fix.
 trait OrMsg<T> {
     fn or_msg(self, msg: &str) -> T;
 }
@@ -42,7 +112,7 @@ impl<T, E: Error> OrMsg<T> for Result<T, E> {
             Ok(value) => value,
             Err(err) => {
                 eprintln!("{}: {}", msg, err);
-                exit(1);
+                shutdown::exit(1);
             }
         }
     }
@@ -54,416 +124,480 @@ impl<T> OrMsg<T> for Option<T> {
             Some(value) => value,
             None => {
                 eprintln!("{}", msg);
-                exit(1)
+                shutdown::exit(1)
             }
         }
     }
 }
 
-static INDEX: AtomicU32 = AtomicU32::new(1);
-static PADDING: AtomicUsize = AtomicUsize::new(0);
-
-fn sanitize_string(mut line: String) -> String {
-    fn do_remove(i: usize, char: char, line: &mut String) -> bool {
-        if char != '\u{1b}' && char != '\u{1B}' {
-            return false;
-        }
-
-        let mut iter = line.chars().skip(i + 1);
-        let mut removals: u32 = 1;
-        while let Some(char) = iter.next() {
-            // it is a coloring sequence, abort
-            if char == 'm' {
-                return false;
-            }
-            if char != '[' && char != ';' && !char.is_ascii_digit() {
-                removals += 1;
-
-                // we boldly assume the escape sequence wanted to delete the line so we do so
-                *line = line[(i + removals as usize)..line.len()].to_string();
-                return true;
-                // if let Some(slice) = line.get((i - 1)..i) {
-                //     println!("{}", slice.to_string());
-                //     if let Ok(index) = slice.parse::<usize>() {
-                //         *line = line[0..(index - 1)].to_string();
-                //     }
-                // }
-            }
-
-            removals += 1;
-        }
+/// Unwraps the outcome of a command of the library, exiting pilot with its error.
+fn or_exit<T>(result: Result<T, pilot_core::Error>) -> T {
+    result.unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        shutdown::exit(1)
+    })
+}
 
-        for _ in 0..removals {
-            line.remove(i);
-        }
+fn find_pilotfile() -> PathBuf {
+    let dir = env::current_dir().or_msg("Could not read the current directory");
 
-        removals > 0
-    }
-
-    let mut i = 0;
-    while let Some(char) = line.get(i..(i + 1)) {
-        // only increase index if no removals (otherwise there is a new char on the index)
-        if do_remove(i, char.chars().nth(0).unwrap().clone(), &mut line) {
-            i = 0;
-        } else {
-            i += 1;
-        }
-    }
-    line
+    Pilotfile::find(dir).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        shutdown::exit(1)
+    })
 }
 
-#[cfg(target_family = "windows")]
-fn get_shell() -> Command {
-    let mut command = Command::new(r"C:\Windows\System32\powershell.exe");
-    command.arg("-c");
-    command
+fn load_pilotfile() -> Pilotfile {
+    load_pilotfile_in(find_pilotfile())
 }
 
-#[cfg(not(target_family = "windows"))]
-fn get_shell() -> Command {
-    let shell = env::var("SHELL").unwrap_or("sh".to_string());
-    let mut command = Command::new(shell);
-    command.arg("-c");
-    command
+fn load_pilotfile_in(dir: PathBuf) -> Pilotfile {
+    Pilotfile::load(&dir).unwrap_or_else(|err| exit_invalid(&dir, &err))
 }
 
-fn run_shell(
-    command: String,
-    task_name: String,
-    quiet_tasks: Vec<String>,
-    raw: bool,
-    timestamp: bool,
-    dir: PathBuf,
-) {
-    // cycle through shell colors
-    // credit: https://github.com/chrismytton/shoreman/
-    let current_index = INDEX.fetch_add(1, Ordering::SeqCst);
-    let color = "\x1b[0;".to_string() + &(31 + current_index % 7).to_string() + "m";
-
-    let mut std_command = get_shell();
-    std_command.arg(command).current_dir(dir);
-
-    let quiet = quiet_tasks.contains(&task_name);
-
-    if raw {
-        if quiet {
-            std_command.stdout(Stdio::null());
-            std_command.stderr(Stdio::null());
-        }
+fn exit_invalid(dir: &Path, err: &str) -> ! {
+    ci::invalid_pilotfile(dir, err);
+    eprintln!("{}", err);
+    shutdown::exit(1)
+}
 
-        std_command
-            .spawn()
-            .or_msg(&format!("Failed to run task {}", task_name))
-            .wait()
-            .or_msg(&format!("Task {} failed", task_name));
-    } else {
-        let process =
-            PtyProcess::spawn(std_command).or_msg(&format!("Failed to run task {}", task_name));
+/// The command line of a run.
+struct Args {
+    tasks: Vec<String>,
+    /// the arguments after `--`
+    command: Vec<String>,
+    rerun_failed: bool,
+    options: RunOptions,
+}
 
-        if !quiet {
-            let this_padding = task_name.len() + 1;
+/// Parses the options of a run, applying the selected profile to the Pilotfile.
+fn parse_args(pilotfile: &mut Pilotfile, args: impl Iterator<Item = String>) -> Args {
+    let mut command_line: Vec<_> = args.collect();
+    let mut args = command_line.clone().into_iter().peekable();
+    let mut tasks_to_run = vec![];
+    let mut command = vec![];
+    let mut quiet_tasks = vec![];
+    let mut only_output = vec![];
+    let mut sinks = vec![];
+    let mut raw = false;
+    let mut timestamp = None;
+    let mut rerun_failed = false;
+    let mut pre_post = true;
+    let mut yes = false;
+    let mut dry_run = false;
+    let mut from_step = None;
+    let mut until_step = None;
+    let mut detach_on_hangup = false;
+    let mut prompt_on_failure = false;
+    let mut timings = false;
+    let mut parallel = false;
+    let mut reload = false;
+    let mut shuffle = None;
+    let mut group_output = false;
+    let mut max_line_length = None;
+    let mut profile_output = None;
+    let mut port = None;
+    let mut env_overrides = vec![];
+    let mut task_args: Vec<(String, Vec<(String, String)>)> = vec![];
+    // whether the last task already got an entry in task_args
+    let mut task_has_args = false;
+    let mut profile = None;
+    let mut shell = None;
+    let mut color = None;
+    let mut prefix_style = None;
+    let mut strict = false;
+
+    // everything after -q is a quiet task
+    let mut quiet = false;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--" => command.extend(args.by_ref()),
+            "-e" | "--env" => env_overrides.push(
+                args.next()
+                    .and_then(|env| {
+                        let (key, value) = env.split_once('=')?;
+                        Some((key.to_string(), value.to_string()))
+                    })
+                    .or_msg("--env needs a KEY=VALUE pair"),
+            ),
+            "--color" => {
+                color = Some(match args.next().as_deref() {
+                    Some("auto") => None,
+                    Some("always") => Some(true),
+                    Some("never") => Some(false),
+                    _ => {
+                        eprintln!("--color needs auto, always or never");
+                        shutdown::exit(1);
+                    }
+                })
+            }
+            "--prefix-style" => {
+                prefix_style = Some(
+                    args.next()
+                        .as_deref()
+                        .and_then(PrefixStyle::parse)
+                        .or_msg("--prefix-style needs color, plain or brackets"),
+                )
+            }
+            "--shuffle" => {
+                shuffle = Some(
+                    args.next_if(|seed| seed.parse::<u64>().is_ok())
+                        .map(|seed| seed.parse().unwrap()),
+                )
+            }
+            "--strict" => strict = true,
+            "--shell" => shell = Some(args.next().or_msg("--shell needs a shell")),
+            "--profile" => {
+                profile = Some(args.next().or_msg("--profile needs the name of a profile"))
+            }
+            "-p" | "--port" => {
+                port = Some(
+                    args.next()
+                        .and_then(|port| port.parse::<u16>().ok())
+                        .or_msg("--port needs a port number"),
+                )
+            }
+            "-q" | "--quiet" => quiet = true,
+            "--output" => {
+                let spec = args.next().or_msg("--output needs a sink");
+                let dir = env::current_dir().or_msg("Could not read the current directory");
+                sinks.push(SinkSpec::parse(&spec, &dir).unwrap_or_else(|err| {
+                    eprintln!("{}", err);
+                    shutdown::exit(1)
+                }));
+            }
+            "--only-output" => only_output.push(args.next().or_msg("--only-output needs a task")),
+            "-r" | "--raw" => raw = true,
+            "-t" | "--timestamp" => timestamp = Some(true),
+            "--no-timestamp" => timestamp = Some(false),
+            "--rerun-failed" => rerun_failed = true,
+            "--no-pre-post" => pre_post = false,
+            "-y" | "--yes" => yes = true,
+            "--dry-run" => dry_run = true,
+            "--from-step" => {
+                from_step = Some(args.next().or_msg("--from-step needs the id of a step"))
+            }
+            "--until-step" => {
+                until_step = Some(args.next().or_msg("--until-step needs the id of a step"))
+            }
+            "--detach-on-hup" => detach_on_hangup = true,
+            "--prompt-on-failure" => prompt_on_failure = true,
+            "--timings" => timings = true,
+            "--parallel" => parallel = true,
+            "--reload" => reload = true,
+            "--group-output" => group_output = true,
+            "--max-line-length" => {
+                max_line_length = Some(
+                    args.next()
+                        .and_then(|length| length.parse::<usize>().ok())
+                        .or_msg("--max-line-length needs a number of characters"),
+                )
+            }
+            "--match" => {
+                tasks_to_run.push(args.next().or_msg("--match needs a pattern"));
+                task_has_args = false;
+            }
+            "--profile-output" => {
+                profile_output = Some(PathBuf::from(
+                    args.next().or_msg("--profile-output needs a file"),
+                ))
+            }
+            _ if quiet => quiet_tasks.push(arg),
+            // `name=value` after a task is an argument of that task
+            _ if arg.contains('=') && !tasks_to_run.is_empty() => {
+                let (name, value) = arg.split_once('=').unwrap();
+                let arg = (name.to_string(), value.to_string());
+
+                match task_args.last_mut() {
+                    Some((_, args)) if task_has_args => args.push(arg),
+                    _ => task_args.push((tasks_to_run.last().unwrap().clone(), vec![arg])),
+                }
 
-            PADDING.fetch_max(this_padding, Ordering::SeqCst);
+                task_has_args = true;
+            }
+            _ => {
+                tasks_to_run.push(arg);
+                task_has_args = false;
+            }
+        }
+    }
 
-            BufReader::new(process.get_pty_stream().or_msg("Could not get pty output"))
-                .lines()
-                .filter_map(|line| line.ok())
-                .map(|line| sanitize_string(line))
-                .for_each(|line| {
-                    let mut time_prefix = "".to_string();
+    if strict {
+        pilotfile.check_strict().unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            shutdown::exit(1)
+        });
+    }
 
-                    if timestamp {
-                        time_prefix = Local::now().format("%H:%M:%S").to_string() + " ";
-                    }
+    // the history keeps the profile of the environment too, for pilot repro
+    let profile = profile.or_else(|| {
+        let profile = env::var("PILOT_PROFILE").ok()?;
+        command_line.extend(["--profile".to_string(), profile.clone()]);
+        Some(profile)
+    });
+
+    if let Some(profile) = profile {
+        let profile_env = pilotfile.apply_profile(&profile).unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            shutdown::exit(1)
+        });
+        // the command line still wins over the profile
+        env_overrides = profile_env.into_iter().chain(env_overrides).collect();
+    }
 
-                    let padding = PADDING.load(Ordering::SeqCst);
-                    let padding_prefix = " ".repeat(padding.saturating_sub(this_padding));
+    let mut options = RunOptions::new(pilotfile);
+    options.quiet_tasks = quiet_tasks;
+    options.only_output = only_output;
+
+    if !sinks.is_empty() {
+        options.sinks = sinks
+            .iter()
+            .map(SinkSpec::open)
+            .collect::<Result<_, _>>()
+            .unwrap_or_else(|err| {
+                eprintln!("{}", err);
+                shutdown::exit(1)
+            });
+    }
+    options.raw = raw;
+    options.timestamp = timestamp;
+    options.env_overrides = env_overrides;
+    options.task_args = task_args;
+    options.pre_post = pre_post;
+    options.yes = yes;
+    options.dry_run = dry_run;
+    options.from_step = from_step;
+    options.until_step = until_step;
+    options.detach_on_hangup = detach_on_hangup;
+    options.prompt_on_failure = prompt_on_failure;
+    options.timings = timings;
+    options.parallel = parallel;
+    options.reload = reload;
+    options.shuffle = shuffle.map(|seed| seed.unwrap_or_else(shuffle::new_seed));
+    options.group_output = group_output;
+    options.max_line_length = max_line_length;
+    options.profile_output = profile_output;
+    options.port = port;
+    options.shell = shell.or(options.shell);
+    options.command_line = command_line;
+
+    if let Some(color) = color {
+        options.color = color.unwrap_or_else(color_default);
+    }
 
-                    println!(
-                        "{}{}{}:\x1b[0m{} {}",
-                        time_prefix, color, task_name, padding_prefix, line
-                    );
-                });
-        }
+    if let Some(prefix_style) = prefix_style {
+        options.prefix_style = prefix_style;
+    }
 
-        process.wait().or_msg(&format!("Task {} failed", task_name));
+    if options.prefix_style != PrefixStyle::Color {
+        options.color = false;
     }
 
-    // subtract one from the index
-    INDEX.fetch_sub(1, Ordering::SeqCst);
+    Args {
+        tasks: tasks_to_run,
+        command,
+        rerun_failed,
+        options,
+    }
 }
 
-fn run_task(
-    task: (Yaml, Yaml),
-    all_tasks: Yaml,
-    task_prefix: String,
-    task_name: String,
-    quiet_tasks: Vec<String>,
-    raw: &mut bool,
-    timestamp: bool,
-    dir: PathBuf,
-) {
-    match task.0.as_str().or_msg(NOT_VALID) {
-        "shell" => run_shell(
-            task.1.as_str().or_msg(NOT_VALID).to_string(),
-            task_name,
-            quiet_tasks,
-            raw.clone(),
-            timestamp,
-            dir.clone(),
-        ),
-        "task" => {
-            let sub_task = task.1.as_str().or_msg(NOT_VALID).to_string();
-            cli_run_task(
-                all_tasks,
-                sub_task.clone(),
-                task_prefix + " > " + &sub_task,
-                quiet_tasks,
-                raw.clone(),
-                timestamp,
-                dir.clone(),
-            );
-        }
-        "parallel" => {
-            let mut threads = vec![];
-
-            for sub_task in task.1.as_vec().or_msg(NOT_VALID) {
-                let sub_task_tuple = sub_task
-                    .as_hash()
-                    .or_msg(NOT_VALID)
-                    .iter()
-                    .nth(0)
-                    .or_msg(NOT_VALID);
-                let sub_task_tuple = (sub_task_tuple.0.clone(), sub_task_tuple.1.clone());
-                let all_tasks_clone = all_tasks.clone();
-                let task_prefix_clone = task_prefix.clone();
-                let task_name_clone = task_name.clone();
-                let quiet_tasks_clone = quiet_tasks.clone();
-
-                let mut raw_clone = raw.clone();
-                let dir_clone = dir.clone();
-
-                threads.push(thread::spawn(move || {
-                    run_task(
-                        sub_task_tuple,
-                        all_tasks_clone,
-                        task_prefix_clone,
-                        task_name_clone,
-                        quiet_tasks_clone,
-                        &mut raw_clone,
-                        timestamp,
-                        dir_clone.clone(),
-                    );
-                }));
-            }
+fn cli_run(mut pilotfile: Pilotfile) {
+    let mut args = parse_args(&mut pilotfile, env::args().skip(1));
 
-            for thread in threads {
-                thread.join().unwrap();
-            }
-        }
-        "raw" => {
-            *raw = task.1.as_bool().or_msg(NOT_VALID);
-        }
-        "description" => {}
-        _ => {
-            eprintln!("Unkown token");
-            exit(1);
-        }
+    if !args.command.is_empty() {
+        eprintln!("Only pilot exec runs a command after --");
+        shutdown::exit(1);
     }
-}
 
-fn cli_run_task(
-    yaml: Yaml,
-    task: String,
-    task_prefix: String,
-    quiet_tasks: Vec<String>,
-    mut raw: bool,
-    timestamp: bool,
-    dir: PathBuf,
-) {
-    if timestamp {
-        println!("{} > {}", Local::now().format("%H:%M:%S"), task_prefix);
-    } else {
-        println!("> {}", task_prefix);
+    if args.tasks.is_empty() && !args.rerun_failed {
+        args.tasks = pilotfile.default_tasks();
     }
 
-    let found_tasks: Vec<_> = yaml
-        .as_hash()
-        .or_msg(NOT_VALID)
-        .iter()
-        .filter(|yaml| yaml.0.as_str().unwrap_or("") == task)
-        .collect();
+    if args.rerun_failed {
+        args.tasks = or_exit(state::failed_tasks(pilotfile.dir()));
 
-    match found_tasks.len() {
-        0 => {
-            eprintln!("Task {} not found in Pilotfile", task);
-            exit(1);
-        }
-        1 => {
-            for sub_task in found_tasks[0].1.as_vec().or_msg(NOT_VALID) {
-                let list: Vec<_> = sub_task
-                    .as_hash()
-                    .or_msg(NOT_VALID)
-                    .iter()
-                    .map(|(first, second)| (first.clone(), second.clone()))
-                    .collect();
-                run_task(
-                    list[0].clone(),
-                    yaml.clone(),
-                    task_prefix.clone(),
-                    task.clone(),
-                    quiet_tasks.clone(),
-                    &mut raw,
-                    timestamp,
-                    dir.clone(),
-                );
-            }
-
-            // the process exited
-            if timestamp {
-                println!(
-                    "{} finished {}",
-                    Local::now().format("%H:%M:%S"),
-                    task_prefix
-                );
-            } else {
-                println!("finished {}", task_prefix);
-            }
-        }
-        _ => {
-            eprintln!("Duplicate task {}", task);
-            exit(1);
+        if args.tasks.is_empty() {
+            println!("No tasks failed in the last run");
+            return;
         }
     }
+
+    run_all(pilotfile, args.tasks, args.options);
 }
 
-fn task_to_string(task: (&Yaml, &Yaml)) -> String {
-    let task_name = task.0.as_str().or_msg(NOT_VALID);
+/// Runs the tasks and exits like them.
+fn run_all(pilotfile: Pilotfile, tasks: Vec<String>, options: RunOptions) {
+    let summary = or_exit(Runner::new(pilotfile, options).run_all(tasks));
 
-    let vec = task.1.as_vec().or_msg(NOT_VALID);
+    // stopping a dev stack of services is how it ends, not an interruption
+    if summary.interrupted && !summary.success() {
+        shutdown::exit(shutdown::INTERRUPTED_EXIT_CODE);
+    }
+
+    if !summary.success() {
+        shutdown::exit(1);
+    }
+}
 
-    let descriptions: Vec<_> = vec
+/// `pilot repro <run-id|last>`, runs the tasks of a run of the history again, with its command
+/// line and the Pilotfile as it was back then. The secrets of `-e` the history hides come from
+/// the environment.
+fn cli_repro() {
+    let dir = find_pilotfile();
+    let run = or_exit(history::recorded_run(
+        &env::args().skip(2).collect::<Vec<_>>(),
+        &dir,
+    ));
+
+    let mut pilotfile = Pilotfile::load_from(&dir, &run.files).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        shutdown::exit(1)
+    });
+    let command_line: Vec<_> = run
+        .command_line
         .iter()
-        .map(|yaml| yaml.as_hash().or_msg(NOT_VALID))
-        .filter(|list| list.contains_key(&Yaml::String("description".to_string())))
+        .map(|arg| shell_quote(arg))
         .collect();
-
-    match descriptions.len() {
-        0 => format!("{}", task_name),
-        1 => match &descriptions[0][&Yaml::String("description".to_string())] {
-            Yaml::String(description) => format!("{} - {}", task_name, description),
-            _ => format!("{}", task_name),
-        },
-        _ => {
-            eprintln!("More than one description for task {}", task_name);
-            exit(1);
-        }
-    }
+    let args = parse_args(&mut pilotfile, run.args.into_iter());
+
+    println!(
+        "reproducing run {}: pilot {}",
+        run.id,
+        command_line.join(" ")
+    );
+    run_all(pilotfile, run.tasks, args.options);
 }
 
-fn cli_list_tasks(yaml: &Yaml) {
-    println!("Available tasks:");
+/// Quotes an argument for `sh`, if it has to be.
+fn shell_quote(arg: &str) -> String {
+    let plain = |char: char| char.is_ascii_alphanumeric() || "-_./=:,+@%".contains(char);
 
-    for task in yaml.as_hash().or_msg(NOT_VALID) {
-        println!("\t{}", task_to_string(task));
+    if !arg.is_empty() && arg.chars().all(plain) {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
     }
 }
 
-fn get_pilotfile_dir() -> PathBuf {
-    let mut path = PathBuf::from(env::current_dir().or_msg("Could not read the current directory"));
-
-    loop {
-        path.push("Pilotfile.yaml");
+/// `pilot exec [OPTIONS] -- <command>`, runs a command like a task, the Pilotfile is optional.
+fn cli_exec() {
+    let dir = env::current_dir().or_msg("Could not read the current directory");
+    let mut pilotfile = match Pilotfile::find(&dir) {
+        Ok(dir) => load_pilotfile_in(dir),
+        Err(_) => Pilotfile::empty(dir),
+    };
 
-        if path.exists() {
-            break;
-        }
+    let args = parse_args(&mut pilotfile, env::args().skip(2));
 
-        if !(path.pop() && path.pop()) {
-            eprintln!("Pilotfile.yaml not found");
-            exit(1);
-        }
+    if !args.tasks.is_empty() || args.command.is_empty() {
+        eprintln!("Usage: pilot exec [OPTIONS] -- <command>");
+        shutdown::exit(1);
     }
 
-    path.pop();
-    path
-}
+    let command: Vec<_> = args.command.iter().map(|arg| shell_quote(arg)).collect();
+    let result = Runner::new(pilotfile, args.options).exec(&command.join(" "));
 
-fn load_pilotfile(mut path: PathBuf) -> Yaml {
-    path.push("Pilotfile.yaml");
+    if shutdown::is_interrupted() {
+        shutdown::exit(shutdown::INTERRUPTED_EXIT_CODE);
+    }
 
-    let file = read_to_string(path).or_msg("Pilotfile.yaml not found");
-    let vec = YamlLoader::load_from_str(&file).or_msg("That is not a valid Pilotfile");
-    vec[0].clone()
+    shutdown::exit(result.code);
 }
 
 fn main() {
-    match args().nth(1) {
-        Some(string) => {
-            if string == "-h" || string == "--help" {
-                println!("{}", HELP_TEXT);
-            } else {
-                let pilotfile_dir = get_pilotfile_dir();
-
-                let yaml = load_pilotfile(pilotfile_dir.clone());
-
-                let mut tasks_to_run = vec![];
-                let mut quiet_tasks = vec![];
-                let mut raw = false;
-                let mut timestamp = false;
-
-                let mut args = args().skip(1);
-
-                while let Some(arg) = args.next() {
-                    if arg == "-q" || arg == "--quiet" {
-                        break;
-                    }
+    // a broken config of the user fails every command, not only the ones using it
+    or_exit(user_config::init());
 
-                    if arg == "-r" || arg == "--raw" {
-                        raw = true;
-                        continue;
-                    }
-
-                    if arg == "-t" || arg == "--timestamp" {
-                        timestamp = true;
-                        continue;
-                    }
+    match env::args().nth(1) {
+        Some(string) if string == "-h" || string == "--help" => println!("{}", HELP_TEXT),
+        Some(string) if string == "import" => {
+            or_exit(import::cli(&env::args().skip(2).collect::<Vec<_>>()))
+        }
+        Some(string) if string == "exec" => cli_exec(),
+        Some(string) if string == "runs" => history::cli_runs(load_pilotfile().dir()),
+        Some(string) if string == "logs" => {
+            let pilotfile = load_pilotfile();
+            or_exit(history::cli_logs(
+                &env::args().skip(2).collect::<Vec<_>>(),
+                pilotfile.dir(),
+            ));
+        }
+        Some(string) if string == "cancel" => {
+            let pilotfile = load_pilotfile();
+            or_exit(history::cli_cancel(
+                &env::args().skip(2).collect::<Vec<_>>(),
+                pilotfile.dir(),
+            ));
+        }
+        Some(string) if string == "repro" => cli_repro(),
+        Some(string) if string == "compare" => {
+            let pilotfile = load_pilotfile();
+            or_exit(history::cli_compare(
+                &env::args().skip(2).collect::<Vec<_>>(),
+                pilotfile.dir(),
+            ));
+        }
+        Some(string) if string == "bench" => {
+            let code = or_exit(bench::cli(
+                &env::args().skip(2).collect::<Vec<_>>(),
+                load_pilotfile(),
+            ));
+            shutdown::exit(code);
+        }
+        Some(string) if string == "fmt" => or_exit(fmt::cli(
+            &env::args().skip(2).collect::<Vec<_>>(),
+            &find_pilotfile(),
+        )),
+        Some(string) if string == "edit" => {
+            let code = or_exit(edit::cli(
+                &env::args().skip(2).collect::<Vec<_>>(),
+                &find_pilotfile(),
+            ));
+            shutdown::exit(code);
+        }
+        Some(string) if string == "upgrade" => {
+            or_exit(upgrade::cli(&env::args().skip(2).collect::<Vec<_>>()))
+        }
+        Some(string) if string == "export" => {
+            let pilotfile = load_pilotfile();
+            or_exit(export::cli(
+                &env::args().skip(2).collect::<Vec<_>>(),
+                &pilotfile,
+            ));
+        }
+        arg => {
+            let dir = find_pilotfile();
+            let plain = arg.as_deref() == Some("--plain");
 
-                    tasks_to_run.push(arg);
+            match Pilotfile::load(&dir) {
+                Ok(pilotfile) if plain || (arg.is_none() && !pilotfile.is_procfile()) => {
+                    list::cli(&pilotfile, plain)
                 }
-
-                while let Some(arg) = args.next() {
-                    if arg == "-r" || arg == "--raw" {
-                        raw = true;
-                        continue;
+                Ok(pilotfile) => cli_run(pilotfile),
+                // while the Pilotfile is being edited, the valid tasks are still listed
+                Err(err) if arg.is_none() || plain => {
+                    // errors of the whole file, like --strict, don't leave out single entries
+                    let (pilotfile, errors) = match Pilotfile::load_lenient(&dir) {
+                        Ok((pilotfile, errors))
+                            if !errors.is_empty() && !pilotfile.tasks().is_empty() =>
+                        {
+                            (pilotfile, errors)
+                        }
+                        _ => exit_invalid(&dir, &err),
+                    };
+
+                    list::cli(&pilotfile, plain);
+                    ci::invalid_pilotfile(&dir, &err);
+
+                    for error in errors {
+                        eprintln!("{}", error);
                     }
 
-                    if arg == "-t" || arg == "--timestamp" {
-                        timestamp = true;
-                        continue;
-                    }
-
-                    quiet_tasks.push(arg);
-                }
-
-                for task in tasks_to_run {
-                    cli_run_task(
-                        yaml.clone(),
-                        task.clone(),
-                        task,
-                        quiet_tasks.clone(),
-                        raw,
-                        timestamp,
-                        pilotfile_dir.clone(),
-                    );
+                    shutdown::exit(1)
                 }
+                Err(err) => exit_invalid(&dir, &err),
             }
         }
-        None => {
-            let path = get_pilotfile_dir();
-
-            let yaml = load_pilotfile(path);
-            cli_list_tasks(&yaml);
-        }
     }
 }
@@ -0,0 +1 @@
+start()
@@ -0,0 +1,7 @@
+#!/bin/sh
+# stands in for kubectl, there is a single running pod with the label app=api
+case "$*" in
+    *"get pods --selector app=api "*) echo api-7d9f ;;
+    *"get pods"*) ;;
+    *) echo "$@" ;;
+esac
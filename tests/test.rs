@@ -1,23 +1,48 @@
 use assert_cmd::Command;
 
 fn run() -> Command {
+    run_in("")
+}
+
+/// Runs pilot in a sub directory of test_data, for tests that need their own Pilotfile.
+fn run_in(dir: &str) -> Command {
     let mut command = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
-    command.current_dir(env!("CARGO_MANIFEST_DIR").to_string() + "/test_data");
+    command.current_dir(env!("CARGO_MANIFEST_DIR").to_string() + "/test_data/" + dir);
+    // the output isn't a terminal in tests, but most of them check the colored output
+    command.env("CLICOLOR_FORCE", "1").env_remove("NO_COLOR");
+    // the preferences of whoever runs the tests must not change the output
+    command.env(
+        "XDG_CONFIG_HOME",
+        env!("CARGO_MANIFEST_DIR").to_string() + "/test_data/user_config/none",
+    );
+    // neither must running them in CI
+    command.env_remove("GITHUB_ACTIONS").env_remove("GITLAB_CI");
     command
 }
 
+/// The stderr of a failed run, the names of the diagnostics files have the time in them.
+fn stderr_of_failure(assert: &assert_cmd::assert::Assert) -> String {
+    String::from_utf8_lossy(&assert.get_output().stderr)
+        .lines()
+        .map(|line| match line.split_once(" .pilot/failures/") {
+            Some((message, _)) => format!("{} .pilot/failures/<file>\n", message),
+            None => format!("{}\n", line),
+        })
+        .collect()
+}
+
 #[test]
 fn list_tasks() {
     run().assert().success().stderr("").stdout(
         "Available tasks:
-\tbuild - build stuff
-\tserver
-\tclient - server
-\tstraw-task
-\trun
-\traw
-\traw-explicit
-\tnot-raw-explicit\n",
+  \x1b[0;32mbuild\x1b[0m             \x1b[2mjob\x1b[0m  build stuff
+  \x1b[0;32mserver\x1b[0m            \x1b[2mjob\x1b[0m
+  \x1b[0;32mclient\x1b[0m            \x1b[2mjob\x1b[0m  server
+  \x1b[0;32mstraw-task\x1b[0m        \x1b[2mjob\x1b[0m
+  \x1b[0;32mrun\x1b[0m               \x1b[2mjob\x1b[0m
+  \x1b[0;32mraw\x1b[0m               \x1b[2mjob\x1b[0m
+  \x1b[0;32mraw-explicit\x1b[0m      \x1b[2mjob\x1b[0m
+  \x1b[0;32mnot-raw-explicit\x1b[0m  \x1b[2mjob\x1b[0m\n",
     );
 }
 
@@ -25,6 +50,7 @@ fn list_tasks() {
 fn run_task() {
     run().arg("build").assert().success().stderr("").stdout(
         "> build
+\x1b[2m  build stuff\x1b[0m
 \x1b[0;32mbuild:\x1b[0m build
 finished build\n",
     );
@@ -38,7 +64,7 @@ fn run_non_existent_task() {
         .arg(NON_EXISTENT_TASK)
         .assert()
         .failure()
-        .stdout("> ".to_string() + NON_EXISTENT_TASK + "\n")
+        .stdout("")
         .stderr("Task ".to_string() + NON_EXISTENT_TASK + " not found in Pilotfile\n");
 }
 
@@ -52,9 +78,11 @@ fn run_multiple_tasks() {
         .stderr("")
         .stdout(
             "> client
+\x1b[2m  server\x1b[0m
 \x1b[0;32mclient:\x1b[0m client
 finished client
 > build
+\x1b[2m  build stuff\x1b[0m
 \x1b[0;32mbuild:\x1b[0m  build
 finished build\n",
         );
@@ -70,6 +98,7 @@ fn run_nested_task() {
         .stdout(
             "> straw-task
 > straw-task > build
+\x1b[2m  build stuff\x1b[0m
 \x1b[0;32mbuild:\x1b[0m build
 finished straw-task > build
 finished straw-task\n",
@@ -81,12 +110,15 @@ fn run_parallel_tasks() {
     run().arg("run").assert().success().stderr("").stdout(
         "> run
 > run > build
+\x1b[2m  build stuff\x1b[0m
 \x1b[0;32mbuild:\x1b[0m build
 finished run > build
 > run > server
 > run > client
+\x1b[2m  server\x1b[0m
 > run > straw-task
 > run > straw-task > build
+\x1b[2m  build stuff\x1b[0m
 \x1b[0;33mclient:\x1b[0m client
 finished run > client
 \x1b[0;34mbuild:\x1b[0m  build
@@ -139,13 +171,3538 @@ fn find_pilotfile_in_parent_dir() {
     // basically same as list_tasks
     command.assert().success().stderr("").stdout(
         "Available tasks:
-\tbuild - build stuff
-\tserver
-\tclient - server
-\tstraw-task
-\trun
-\traw
-\traw-explicit
-\tnot-raw-explicit\n",
+  build             job  build stuff
+  server            job
+  client            job  server
+  straw-task        job
+  run               job
+  raw               job
+  raw-explicit      job
+  not-raw-explicit  job\n",
+    );
+}
+
+#[test]
+fn rerun_failed_tasks() {
+    let assert = run_in("rerun")
+        .arg("ok")
+        .arg("fail")
+        .assert()
+        .failure()
+        .stdout(
+            "> ok
+\x1b[0;32mok:\x1b[0m ok
+finished ok
+> fail
+failed fail\n",
+        );
+    assert_eq!(
+        stderr_of_failure(&assert),
+        "Task fail failed, its diagnostics are in .pilot/failures/<file>\n"
+    );
+
+    let assert = run_in("rerun")
+        .arg("--rerun-failed")
+        .assert()
+        .failure()
+        .stdout("> fail\nfailed fail\n");
+    assert_eq!(
+        stderr_of_failure(&assert),
+        "Task fail failed, its diagnostics are in .pilot/failures/<file>\n"
+    );
+}
+
+#[test]
+fn run_before_all_and_after_all() {
+    // after_all runs even though the task failed
+    let assert = run_in("hooks").arg("fail").assert().failure().stdout(
+        "> before_all
+\x1b[0;32mbefore_all:\x1b[0m setup
+finished before_all
+> fail
+failed fail
+> after_all
+\x1b[0;32mafter_all:\x1b[0m  teardown
+finished after_all\n",
+    );
+    assert_eq!(
+        stderr_of_failure(&assert),
+        "Task fail failed, its diagnostics are in .pilot/failures/<file>\n"
+    );
+}
+
+#[test]
+fn run_on_failure_hook() {
+    let assert = run_in("hooks").arg("notify").assert().failure().stdout(
+        "> before_all
+\x1b[0;32mbefore_all:\x1b[0m setup
+finished before_all
+> notify
+\x1b[0;32mnotify:\x1b[0m     failed with 3
+failed notify
+> after_all
+\x1b[0;32mafter_all:\x1b[0m  teardown
+finished after_all\n",
+    );
+    assert_eq!(
+        stderr_of_failure(&assert),
+        "Task notify failed, its diagnostics are in .pilot/failures/<file>\n"
+    );
+}
+
+#[test]
+fn run_finally_after_failure() {
+    let assert = run_in("hooks").arg("tidy").assert().failure().stdout(
+        "> before_all
+\x1b[0;32mbefore_all:\x1b[0m setup
+finished before_all
+> tidy
+\x1b[0;32mtidy:\x1b[0m       cleaned up
+failed tidy
+> after_all
+\x1b[0;32mafter_all:\x1b[0m  teardown
+finished after_all\n",
+    );
+    assert_eq!(
+        stderr_of_failure(&assert),
+        "Task tidy failed, its diagnostics are in .pilot/failures/<file>\n"
+    );
+}
+
+#[test]
+fn run_pre_and_post_tasks() {
+    run_in("pre_post")
+        .arg("build")
+        .assert()
+        .success()
+        .stderr("")
+        .stdout(
+            "> build
+> build > prebuild
+\x1b[0;32mprebuild:\x1b[0m before
+finished build > prebuild
+\x1b[0;32mbuild:\x1b[0m    build
+> build > postbuild
+\x1b[0;32mpostbuild:\x1b[0m after
+finished build > postbuild
+finished build\n",
+        );
+}
+
+#[test]
+fn run_without_pre_and_post_tasks() {
+    run_in("pre_post")
+        .arg("build")
+        .arg("--no-pre-post")
+        .assert()
+        .success()
+        .stderr("")
+        .stdout(
+            "> build
+\x1b[0;32mbuild:\x1b[0m build
+finished build\n",
+        );
+}
+
+#[test]
+fn refuse_confirmation_without_terminal() {
+    run_in("confirm")
+        .arg("wipe")
+        .assert()
+        .failure()
+        .stderr(
+            "Task wipe needs confirmation, but there is no terminal to ask. Use --yes to run it anyway\n",
+        )
+        .stdout("> wipe\nfailed wipe\n");
+}
+
+#[test]
+fn skip_confirmation_with_yes() {
+    run_in("confirm")
+        .arg("wipe")
+        .arg("--yes")
+        .assert()
+        .success()
+        .stderr("")
+        .stdout("> wipe\n\x1b[0;32mwipe:\x1b[0m wiped\nfinished wipe\n");
+}
+
+#[test]
+fn dry_run_shows_previews() {
+    run_in("preview")
+        .env_remove("GITHUB_ACTIONS")
+        .args(["--dry-run", "deploy"])
+        .assert()
+        .success()
+        .stderr("")
+        .stdout(
+            "> deploy
+preview deploy:
+   unchanged
+  \x1b[0;32m+added\x1b[0m
+  \x1b[0;31m-removed\x1b[0m
+> deploy > migrate
+preview migrate:
+  2 pending migrations
+skipped deploy > migrate
+skipped deploy
+",
+        );
+}
+
+#[test]
+fn dry_run_fails_with_preview() {
+    run_in("preview")
+        .args(["--dry-run", "broken"])
+        .assert()
+        .failure()
+        .stderr("Task broken failed: its preview `exit 3` failed with exit code 3\n")
+        .stdout("> broken\nfailed broken\n");
+}
+
+#[test]
+fn skip_preview_with_yes() {
+    run_in("preview")
+        .args(["--color", "never", "--yes", "deploy"])
+        .assert()
+        .success()
+        .stderr("")
+        .stdout(
+            "> deploy
+> deploy > migrate
+migrate: migrated
+finished deploy > migrate
+deploy: deployed
+finished deploy
+",
+        );
+}
+
+#[test]
+fn run_as_unknown_user() {
+    run_in("process")
+        .arg("unknown-user")
+        .assert()
+        .failure()
+        .stderr("Failed to run task unknown-user: Unknown user this-user-does-not-exist\n")
+        .stdout("> unknown-user\nfailed unknown-user\n");
+}
+
+#[test]
+fn run_with_niceness() {
+    run_in("process")
+        .arg("niceness")
+        .arg("priority-class")
+        .assert()
+        .success()
+        .stderr("")
+        .stdout(
+            "> niceness
+\x1b[0;32mniceness:\x1b[0m 10
+finished niceness
+> priority-class
+\x1b[0;32mpriority-class:\x1b[0m 19
+finished priority-class\n",
+        );
+}
+
+#[test]
+fn run_with_limits() {
+    run_in("process")
+        .arg("limited")
+        .assert()
+        .success()
+        .stderr("")
+        .stdout(
+            "> limited
+\x1b[0;32mlimited:\x1b[0m 64
+\x1b[0;32mlimited:\x1b[0m 524288
+finished limited\n",
+        );
+}
+
+#[test]
+fn run_in_container() {
+    run_in("container")
+        .arg("node")
+        .assert()
+        .success()
+        .stderr("")
+        .stdout(
+            "> node
+\x1b[0;32mnode:\x1b[0m run --rm -i -t -v "
+                .to_string()
+                + env!("CARGO_MANIFEST_DIR")
+                + "/test_data/container:/app -w /app node:20 sh -c npm test
+finished node\n",
+        );
+}
+
+#[test]
+fn import_compose_services() {
+    run_in("import")
+        .arg("import")
+        .arg("compose")
+        .assert()
+        .success()
+        .stderr("")
+        .stdout(
+            "api:
+  - description: depends on db
+  - env:
+      DATABASE_URL: \"postgres://db/app\"
+      DEBUG: \"1\"
+  - container:
+      image: \"node:20\"
+      volumes:
+        - \".:/app\"
+      ports:
+        - \"3000:3000\"
+      workdir: /app
+  - shell: npm run dev
+
+worker:
+  - description: depends on db
+  - env:
+      QUEUE: jobs
+  - container:
+      image: \"node:20\"
+  - shell: \"node worker.js --queue 'jobs and more'\"
+
+db:
+  - compose:
+      service: db
+      file: docker-compose.yml
+
+compose-up:
+  - description: start all services
+  - parallel:
+      - task: db
+      - task: api
+      - task: worker
+",
+        );
+}
+
+#[test]
+fn run_with_env() {
+    run_in("process")
+        .arg("env-vars")
+        .assert()
+        .success()
+        .stderr("")
+        .stdout("> env-vars\n\x1b[0;32menv-vars:\x1b[0m hello 3\nfinished env-vars\n");
+}
+
+#[test]
+fn override_env_from_cli() {
+    run_in("process")
+        .arg("-e")
+        .arg("COUNT=42")
+        .arg("--env")
+        .arg("GREETING=hi=there")
+        .arg("env-vars")
+        .assert()
+        .success()
+        .stderr("")
+        .stdout("> env-vars\n\x1b[0;32menv-vars:\x1b[0m hi=there 42\nfinished env-vars\n");
+}
+
+#[test]
+fn run_procfile_entry() {
+    run_in("procfile")
+        .arg("web")
+        .assert()
+        .success()
+        .stderr("")
+        .stdout(
+            "> web
+\x1b[0;32mweb:\x1b[0m web
+finished web\n",
+        );
+}
+
+#[test]
+fn run_all_procfile_entries() {
+    let output = run_in("procfile").output().unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+
+    assert!(output.status.success());
+    // the processes run in parallel, so the order of their lines isn't fixed
+    assert!(stdout.starts_with("> Procfile\n"));
+    // the padding of web depends on whether worker started before it printed
+    assert!(stdout.contains("\x1b[0;32mweb:\x1b[0m"));
+    assert!(stdout.contains(" web\n"));
+    assert!(stdout.contains("\x1b[0;33mworker:\x1b[0m worker\n"));
+    assert!(stdout.contains("finished Procfile > web\n"));
+    assert!(stdout.contains("finished Procfile > worker\n"));
+    assert!(stdout.ends_with("finished Procfile\n"));
+}
+
+#[test]
+fn export_procfile() {
+    run()
+        .arg("export")
+        .arg("procfile")
+        .assert()
+        .success()
+        .stderr(
+            "Skipping task server, a Procfile can't represent commands spanning multiple lines
+Skipping task straw-task, a Procfile can't represent its task steps
+Skipping task run, a Procfile can't represent its task, parallel steps
+Skipping task raw, a Procfile can't represent commands spanning multiple lines
+Skipping task raw-explicit, a Procfile can't represent its task steps\n",
+        )
+        .stdout(
+            "build: sleep 1; echo build
+client: echo client
+not-raw-explicit: echo not raw\n",
+        );
+}
+
+#[test]
+fn import_npm_scripts() {
+    run_in("import")
+        .arg("import")
+        .arg("npm")
+        .arg("npm/package.json")
+        .assert()
+        .success()
+        .stderr("")
+        .stdout(
+            "build:
+  - description: tsc -p .
+  - shell: pnpm run build
+
+test:
+  - description: jest --coverage
+  - shell: pnpm run test
+
+\"lint:fix\":
+  - description: eslint . --fix
+  - shell: \"pnpm run lint:fix\"
+
+prepare:
+  - description: husky install
+  - shell: pnpm run prepare\n",
+        );
+}
+
+#[test]
+fn import_make_targets() {
+    run_in("import")
+        .arg("import")
+        .arg("make")
+        .arg("make/Makefile")
+        .assert()
+        .success()
+        .stderr("")
+        .stdout(
+            "build:
+  - description: Compile the program
+  - shell: make -f make/Makefile build
+
+test:
+  - description: Run the tests
+  - shell: make -f make/Makefile test
+
+install:
+  - shell: make -f make/Makefile install
+
+clean:
+  - shell: make -f make/Makefile clean
+
+lint:
+  - shell: make -f make/Makefile lint\n",
+        );
+}
+
+#[test]
+fn import_just_recipes() {
+    run_in("import")
+        .arg("import")
+        .arg("just")
+        .arg("just/justfile")
+        .assert()
+        .success()
+        .stderr("")
+        .stdout(
+            "build:
+  - description: Build the project
+  - task: lint
+  - shell: echo building
+  - shell: cargo build
+
+lint:
+  - shell: cargo clippy
+
+deploy:
+  - description: Deploy to an environment
+  - shell: just --justfile just/justfile deploy
+
+version:
+  - description: Print the version
+  - shell: just --justfile just/justfile version\n",
+        );
+}
+
+#[test]
+fn export_systemd_unit() {
+    run_in("export")
+        .arg("export")
+        .arg("systemd")
+        .arg("web")
+        .assert()
+        .success()
+        .stderr("Ignoring the task steps of task web, a systemd unit can't represent them\n")
+        .stdout(format!(
+            "[Unit]
+Description=serve the app
+
+[Service]
+Type=simple
+WorkingDirectory={}/test_data/export
+Environment=\"PORT=8080\"
+ExecStart=/bin/sh -c \"npm ci && echo \\\"serving 100%% on $$PORT\\\"\"
+Restart=on-failure
+Nice=5
+UMask=0027
+MemoryMax=536870912
+CPUQuota=150%
+KillSignal=SIGINT
+TimeoutStopSec=90s
+
+[Install]
+WantedBy=default.target\n",
+            env!("CARGO_MANIFEST_DIR")
+        ));
+}
+
+#[test]
+fn export_launchd_agent() {
+    run_in("export")
+        .arg("export")
+        .arg("launchd")
+        .arg("web")
+        .assert()
+        .success()
+        .stderr(
+            "Ignoring the task steps of task web, a launchd agent can't represent them
+Ignoring the memory and cpu limits of task web, launchd doesn't enforce them
+Ignoring the stop_signal of task web, launchd always stops it with SIGTERM\n",
+        )
+        .stdout(format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" \"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">
+<plist version=\"1.0\">
+<dict>
+\t<key>Label</key>
+\t<string>pilot.web</string>
+\t<key>ProgramArguments</key>
+\t<array>
+\t\t<string>/bin/sh</string>
+\t\t<string>-c</string>
+\t\t<string>npm ci &amp;&amp; echo \"serving 100% on $PORT\"</string>
+\t</array>
+\t<key>WorkingDirectory</key>
+\t<string>{}/test_data/export</string>
+\t<key>EnvironmentVariables</key>
+\t<dict>
+\t\t<key>PORT</key>
+\t\t<string>8080</string>
+\t</dict>
+\t<key>RunAtLoad</key>
+\t<true/>
+\t<key>KeepAlive</key>
+\t<dict>
+\t\t<key>SuccessfulExit</key>
+\t\t<false/>
+\t</dict>
+\t<key>Nice</key>
+\t<integer>5</integer>
+\t<key>Umask</key>
+\t<integer>23</integer>
+\t<key>ExitTimeOut</key>
+\t<integer>90</integer>
+</dict>
+</plist>\n",
+            env!("CARGO_MANIFEST_DIR")
+        ));
+}
+
+#[test]
+fn run_on_remote_host() {
+    run_in("remote").arg("build").assert().success().stderr("").stdout(
+        "> build
+\x1b[0;32mbuild:\x1b[0m -t -p 2222 ci@build-box -- cd '/srv/app' && env 'PROFILE=release' sh -c 'cargo build --profile $PROFILE'
+finished build\n",
+    );
+}
+
+#[test]
+fn run_in_kubernetes_pod() {
+    run_in("k8s").arg("migrate").assert().success().stderr("").stdout(
+        "> migrate
+\x1b[0;32mmigrate:\x1b[0m --context dev --namespace app exec --stdin --tty api-7d9f -- env RAILS_ENV=development sh -c rails db:migrate
+finished migrate\n",
+    );
+}
+
+#[test]
+fn run_without_kubernetes_pod() {
+    run_in("k8s")
+        .arg("missing-pod")
+        .assert()
+        .failure()
+        .stderr("Failed to run task missing-pod: no running pod matches app=worker\n")
+        .stdout(
+            "> missing-pod
+failed missing-pod\n",
+        );
+}
+
+#[test]
+fn assign_ports() {
+    let output = run_in("port")
+        .arg("--port")
+        .arg("5000")
+        .arg("web")
+        .arg("all")
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+
+    assert!(output.status.success());
+    assert!(stdout.starts_with("> web\n\x1b[0;32mweb:\x1b[0m web on 5000\nfinished web\n"));
+    // the branches get their ports in order, even if they print in any order
+    assert!(stdout.contains(" web on 5001\n"));
+    assert!(stdout.contains(" worker on 5002\n"));
+}
+
+#[test]
+fn keep_port_for_shells_of_task() {
+    run_in("port")
+        .arg("--port")
+        .arg("5000")
+        .arg("stack")
+        .assert()
+        .success()
+        .stderr("")
+        .stdout(
+            "> stack
+poll stack: ready after 5 attempts
+\x1b[0;32mstack:\x1b[0m stack on 5000, captured no port
+\x1b[0;32mstack:\x1b[0m still on 5000
+> stack > web
+\x1b[0;32mweb:\x1b[0m   web on 5001
+finished stack > web
+finished stack\n",
+        );
+}
+
+#[test]
+fn refuse_port_in_use() {
+    let _listener = std::net::TcpListener::bind(("127.0.0.1", 47813)).unwrap();
+
+    let output = run_in("ports").arg("server").output().unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "> server\nfailed server\n"
     );
+    // the test binary owns the port
+    assert!(stderr.starts_with("Port 47813 needed by task server is already in use by "));
+    assert!(stderr.contains(&format!("(pid {})", std::process::id())));
+}
+
+#[test]
+fn run_with_free_port() {
+    run_in("ports")
+        .arg("free")
+        .assert()
+        .success()
+        .stderr("")
+        .stdout(
+            "> free
+\x1b[0;32mfree:\x1b[0m serving
+finished free\n",
+        );
+}
+
+#[test]
+fn wait_for_tcp_port() {
+    let _listener = std::net::TcpListener::bind(("127.0.0.1", 47815)).unwrap();
+
+    run_in("wait")
+        .arg("database")
+        .assert()
+        .success()
+        .stderr("")
+        .stdout(
+            "> database
+\x1b[0;32mdatabase:\x1b[0m database is up
+finished database\n",
+        );
+}
+
+#[test]
+fn wait_for_file() {
+    run_in("wait")
+        .arg("config")
+        .assert()
+        .success()
+        .stderr("")
+        .stdout(
+            "> config
+\x1b[0;32mconfig:\x1b[0m config is there
+finished config\n",
+        );
+}
+
+#[test]
+fn wait_for_timeout() {
+    run_in("wait")
+        .arg("missing")
+        .assert()
+        .failure()
+        .stderr("Task missing failed: timed out after 300ms waiting for file does-not-exist\n")
+        .stdout(
+            "> missing
+failed missing\n",
+        );
+}
+
+#[test]
+fn run_with_path_prepend() {
+    run_in("path")
+        .arg("hello")
+        .arg("tools")
+        .assert()
+        .success()
+        .stderr("")
+        .stdout(
+            "> hello
+\x1b[0;32mhello:\x1b[0m hello from bin
+finished hello
+> tools
+\x1b[0;32mtools:\x1b[0m hello from tools
+\x1b[0;32mtools:\x1b[0m tool from tools
+finished tools\n",
+        );
+}
+
+#[test]
+fn expand_variables() {
+    run_in("expand")
+        .arg("greet")
+        .assert()
+        .success()
+        .stderr("")
+        .stdout(
+            "> greet
+\x1b[0;32mgreet:\x1b[0m hello world, nobody else
+\x1b[0;32mgreet:\x1b[0m hi ${NAME}
+finished greet\n",
+        );
+}
+
+#[test]
+#[cfg(unix)]
+fn keep_escaped_references_from_the_shell() {
+    run_in("expand")
+        .arg("literal")
+        .assert()
+        .success()
+        .stderr("")
+        .stdout(
+            "> literal
+\x1b[0;32mliteral:\x1b[0m ${NAME} ${HOME} ${NAME:-none}
+finished literal\n",
+        );
+}
+
+#[test]
+fn expand_variables_without_reading_values_as_shell() {
+    run_in("expand")
+        .arg("secret")
+        .assert()
+        .success()
+        .stderr("")
+        .stdout(
+            "> secret
+\x1b[0;32msecret:\x1b[0m pa$$word \"quoted\" 'single' `tick`; echo injected
+\x1b[0;32msecret:\x1b[0m pa$$word \"quoted\" 'single' `tick`; echo injected a default
+finished secret\n",
+        );
+}
+
+#[test]
+fn run_with_profile() {
+    run_in("profiles")
+        .arg("--profile")
+        .arg("ci")
+        .arg("build")
+        .arg("test")
+        .assert()
+        .success()
+        .stderr("")
+        .stdout(
+            "> build
+\x1b[0;32mbuild:\x1b[0m release build in ci mode
+finished build
+> test
+\x1b[0;32mtest:\x1b[0m  testing in ci mode
+finished test\n",
+        );
+}
+
+#[test]
+fn run_without_profile() {
+    run_in("profiles")
+        .arg("build")
+        .assert()
+        .success()
+        .stderr("")
+        .stdout(
+            "> build
+\x1b[0;32mbuild:\x1b[0m debug build in dev mode
+finished build\n",
+        );
+}
+
+#[test]
+fn run_with_unknown_profile() {
+    run_in("profiles")
+        .arg("--profile")
+        .arg("staging")
+        .arg("build")
+        .assert()
+        .failure()
+        .stderr("Profile staging not found in Pilotfile\n")
+        .stdout("");
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn run_command_for_os() {
+    run_in("os")
+        .arg("open")
+        .arg("unix")
+        .assert()
+        .success()
+        .stderr("")
+        .stdout(
+            "> open
+\x1b[0;32mopen:\x1b[0m xdg-open .
+finished open
+> unix
+\x1b[0;32munix:\x1b[0m unix
+finished unix\n",
+        );
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn run_without_command_for_os() {
+    run_in("os")
+        .arg("windows-only")
+        .assert()
+        .failure()
+        .stderr("Task windows-only has no shell command for linux\n")
+        .stdout("> windows-only\nfailed windows-only\n");
+}
+
+#[test]
+fn run_with_pilotfile_shell() {
+    let assert = run_in("shell")
+        .arg("strict")
+        .assert()
+        .failure()
+        .stdout("> strict\nfailed strict\n");
+    assert_eq!(
+        stderr_of_failure(&assert),
+        "Task strict failed, its diagnostics are in .pilot/failures/<file>\n"
+    );
+}
+
+#[test]
+fn run_with_cli_shell() {
+    run_in("shell")
+        .arg("--shell")
+        .arg("posix")
+        .arg("strict")
+        .assert()
+        .success()
+        .stderr("")
+        .stdout(
+            "> strict
+\x1b[0;32mstrict:\x1b[0m unreachable
+finished strict\n",
+        );
+}
+
+#[test]
+fn list_tasks_of_invalid_pilotfile() {
+    run_in("invalid")
+        .assert()
+        .failure()
+        .stderr(
+            "This is not a valid Pilotfile: step 2 of task build: user needs the name of a user\n",
+        )
+        .stdout("");
+}
+
+#[test]
+fn run_without_color() {
+    run()
+        .arg("--color")
+        .arg("never")
+        .arg("build")
+        .assert()
+        .success()
+        .stderr("")
+        .stdout("> build\n  build stuff\nbuild: build\nfinished build\n");
+}
+
+#[test]
+fn run_without_color_when_piped() {
+    run()
+        .env_remove("CLICOLOR_FORCE")
+        .arg("client")
+        .assert()
+        .success()
+        .stderr("")
+        .stdout("> client\n  server\nclient: client\nfinished client\n");
+}
+
+#[test]
+fn group_output_of_parallel_tasks() {
+    run_in("group_output")
+        .args(["--color", "never", "--group-output", "both"])
+        .assert()
+        .success()
+        .stderr("")
+        .stdout(
+            "> both
+> both > fast
+> both > slow
+fast: fast 1
+fast: fast 2
+finished both > fast
+slow: slow 1
+slow: slow 2
+finished both > slow
+finished both
+",
+        );
+}
+
+#[test]
+fn run_with_success_codes() {
+    run_in("success_codes")
+        .arg("diff")
+        .assert()
+        .success()
+        .stderr("")
+        .stdout("> diff\n\x1b[0;32mdiff:\x1b[0m compared\nfinished diff\n");
+}
+
+#[test]
+fn fail_without_success_code() {
+    let assert = run_in("success_codes")
+        .arg("inverted")
+        .assert()
+        .failure()
+        .stdout("> inverted\nfailed inverted\n");
+    assert_eq!(
+        stderr_of_failure(&assert),
+        "Task inverted failed, its diagnostics are in .pilot/failures/<file>\n"
+    );
+}
+
+#[test]
+fn fail_without_terminal_to_prompt() {
+    // without a terminal there is nobody to ask, so the task just fails
+    let assert = run_in("hooks")
+        .arg("--prompt-on-failure")
+        .arg("tidy")
+        .assert()
+        .failure()
+        .stdout(
+            "> before_all
+\x1b[0;32mbefore_all:\x1b[0m setup
+finished before_all
+> tidy
+\x1b[0;32mtidy:\x1b[0m       cleaned up
+failed tidy
+> after_all
+\x1b[0;32mafter_all:\x1b[0m  teardown
+finished after_all\n",
+        );
+    assert_eq!(
+        stderr_of_failure(&assert),
+        "Task tidy failed, its diagnostics are in .pilot/failures/<file>\n"
+    );
+}
+
+#[test]
+fn capture_output() {
+    run_in("capture")
+        .arg("tag")
+        .assert()
+        .success()
+        .stderr("")
+        .stdout("> tag\n\x1b[0;32mtag:\x1b[0m app:1.2.3 1.2.3\nfinished tag\n");
+}
+
+#[test]
+fn pipe_steps() {
+    run_in("pipe")
+        .arg("shout")
+        .assert()
+        .success()
+        .stderr("")
+        .stdout("> shout\n\x1b[0;34mshout:\x1b[0m HELLO!\nfinished shout\n");
+}
+
+#[test]
+fn pipe_task_into_step() {
+    let output = run_in("pipe")
+        .arg("--color")
+        .arg("never")
+        .arg("count")
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+
+    assert!(output.status.success());
+    // the stderr of the generator is still shown, its stdout goes to the counter
+    assert!(stdout.contains("> count > generate\n"));
+    assert!(stdout.contains("generate: generated\n"));
+    assert!(stdout.contains("count: 3\n"));
+    assert!(!stdout.contains("generate: a\n"));
+}
+
+#[test]
+fn print_step_timings() {
+    let output = run_in("timings")
+        .arg("--timings")
+        .arg("ci")
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let timings: Vec<_> = stdout
+        .split("finished ci\ntimings ci\n")
+        .nth(1)
+        .unwrap()
+        .lines()
+        .collect();
+
+    assert!(output.status.success());
+    // lint has a single step, so it has no breakdown
+    assert!(!stdout.contains("timings ci > lint"));
+    assert_eq!(timings.len(), 2);
+    assert!(timings[0].ends_with("s shell: echo one"));
+    assert!(timings[1].ends_with("s task: lint"));
+    // steps shorter than a second are shown in whole milliseconds
+    assert!(timings[0]
+        .trim_start()
+        .split_once(' ')
+        .unwrap()
+        .0
+        .ends_with("ms"));
+    assert!(!timings[0].contains('.'));
+}
+
+#[test]
+fn write_profile_output() {
+    let trace = std::env::temp_dir().join(format!("pilot-trace-{}.json", std::process::id()));
+
+    run_in("timings")
+        .arg("--profile-output")
+        .arg(&trace)
+        .arg("ci")
+        .assert()
+        .success()
+        .stderr("");
+
+    let trace_json = std::fs::read_to_string(&trace).unwrap();
+    std::fs::remove_file(&trace).unwrap();
+
+    assert!(trace_json.starts_with("[\n{"));
+    assert!(trace_json.contains("{\"name\":\"ci\",\"cat\":\"task\",\"ph\":\"X\","));
+    assert!(trace_json.contains("{\"name\":\"ci > lint\",\"cat\":\"task\",\"ph\":\"X\","));
+    assert!(trace_json.contains("{\"name\":\"shell: echo one\",\"cat\":\"step\",\"ph\":\"X\","));
+}
+
+#[test]
+fn exec_command() {
+    run_in("timings")
+        .args([
+            "exec",
+            "-e",
+            "NAME=pilot",
+            "--",
+            "sh",
+            "-c",
+            "echo hello $NAME",
+        ])
+        .assert()
+        .success()
+        .stderr("")
+        .stdout("> sh\n\x1b[0;32msh:\x1b[0m hello pilot\nfinished sh\n");
+}
+
+#[test]
+fn exec_without_pilotfile() {
+    let assert = run()
+        .current_dir(std::env::temp_dir())
+        .args(["exec", "--", "sh", "-c", "exit 3"])
+        .assert()
+        .code(3)
+        .stdout("> sh\nfailed sh\n");
+    assert_eq!(
+        stderr_of_failure(&assert),
+        "Task sh failed, its diagnostics are in .pilot/failures/<file>\n"
+    );
+}
+
+#[test]
+fn run_task_chain() {
+    run_in("chain")
+        .arg("lint+build")
+        .assert()
+        .success()
+        .stderr("")
+        .stdout(
+            "> lint+build
+> lint+build > lint
+\x1b[2m  check the style\x1b[0m
+\x1b[0;32mlint:\x1b[0m lint
+finished lint+build > lint
+> lint+build > build
+\x1b[0;32mbuild:\x1b[0m build
+finished lint+build > build
+finished lint+build
+summary lint+build
+  finished lint - check the style
+  finished build\n",
+        );
+}
+
+#[test]
+fn stop_task_chain_at_failure() {
+    let assert = run_in("chain")
+        .arg("lint,test,build")
+        .assert()
+        .failure()
+        .stdout(
+            "> lint,test,build
+> lint,test,build > lint
+\x1b[2m  check the style\x1b[0m
+\x1b[0;32mlint:\x1b[0m lint
+finished lint,test,build > lint
+> lint,test,build > test
+failed lint,test,build > test
+failed lint,test,build
+summary lint,test,build
+  finished lint - check the style
+  failed test
+  skipped build\n",
+        );
+    assert_eq!(
+        stderr_of_failure(&assert),
+        "Task test failed, its diagnostics are in .pilot/failures/<file>\n"
+    );
+}
+
+#[test]
+fn run_matching_tasks() {
+    run_in("glob")
+        .arg("test:*")
+        .assert()
+        .success()
+        .stderr("")
+        .stdout(
+            "> test:*
+> test:* > test:unit
+\x1b[0;32mtest:unit:\x1b[0m unit
+finished test:* > test:unit
+> test:* > test:lint
+> test:* > test:lint > pretest:lint
+\x1b[0;32mpretest:lint:\x1b[0m prelint
+finished test:* > test:lint > pretest:lint
+\x1b[0;32mtest:lint:\x1b[0m    lint
+finished test:* > test:lint
+finished test:*\n",
+        );
+}
+
+#[test]
+fn run_matching_tasks_in_parallel() {
+    let output = run_in("glob")
+        .args(["--color", "never", "--parallel", "--match", "test:????"])
+        .assert()
+        .success()
+        .stderr("")
+        .get_output()
+        .stdout
+        .clone();
+    let output = String::from_utf8(output).unwrap();
+
+    // the tasks run at the same time, so the order of their lines isn't fixed
+    assert!(output.starts_with("> test:????\n"));
+    assert!(output.contains("test:unit: unit\n"));
+    assert!(output.contains("pretest:lint: prelint\n"));
+    assert!(output.contains("test:lint: lint\n"));
+    assert!(output.ends_with("finished test:????\n"));
+}
+
+#[test]
+fn fail_without_matching_task() {
+    run_in("glob")
+        .arg("deploy-*")
+        .assert()
+        .failure()
+        .stderr("No task in the Pilotfile matches deploy-*\n")
+        .stdout("");
+}
+
+#[test]
+fn show_only_output_of_task() {
+    run_in("chain")
+        .args(["--only-output", "build", "lint", "build"])
+        .assert()
+        .success()
+        .stderr("")
+        .stdout(
+            "> lint
+\x1b[2m  check the style\x1b[0m
+finished lint
+> build
+\x1b[0;32mbuild:\x1b[0m build
+finished build\n",
+        );
+}
+
+#[test]
+fn timestamp_from_pilotfile() {
+    let output = run_in("timestamp")
+        .args(["--color", "never", "stamped", "plain"])
+        .assert()
+        .success()
+        .stderr("")
+        .get_output()
+        .stdout
+        .clone();
+    let output = String::from_utf8(output).unwrap();
+    let lines: Vec<_> = output.lines().collect();
+
+    // the time changes, so only the shape of the lines is fixed
+    assert_eq!(lines.len(), 6);
+    assert!(lines[0].ends_with(" > stamped"));
+    assert!(lines[1].ends_with(" stamped: stamped"));
+    assert!(lines[2].ends_with(" finished stamped"));
+    assert_eq!(&lines[3..], ["> plain", "plain: plain", "finished plain"]);
+}
+
+#[test]
+fn override_timestamp_of_pilotfile() {
+    run_in("timestamp")
+        .args(["--color", "never", "--no-timestamp", "stamped"])
+        .assert()
+        .success()
+        .stderr("")
+        .stdout("> stamped\nstamped: stamped\nfinished stamped\n");
+}
+
+#[test]
+fn truncate_long_lines() {
+    run_in("max_line_length")
+        .args(["--color", "never", "--max-line-length", "10", "long"])
+        .assert()
+        .success()
+        .stderr("")
+        .stdout("> long\nlong: 0123456789… (6 more bytes)\nfinished long\n");
+}
+
+#[test]
+fn truncate_long_lines_of_task() {
+    run_in("max_line_length")
+        .args(["--color", "never", "short", "long"])
+        .assert()
+        .success()
+        .stderr("")
+        .stdout(
+            "> short
+short: 0123… (12 more bytes)
+finished short
+> long
+long: 0123456789abcdef
+finished long\n",
+        );
+}
+
+#[test]
+fn keep_output_in_history() {
+    run_in("history")
+        .args(["--only-output", "shown", "shown", "hidden"])
+        .assert()
+        .success();
+
+    // quiet tasks are still logged
+    run_in("history")
+        .args(["logs", "last"])
+        .assert()
+        .success()
+        .stderr("")
+        .stdout("shown: shown\nhidden: hidden\n");
+
+    run_in("history")
+        .args(["logs", "last", "hidden"])
+        .assert()
+        .success()
+        .stderr("")
+        .stdout("hidden\n");
+
+    let output = run_in("history")
+        .arg("runs")
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let output = String::from_utf8(output).unwrap();
+    assert!(output
+        .lines()
+        .next()
+        .unwrap()
+        .ends_with("  finished   shown, hidden"));
+}
+
+#[test]
+fn fail_without_run_in_history() {
+    run_in("history")
+        .args(["logs", "19700101-000000"])
+        .assert()
+        .failure()
+        .stderr("No run 19700101-000000 in the history, pilot runs lists them\n")
+        .stdout("");
+}
+
+#[test]
+fn set_task_metadata_variables() {
+    run_in("metadata")
+        .arg("run")
+        .assert()
+        .success()
+        .stderr("")
+        .stdout(
+            "> run
+> run > show
+\x1b[0;32mshow:\x1b[0m show|run > show|0;32
+finished run > show
+finished run\n",
+        );
+}
+
+#[test]
+fn suggest_misspelled_step() {
+    run_in("typo")
+        .assert()
+        .failure()
+        .stderr(
+            "This is not a valid Pilotfile: step 1 of task serve: unknown step descripton, did you mean description?\n",
+        )
+        .stdout("");
+}
+
+#[test]
+fn fail_on_misspelled_setting_when_strict() {
+    run_in("strict").arg("run").assert().success().stderr("");
+
+    run_in("strict")
+        .args(["--strict", "run"])
+        .assert()
+        .failure()
+        .stderr(
+            "This is not a valid Pilotfile: befor_all looks like a misspelled before_all, rename the task if it is one\n",
+        )
+        .stdout("");
+}
+
+#[test]
+fn fail_on_unknown_key_of_step_when_strict() {
+    run_in("strict_file")
+        .assert()
+        .failure()
+        .stderr(
+            "This is not a valid Pilotfile: step 1 of parallel of task serve: wait_for has no timout, did you mean timeout?\n",
+        )
+        .stdout("");
+}
+
+#[test]
+fn run_with_overlay_document() {
+    run_in("overlay")
+        .args(["--color", "never", "--profile", "ci", "build", "test"])
+        .assert()
+        .success()
+        .stderr("")
+        .stdout(
+            "> build
+build: release build
+finished build
+> test
+test: testing
+finished test\n",
+        );
+}
+
+#[test]
+fn run_with_overlay_document_from_env() {
+    run_in("overlay")
+        .env("PILOT_PROFILE", "ci")
+        .args(["--color", "never", "build"])
+        .assert()
+        .success()
+        .stderr("")
+        .stdout("> build\nbuild: release build\nfinished build\n");
+}
+
+#[test]
+fn keep_task_durations_in_history() {
+    run_in("durations").args(["run", "fail"]).assert().failure();
+
+    let runs =
+        std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("test_data/durations/.pilot/runs");
+    let last_run = std::fs::read_dir(&runs)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .max()
+        .unwrap();
+    let run = std::fs::read_to_string(last_run.join("run.json")).unwrap();
+
+    // nested tasks get a duration as well, but only if they succeeded
+    assert!(run.contains("\n    \"fast\": "));
+    assert!(run.contains("\n    \"run\": "));
+    assert!(!run.contains("\n    \"fail\": "));
+}
+
+#[test]
+fn run_task_with_env_of_reference() {
+    run_in("task_env")
+        .args(["--color", "never", "run"])
+        .assert()
+        .success()
+        .stderr("")
+        .stdout(
+            "> run
+> run > server
+server: listening on 9090
+finished run > server
+> run > server
+server: listening on 8080
+finished run > server
+finished run\n",
+        );
+}
+
+#[test]
+fn forward_args_to_task() {
+    run_in("args")
+        .args(["--color", "never", "release", "build"])
+        .assert()
+        .success()
+        .stderr("")
+        .stdout(
+            "> release
+> release > build
+build: building --release --locked
+finished release > build
+finished release
+> build
+build: building
+finished build\n",
+        );
+}
+
+#[test]
+fn stop_background_step_with_task() {
+    let start = std::time::Instant::now();
+
+    run_in("background")
+        .args(["--color", "never", "test", "test_task"])
+        .assert()
+        .success()
+        .stderr("")
+        .stdout(
+            "> test
+test: server started
+test: tests passed
+finished test
+> test_task
+> test_task > server
+server: serving
+test_task: tests passed
+finished test_task > server
+finished test_task\n",
+        );
+
+    // the servers would sleep for 10 seconds
+    assert!(start.elapsed().as_secs() < 5);
+}
+
+#[test]
+fn fail_on_failed_background_step() {
+    let assert = run_in("background")
+        .args(["--color", "never", "crash"])
+        .assert()
+        .failure()
+        .stdout("> crash\ncrash: tests passed\nfailed crash\n");
+    assert_eq!(
+        stderr_of_failure(&assert),
+        "Task crash failed, its diagnostics are in .pilot/failures/<file>\n"
+    );
+}
+
+#[test]
+fn poll_until_ready() {
+    let output = run_in("poll")
+        .args(["--color", "never", "ready"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+
+    assert!(output.status.success());
+    assert!(stdout.contains("poll ready: ready after "));
+    assert!(stdout.contains("ready: tested\n"));
+    // the output of the attempts only ends up in the history
+    assert!(!stdout.contains("found"));
+}
+
+#[test]
+fn fail_on_poll_timeout() {
+    let output = run_in("poll")
+        .args(["--color", "never", "timeout"])
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "> timeout\nfailed timeout\n"
+    );
+    assert!(stderr.starts_with("Task timeout failed: `echo not yet; exit 1` still failed after "));
+    assert!(stderr.ends_with(" attempts in 300ms, pilot logs last timeout shows their output\n"));
+
+    let logs = run_in("poll")
+        .args(["logs", "last", "timeout"])
+        .output()
+        .unwrap();
+    assert!(String::from_utf8(logs.stdout)
+        .unwrap()
+        .starts_with("not yet\nnot yet\n"));
+}
+
+#[test]
+#[cfg(unix)]
+fn stop_polling_when_interrupted() {
+    let mut run = std::process::Command::new(assert_cmd::cargo::cargo_bin(env!("CARGO_PKG_NAME")))
+        .current_dir(env!("CARGO_MANIFEST_DIR").to_string() + "/test_data/poll")
+        .env(
+            "XDG_CONFIG_HOME",
+            env!("CARGO_MANIFEST_DIR").to_string() + "/test_data/user_config/none",
+        )
+        .args(["--color", "never", "slow"])
+        .stdout(std::process::Stdio::null())
+        .spawn()
+        .unwrap();
+
+    // the first attempt failed, the next one is 30 seconds away
+    std::thread::sleep(std::time::Duration::from_secs(1));
+    let interrupted = std::time::Instant::now();
+    std::process::Command::new("kill")
+        .args(["-INT", &run.id().to_string()])
+        .status()
+        .unwrap();
+
+    let status = run.wait().unwrap();
+    assert_eq!(status.code(), Some(130));
+    assert!(interrupted.elapsed() < std::time::Duration::from_secs(5));
+}
+
+#[test]
+fn drop_text_before_escape_sequences() {
+    run_in("output")
+        .args(["--color", "never", "progress"])
+        .assert()
+        .success()
+        .stderr("")
+        .stdout("> progress\nprogress: \rloaded red\nfinished progress\n");
+}
+
+#[test]
+fn cut_huge_lines_while_reading() {
+    let output = run_in("output")
+        .args(["--color", "never", "huge_line"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+
+    assert!(output.status.success());
+    assert!(stdout.starts_with("> huge_line\nhuge_line: aaaa"));
+    assert!(stdout.ends_with("a… (34464 more bytes)\nhuge_line: next\nfinished huge_line\n"));
+    assert_eq!(stdout.matches('a').count(), 65536);
+}
+
+#[test]
+fn bench_task_against_baseline() {
+    // start without the baseline of an earlier test run
+    let _ = std::fs::remove_dir_all("test_data/bench/.pilot/bench");
+
+    let output = run_in("bench")
+        .args(["bench", "build", "--runs", "3", "--save-baseline"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let labels: Vec<_> = stdout
+        .lines()
+        .map(|line| {
+            line.trim_start()
+                .split_once(' ')
+                .map_or(line, |(_, label)| label)
+        })
+        .collect();
+
+    assert!(output.status.success());
+    assert_eq!(
+        labels,
+        [
+            "build",
+            "run 1",
+            "run 2",
+            "run 3",
+            "of 3 runs",
+            "min",
+            "median",
+            "max",
+            "stddev",
+            "the runs as the baseline of build"
+        ]
+    );
+
+    let output = run_in("bench")
+        .args(["bench", "build", "--runs", "1", "--max-regression", "1000%"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout)
+        .unwrap()
+        .contains(" baseline median, "));
+
+    // any run is slower than -100%
+    let output = run_in("bench")
+        .args(["bench", "build", "--runs", "1", "--max-regression", "-100"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .starts_with("Task build got "));
+}
+
+#[test]
+fn fail_bench_of_failing_task() {
+    let assert = run_in("bench")
+        .args(["bench", "fail"])
+        .assert()
+        .failure()
+        .stdout("bench fail\n");
+    assert_eq!(
+        stderr_of_failure(&assert),
+        "Task fail failed, its diagnostics are in .pilot/failures/<file>\nTask fail failed in run 1, pilot fail shows its output\n"
+    );
+}
+
+#[test]
+fn restore_outputs_from_cache() {
+    let dir = std::env::temp_dir().join(format!("pilot-cache-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::copy("test_data/cache/Pilotfile.yaml", dir.join("Pilotfile.yaml")).unwrap();
+    std::fs::write(dir.join("input.txt"), "first").unwrap();
+
+    let build = |stdout: &str| {
+        run()
+            .current_dir(&dir)
+            .args(["--color", "never", "build"])
+            .assert()
+            .success()
+            .stderr("")
+            .stdout(stdout.to_string());
+    };
+    let result = || std::fs::read_to_string(dir.join("out/result.txt")).unwrap();
+    let shared_entries = || std::fs::read_dir(dir.join("shared")).unwrap().count();
+
+    build("> build\nbuild: building\nfinished build\n");
+    std::fs::remove_dir_all(dir.join("out")).unwrap();
+    build("> build\nrestored build\n");
+    assert_eq!(result(), "first");
+
+    // another machine only has the shared cache
+    std::fs::remove_dir_all(dir.join("out")).unwrap();
+    std::fs::remove_dir_all(dir.join(".pilot/cache")).unwrap();
+    build("> build\nrestored build\n");
+    assert_eq!(result(), "first");
+
+    std::fs::write(dir.join("input.txt"), "second").unwrap();
+    build("> build\nbuild: building\nfinished build\n");
+    assert_eq!(result(), "second");
+    assert_eq!(shared_entries(), 2);
+
+    // a read-only machine only restores from the shared cache
+    let pilotfile = std::fs::read_to_string(dir.join("Pilotfile.yaml")).unwrap();
+    std::fs::write(
+        dir.join("Pilotfile.yaml"),
+        pilotfile.replace("{dir: shared}", "{dir: shared, mode: read_only}"),
+    )
+    .unwrap();
+    std::fs::write(dir.join("input.txt"), "third").unwrap();
+    build("> build\nbuild: building\nfinished build\n");
+    assert_eq!(shared_entries(), 2);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn fmt_check_unformatted_pilotfile() {
+    run_in("fmt")
+        .args(["fmt", "--check"])
+        .assert()
+        .code(1)
+        .stderr("Pilotfile.yaml is not formatted, pilot fmt formats it\n")
+        .stdout("");
+}
+
+#[test]
+fn fmt_pilotfile() {
+    let dir = std::env::temp_dir().join(format!("pilot-fmt-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::copy("test_data/fmt/Pilotfile.yaml", dir.join("Pilotfile.yaml")).unwrap();
+
+    run()
+        .current_dir(&dir)
+        .arg("fmt")
+        .assert()
+        .success()
+        .stderr("")
+        .stdout("");
+    run()
+        .current_dir(&dir)
+        .args(["fmt", "--check"])
+        .assert()
+        .success();
+
+    let formatted = std::fs::read_to_string(dir.join("Pilotfile.yaml")).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(
+        formatted,
+        "path_prepend: [bin, tools]
+
+build:
+  - description: build it
+  - shell: cargo build
+    timeout: 5m
+
+test:
+  - parallel:
+    - shell: cargo test
+    - task: build
+  - shell: |
+      echo one
+      echo two
+  - env:
+      RUST_LOG: debug
+  - on_failure:
+    - shell: echo \"failed\"
+"
+    );
+}
+
+#[test]
+fn fmt_keeps_comments() {
+    run_in("path")
+        .args(["fmt", "--check"])
+        .assert()
+        .success()
+        .stderr("")
+        .stdout("");
+}
+
+#[test]
+fn run_task_with_args() {
+    run_in("task_args")
+        .args([
+            "--color", "never", "deploy", "env=prod", "tag=v1.2", "deploy", "env=dev",
+        ])
+        .assert()
+        .success()
+        .stderr("")
+        .stdout(
+            "> deploy
+deploy: deploying v1.2 to prod
+finished deploy
+> deploy
+deploy: deploying latest to dev
+finished deploy
+",
+        );
+}
+
+#[test]
+fn pass_args_in_task_step() {
+    run_in("task_args")
+        .args(["--color", "never", "release"])
+        .assert()
+        .success()
+        .stderr("")
+        .stdout(
+            "> release
+> release > deploy
+deploy: deploying v2 to prod
+finished release > deploy
+finished release
+",
+        );
+}
+
+#[test]
+fn fail_on_invalid_args() {
+    run_in("task_args")
+        .arg("deploy")
+        .assert()
+        .failure()
+        .stderr(
+            "Task deploy needs the argument env\nUsage: pilot deploy env=dev|prod [tag=latest]\n",
+        )
+        .stdout("");
+    run_in("task_args")
+        .args(["deploy", "env=staging"])
+        .assert()
+        .failure()
+        .stderr(
+            "env of task deploy can't be staging, only dev or prod\nUsage: pilot deploy env=dev|prod [tag=latest]\n",
+        )
+        .stdout("");
+}
+
+/// Starts pilot in test_data/hangup and hangs up its terminal after it started the task.
+#[cfg(unix)]
+fn hang_up(args: &[&str]) -> std::process::Output {
+    let child = std::process::Command::new(assert_cmd::cargo::cargo_bin(env!("CARGO_PKG_NAME")))
+        .current_dir(env!("CARGO_MANIFEST_DIR").to_string() + "/test_data/hangup")
+        .args(args)
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    std::thread::sleep(std::time::Duration::from_millis(500));
+    std::process::Command::new("kill")
+        .args(["-HUP", &child.id().to_string()])
+        .status()
+        .unwrap();
+
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+#[cfg(unix)]
+fn stop_tasks_on_hangup() {
+    let started = std::time::Instant::now();
+    let output = hang_up(&["--color", "never", "serve"]);
+
+    assert_eq!(output.status.code(), Some(130));
+    assert!(started.elapsed() < std::time::Duration::from_secs(5));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "> serve\n");
+}
+
+#[test]
+#[cfg(unix)]
+fn detach_on_hangup() {
+    let output = hang_up(&["--color", "never", "--detach-on-hup", "deploy"]);
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "> deploy\n");
+
+    let runs =
+        std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("test_data/hangup/.pilot/runs");
+    let detached = std::fs::read_dir(&runs)
+        .unwrap()
+        .filter_map(|run| std::fs::read_to_string(run.unwrap().path().join("detached.log")).ok())
+        .collect::<Vec<_>>();
+
+    assert!(detached.contains(&"deploy: deployed\nfinished deploy\n".to_string()));
+}
+
+#[test]
+fn drop_output_with_null_sink() {
+    run_in("sinks")
+        .args(["--output", "null", "build"])
+        .assert()
+        .success()
+        .stderr("")
+        .stdout("> build\nfinished build\n");
+}
+
+#[test]
+fn write_output_to_json_sink() {
+    let json = std::env::temp_dir().join(format!("pilot-output-{}.json", std::process::id()));
+
+    run_in("sinks")
+        .args(["--color", "never", "--output", "console", "--output"])
+        .arg(format!("json:{}", json.display()))
+        .arg("build")
+        .assert()
+        .success()
+        .stderr("")
+        .stdout("> build\nbuild: building\nfinished build\n");
+
+    let lines = std::fs::read_to_string(&json).unwrap();
+    std::fs::remove_file(&json).unwrap();
+
+    assert_eq!(lines.lines().count(), 1);
+    assert!(lines.contains("\"task\": \"build\", \"path\": \"build\", \"line\": \"building\"}"));
+}
+
+#[test]
+fn write_output_of_task_to_file_sink() {
+    let log =
+        std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("test_data/sinks/.pilot/logged.log");
+    std::fs::create_dir_all(log.parent().unwrap()).unwrap();
+    let _ = std::fs::remove_file(&log);
+
+    run_in("sinks")
+        .args(["--color", "never", "logged"])
+        .assert()
+        .success()
+        .stderr("")
+        .stdout("> logged\nlogged: logged\nfinished logged\n");
+
+    assert_eq!(std::fs::read_to_string(&log).unwrap(), "logged: logged\n");
+}
+
+#[test]
+fn run_task_from_step() {
+    run_in("step_ids")
+        .args(["--color", "never", "deploy", "--from-step", "push-image"])
+        .assert()
+        .success()
+        .stderr("")
+        .stdout(
+            "> deploy
+deploy: pushing app:1.0
+deploy: rolling out app:1.0
+finished deploy
+",
+        );
+}
+
+#[test]
+fn run_task_until_step() {
+    run_in("step_ids")
+        .args(["--color", "never", "deploy", "--until-step", "build-image"])
+        .assert()
+        .success()
+        .stderr("")
+        .stdout(
+            "> deploy
+> deploy > predeploy
+predeploy: checking
+finished deploy > predeploy
+deploy: building app:1.0
+finished deploy
+",
+        );
+}
+
+#[test]
+fn fail_on_unknown_step_id() {
+    run_in("step_ids")
+        .args(["deploy", "--from-step", "push"])
+        .assert()
+        .failure()
+        .stderr("Task deploy has no step with the id push\n")
+        .stdout("");
+}
+
+fn run_with_user_config(dir: &str, config: &str) -> Command {
+    let mut command = run_in(dir);
+    command.env(
+        "XDG_CONFIG_HOME",
+        env!("CARGO_MANIFEST_DIR").to_string() + "/test_data/user_config/" + config,
+    );
+    command
+}
+
+#[test]
+fn run_with_user_config_defaults() {
+    let log_dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("test_data/user_config/config/pilot/.pilot/logs");
+    let _ = std::fs::remove_dir_all(&log_dir);
+
+    run_with_user_config("user_config", "config")
+        .arg("-t")
+        .arg("build")
+        .assert()
+        .success()
+        .stderr("")
+        .stdout(
+            "[%] > build
+[%] \x1b[0;36mbuild:\x1b[0m user
+[%] finished build\n",
+        );
+
+    assert_eq!(std::fs::read_dir(log_dir).unwrap().count(), 1);
+}
+
+#[test]
+fn pilotfile_overrides_user_config() {
+    let assert = run_with_user_config("shell", "config")
+        .arg("strict")
+        .assert()
+        .failure()
+        .stdout("> strict\nfailed strict\n");
+    assert_eq!(
+        stderr_of_failure(&assert),
+        "Task strict failed, its diagnostics are in .pilot/failures/<file>\n"
+    );
+}
+
+#[test]
+fn cli_overrides_user_config() {
+    run_with_user_config("user_config", "config")
+        .arg("--shell")
+        .arg("posix")
+        .arg("--color")
+        .arg("never")
+        .arg("build")
+        .assert()
+        .success()
+        .stderr("")
+        .stdout("> build\nbuild: \nfinished build\n");
+}
+
+#[test]
+fn fail_on_invalid_user_config() {
+    run_with_user_config("user_config", "broken")
+        .arg("build")
+        .assert()
+        .failure()
+        .stdout("")
+        .stderr(
+            env!("CARGO_MANIFEST_DIR").to_string()
+                + "/test_data/user_config/broken/pilot/config.yaml: \"colours\" is no setting, use one of color, colors, prefix_style, timestamp_format, shell, log_dir, editor\n",
+        );
+}
+
+#[test]
+fn edit_pilotfile_at_task() {
+    run_in("path")
+        .env("EDITOR", "echo")
+        .env_remove("VISUAL")
+        .arg("edit")
+        .arg("tools")
+        .assert()
+        .success()
+        .stderr("")
+        .stdout(
+            "+6 ".to_string() + env!("CARGO_MANIFEST_DIR") + "/test_data/path/Pilotfile.yaml\n",
+        );
+}
+
+#[test]
+fn edit_pilotfile_with_editor_of_user_config() {
+    run_with_user_config("path", "editor")
+        .env("EDITOR", "false")
+        .arg("edit")
+        .assert()
+        .success()
+        .stderr("")
+        .stdout(
+            "--wait ".to_string() + env!("CARGO_MANIFEST_DIR") + "/test_data/path/Pilotfile.yaml\n",
+        );
+}
+
+#[test]
+fn edit_unknown_task() {
+    run_in("path")
+        .env("EDITOR", "echo")
+        .arg("edit")
+        .arg("missing")
+        .assert()
+        .failure()
+        .stdout("")
+        .stderr("Task missing not found in Pilotfile\n");
+}
+
+#[test]
+fn fail_on_missing_required_env() {
+    run_in("requires_env")
+        .env_remove("DATABASE_URL")
+        .env("AWS_PROFILE", "")
+        .arg("deploy")
+        .assert()
+        .failure()
+        .stderr(
+            "Task deploy needs environment variables that are not set: DATABASE_URL, AWS_PROFILE\n",
+        )
+        .stdout("> deploy\nfailed deploy\n");
+}
+
+#[test]
+fn run_with_required_env() {
+    run_in("requires_env")
+        .env("AWS_PROFILE", "dev")
+        .arg("-e")
+        .arg("DATABASE_URL=sqlite://")
+        .arg("deploy")
+        .assert()
+        .success()
+        .stderr("")
+        .stdout(
+            "> deploy
+\x1b[0;32mdeploy:\x1b[0m sqlite:// dev
+finished deploy\n",
+        );
+}
+
+#[test]
+fn run_with_required_env_of_task_steps() {
+    run_in("requires_env")
+        .env_remove("DATABASE_URL")
+        .env_remove("AWS_PROFILE")
+        .arg("release")
+        .assert()
+        .success()
+        .stderr("")
+        .stdout(
+            "> release
+> release > deploy
+\x1b[0;32mdeploy:\x1b[0m postgres://db release
+finished release > deploy
+finished release\n",
+        );
+}
+
+#[test]
+fn run_with_inline_stdin() {
+    run_in("stdin")
+        .arg("query")
+        .assert()
+        .success()
+        .stderr("")
+        .stdout(
+            "> query
+\x1b[0;32mquery:\x1b[0m select 1;
+\x1b[0;32mquery:\x1b[0m select 2;
+finished query\n",
+        );
+}
+
+#[test]
+fn run_with_stdin_from_file() {
+    run_in("stdin")
+        .arg("from-file")
+        .assert()
+        .success()
+        .stderr("")
+        .stdout(
+            "> from-file
+\x1b[0;32mfrom-file:\x1b[0m FIRST LINE
+\x1b[0;32mfrom-file:\x1b[0m SECOND LINE
+\x1b[0;32mfrom-file:\x1b[0m 2 lines
+finished from-file\n",
+        );
+}
+
+#[test]
+fn fail_on_missing_stdin_file() {
+    run_in("stdin")
+        .arg("missing-file")
+        .assert()
+        .failure()
+        .stderr(
+            "Failed to run task missing-file: could not read its stdin ".to_string()
+                + env!("CARGO_MANIFEST_DIR")
+                + "/test_data/stdin/missing.txt: No such file or directory (os error 2)\n",
+        )
+        .stdout("> missing-file\nfailed missing-file\n");
+}
+
+#[test]
+fn run_with_bracket_prefixes() {
+    run_in("stdin")
+        .arg("--prefix-style")
+        .arg("brackets")
+        .arg("query")
+        .assert()
+        .success()
+        .stderr("")
+        .stdout("> query\n[query] select 1;\n[query] select 2;\nfinished query\n");
+}
+
+#[test]
+fn run_with_plain_prefixes() {
+    // the description is dimmed with colors
+    run()
+        .arg("--prefix-style")
+        .arg("plain")
+        .arg("build")
+        .assert()
+        .success()
+        .stderr("")
+        .stdout("> build\n  build stuff\nbuild: build\nfinished build\n");
+}
+
+#[test]
+fn run_tasks_shuffled() {
+    run_in("shuffle")
+        .arg("--color")
+        .arg("never")
+        .arg("--shuffle")
+        .arg("7")
+        .arg("test:a")
+        .arg("test:b")
+        .arg("test:c")
+        .arg("test:d")
+        .assert()
+        .success()
+        .stderr("")
+        .stdout(
+            "shuffled with seed 7, --shuffle 7 runs the tasks in this order again
+> test:d
+test:d: d
+finished test:d
+> test:b
+test:b: b
+finished test:b
+> test:a
+test:a: a
+finished test:a
+> test:c
+test:c: c
+finished test:c\n",
+        );
+}
+
+#[test]
+fn run_matching_tasks_shuffled() {
+    run_in("shuffle")
+        .arg("--color")
+        .arg("never")
+        .arg("--shuffle")
+        .arg("7")
+        .arg("test:*")
+        .assert()
+        .success()
+        .stderr("")
+        .stdout(
+            "shuffled with seed 7, --shuffle 7 runs the tasks in this order again
+> test:*
+> test:* > test:a
+test:a: a
+finished test:* > test:a
+> test:* > test:d
+test:d: d
+finished test:* > test:d
+> test:* > test:c
+test:c: c
+finished test:* > test:c
+> test:* > test:b
+test:b: b
+finished test:* > test:b
+finished test:*\n",
+        );
+}
+
+#[test]
+fn run_shuffled_with_new_seed() {
+    let output = run_in("shuffle")
+        .arg("--shuffle")
+        .arg("test:a")
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let seed = stdout
+        .strip_prefix("shuffled with seed ")
+        .and_then(|rest| rest.split(',').next())
+        .unwrap();
+
+    assert!(seed.parse::<u64>().is_ok());
+}
+
+#[test]
+fn run_with_umask() {
+    run_in("umask")
+        .arg("artifact")
+        .arg("shared")
+        .assert()
+        .success()
+        .stderr("")
+        .stdout(
+            "> artifact
+\x1b[0;32martifact:\x1b[0m 0027
+finished artifact
+> shared
+\x1b[0;32mshared:\x1b[0m   0002
+finished shared\n",
+        );
+}
+
+#[test]
+#[cfg(unix)]
+fn run_in_own_session() {
+    run_in("umask")
+        .arg("session")
+        .assert()
+        .success()
+        .stderr("")
+        .stdout("> session\nown session\nfinished session\n");
+}
+
+#[test]
+fn stop_idle_task() {
+    let assert = run_in("idle_timeout")
+        .arg("hang")
+        .timeout(std::time::Duration::from_secs(3))
+        .assert()
+        .failure()
+        .stdout("> hang\n\x1b[0;32mhang:\x1b[0m start\nfailed hang\n");
+    assert_eq!(
+        stderr_of_failure(&assert),
+        "Task hang printed nothing for 500ms, stopping it\nTask hang failed, its diagnostics are in .pilot/failures/<file>\n"
+    );
+}
+
+#[test]
+fn keep_busy_tasks_running() {
+    run_in("idle_timeout")
+        .arg("--color")
+        .arg("never")
+        .arg("busy")
+        .arg("progress")
+        .assert()
+        .success()
+        .stderr("")
+        .stdout(
+            "> busy
+busy: 1
+busy: 2
+busy: 3
+busy: 4
+finished busy
+> progress
+progress: ....
+finished progress\n",
+        );
+}
+
+#[test]
+fn save_diagnostics_of_failure() {
+    let failures =
+        std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("test_data/failures/.pilot/failures");
+    let _ = std::fs::remove_dir_all(&failures);
+
+    let assert = run_in("failures")
+        .args(["--color", "never", "deploy"])
+        .assert()
+        .failure()
+        .stdout("> deploy\ndeploy: connecting to eu\ndeploy: no access\nfailed deploy\n");
+    assert_eq!(
+        stderr_of_failure(&assert),
+        "Task deploy failed, its diagnostics are in .pilot/failures/<file>\n"
+    );
+
+    let files: Vec<_> = std::fs::read_dir(&failures).unwrap().collect();
+    assert_eq!(files.len(), 1);
+
+    let diagnostics = std::fs::read_to_string(files[0].as_ref().unwrap().path()).unwrap();
+    assert!(diagnostics.starts_with(
+        "task: deploy
+command: echo connecting to $REGION; echo no access; exit 3
+exit code: 3\n"
+    ));
+    assert!(diagnostics.contains("last 2 lines of output:\n  connecting to eu\n  no access\n"));
+    assert!(diagnostics.contains("\n  REGION=eu\n"));
+    // secrets are masked
+    assert!(diagnostics.contains("\n  API_TOKEN=***\n"));
+    assert!(diagnostics.contains("\n  DATABASE_URL=postgres://admin:***@db/app\n"));
+    assert!(!diagnostics.contains("hunter2"));
+}
+
+#[test]
+fn run_windows_paths_in_posix_shell() {
+    run_in("portable")
+        .args(["--color", "never", "hello"])
+        .assert()
+        .success()
+        .stderr("")
+        .stdout("> hello\nhello: hello from scripts\nfinished hello\n");
+}
+
+#[test]
+fn translate_commands_for_powershell() {
+    // the fake pwsh prints the command it gets
+    run_in("portable")
+        .args(["--color", "never", "--shell", "bin/pwsh -Command", "chain"])
+        .assert()
+        .success()
+        .stderr("")
+        .stdout(
+            "> chain
+chain: echo \"say `\"hi`\" to $env:NAME\"; $pilot_succeeded = $?; \
+if ($pilot_succeeded) { ./deploy; $pilot_succeeded = $? }; \
+if (-not $pilot_succeeded) { echo '$NAME failed'; $pilot_succeeded = $? }; \
+cd ..; $pilot_succeeded = $?; if ($pilot_succeeded) { ls; $pilot_succeeded = $? }; \
+if (-not $pilot_succeeded) { exit 1 }
+finished chain\n",
+        );
+}
+
+#[test]
+fn tee_output_to_command() {
+    let log = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("test_data/tee_to/.pilot/requests.log");
+    let _ = std::fs::remove_file(&log);
+
+    run_in("tee_to")
+        .args(["--color", "never", "log"])
+        .assert()
+        .success()
+        .stderr("")
+        .stdout("> log\nlog: healthcheck ok\nlog: request served\nfinished log\n");
+
+    assert_eq!(std::fs::read_to_string(log).unwrap(), "request served\n");
+}
+
+#[test]
+fn replace_output_with_tee_command() {
+    run_in("tee_to")
+        .args(["--color", "never", "filter"])
+        .assert()
+        .success()
+        .stderr("")
+        .stdout("> filter\nfilter: request served\nfinished filter\n");
+}
+
+#[test]
+fn delay_parallel_branch() {
+    let start = std::time::Instant::now();
+
+    run_in("delay")
+        .args(["--color", "never", "staggered"])
+        .assert()
+        .success()
+        .stderr("")
+        .stdout("> staggered\nstaggered: license server\nstaggered: app\nfinished staggered\n");
+
+    assert!(start.elapsed() >= std::time::Duration::from_millis(500));
+}
+
+#[test]
+fn fail_on_empty_pilotfile() {
+    run_in("empty").assert().failure().stdout("").stderr(
+        "Pilotfile.yaml is empty, add some tasks like
+
+    build:
+      - shell: cargo build
+    test:
+      - task: build
+      - shell: cargo test
+",
+    );
+}
+
+#[test]
+fn fail_on_pilotfile_without_map() {
+    run_in("top_level_list")
+        .assert()
+        .failure()
+        .stdout("")
+        .stderr(
+        "This is not a valid Pilotfile: the top level is a list, but it has to map task names to \
+their steps like
+
+    build:
+      - shell: cargo build
+    test:
+      - task: build
+      - shell: cargo test
+",
+    );
+}
+
+#[test]
+fn show_labels_of_parallel_branches() {
+    run_in("labels")
+        .arg("run")
+        .assert()
+        .success()
+        .stderr("")
+        .stdout(
+            "> run
+> run/infra > server
+\x1b[0;32mrun/infra > server:\x1b[0m serving
+finished run/infra > server
+> run/app > server
+\x1b[0;33mrun/app > server:\x1b[0m   serving
+finished run/app > server
+\x1b[0;34mrun/worker:\x1b[0m         working
+finished run\n",
+        );
+}
+
+#[test]
+fn run_task_extending_others() {
+    // the env of worker wins over the one of api and service, even in the steps of service
+    run_in("extends")
+        .args(["--color", "never", "worker"])
+        .assert()
+        .success()
+        .stderr("")
+        .stdout(
+            "> worker
+worker: starting worker in eu at debug
+worker: api ready
+worker: worker ready
+finished worker\n",
+        );
+}
+
+#[test]
+fn annotate_failed_task_on_github() {
+    let assert = run_in("annotations")
+        .env("GITHUB_ACTIONS", "true")
+        .env("GITHUB_WORKSPACE", env!("CARGO_MANIFEST_DIR"))
+        .args(["--color", "never", "lint", "test"])
+        .assert()
+        .failure()
+        .stdout(
+            "> lint
+lint: linted
+finished lint
+> test
+failed test
+::error file=test_data/annotations/Pilotfile.yaml,line=3,title=Failed task::Task test failed with exit code 2\n",
+        );
+    assert_eq!(
+        stderr_of_failure(&assert),
+        "Task test failed, its diagnostics are in .pilot/failures/<file>\n"
+    );
+}
+
+#[test]
+fn annotate_invalid_pilotfile_on_github() {
+    run_in("invalid")
+        .env("GITHUB_ACTIONS", "true")
+        .env("GITHUB_WORKSPACE", env!("CARGO_MANIFEST_DIR"))
+        .arg("build")
+        .assert()
+        .failure()
+        .stdout(
+            "::error file=test_data/invalid/Pilotfile.yaml,line=1,title=Invalid Pilotfile::This is not a valid Pilotfile: step 2 of task build: user needs the name of a user\n",
+        )
+        .stderr(
+            "This is not a valid Pilotfile: step 2 of task build: user needs the name of a user\n",
+        );
+}
+
+#[test]
+fn report_failed_task_to_gitlab() {
+    let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("test_data/annotations");
+    let report = dir.join("gl-code-quality-report.json");
+    let _ = std::fs::remove_file(&report);
+
+    for _ in 0..2 {
+        run_in("annotations")
+            .env("GITLAB_CI", "true")
+            .env("CI_PROJECT_DIR", &dir)
+            .args(["--color", "never", "test"])
+            .assert()
+            .failure()
+            .stdout("> test\nfailed test\n");
+    }
+
+    let report = std::fs::read_to_string(report).unwrap();
+    let issues: Vec<_> = report.lines().collect();
+    assert_eq!(issues.len(), 4);
+    assert_eq!((issues[0], issues[3]), ("[", "]"));
+    assert_eq!(issues[1], issues[2].trim_end_matches(',').to_string() + ",");
+
+    let issue = issues[2];
+    assert!(issue.starts_with("{\"description\": \"Task test failed with exit code 2\", \"check_name\": \"pilot\", \"fingerprint\": \""));
+    assert!(issue.ends_with(
+        "\"severity\": \"major\", \"location\": {\"path\": \"Pilotfile.yaml\", \"lines\": {\"begin\": 3}}}"
+    ));
+}
+
+#[test]
+fn run_file_steps() {
+    let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("test_data/file_steps");
+    let out = dir.join("out");
+    // the step removes an old out directory itself
+    std::fs::create_dir_all(out.join("old")).unwrap();
+
+    run_in("file_steps")
+        .arg("build")
+        .assert()
+        .success()
+        .stderr("")
+        .stdout("> build\nfinished build\n");
+
+    assert!(!out.join("old").exists());
+    assert_eq!(std::fs::read_dir(out.join("logs")).unwrap().count(), 1);
+    assert_eq!(
+        std::fs::read_to_string(out.join("logs/build.log")).unwrap(),
+        ""
+    );
+    assert_eq!(
+        std::fs::read_to_string(out.join("src/app.js")).unwrap(),
+        "start()\n"
+    );
+    assert_eq!(
+        std::fs::read_to_string(out.join("assets/start.html")).unwrap(),
+        std::fs::read_to_string(dir.join("src/index.html")).unwrap()
+    );
+}
+
+#[test]
+fn fail_file_steps() {
+    run_in("file_steps")
+        .arg("missing")
+        .assert()
+        .failure()
+        .stderr("Task missing failed: could not copy nowhere.txt to out/: No such file or directory (os error 2)\n")
+        .stdout("> missing\nfailed missing\n");
+
+    run_in("file_steps")
+        .arg("empty")
+        .assert()
+        .failure()
+        .stderr("Task empty failed: could not remove ${OUT}: the path is empty\n")
+        .stdout("> empty\nfailed empty\n");
+}
+
+#[test]
+fn run_hooks_on_events() {
+    let assert = run_in("events")
+        .args(["--color", "never", "build", "lint"])
+        .assert()
+        .failure()
+        .stdout(
+            "started build lint
+> build
+build: built
+finished build
+> lint
+failed lint
+lint failed with exit code 4
+{\"event\": \"run_finished\", \"success\": false, \"failed\": [\"lint\"], \"interrupted\": false}\n",
+        );
+    assert_eq!(
+        stderr_of_failure(&assert),
+        "Task lint failed, its diagnostics are in .pilot/failures/<file>\n"
+    );
+
+    // a dry run doesn't fire them
+    run_in("events")
+        .args(["--dry-run", "build"])
+        .assert()
+        .success()
+        .stdout("> build\nskipped build\n");
+}
+
+#[test]
+#[cfg(unix)]
+fn run_hooks_in_the_shell_of_the_tasks() {
+    run_in("events")
+        .args(["--color", "never", "--shell", "bin/tagged-sh -c", "build"])
+        .assert()
+        .success()
+        .stderr("")
+        .stdout(
+            "tagged: started build
+> build
+build: tagged: built
+finished build
+tagged: {\"event\": \"run_finished\", \"success\": true, \"failed\": [], \"interrupted\": false}\n",
+        );
+}
+
+#[test]
+fn reject_unknown_hook_event() {
+    run_in("unknown_event")
+        .arg("build")
+        .assert()
+        .failure()
+        .stdout("")
+        .stderr("This is not a valid Pilotfile: hooks has no event task_finished, the events are run_started, task_failed, task_restarted, run_finished\n");
+}
+
+#[test]
+fn list_valid_tasks_of_invalid_pilotfile() {
+    run_in("malformed")
+        .assert()
+        .failure()
+        .stdout(
+            "Available tasks:
+  \x1b[0;32mbuild\x1b[0m  \x1b[2mjob\x1b[0m  build it
+  \x1b[0;32mtest\x1b[0m   \x1b[2mjob\x1b[0m\n",
+        )
+        .stderr(
+            "Pilotfile.yaml:4: step 2 of task lint: shell needs a command or a map of operating systems to commands
+Pilotfile.yaml:9: hooks has no event task_finished, the events are run_started, task_failed, task_restarted, run_finished
+Pilotfile.yaml:11: task deploy needs a list of steps\n",
+        );
+
+    // running a task still needs a valid Pilotfile
+    run_in("malformed")
+        .arg("build")
+        .assert()
+        .failure()
+        .stdout("")
+        .stderr("This is not a valid Pilotfile: step 2 of task lint: shell needs a command or a map of operating systems to commands\n");
+}
+
+#[test]
+fn print_output_of_failed_quiet_task() {
+    let assert = run_in("quiet_failure")
+        .args(["--color", "never", "lint", "check", "-q", "lint", "check"])
+        .assert()
+        .failure()
+        .stdout(
+            "> lint
+finished lint
+> check
+check is quiet, these are its last 2 lines of output:
+check: checking
+check: broken
+failed check\n",
+        );
+    assert_eq!(
+        stderr_of_failure(&assert),
+        "Task check failed, its diagnostics are in .pilot/failures/<file>\n"
+    );
+}
+
+#[test]
+fn run_tasks_of_command_line_in_parallel() {
+    let _ = std::fs::remove_file(
+        std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("test_data/parallel_cli/ready"),
+    );
+
+    // the client waits for the server, one after another it would time out
+    let output = run_in("parallel_cli")
+        .args(["--color", "never", "--parallel", "client", "server"])
+        .assert()
+        .success()
+        .stderr("")
+        .get_output()
+        .stdout
+        .clone();
+    let output = String::from_utf8(output).unwrap();
+
+    let mut lines: Vec<_> = output.lines().collect();
+    lines.sort();
+    assert_eq!(
+        lines,
+        [
+            "> client",
+            "> server",
+            "client: connected",
+            "finished client",
+            "finished server",
+            "server: serving",
+        ]
+    );
+}
+
+#[test]
+#[cfg(unix)]
+fn notify_about_finished_run() {
+    let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("test_data/notify");
+    for file in ["summary.json", "posted.json", "posted_args"] {
+        let _ = std::fs::remove_file(dir.join(file));
+    }
+
+    // the fake curl records the post instead of sending it
+    let path = format!(
+        "{}:{}",
+        dir.join("bin").display(),
+        std::env::var("PATH").unwrap()
+    );
+    run_in("notify")
+        .env("PATH", &path)
+        .args(["--color", "never", "build", "lint"])
+        .assert()
+        .failure();
+
+    let summary = std::fs::read_to_string(dir.join("summary.json")).unwrap();
+    assert!(summary.contains("\"success\": false, \"interrupted\": false"));
+    assert!(summary.contains(
+        "{\"name\": \"build\", \"kind\": \"job\", \"status\": \"success\", \"exit_code\": 0"
+    ));
+    assert!(summary.contains(
+        "{\"name\": \"lint\", \"kind\": \"job\", \"status\": \"failed\", \"exit_code\": 4"
+    ));
+
+    let build_log = summary.split("tasks/build.log").next().unwrap();
+    let build_log = &build_log[build_log.rfind('"').unwrap() + 1..];
+    assert_eq!(
+        std::fs::read_to_string(build_log.to_string() + "tasks/build.log").unwrap(),
+        "built\n"
+    );
+
+    assert_eq!(
+        std::fs::read_to_string(dir.join("posted.json")).unwrap(),
+        summary
+    );
+    assert!(std::fs::read_to_string(dir.join("posted_args"))
+        .unwrap()
+        .ends_with("--data-binary @- https://chat.example.com/hooks/dev\n"));
+
+    // a dry run doesn't notify anyone
+    std::fs::remove_file(dir.join("summary.json")).unwrap();
+    run_in("notify")
+        .env("PATH", &path)
+        .args(["--dry-run", "build"])
+        .assert()
+        .success();
+    assert!(!dir.join("summary.json").exists());
+}
+
+#[test]
+fn compare_runs() {
+    let _ = std::fs::remove_dir_all(
+        std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("test_data/compare/.pilot"),
+    );
+
+    run_in("compare")
+        .args(["build", "check"])
+        .env("STAGE", "one")
+        .env("DATABASE_URL", "postgres://app:hunter2@db/app")
+        .env("API_TOKEN", "first")
+        .assert()
+        .success();
+    run_in("compare")
+        .args(["build", "check"])
+        .env("STAGE", "two")
+        .env("DATABASE_URL", "postgres://app:hunter2@db/app")
+        .env("API_TOKEN", "second")
+        .assert()
+        .failure();
+
+    let output = run_in("compare")
+        .arg("compare")
+        .assert()
+        .success()
+        .stderr("")
+        .get_output()
+        .stdout
+        .clone();
+    let output = String::from_utf8(output).unwrap();
+
+    let lines: Vec<_> = output.lines().collect();
+    assert!(lines[0].starts_with("compare "));
+    assert!(lines[1].contains("% build took "));
+    assert_eq!(
+        lines[2..],
+        [
+            "newly failed: check",
+            "changed environment:",
+            "  API_TOKEN: changed",
+            "  STAGE: one -> two",
+        ]
+    );
+
+    // secrets only get a fingerprint in the history
+    let runs =
+        std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("test_data/compare/.pilot/runs");
+    for run in std::fs::read_dir(runs).unwrap() {
+        let report = std::fs::read_to_string(run.unwrap().path().join("run.json")).unwrap();
+        assert!(!report.contains("first") && !report.contains("second"));
+        assert!(report.contains("\"DATABASE_URL\": \"postgres://app:hidden:"));
+        assert!(!report.contains("hunter2"));
+    }
+
+    run_in("compare")
+        .args(["compare", "last"])
+        .assert()
+        .failure()
+        .stderr("Usage: pilot compare [<run-id|last> <run-id|last>]\n");
+}
+
+#[test]
+fn reproduce_run() {
+    // the Pilotfile changes after the first run, so the test writes it
+    let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("test_data/repro");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let pilotfile = dir.join("Pilotfile.yaml");
+
+    std::fs::write(
+        &pilotfile,
+        "greet:\n  - shell: echo \"$GREETING, first Pilotfile\"\n",
+    )
+    .unwrap();
+    run_in("repro")
+        .args(["greet", "-e", "GREETING=hello"])
+        .assert()
+        .success();
+
+    std::fs::write(&pilotfile, "greet:\n  - shell: echo second Pilotfile\n").unwrap();
+    let output = run_in("repro")
+        .args(["repro", "last"])
+        .assert()
+        .success()
+        .stderr("")
+        .get_output()
+        .stdout
+        .clone();
+    let output = String::from_utf8(output).unwrap();
+
+    let (reproducing, output) = output.split_once('\n').unwrap();
+    assert!(reproducing.starts_with("reproducing run "));
+    assert!(reproducing.ends_with(": pilot greet -e GREETING=hello"));
+    assert_eq!(
+        output,
+        "> greet\n\x1b[0;32mgreet:\x1b[0m hello, first Pilotfile\nfinished greet\n"
+    );
+
+    run_in("repro")
+        .arg("repro")
+        .assert()
+        .failure()
+        .stderr("Usage: pilot repro <run-id|last>\n");
+}
+
+#[test]
+fn reproduce_run_without_keeping_secrets() {
+    let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("test_data/repro_secret");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("Pilotfile.yaml"),
+        "login:\n  - shell: echo \"$USER_NAME $API_TOKEN\"\n",
+    )
+    .unwrap();
+
+    run_in("repro_secret")
+        .args([
+            "login",
+            "-e",
+            "USER_NAME=ada",
+            "-e",
+            "API_TOKEN=supersecret",
+        ])
+        .env_remove("API_TOKEN")
+        .assert()
+        .success();
+
+    let runs = dir.join(".pilot/runs");
+    let run = std::fs::read_dir(&runs).unwrap().next().unwrap().unwrap();
+    let report = std::fs::read_to_string(run.path().join("run.json")).unwrap();
+    assert!(report.contains("\"USER_NAME=ada\""));
+    assert!(!report.contains("supersecret"));
+
+    run_in("repro_secret")
+        .args(["repro", "last"])
+        .env_remove("API_TOKEN")
+        .assert()
+        .failure()
+        .stderr(format!(
+            "Run {} set API_TOKEN with -e, which the history hides, set API_TOKEN in the \
+environment to reproduce it\n",
+            run.file_name().to_string_lossy()
+        ));
+
+    let output = run_in("repro_secret")
+        .args(["repro", "last"])
+        .env("API_TOKEN", "supersecret")
+        .assert()
+        .success()
+        .stderr("")
+        .get_output()
+        .stdout
+        .clone();
+    let output = String::from_utf8(output).unwrap();
+
+    let (reproducing, output) = output.split_once('\n').unwrap();
+    assert!(reproducing.contains(": pilot login -e USER_NAME=ada -e API_TOKEN=hidden:"));
+    assert!(!reproducing.contains("supersecret"));
+    assert_eq!(
+        output,
+        "> login\n\x1b[0;32mlogin:\x1b[0m ada supersecret\nfinished login\n"
+    );
+}
+
+#[test]
+#[cfg(unix)]
+fn cancel_task_of_active_run() {
+    let dir = env!("CARGO_MANIFEST_DIR").to_string() + "/test_data/cancel";
+    let _ = std::fs::remove_file(dir.clone() + "/go");
+
+    let run = std::process::Command::new(assert_cmd::cargo::cargo_bin(env!("CARGO_PKG_NAME")))
+        .current_dir(&dir)
+        .env(
+            "XDG_CONFIG_HOME",
+            env!("CARGO_MANIFEST_DIR").to_string() + "/test_data/user_config/none",
+        )
+        .args(["--color", "never", "stack"])
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    // the watcher can only be cancelled once it runs
+    let started = std::time::Instant::now();
+    while !run_in("cancel")
+        .args(["cancel", "watcher"])
+        .output()
+        .unwrap()
+        .status
+        .success()
+    {
+        assert!(started.elapsed() < std::time::Duration::from_secs(10));
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+
+    // the server keeps running after the watcher is gone
+    std::fs::write(dir + "/go", "").unwrap();
+
+    let output = run.wait_with_output().unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(String::from_utf8(output.stderr).unwrap(), "");
+    assert!(stdout.contains("watcher was cancelled\nfailed stack > watcher\n"));
+    assert!(stdout.contains("server: still serving\nfinished stack > server\n"));
+    assert!(started.elapsed() < std::time::Duration::from_secs(20));
+
+    run_in("cancel")
+        .args(["cancel", "watcher"])
+        .assert()
+        .failure()
+        .stderr("No run is going on\n");
+}
+
+#[test]
+#[cfg(unix)]
+fn follow_task_log() {
+    use std::io::BufRead;
+
+    let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("test_data/follow_log");
+    let _ = std::fs::remove_dir_all(dir.join(".pilot"));
+    let _ = std::fs::remove_file(dir.join("go"));
+
+    let mut run = std::process::Command::new(assert_cmd::cargo::cargo_bin(env!("CARGO_PKG_NAME")))
+        .current_dir(&dir)
+        .env(
+            "XDG_CONFIG_HOME",
+            env!("CARGO_MANIFEST_DIR").to_string() + "/test_data/user_config/none",
+        )
+        .arg("server")
+        .stdout(std::process::Stdio::null())
+        .spawn()
+        .unwrap();
+
+    // the run is the last one once the server printed something
+    let started = std::time::Instant::now();
+    while !std::fs::read_dir(dir.join(".pilot/runs"))
+        .into_iter()
+        .flatten()
+        .any(|run| run.unwrap().path().join("tasks/server.log").exists())
+    {
+        assert!(started.elapsed() < std::time::Duration::from_secs(10));
+        std::thread::sleep(std::time::Duration::from_millis(50));
+    }
+
+    let mut follow =
+        std::process::Command::new(assert_cmd::cargo::cargo_bin(env!("CARGO_PKG_NAME")))
+            .current_dir(&dir)
+            .env("CLICOLOR_FORCE", "1")
+            .args(["logs", "server", "-f"])
+            .stdout(std::process::Stdio::piped())
+            .spawn()
+            .unwrap();
+    let mut lines = std::io::BufReader::new(follow.stdout.take().unwrap()).lines();
+
+    assert_eq!(
+        lines.next().unwrap().unwrap(),
+        "\x1b[0;32mserver:\x1b[0m starting"
+    );
+
+    // the lines printed after it started follow, until the run is over
+    std::fs::write(dir.join("go"), "").unwrap();
+    assert_eq!(
+        lines.next().unwrap().unwrap(),
+        "\x1b[0;32mserver:\x1b[0m stopping"
+    );
+    assert!(lines.next().is_none());
+    assert!(follow.wait().unwrap().success());
+    assert!(run.wait().unwrap().success());
+
+    // after the run the whole log is printed
+    run_in("follow_log")
+        .args(["logs", "server", "-f"])
+        .assert()
+        .success()
+        .stdout("\x1b[0;32mserver:\x1b[0m starting\n\x1b[0;32mserver:\x1b[0m stopping\n");
+
+    run_in("follow_log")
+        .args(["logs", "-f"])
+        .assert()
+        .failure()
+        .stderr("Usage: pilot logs <run-id|last> [task] or pilot logs <task> -f\n");
+}
+
+#[test]
+#[cfg(unix)]
+fn keep_supervising_task_after_output_failed() {
+    use std::io::BufRead;
+
+    let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("test_data/closed_stdout");
+    let _ = std::fs::remove_file(dir.join("finished"));
+
+    let mut run = std::process::Command::new(assert_cmd::cargo::cargo_bin(env!("CARGO_PKG_NAME")))
+        .current_dir(&dir)
+        .env(
+            "XDG_CONFIG_HOME",
+            env!("CARGO_MANIFEST_DIR").to_string() + "/test_data/user_config/none",
+        )
+        .arg("server")
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    // like `pilot server | head -1`, printing the output of the server fails once it is closed
+    let mut line = String::new();
+    std::io::BufReader::new(run.stdout.take().unwrap())
+        .read_line(&mut line)
+        .unwrap();
+    assert_eq!(line, "> server\n");
+
+    let output = run.wait_with_output().unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    // printing starts again with the next line a few times before it gives up
+    assert_eq!(
+        stderr
+            .matches(
+                "Could not print the output of task server: failed printing to stdout: Broken \
+pipe (os error 32), printing it again from the next line\n"
+            )
+            .count(),
+        3
+    );
+    assert!(stderr.contains(
+        "Could not print the output of task server: failed printing to stdout: Broken pipe \
+(os error 32), it keeps running without it\n"
+    ));
+    // the rest of its output was read, so it didn't block on a full pty
+    assert!(dir.join("finished").exists());
+
+    // the lines read while printing was started again are in the history
+    run_in("closed_stdout")
+        .args(["logs", "last", "server"])
+        .assert()
+        .success()
+        .stdout("ready\nline 1\nline 2\nline 3\n");
+}
+
+#[test]
+fn fail_service_that_exits_by_itself() {
+    run_in("kind")
+        .args(["--color", "never", "crashing"])
+        .assert()
+        .failure()
+        .stderr("Service crashing exited by itself, services run until they are stopped\n")
+        .stdout("> crashing\ncrashing: started\nfailed crashing\n");
+}
+
+#[test]
+fn restart_service_that_exits_by_itself() {
+    run_in("kind")
+        .args(["--color", "never", "restarting"])
+        .assert()
+        .failure()
+        .stderr(
+            "Starting service restarting again, restart 1 of 2
+Starting service restarting again, restart 2 of 2
+Service restarting exited by itself, services run until they are stopped
+",
+        )
+        .stdout(
+            "> restarting
+restarting: started
+hook: restarting restart 1
+restarting: started
+hook: restarting restart 2
+restarting: started
+failed restarting
+",
+        );
+}
+
+#[test]
+#[cfg(unix)]
+fn stop_service_that_fails_its_health_check() {
+    let assert = run_in("kind")
+        .args(["--color", "never", "unhealthy"])
+        .timeout(std::time::Duration::from_secs(5))
+        .assert()
+        .failure()
+        .stdout("> unhealthy\nunhealthy: started\nfailed unhealthy\n");
+
+    assert!(stderr_of_failure(&assert)
+        .starts_with("Service unhealthy failed its health check: `exit 1` still failed after "));
+}
+
+#[test]
+#[cfg(unix)]
+fn run_the_tasks_a_task_needs_first() {
+    let assert = run_in("needs")
+        .args(["--color", "never", "api"])
+        .timeout(std::time::Duration::from_secs(10))
+        .assert()
+        .success()
+        .stderr("");
+    let output = String::from_utf8_lossy(&assert.get_output().stdout).to_string();
+
+    // the job finished and the service is ready before the task starts, the service stops with it
+    let position = |line: &str| output.find(line).unwrap();
+    assert!(position("finished api > migrate\n") < position("api: api is up\n"));
+    assert!(
+        position("health api > database: ready after 1 attempt\n") < position("api: api is up\n")
+    );
+    assert!(output.ends_with("api: api is up\nstopped api > database\nfinished api\n"));
+}
+
+#[test]
+fn skip_task_whose_dependency_failed() {
+    run_in("needs")
+        .args(["--color", "never", "report"])
+        .assert()
+        .failure()
+        .stdout(
+            "> report
+> report > broken
+failed report > broken
+skipped report: dependency broken failed
+",
+        );
+}
+
+#[test]
+#[cfg(unix)]
+fn stop_task_when_its_dependency_crashes() {
+    let assert = run_in("needs")
+        .args(["--color", "never", "worker"])
+        .timeout(std::time::Duration::from_secs(10))
+        .assert()
+        .failure()
+        .stdout(
+            "> worker
+> worker > crashing
+worker: working
+failed worker > crashing
+stopped worker: dependency crashing failed
+",
+        );
+
+    assert_eq!(
+        stderr_of_failure(&assert),
+        "Service crashing exited by itself, services run until they are stopped\n"
+    );
+
+    // cancelled right away, its finally steps still run
+    run_in("needs")
+        .args(["--color", "never", "cancelled"])
+        .timeout(std::time::Duration::from_secs(10))
+        .assert()
+        .failure()
+        .stdout(
+            "> cancelled
+> cancelled > crashing
+cancelled: working
+failed cancelled > crashing
+cancelled: cleaned up
+stopped cancelled: dependency crashing failed
+",
+        );
+}
+
+#[test]
+#[cfg(unix)]
+fn succeed_after_stopping_services() {
+    use std::io::{BufRead, Read};
+
+    let mut run = std::process::Command::new(assert_cmd::cargo::cargo_bin(env!("CARGO_PKG_NAME")))
+        .current_dir(env!("CARGO_MANIFEST_DIR").to_string() + "/test_data/kind")
+        .env(
+            "XDG_CONFIG_HOME",
+            env!("CARGO_MANIFEST_DIR").to_string() + "/test_data/user_config/none",
+        )
+        .args(["--color", "never", "dev"])
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    // the job is done and the service is up
+    let mut stdout = std::io::BufReader::new(run.stdout.take().unwrap());
+    let mut output = String::new();
+    while !output.contains("finished dev > migrate\n")
+        || !output.contains("server: serving\n")
+        || !output.contains("health dev > server: ready after 1 attempt\n")
+    {
+        assert!(stdout.read_line(&mut output).unwrap() > 0);
+    }
+
+    std::process::Command::new("kill")
+        .args(["-INT", &run.id().to_string()])
+        .status()
+        .unwrap();
+    stdout.read_to_string(&mut output).unwrap();
+
+    let status = run.wait().unwrap();
+    assert!(output.contains("stopped dev > server\n"));
+    assert!(!output.contains("failed"));
+    assert!(status.success());
+}
+
+#[test]
+#[cfg(unix)]
+fn reload_tasks_when_the_pilotfile_changes() {
+    use std::io::{BufRead, Read};
+
+    let dir = std::env::temp_dir().join(format!("pilot-reload-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::copy(
+        "test_data/reload/Pilotfile.yaml",
+        dir.join("Pilotfile.yaml"),
+    )
+    .unwrap();
+
+    let mut run = std::process::Command::new(assert_cmd::cargo::cargo_bin(env!("CARGO_PKG_NAME")))
+        .current_dir(&dir)
+        .env(
+            "XDG_CONFIG_HOME",
+            env!("CARGO_MANIFEST_DIR").to_string() + "/test_data/user_config/none",
+        )
+        .args(["--color", "never", "--reload", "dev:*"])
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let mut stdout = std::io::BufReader::new(run.stdout.take().unwrap());
+    let mut output = String::new();
+    let mut wait_for = |line: &str| {
+        while !output.contains(line) {
+            assert!(stdout.read_line(&mut output).unwrap() > 0);
+        }
+    };
+
+    wait_for("dev:web: web up\n");
+    wait_for("dev:worker: worker up\n");
+
+    // the worker changes, the web task stays and an api task is added
+    std::fs::write(
+        dir.join("Pilotfile.yaml"),
+        "hooks:\n  task_restarted: echo \"restarted by the hook $PILOT_TASK\"\n\n\
+         dev:web:\n  - shell: echo web up; sleep 30\n\n\
+         dev:worker:\n  - shell: echo worker up again; sleep 30\n\n\
+         dev:api:\n  - shell: echo api up; sleep 30\n",
+    )
+    .unwrap();
+    wait_for("reloaded the Pilotfile: restarted dev:worker, started dev:api\n");
+    wait_for("restarted by the hook dev:worker\n");
+    wait_for("dev:worker: worker up again\n");
+    wait_for("dev:api: api up\n");
+
+    // a Pilotfile that can't be loaded leaves the tasks running
+    std::fs::write(dir.join("Pilotfile.yaml"), "dev:api: [").unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(500));
+
+    std::fs::write(
+        dir.join("Pilotfile.yaml"),
+        "dev:api:\n  - shell: echo api up; sleep 30\n",
+    )
+    .unwrap();
+    wait_for("reloaded the Pilotfile: stopped dev:web, stopped dev:worker\n");
+
+    std::process::Command::new("kill")
+        .args(["-INT", &run.id().to_string()])
+        .status()
+        .unwrap();
+    stdout.read_to_string(&mut output).unwrap();
+    run.wait().unwrap();
+
+    let mut stderr = String::new();
+    run.stderr
+        .take()
+        .unwrap()
+        .read_to_string(&mut stderr)
+        .unwrap();
+    assert!(stderr.starts_with("Could not reload the Pilotfile, the tasks keep running: "));
+    assert!(output.contains("stopped dev:web\n"));
+    assert_eq!(output.matches("> dev:web\n").count(), 1);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+#[cfg(unix)]
+fn stop_task_with_its_signal() {
+    let assert = run_in("stop_signal")
+        .args(["--color", "never", "database"])
+        .timeout(std::time::Duration::from_secs(5))
+        .assert()
+        .success()
+        .stdout(
+            "> database
+database: started
+database: shutting down cleanly
+finished database\n",
+        );
+    assert_eq!(
+        stderr_of_failure(&assert),
+        "Task database printed nothing for 500ms, stopping it\n"
+    );
+}
+
+#[test]
+#[cfg(unix)]
+fn kill_task_after_grace_period() {
+    let started = std::time::Instant::now();
+
+    let assert = run_in("stop_signal")
+        .args(["--color", "never", "stubborn"])
+        .timeout(std::time::Duration::from_secs(10))
+        .assert()
+        .failure()
+        .stdout("> stubborn\nstubborn: started\nfailed stubborn\n");
+    assert_eq!(
+        stderr_of_failure(&assert),
+        "Task stubborn printed nothing for 500ms, stopping it\nTask stubborn failed, its diagnostics are in .pilot/failures/<file>\n"
+    );
+
+    // it ignores SIGTERM, so only SIGKILL stops it before its sleep ends
+    assert!(started.elapsed() < std::time::Duration::from_secs(10));
+}
+
+#[test]
+fn reject_unknown_stop_signal() {
+    run_in("invalid_stop_signal")
+        .arg("database")
+        .assert()
+        .failure()
+        .stdout("")
+        .stderr("This is not a valid Pilotfile: step 1 of task database: stop_signal needs one of SIGTERM, SIGINT, SIGHUP, SIGQUIT, SIGKILL, SIGUSR1, SIGUSR2\n");
+}
+
+#[test]
+fn list_tasks_as_table() {
+    run_in("list_table")
+        .env_remove("CLICOLOR_FORCE")
+        .assert()
+        .success()
+        .stderr("")
+        .stdout(
+            "Available tasks:
+  build                        ci, rust  job      build the app
+  dev-server-with-a-long-name            service  serve the app on port 8080 and
+                                                  rebuild it whenever one of its
+                                                  files changes
+  lint                         ci        job
+  queue                                  service\n",
+        );
+
+    run_in("list_table").arg("--plain").assert().success().stdout(
+        "build\tci,rust\tjob\tbuild the app
+dev-server-with-a-long-name\t\tservice\tserve the app on port 8080 and rebuild it whenever one of its files changes
+lint\tci\tjob\t
+queue\t\tservice\t\n",
+    );
+}
+
+#[test]
+#[cfg(unix)]
+fn upgrade_to_the_latest_release() {
+    let dir = std::env::temp_dir().join(format!("pilot-upgrade-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let pilot = dir.join("pilot");
+    std::fs::copy(assert_cmd::cargo::cargo_bin(env!("CARGO_PKG_NAME")), &pilot).unwrap();
+
+    let data = env!("CARGO_MANIFEST_DIR").to_string() + "/test_data/upgrade";
+    let asset = format!("pilot-{}-{}", std::env::consts::ARCH, std::env::consts::OS);
+    let release = |tag: &str, checksum: &str| {
+        std::fs::write(
+            dir.join("release.json"),
+            format!(
+                r#"{{"tag_name": "{}", "assets": [
+                    {{"name": "{}", "browser_download_url": "file://{}/new-pilot"}},
+                    {{"name": "{}.sha256", "browser_download_url": "file://{}/{}"}}
+                ]}}"#,
+                tag, asset, data, asset, data, checksum
+            ),
+        )
+        .unwrap();
+    };
+    let upgrade = |args: &[&str]| {
+        Command::new(&pilot)
+            .arg("upgrade")
+            .args(args)
+            .env(
+                "PILOT_RELEASES_URL",
+                format!("file://{}", dir.join("release.json").display()),
+            )
+            .assert()
+    };
+
+    release("v0.1.0", "new-pilot.sha256");
+    upgrade(&[]).success().stdout(format!(
+        "pilot {} is the latest version\n",
+        env!("CARGO_PKG_VERSION")
+    ));
+
+    release("v99.0.0", "wrong.sha256");
+    upgrade(&["--check"]).success().stdout(format!(
+        "pilot 99.0.0 is available, this is {}, pilot upgrade installs {}\n",
+        env!("CARGO_PKG_VERSION"),
+        asset
+    ));
+    upgrade(&[]).failure().stdout("").stderr(format!(
+        "The checksum of {} is c722da17dd3a7b11a6d66feaa60d453a3d83876927748c01c2f6eef14bd34a5e, \
+but the release says 0000000000000000000000000000000000000000000000000000000000000000, \
+pilot was not upgraded\n",
+        asset
+    ));
+
+    release("v99.0.0", "new-pilot.sha256");
+    upgrade(&[]).success().stdout(format!(
+        "Upgraded pilot from {} to 99.0.0\n",
+        env!("CARGO_PKG_VERSION")
+    ));
+    Command::new(&pilot)
+        .assert()
+        .success()
+        .stdout("upgraded pilot\n");
+
+    std::fs::remove_dir_all(dir).unwrap();
 }